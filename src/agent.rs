@@ -1,22 +1,33 @@
 use super::capabilities::Capabilities;
+use super::class::ClassId;
 use super::config::Config;
+use super::context::static_context;
 use super::environment::jvm::{JVMF, JVMAgent};
 use super::environment::jvmti::JVMTI;
 use super::event::*;
+use super::event_handler::{capture_breakpoint_frames, capture_exception_frames, capture_monitor_frames, subscribe, track_loaded_classes, CALLBACK_TABLE};
 use super::error::*;
-use super::native::JavaVMPtr;
+use super::heap::{class_histogram, ClassHistogram};
+use super::instrumentation::probe::ProbeFilter;
+use super::method::{line_start_location, MethodId};
+use super::native::{JavaLong, JavaVMPtr};
 use super::options::Options;
+use super::perf_map::PerfMap;
+use super::profile::heap::HeapSnapshot;
+use super::pipeline;
 use super::version::VersionNumber;
 use environment::Environment;
 use environment::jvmti::JVMTIEnvironment;
 use environment::jni::{JNIEnvironment, JNI};
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
 
 pub struct Agent {
     jvm: Box<JVMF>,
     pub jvm_env: Box<Environment>,
     //pub jvmti: Box<JVMTI>,
-    pub capabilities: Capabilities,
-    callbacks: EventCallbacks,
+    pub capabilities: Capabilities
 }
 
 impl Agent {
@@ -29,7 +40,6 @@ impl Agent {
             Ok(jvmti) => Agent {
                 jvm: Box::new(jvm_agent),
                 capabilities: Capabilities::new(),
-                callbacks: EventCallbacks::new(),
                 jvm_env: Agent::create_jvm_env(jvmti, jni)
             },
             Err(err) => panic!("FATAL: Could not get JVMTI environment: {}", translate_error(&err))
@@ -44,7 +54,6 @@ impl Agent {
             Ok(jvmti) => Agent {
                 jvm: jvm,
                 capabilities: Capabilities::new(),
-                callbacks: EventCallbacks::new(),
                 jvm_env: Agent::create_jvm_env(jvmti, jni)
             },
             Err(err) => panic!("FATAL: Could not get JVMTI Env: {}", translate_error(&err))
@@ -59,7 +68,6 @@ impl Agent {
                 Agent {
                     jvm: Box::new(jvm_agent),
                     capabilities: Capabilities::new(),
-                    callbacks: EventCallbacks::new(),
                     jvm_env: Agent::create_jvm_env(jvmti, jni)
                 }
             },
@@ -102,6 +110,14 @@ impl Agent {
         self.jvm_env.set_event_notification_mode(VMEvent::GarbageCollectionStart, false);
         self.jvm_env.set_event_notification_mode(VMEvent::GarbageCollectionFinish, false);
         self.jvm_env.set_event_notification_mode(VMEvent::ClassFileLoadHook, false);
+        self.jvm_env.set_event_notification_mode(VMEvent::ClassLoad, false);
+        self.jvm_env.set_event_notification_mode(VMEvent::ClassPrepare, false);
+        self.jvm_env.set_event_notification_mode(VMEvent::CompiledMethodLoad, false);
+        self.jvm_env.set_event_notification_mode(VMEvent::CompiledMethodUnload, false);
+        self.jvm_env.set_event_notification_mode(VMEvent::DynamicCodeGenerated, false);
+        self.jvm_env.set_event_notification_mode(VMEvent::Breakpoint, false);
+        self.jvm_env.set_event_notification_mode(VMEvent::FramePop, false);
+        self.jvm_env.set_event_notification_mode(VMEvent::SingleStep, false);
         println!("Jvmti event tracing is stopped.")
     }
 
@@ -132,149 +148,362 @@ impl Agent {
             }
         }
 
-        match self.jvm_env.set_event_callbacks(self.callbacks.clone()) {
+        match self.jvm_env.set_event_callbacks() {
             None => {
-                self.jvm_env.set_event_notification_mode(VMEvent::VMObjectAlloc, self.callbacks.vm_object_alloc.is_some());
-                self.jvm_env.set_event_notification_mode(VMEvent::VMObjectFree, self.callbacks.vm_object_free.is_some());
-                self.jvm_env.set_event_notification_mode(VMEvent::VMStart, self.callbacks.vm_start.is_some());
-                self.jvm_env.set_event_notification_mode(VMEvent::VMInit, self.callbacks.vm_init.is_some());
-                self.jvm_env.set_event_notification_mode(VMEvent::VMDeath, self.callbacks.vm_death.is_some());
-                self.jvm_env.set_event_notification_mode(VMEvent::MethodEntry, self.callbacks.method_entry.is_some());
-                self.jvm_env.set_event_notification_mode(VMEvent::MethodExit, self.callbacks.method_exit.is_some());
-                self.jvm_env.set_event_notification_mode(VMEvent::ThreadStart, self.callbacks.thread_start.is_some());
-                self.jvm_env.set_event_notification_mode(VMEvent::ThreadEnd, self.callbacks.thread_end.is_some());
-                self.jvm_env.set_event_notification_mode(VMEvent::Exception, self.callbacks.exception.is_some());
-                self.jvm_env.set_event_notification_mode(VMEvent::ExceptionCatch, self.callbacks.exception_catch.is_some());
-                self.jvm_env.set_event_notification_mode(VMEvent::MonitorWait, self.callbacks.monitor_wait.is_some());
-                self.jvm_env.set_event_notification_mode(VMEvent::MonitorWaited, self.callbacks.monitor_waited.is_some());
-                self.jvm_env.set_event_notification_mode(VMEvent::MonitorContendedEnter, self.callbacks.monitor_contended_enter.is_some());
-                self.jvm_env.set_event_notification_mode(VMEvent::MonitorContendedEntered, self.callbacks.monitor_contended_entered.is_some());
-                self.jvm_env.set_event_notification_mode(VMEvent::FieldAccess, self.callbacks.field_access.is_some());
-                self.jvm_env.set_event_notification_mode(VMEvent::FieldModification, self.callbacks.field_modification.is_some());
-                self.jvm_env.set_event_notification_mode(VMEvent::GarbageCollectionStart, self.callbacks.garbage_collection_start.is_some());
-                self.jvm_env.set_event_notification_mode(VMEvent::GarbageCollectionFinish, self.callbacks.garbage_collection_finish.is_some());
-                self.jvm_env.set_event_notification_mode(VMEvent::ClassFileLoadHook, self.callbacks.class_file_load_hook.is_some());
+                // Handlers may subscribe while events are being enabled, so the table isn't kept locked
+                // Classes are tracked for unloading as they're prepared, and freed classes reported
+                // along with the other freed objects. Single stepping is only enabled for the threads
+                // handlers ask for, see `set_thread_event_notification_mode`
+                let (notifications, track_unload) = match CALLBACK_TABLE.read() {
+                    Ok(callbacks) => (callbacks.notifications(), !callbacks.class_unload.is_empty()),
+                    Err(_) => (vec![], false)
+                };
+
+                for (event, enabled) in notifications {
+                    self.jvm_env.set_event_notification_mode(event, enabled);
+                }
+
+                if track_unload {
+                    match track_loaded_classes(&self.jvm_env) {
+                        Ok(count) => println!("Tracking {} loaded classes for unloading", count),
+                        Err(error) => println!("Couldn't track loaded classes for unloading: {}", translate_error(&error))
+                    }
+                }
+
                 println!("Jvmti event tracing is started.")
             },
             Some(error) => println!("Couldn't register callbacks: {}", translate_error(&error))
         }
     }
 
-    pub fn on_method_entry(&mut self, handler: Option<FnMethodEntry>) {
-        self.callbacks.method_entry = handler;
-        self.capabilities.can_generate_method_entry_events = handler.is_some();
+    // Handlers are added to the ones already subscribed. The events they handle are enabled, and
+    // the capabilities they require requested, by the next `update`
+
+    pub fn on_method_entry(&mut self, handler: Box<FnMethodEntry>) -> Subscription {
+        self.capabilities.can_generate_method_entry_events = true;
+        subscribe(|callbacks| &mut callbacks.method_entry, handler)
+    }
+
+    pub fn on_method_exit(&mut self, handler: Box<FnMethodExit>) -> Subscription {
+        self.capabilities.can_generate_method_exit_events = true;
+        subscribe(|callbacks| &mut callbacks.method_exit, handler)
+    }
+
+    pub fn on_vm_init(&mut self, handler: Box<FnVMInit>) -> Subscription {
+        subscribe(|callbacks| &mut callbacks.vm_init, handler)
+    }
+
+    pub fn on_vm_death(&mut self, handler: Box<FnVMDeath>) -> Subscription {
+        subscribe(|callbacks| &mut callbacks.vm_death, handler)
+    }
+
+    pub fn on_vm_start(&mut self, handler: Box<FnVMStart>) -> Subscription {
+        subscribe(|callbacks| &mut callbacks.vm_start, handler)
+    }
+
+    pub fn on_vm_object_alloc(&mut self, handler: Box<FnVMObjectAlloc>) -> Subscription {
+        self.capabilities.can_generate_vm_object_alloc_events = true;
+        subscribe(|callbacks| &mut callbacks.vm_object_alloc, handler)
+    }
+
+    pub fn on_vm_object_free(&mut self, handler: Box<FnVMObjectFree>) -> Subscription {
+        self.capabilities.can_generate_object_free_events = true;
+        subscribe(|callbacks| &mut callbacks.vm_object_free, handler)
+    }
+
+    pub fn on_thread_start(&mut self, handler: Box<FnThreadStart>) -> Subscription {
+        subscribe(|callbacks| &mut callbacks.thread_start, handler)
+    }
+
+    pub fn on_thread_end(&mut self, handler: Box<FnThreadEnd>) -> Subscription {
+        subscribe(|callbacks| &mut callbacks.thread_end, handler)
+    }
+
+    pub fn on_exception(&mut self, handler: Box<FnException>) -> Subscription {
+        self.capabilities.can_generate_exception_events = true;
+        subscribe(|callbacks| &mut callbacks.exception, handler)
+    }
+
+    /// Capture the top `max_frames` frames of the throwing thread, with the values of their local
+    /// variables, along with the Exception events dispatched from now on. Zero turns the capture off.
+    /// Local variables are only read with the can_access_local_variables capability, which may only
+    /// be available to agents loaded at startup.
+    pub fn capture_exception_frames(&mut self, max_frames: usize) {
+        self.capabilities.can_access_local_variables = true;
+        capture_exception_frames(max_frames);
+    }
+
+    pub fn on_exception_catch(&mut self, handler: Box<FnExceptionCatch>) -> Subscription {
+        self.capabilities.can_generate_exception_events = true;
+        subscribe(|callbacks| &mut callbacks.exception_catch, handler)
+    }
+
+    pub fn on_monitor_wait(&mut self, handler: Box<FnMonitorWait>) -> Subscription {
+        self.capabilities.can_generate_monitor_events = true;
+        subscribe(|callbacks| &mut callbacks.monitor_wait, handler)
+    }
+
+    pub fn on_monitor_waited(&mut self, handler: Box<FnMonitorWaited>) -> Subscription {
+        self.capabilities.can_generate_monitor_events = true;
+        subscribe(|callbacks| &mut callbacks.monitor_waited, handler)
+    }
+
+    pub fn on_monitor_contended_enter(&mut self, handler: Box<FnMonitorContendedEnter>) -> Subscription {
+        self.capabilities.can_generate_monitor_events = true;
+        subscribe(|callbacks| &mut callbacks.monitor_contended_enter, handler)
+    }
+
+    pub fn on_monitor_contended_entered(&mut self, handler: Box<FnMonitorContendedEntered>) -> Subscription {
+        self.capabilities.can_generate_monitor_events = true;
+        subscribe(|callbacks| &mut callbacks.monitor_contended_entered, handler)
+    }
+
+    /// Capture the top `max_frames` frames of the threads entering monitors along with the
+    /// MonitorContendedEntered events dispatched from now on. Zero turns the capture off.
+    pub fn capture_monitor_frames(&mut self, max_frames: usize) {
+        capture_monitor_frames(max_frames);
+    }
+
+    pub fn on_field_access(&mut self, handler: Box<FnFieldAccess>) -> Subscription {
+        self.capabilities.can_generate_field_access_events = true;
+        subscribe(|callbacks| &mut callbacks.field_access, handler)
     }
 
-    pub fn on_method_exit(&mut self, handler: Option<FnMethodExit>) {
-        self.callbacks.method_exit = handler;
-        self.capabilities.can_generate_method_exit_events = handler.is_some();
+    pub fn on_field_modification(&mut self, handler: Box<FnFieldModification>) -> Subscription {
+        self.capabilities.can_generate_field_modification_events = true;
+        subscribe(|callbacks| &mut callbacks.field_modification, handler)
     }
 
-    pub fn on_vm_init(&mut self, handler: Option<FnVMInit>) {
-        self.callbacks.vm_init = handler;
+    /// Breakpoints are set with `set_breakpoint` or `set_line_breakpoints`
+    pub fn on_breakpoint(&mut self, handler: Box<FnBreakpoint>) -> Subscription {
+        self.capabilities.can_generate_breakpoint_events = true;
+        subscribe(|callbacks| &mut callbacks.breakpoint, handler)
     }
 
-    pub fn on_vm_death(&mut self, handler: Option<FnVMDeath>) {
-        self.callbacks.vm_death = handler;
+    /// Capture the top `max_frames` frames of the thread hitting a breakpoint, with the values of
+    /// their local variables, along with the Breakpoint events dispatched from now on. Zero turns
+    /// the capture off.
+    pub fn capture_breakpoint_frames(&mut self, max_frames: usize) {
+        self.capabilities.can_access_local_variables = true;
+        capture_breakpoint_frames(max_frames);
     }
 
-    pub fn on_vm_start(&mut self, handler: Option<FnVMStart>) {
-        self.callbacks.vm_start = handler;
+    /// Single stepping is never enabled globally, handlers enable it for the thread of an event with
+    /// `set_thread_event_notification_mode`, eg. once a thread hits a breakpoint
+    pub fn on_single_step(&mut self, handler: Box<FnSingleStep>) -> Subscription {
+        self.capabilities.can_generate_single_step_events = true;
+        subscribe(|callbacks| &mut callbacks.single_step, handler)
     }
 
-    pub fn on_vm_object_alloc(&mut self, handler: Option<FnVMObjectAlloc>) {
-        self.callbacks.vm_object_alloc = handler;
-        self.capabilities.can_generate_vm_object_alloc_events = handler.is_some();
+    /// FramePop events are only sent for the frames handlers ask for with `notify_frame_pop`
+    pub fn on_frame_pop(&mut self, handler: Box<FnFramePop>) -> Subscription {
+        self.capabilities.can_generate_frame_pop_events = true;
+        subscribe(|callbacks| &mut callbacks.frame_pop, handler)
     }
 
-    pub fn on_vm_object_free(&mut self, handler: Option<FnVMObjectFree>) {
-        self.callbacks.vm_object_free = handler;
-        self.capabilities.can_generate_object_free_events = handler.is_some();
+    pub fn on_garbage_collection_start(&mut self, handler: Box<FnGarbageCollectionStart>) -> Subscription {
+        self.capabilities.can_generate_garbage_collection_events = true;
+        subscribe(|callbacks| &mut callbacks.garbage_collection_start, handler)
     }
 
-    pub fn on_thread_start(&mut self, handler: Option<FnThreadStart>) {
-        self.callbacks.thread_start = handler;
+    pub fn on_garbage_collection_finish(&mut self, handler: Box<FnGarbageCollectionFinish>) -> Subscription {
+        self.capabilities.can_generate_garbage_collection_events = true;
+        subscribe(|callbacks| &mut callbacks.garbage_collection_finish, handler)
     }
 
-    pub fn on_thread_end(&mut self, handler: Option<FnThreadEnd>) {
-        self.callbacks.thread_end = handler;
+    pub fn on_class_file_load(&mut self, handler: Box<FnClassFileLoad>) -> Subscription {
+        self.capabilities.can_retransform_classes = true;
+        subscribe(|callbacks| &mut callbacks.class_file_load_hook, handler)
     }
 
-    pub fn on_exception(&mut self, handler: Option<FnException>) {
-        self.callbacks.exception = handler;
-        self.capabilities.can_generate_exception_events = handler.or(self.callbacks.exception_catch).is_some();
+    pub fn on_class_load(&mut self, handler: Box<FnClassLoad>) -> Subscription {
+        subscribe(|callbacks| &mut callbacks.class_load, handler)
     }
 
-    pub fn on_exception_catch(&mut self, handler: Option<FnExceptionCatch>) {
-        self.callbacks.exception_catch = handler;
-        self.capabilities.can_generate_exception_events = handler.or(self.callbacks.exception).is_some();
+    pub fn on_class_prepare(&mut self, handler: Box<FnClassPrepare>) -> Subscription {
+        subscribe(|callbacks| &mut callbacks.class_prepare, handler)
     }
 
-    pub fn on_monitor_wait(&mut self, handler: Option<FnMonitorWait>) {
-        self.callbacks.monitor_wait = handler;
+    /// Classes are tracked by tagging them, those loaded before the next `update` included
+    pub fn on_class_unload(&mut self, handler: Box<FnClassUnload>) -> Subscription {
+        self.capabilities.can_tag_objects = true;
+        self.capabilities.can_generate_object_free_events = true;
+        subscribe(|callbacks| &mut callbacks.class_unload, handler)
+    }
 
-        let has_some = handler
-            .or(self.callbacks.monitor_waited)
-            .or(self.callbacks.monitor_contended_enter)
-            .or(self.callbacks.monitor_contended_entered).is_some();
+    pub fn on_compiled_method_load(&mut self, handler: Box<FnCompiledMethodLoad>) -> Subscription {
+        self.capabilities.can_generate_compiled_method_load_events = true;
+        subscribe(|callbacks| &mut callbacks.compiled_method_load, handler)
+    }
 
-        self.capabilities.can_generate_monitor_events = has_some;
+    pub fn on_compiled_method_unload(&mut self, handler: Box<FnCompiledMethodUnload>) -> Subscription {
+        self.capabilities.can_generate_compiled_method_load_events = true;
+        subscribe(|callbacks| &mut callbacks.compiled_method_unload, handler)
     }
 
-    pub fn on_monitor_waited(&mut self, handler: Option<FnMonitorWaited>) {
-        self.callbacks.monitor_waited = handler;
+    pub fn on_dynamic_code_generated(&mut self, handler: Box<FnDynamicCodeGenerated>) -> Subscription {
+        subscribe(|callbacks| &mut callbacks.dynamic_code_generated, handler)
+    }
 
-        let has_some = handler
-            .or(self.callbacks.monitor_wait)
-            .or(self.callbacks.monitor_contended_enter)
-            .or(self.callbacks.monitor_contended_entered).is_some();
+    /// Send the CompiledMethodLoad and DynamicCodeGenerated events of the code generated before
+    /// they were enabled to the handlers subscribed to them. Call after `update`.
+    pub fn generate_code_events(&self) -> Result<(), NativeError> {
+        if self.capabilities.can_generate_compiled_method_load_events {
+            self.jvm_env.generate_events(VMEvent::CompiledMethodLoad)?;
+        }
 
-        self.capabilities.can_generate_monitor_events = has_some;
+        self.jvm_env.generate_events(VMEvent::DynamicCodeGenerated)
     }
 
-    pub fn on_monitor_contended_enter(&mut self, handler: Option<FnMonitorContendedEnter>) {
-        self.callbacks.monitor_contended_enter = handler;
+    /// Write the symbols of the JIT compiled code to a perf map at `path`, see `PerfMap`. The
+    /// code compiled before the next `update` is written by `generate_code_events`.
+    pub fn write_perf_map(&mut self, path: &Path) -> io::Result<Vec<Subscription>> {
+        let perf_map = Arc::new(Mutex::new(PerfMap::create(path)?));
+        let methods_map = perf_map.clone();
 
-        let has_some = handler
-            .or(self.callbacks.monitor_wait)
-            .or(self.callbacks.monitor_waited)
-            .or(self.callbacks.monitor_contended_entered).is_some();
+        let methods = self.on_compiled_method_load(Box::new(move |event| {
+            if let Err(err) = methods_map.lock().unwrap().write_compiled_method(&event) {
+                println!("Couldn't write compiled method to perf map: {}", err);
+            }
+        }));
+
+        let dynamic_code = self.on_dynamic_code_generated(Box::new(move |event| {
+            if let Err(err) = perf_map.lock().unwrap().write_dynamic_code(&event) {
+                println!("Couldn't write generated code to perf map: {}", err);
+            }
+        }));
+
+        Ok(vec![methods, dynamic_code])
+    }
+
+    /// Count the objects on the heap by class, see `heap::class_histogram`. The can_tag_objects
+    /// capability is added if the agent doesn't have it yet.
+    pub fn class_histogram(&mut self) -> Result<ClassHistogram, NativeError> {
+        self.add_tag_objects_capability()?;
+
+        class_histogram(&self.jvm_env)
+    }
+
+    /// Capture a graph of the objects reachable from the heap roots, see `HeapSnapshot::capture`.
+    /// The can_tag_objects capability is added if the agent doesn't have it yet.
+    pub fn heap_snapshot(&mut self) -> Result<HeapSnapshot, NativeError> {
+        self.add_tag_objects_capability()?;
+
+        HeapSnapshot::capture(&self.jvm_env)
+    }
+
+    fn add_tag_objects_capability(&mut self) -> Result<(), NativeError> {
+        if !self.jvm_env.get_capabilities().can_tag_objects {
+            let mut capabilities = Capabilities::new();
+            capabilities.can_tag_objects = true;
+
+            self.jvm_env.add_capabilities(&capabilities)?;
+            self.capabilities.can_tag_objects = true;
+        }
 
-        self.capabilities.can_generate_monitor_events = has_some;
+        Ok(())
     }
 
-    pub fn on_monitor_contended_entered(&mut self, handler: Option<FnMonitorContendedEntered>) {
-        self.callbacks.monitor_contended_entered = handler;
+    /// Set a breakpoint at the start of a source line in each method of the loaded classes with code
+    /// on that line, the classes being named `class_name`, eg. `com.example.Foo`, or nested in it.
+    /// Classes loaded later aren't covered. Returns the methods and locations breakpoints were set
+    /// at, to be cleared with `clear_breakpoints`.
+    pub fn set_line_breakpoints(&mut self, class_name: &str, line_number: u32) -> Result<Vec<(MethodId, JavaLong)>, NativeError> {
+        let nested_prefix = format!("{}$", class_name);
+        let mut breakpoints = vec![];
+
+        for class_id in self.jvm_env.get_loaded_classes()? {
+            let matching = self.jvm_env.get_class_signature(&class_id)
+                .map(|signature| signature.name == class_name || signature.name.starts_with(&nested_prefix))
+                .unwrap_or(false);
+
+            if matching {
+                for method_id in self.jvm_env.get_class_methods(&class_id).unwrap_or(vec![]) {
+                    // Abstract and native methods have no line number table
+                    if let Some(location) = self.jvm_env.get_line_number_table(&method_id).ok().and_then(|table| line_start_location(&table, line_number)) {
+                        breakpoints.push((method_id, location));
+                    }
+                }
+            }
 
-        let has_some = handler
-            .or(self.callbacks.monitor_wait)
-            .or(self.callbacks.monitor_waited)
-            .or(self.callbacks.monitor_contended_enter).is_some();
+            self.jvm_env.delete_local_ref(class_id.native_id);
+        }
+
+        for (set, &(method_id, location)) in breakpoints.iter().enumerate() {
+            if let Err(err) = self.jvm_env.set_breakpoint(&method_id, location) {
+                // the caller gets no breakpoints to clear, so none may be left behind
+                let _ = self.clear_breakpoints(&breakpoints[..set].to_vec());
+                return Err(err);
+            }
+        }
 
-        self.capabilities.can_generate_monitor_events = has_some;
+        Ok(breakpoints)
     }
 
-    pub fn on_field_access(&mut self, handler: Option<FnFieldAccess>) {
-        self.callbacks.field_access = handler;
-        self.capabilities.can_generate_field_access_events = handler.is_some();
+    /// Clear the given breakpoints, all of them even if some can't be cleared, returning the first error
+    pub fn clear_breakpoints(&mut self, breakpoints: &Vec<(MethodId, JavaLong)>) -> Result<(), NativeError> {
+        breakpoints.iter().fold(Ok(()), |cleared, &(method_id, location)| cleared.and(self.jvm_env.clear_breakpoint(&method_id, location)))
     }
 
-    pub fn on_field_modification(&mut self, handler: Option<FnFieldModification>) {
-        self.callbacks.field_modification = handler;
-        self.capabilities.can_generate_field_modification_events = handler.is_some();
+    /// Dispatch method, thread, monitor and garbage collection events from a dedicated thread, see
+    /// `pipeline::start`. The callbacks on the application threads then only buffer the events,
+    /// up to `capacity` events per thread. Buffered events are dispatched without the frames
+    /// `capture_monitor_frames` asks for. Returns whether the pipeline was started.
+    pub fn enable_async_events(&mut self, capacity: usize) -> bool {
+        match self.jvm_env.get_java_vm() {
+            Some(vm) => pipeline::start(vm, capacity),
+            None => false
+        }
     }
 
-    pub fn on_garbage_collection_start(&mut self, handler: Option<FnGarbageCollectionStart>) {
-        self.callbacks.garbage_collection_start = handler;
-        self.capabilities.can_generate_garbage_collection_events = handler.or(self.callbacks.garbage_collection_finish).is_some();
+    /// Dispatch events from the callbacks again, once the events buffered so far are dispatched
+    pub fn disable_async_events(&mut self) {
+        pipeline::stop();
     }
 
-    pub fn on_garbage_collection_finish(&mut self, handler: Option<FnGarbageCollectionFinish>) {
-        self.callbacks.garbage_collection_finish = handler;
-        self.capabilities.can_generate_garbage_collection_events = handler.or(self.callbacks.garbage_collection_start).is_some();
+    /// Inject probes into the methods selected by the given patterns (see `ProbeFilter`) instead
+    /// of the ones selected so far. Loaded classes matching either selection are retransformed
+    /// right away, so probes are added and dropped without waiting for classes to be loaded.
+    /// Returns the number of classes retransformed.
+    pub fn instrument(&mut self, entry_points: Vec<String>, active_classes: Vec<String>) -> Result<usize, NativeError> {
+        let previous = match static_context().config.read() {
+            Ok(cfg) => ProbeFilter::from_config(&*cfg),
+            Err(_) => ProbeFilter::new(&vec![], &vec![])
+        };
+        let current = ProbeFilter::new(&entry_points, &active_classes);
+
+        static_context().set_probe_patterns(entry_points, active_classes);
+
+        self.retransform_matching(|class_name| previous.matches_class(class_name) || current.matches_class(class_name))
     }
 
-    pub fn on_class_file_load(&mut self, handler: Option<FnClassFileLoad>) {
-        self.callbacks.class_file_load_hook = handler;
+    /// Remove every probe, restoring the original definitions of the loaded classes that had any
+    pub fn remove_instrumentation(&mut self) -> Result<usize, NativeError> {
+        self.instrument(vec![], vec![])
+    }
+
+    /// Retransform the modifiable loaded classes whose names match `predicate`
+    fn retransform_matching<F>(&self, predicate: F) -> Result<usize, NativeError> where F: Fn(&str) -> bool {
+        let mut selected: Vec<ClassId> = vec![];
+
+        for class_id in self.jvm_env.get_loaded_classes()? {
+            let matching = self.jvm_env.is_modifiable_class(&class_id).unwrap_or(false) &&
+                self.jvm_env.get_class_signature(&class_id).map(|signature| predicate(&signature.name)).unwrap_or(false);
+
+            if matching {
+                selected.push(class_id);
+            } else {
+                self.jvm_env.delete_local_ref(class_id.native_id);
+            }
+        }
+
+        let result = if selected.is_empty() { Ok(()) } else { self.jvm_env.retransform_classes(&selected) };
+        let count = selected.len();
+
+        for class_id in selected {
+            self.jvm_env.delete_local_ref(class_id.native_id);
+        }
+
+        result.map(|_| count)
     }
 }
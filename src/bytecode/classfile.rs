@@ -4,7 +4,7 @@
 /// itself, it doesn't represent every byte in the class definition, though, many information are
 /// encoded in the type system instead. This approach may seem restrictive but it helps achieving
 /// bytecode safety.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Classfile {
     pub version: ClassfileVersion,
     pub constant_pool: ConstantPool,
@@ -42,7 +42,7 @@ impl Default for Classfile {
 
 ///
 /// Describe a classfile version number.
-#[derive(Ord, PartialOrd, Eq, PartialEq, Debug)]
+#[derive(Ord, PartialOrd, Eq, PartialEq, Debug, Clone)]
 pub struct ClassfileVersion {
     pub minor_version: u16,
     pub major_version: u16
@@ -66,7 +66,7 @@ impl Default for ClassfileVersion {
 ///
 /// A `ConstantPool` is a table of various string and number literal constants that are referred
 /// within the substructures of the `Classfile`.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ConstantPool {
     pub constants: Vec<Constant>
 }
@@ -125,8 +125,24 @@ impl ConstantPool {
     }
 
     pub fn add_constant(&mut self, constant: Constant) -> ConstantPoolIndex {
+        // Index 0 is never used, and long and double constants take up two entries
+        if self.constants.is_empty() {
+            self.constants.push(Constant::Placeholder);
+        }
+
+        let idx = self.constants.len();
+        let wide = match constant {
+            Constant::Long(_) | Constant::Double(_) => true,
+            _ => false
+        };
+
         self.constants.push(constant);
-        ConstantPoolIndex::new(self.cp_len())
+
+        if wide {
+            self.constants.push(Constant::Placeholder);
+        }
+
+        ConstantPoolIndex::new(idx)
     }
 
     pub fn get_constant_index(&self, constant: &Constant) -> Option<ConstantPoolIndex> {
@@ -151,7 +167,7 @@ impl Default for ConstantPool {
     }
 }
 
-#[derive(Default, Debug, PartialEq)]
+#[derive(Default, Debug, Clone, PartialEq)]
 pub struct ConstantPoolIndex {
     pub idx: usize
 }
@@ -162,7 +178,7 @@ impl ConstantPoolIndex {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Constant {
     Utf8(Vec<u8>),
     Integer(u32),
@@ -177,7 +193,10 @@ pub enum Constant {
     NameAndType { name_index: ConstantPoolIndex, descriptor_index: ConstantPoolIndex },
     MethodHandle { reference_kind: ReferenceKind, reference_index: ConstantPoolIndex },
     MethodType(ConstantPoolIndex),
+    Dynamic { bootstrap_method_attr_index: ConstantPoolIndex, name_and_type_index: ConstantPoolIndex },
     InvokeDynamic { bootstrap_method_attr_index: ConstantPoolIndex, name_and_type_index: ConstantPoolIndex },
+    Module(ConstantPoolIndex),
+    Package(ConstantPoolIndex),
     Unknown(u8),
     Placeholder
 }
@@ -193,7 +212,7 @@ impl Constant {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ReferenceKind {
     GetField = 1,
     GetStatic = 2,
@@ -239,7 +258,7 @@ impl ReferenceKind {
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct AccessFlags {
     pub flags: u16
 }
@@ -323,7 +342,7 @@ pub enum ParameterAccessFlags {
     Mandated = 0x8000
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct Field {
     pub access_flags: AccessFlags,
     pub name_index: ConstantPoolIndex,
@@ -331,7 +350,7 @@ pub struct Field {
     pub attributes: Vec<Attribute>
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct Method {
     pub access_flags: AccessFlags,
     pub name_index: ConstantPoolIndex,
@@ -339,7 +358,7 @@ pub struct Method {
     pub attributes: Vec<Attribute>
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Attribute {
     ConstantValue(ConstantPoolIndex),
     Code { max_stack: u16, max_locals: u16, code: Vec<Instruction>, exception_table: Vec<ExceptionHandler>, attributes: Vec<Attribute> },
@@ -364,10 +383,17 @@ pub enum Attribute {
     AnnotationDefault(ElementValue),
     BootstrapMethods(Vec<BootstrapMethod>),
     MethodParameters(Vec<MethodParameter>),
+    Module { name_index: ConstantPoolIndex, flags: AccessFlags, version_index: ConstantPoolIndex, requires: Vec<ModuleRequires>, exports: Vec<ModuleExports>, opens: Vec<ModuleOpens>, uses: Vec<ConstantPoolIndex>, provides: Vec<ModuleProvides> },
+    ModulePackages(Vec<ConstantPoolIndex>),
+    ModuleMainClass(ConstantPoolIndex),
+    NestHost(ConstantPoolIndex),
+    NestMembers(Vec<ConstantPoolIndex>),
+    Record(Vec<RecordComponent>),
+    PermittedSubclasses(Vec<ConstantPoolIndex>),
     RawAttribute { name_index: ConstantPoolIndex, info: Vec<u8> }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum StackMapFrame {
     SameFrame { tag: u8 },
     SameLocals1StackItemFrame { tag: u8, stack: VerificationType },
@@ -394,7 +420,7 @@ impl StackMapFrame {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum VerificationType {
     Top,
     Integer,
@@ -417,7 +443,7 @@ impl VerificationType {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ExceptionHandler {
     pub start_pc: u16,
     pub end_pc: u16,
@@ -425,7 +451,7 @@ pub struct ExceptionHandler {
     pub catch_type: ConstantPoolIndex
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct InnerClass {
     pub inner_class_info_index: ConstantPoolIndex,
     pub outer_class_info_index: ConstantPoolIndex,
@@ -433,13 +459,13 @@ pub struct InnerClass {
     pub access_flags: AccessFlags
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct LineNumberTable {
     pub start_pc: u16,
     pub line_number: u16
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct LocalVariableTable {
     pub start_pc: u16,
     pub length: u16,
@@ -448,7 +474,7 @@ pub struct LocalVariableTable {
     pub index: u16
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct LocalVariableTypeTable {
     pub start_pc: u16,
     pub length: u16,
@@ -457,7 +483,7 @@ pub struct LocalVariableTypeTable {
     pub index: u16
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Annotation {
     pub type_index: ConstantPoolIndex,
     pub element_value_pairs: Vec<ElementValuePair>
@@ -470,7 +496,7 @@ impl Annotation {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ElementValuePair {
     pub element_name_index: ConstantPoolIndex,
     pub value: ElementValue
@@ -482,7 +508,7 @@ impl ElementValuePair {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ElementValue {
     ConstantValue(u8, ConstantPoolIndex),
     Enum { type_name_index: ConstantPoolIndex, const_name_index: ConstantPoolIndex },
@@ -503,7 +529,7 @@ impl ElementValue {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TypeAnnotation {
     pub target_info: TargetInfo,
     pub target_path: TypePath,
@@ -517,7 +543,7 @@ impl TypeAnnotation {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum TargetInfo {
     TypeParameter { subtype: u8, idx: u8 },
     SuperType { idx: u16 },
@@ -563,7 +589,7 @@ impl TargetInfo {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TypePath {
     pub path: Vec<(TypePathKind, u8)>
 }
@@ -574,7 +600,7 @@ impl TypePath {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum TypePathKind {
     Array, // Annotation is deeper in an array type
     Nested, // Annotation is deeper in a nested type
@@ -593,7 +619,7 @@ impl TypePathKind {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct BootstrapMethod {
     pub bootstrap_method_ref: ConstantPoolIndex,
     pub bootstrap_arguments: Vec<ConstantPoolIndex>
@@ -602,7 +628,7 @@ pub struct BootstrapMethod {
 impl BootstrapMethod {
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct MethodParameter {
     pub name_index: ConstantPoolIndex,
     pub access_flags: AccessFlags
@@ -612,8 +638,64 @@ impl MethodParameter {
     pub fn len(&self) -> usize { 4 }
 }
 
+#[derive(Debug, Clone)]
+pub struct ModuleRequires {
+    pub requires_index: ConstantPoolIndex,
+    pub requires_flags: AccessFlags,
+    pub requires_version_index: ConstantPoolIndex
+}
+
+impl ModuleRequires {
+    pub fn len(&self) -> usize { 6 }
+}
+
+#[derive(Debug, Clone)]
+pub struct ModuleExports {
+    pub exports_index: ConstantPoolIndex,
+    pub exports_flags: AccessFlags,
+    pub exports_to_index: Vec<ConstantPoolIndex>
+}
+
+impl ModuleExports {
+    pub fn len(&self) -> usize {
+        6 + self.exports_to_index.len() * 2
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ModuleOpens {
+    pub opens_index: ConstantPoolIndex,
+    pub opens_flags: AccessFlags,
+    pub opens_to_index: Vec<ConstantPoolIndex>
+}
+
+impl ModuleOpens {
+    pub fn len(&self) -> usize {
+        6 + self.opens_to_index.len() * 2
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ModuleProvides {
+    pub provides_index: ConstantPoolIndex,
+    pub provides_with_index: Vec<ConstantPoolIndex>
+}
+
+impl ModuleProvides {
+    pub fn len(&self) -> usize {
+        4 + self.provides_with_index.len() * 2
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordComponent {
+    pub name_index: ConstantPoolIndex,
+    pub descriptor_index: ConstantPoolIndex,
+    pub attributes: Vec<Attribute>
+}
+
 #[allow(non_camel_case_types)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Instruction {
     AALOAD,
     AASTORE,
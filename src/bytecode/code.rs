@@ -0,0 +1,750 @@
+use std::collections::{ BTreeMap, HashSet };
+use std::error::Error;
+use std::fmt;
+use std::mem;
+use super::classfile::*;
+use super::io::*;
+
+///
+/// A symbolic position in a `CodeBody`. Labels are placed in the instruction list with
+/// `CodeItem::Label` and resolved to bytecode offsets when the body is lowered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Label(usize);
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Jump {
+    IFEQ,
+    IFNE,
+    IFLT,
+    IFGE,
+    IFGT,
+    IFLE,
+    IF_ICMPEQ,
+    IF_ICMPNE,
+    IF_ICMPLT,
+    IF_ICMPGE,
+    IF_ICMPGT,
+    IF_ICMPLE,
+    IF_ACMPEQ,
+    IF_ACMPNE,
+    IFNULL,
+    IFNONNULL,
+    /// Unconditional jump, promoted to `goto_w` if the target is out of range
+    GOTO,
+    /// Unconditional jump that is always encoded as `goto_w`
+    GOTO_W,
+    JSR,
+    JSR_W
+}
+
+impl Jump {
+    fn from_instruction(instruction: &Instruction) -> Option<(Jump, i32)> {
+        match instruction {
+            &Instruction::IFEQ(offset) => Some((Jump::IFEQ, offset as i32)),
+            &Instruction::IFNE(offset) => Some((Jump::IFNE, offset as i32)),
+            &Instruction::IFLT(offset) => Some((Jump::IFLT, offset as i32)),
+            &Instruction::IFGE(offset) => Some((Jump::IFGE, offset as i32)),
+            &Instruction::IFGT(offset) => Some((Jump::IFGT, offset as i32)),
+            &Instruction::IFLE(offset) => Some((Jump::IFLE, offset as i32)),
+            &Instruction::IF_ICMPEQ(offset) => Some((Jump::IF_ICMPEQ, offset as i32)),
+            &Instruction::IF_ICMPNE(offset) => Some((Jump::IF_ICMPNE, offset as i32)),
+            &Instruction::IF_ICMPLT(offset) => Some((Jump::IF_ICMPLT, offset as i32)),
+            &Instruction::IF_ICMPGE(offset) => Some((Jump::IF_ICMPGE, offset as i32)),
+            &Instruction::IF_ICMPGT(offset) => Some((Jump::IF_ICMPGT, offset as i32)),
+            &Instruction::IF_ICMPLE(offset) => Some((Jump::IF_ICMPLE, offset as i32)),
+            &Instruction::IF_ACMPEQ(offset) => Some((Jump::IF_ACMPEQ, offset as i32)),
+            &Instruction::IF_ACMPNE(offset) => Some((Jump::IF_ACMPNE, offset as i32)),
+            &Instruction::IFNULL(offset) => Some((Jump::IFNULL, offset as i32)),
+            &Instruction::IFNONNULL(offset) => Some((Jump::IFNONNULL, offset as i32)),
+            &Instruction::GOTO(offset) => Some((Jump::GOTO, offset as i32)),
+            &Instruction::GOTO_W(offset) => Some((Jump::GOTO_W, offset)),
+            &Instruction::JSR(offset) => Some((Jump::JSR, offset as i32)),
+            &Instruction::JSR_W(offset) => Some((Jump::JSR_W, offset)),
+            _ => None
+        }
+    }
+
+    /// The short (16 bit offset) form of this jump
+    fn to_instruction(&self, offset: i16) -> Instruction {
+        match self {
+            &Jump::IFEQ => Instruction::IFEQ(offset),
+            &Jump::IFNE => Instruction::IFNE(offset),
+            &Jump::IFLT => Instruction::IFLT(offset),
+            &Jump::IFGE => Instruction::IFGE(offset),
+            &Jump::IFGT => Instruction::IFGT(offset),
+            &Jump::IFLE => Instruction::IFLE(offset),
+            &Jump::IF_ICMPEQ => Instruction::IF_ICMPEQ(offset),
+            &Jump::IF_ICMPNE => Instruction::IF_ICMPNE(offset),
+            &Jump::IF_ICMPLT => Instruction::IF_ICMPLT(offset),
+            &Jump::IF_ICMPGE => Instruction::IF_ICMPGE(offset),
+            &Jump::IF_ICMPGT => Instruction::IF_ICMPGT(offset),
+            &Jump::IF_ICMPLE => Instruction::IF_ICMPLE(offset),
+            &Jump::IF_ACMPEQ => Instruction::IF_ACMPEQ(offset),
+            &Jump::IF_ACMPNE => Instruction::IF_ACMPNE(offset),
+            &Jump::IFNULL => Instruction::IFNULL(offset),
+            &Jump::IFNONNULL => Instruction::IFNONNULL(offset),
+            &Jump::GOTO | &Jump::GOTO_W => Instruction::GOTO(offset),
+            &Jump::JSR | &Jump::JSR_W => Instruction::JSR(offset)
+        }
+    }
+
+    /// The conditional jump taken exactly when this one is not, `None` for unconditional jumps
+    fn negate(&self) -> Option<Jump> {
+        match self {
+            &Jump::IFEQ => Some(Jump::IFNE),
+            &Jump::IFNE => Some(Jump::IFEQ),
+            &Jump::IFLT => Some(Jump::IFGE),
+            &Jump::IFGE => Some(Jump::IFLT),
+            &Jump::IFGT => Some(Jump::IFLE),
+            &Jump::IFLE => Some(Jump::IFGT),
+            &Jump::IF_ICMPEQ => Some(Jump::IF_ICMPNE),
+            &Jump::IF_ICMPNE => Some(Jump::IF_ICMPEQ),
+            &Jump::IF_ICMPLT => Some(Jump::IF_ICMPGE),
+            &Jump::IF_ICMPGE => Some(Jump::IF_ICMPLT),
+            &Jump::IF_ICMPGT => Some(Jump::IF_ICMPLE),
+            &Jump::IF_ICMPLE => Some(Jump::IF_ICMPGT),
+            &Jump::IF_ACMPEQ => Some(Jump::IF_ACMPNE),
+            &Jump::IF_ACMPNE => Some(Jump::IF_ACMPEQ),
+            &Jump::IFNULL => Some(Jump::IFNONNULL),
+            &Jump::IFNONNULL => Some(Jump::IFNULL),
+            _ => None
+        }
+    }
+
+    /// Encoded length of the jump in its short or wide form. Wide conditional jumps are emitted as
+    /// the negated condition skipping over a `goto_w`
+    fn len(&self, wide: bool) -> usize {
+        match (self, wide) {
+            (&Jump::GOTO_W, _) | (&Jump::JSR_W, _) | (&Jump::GOTO, true) | (&Jump::JSR, true) => 5,
+            (_, true) => 8,
+            (_, false) => 3
+        }
+    }
+
+    fn is_wide(&self) -> bool {
+        *self == Jump::GOTO_W || *self == Jump::JSR_W
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum CodeItem {
+    /// Marks the position of the following item
+    Label(Label),
+    /// Any instruction that doesn't refer to other code positions
+    Instruction(Instruction),
+    Jump(Jump, Label),
+    TableSwitch { default: Label, low: i32, high: i32, targets: Vec<Label> },
+    LookupSwitch { default: Label, pairs: Vec<(i32, Label)> }
+}
+
+#[derive(Debug, Clone)]
+pub struct TryCatchBlock {
+    pub start: Label,
+    pub end: Label,
+    pub handler: Label,
+    pub catch_type: ConstantPoolIndex
+}
+
+#[derive(Debug, Clone)]
+pub struct LineNumber {
+    pub start: Label,
+    pub line_number: u16
+}
+
+#[derive(Debug, Clone)]
+pub struct LocalVariable {
+    pub start: Label,
+    pub end: Label,
+    pub name_index: ConstantPoolIndex,
+    /// The descriptor index for `LocalVariableTable` entries, the signature index for `LocalVariableTypeTable` entries
+    pub descriptor_index: ConstantPoolIndex,
+    pub index: u16
+}
+
+#[derive(Debug, Clone)]
+pub enum FrameValue {
+    Top,
+    Integer,
+    Float,
+    Long,
+    Double,
+    Null,
+    UninitializedThis,
+    Object(ConstantPoolIndex),
+    /// An object created by the `new` instruction at the given label but not yet initialised
+    Uninitialized(Label)
+}
+
+#[derive(Debug, Clone)]
+pub enum FrameKind {
+    Same,
+    SameLocals1StackItem(FrameValue),
+    Chop(u8),
+    Append(Vec<FrameValue>),
+    Full { locals: Vec<FrameValue>, stack: Vec<FrameValue> }
+}
+
+///
+/// A `StackMapTable` frame anchored to a label. Frames are copied from the original code as they
+/// are, they are not updated when instructions are inserted.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub label: Label,
+    pub kind: FrameKind
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CodeError {
+    /// The attribute passed in is not a `Code` attribute
+    NotCode,
+    /// The instruction or table entry at `pc` refers to an offset that is not an instruction boundary
+    InvalidTarget { pc: usize, target: i64 },
+    /// A label is referred to but not placed in the instruction list
+    UnplacedLabel(Label),
+    /// A label is placed in the instruction list more than once
+    DuplicateLabel(Label),
+    /// The end of a range is placed before its start
+    InvalidRange { start: Label, end: Label },
+    /// Frames are not in increasing bytecode order after lowering
+    InvalidFrameOrder(Label),
+    /// The lowered code exceeds the 65535 byte limit of the class file format
+    CodeTooLarge(usize)
+}
+
+impl fmt::Display for CodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &CodeError::NotCode => write!(f, "Not a Code attribute"),
+            &CodeError::InvalidTarget { pc, target } => write!(f, "Reference from offset {} to {} is not an instruction boundary", pc, target),
+            &CodeError::UnplacedLabel(label) => write!(f, "{:?} is used but not placed", label),
+            &CodeError::DuplicateLabel(label) => write!(f, "{:?} is placed more than once", label),
+            &CodeError::InvalidRange { start, end } => write!(f, "Range end {:?} is placed before its start {:?}", end, start),
+            &CodeError::InvalidFrameOrder(label) => write!(f, "Frame at {:?} is not after the previous frame", label),
+            &CodeError::CodeTooLarge(len) => write!(f, "Code is {} bytes long, the limit is 65535", len)
+        }
+    }
+}
+
+impl Error for CodeError {
+    fn description(&self) -> &str {
+        "Invalid method code"
+    }
+}
+
+///
+/// An editable representation of a `Code` attribute. Every offset found in the code (branch and
+/// switch targets, exception ranges, line numbers, local variable ranges and stack map frames) is
+/// replaced with a `Label`, so instructions can be inserted and removed freely. `lower` turns the
+/// body back into a `Code` attribute, recomputing all offsets, switch padding and jump widths.
+///
+/// `max_stack`, `max_locals` and the stack map frames are carried over as they are, use
+/// `frames::compute_frames` or `frames::compute_maxs` to recompute them after editing.
+#[derive(Debug, Clone)]
+pub struct CodeBody {
+    pub max_stack: u16,
+    pub max_locals: u16,
+    pub items: Vec<CodeItem>,
+    pub try_catch_blocks: Vec<TryCatchBlock>,
+    pub line_numbers: Vec<LineNumber>,
+    pub local_variables: Vec<LocalVariable>,
+    pub local_variable_types: Vec<LocalVariable>,
+    pub frames: Vec<Frame>,
+    /// The attributes of the `Code` attribute. The tables maintained by this body are kept as
+    /// empty placeholders, so their position is preserved when lowering
+    pub attributes: Vec<Attribute>,
+    next_label: usize
+}
+
+impl CodeBody {
+    pub fn new(max_stack: u16, max_locals: u16) -> CodeBody {
+        CodeBody {
+            max_stack: max_stack,
+            max_locals: max_locals,
+            items: vec![],
+            try_catch_blocks: vec![],
+            line_numbers: vec![],
+            local_variables: vec![],
+            local_variable_types: vec![],
+            frames: vec![],
+            attributes: vec![],
+            next_label: 0
+        }
+    }
+
+    pub fn from_code(attribute: &Attribute) -> Result<CodeBody, CodeError> {
+        match attribute {
+            &Attribute::Code { max_stack, max_locals, ref code, ref exception_table, ref attributes } => {
+                let mut body = CodeBody::new(max_stack, max_locals);
+
+                let mut pcs: Vec<usize> = Vec::with_capacity(code.len());
+                let code_len = code.iter().fold(0, |pc, instruction| {
+                    pcs.push(pc);
+                    pc + instruction_len(instruction, pc)
+                });
+
+                let mut labels = LabelMap { boundaries: pcs.iter().map(|pc| *pc).collect(), code_len: code_len, labels: BTreeMap::new() };
+
+                let mut items: Vec<(usize, CodeItem)> = Vec::with_capacity(code.len());
+
+                for (instruction, &pc) in code.iter().zip(pcs.iter()) {
+                    let item = match (Jump::from_instruction(instruction), instruction) {
+                        (Some((jump, offset)), _) => CodeItem::Jump(jump, labels.relative(&mut body, pc, offset)?),
+                        (_, &Instruction::TABLESWITCH(default, low, high, ref offsets)) => CodeItem::TableSwitch {
+                            default: labels.relative(&mut body, pc, default)?,
+                            low: low,
+                            high: high,
+                            targets: offsets.iter().map(|offset| labels.relative(&mut body, pc, *offset)).collect::<Result<Vec<Label>, CodeError>>()?
+                        },
+                        (_, &Instruction::LOOKUPSWITCH(default, ref pairs)) => CodeItem::LookupSwitch {
+                            default: labels.relative(&mut body, pc, default)?,
+                            pairs: pairs.iter().map(|&(key, offset)| labels.relative(&mut body, pc, offset).map(|label| (key, label))).collect::<Result<Vec<(i32, Label)>, CodeError>>()?
+                        },
+                        _ => CodeItem::Instruction(instruction.clone())
+                    };
+
+                    items.push((pc, item));
+                }
+
+                for handler in exception_table {
+                    let block = TryCatchBlock {
+                        start: labels.absolute(&mut body, handler.start_pc as usize, handler.start_pc as usize)?,
+                        end: labels.absolute(&mut body, handler.start_pc as usize, handler.end_pc as usize)?,
+                        handler: labels.absolute(&mut body, handler.start_pc as usize, handler.handler_pc as usize)?,
+                        catch_type: handler.catch_type.clone()
+                    };
+
+                    body.try_catch_blocks.push(block);
+                }
+
+                for attribute in attributes {
+                    match attribute {
+                        &Attribute::LineNumberTable(ref table) => {
+                            for entry in table {
+                                let line = LineNumber { start: labels.absolute(&mut body, entry.start_pc as usize, entry.start_pc as usize)?, line_number: entry.line_number };
+                                body.line_numbers.push(line);
+                            }
+
+                            body.add_placeholder(Attribute::LineNumberTable(vec![]));
+                        },
+                        &Attribute::LocalVariableTable(ref table) => {
+                            for entry in table {
+                                let variable = LocalVariable {
+                                    start: labels.absolute(&mut body, entry.start_pc as usize, entry.start_pc as usize)?,
+                                    end: labels.absolute(&mut body, entry.start_pc as usize, entry.start_pc as usize + entry.length as usize)?,
+                                    name_index: entry.name_index.clone(),
+                                    descriptor_index: entry.descriptor_index.clone(),
+                                    index: entry.index
+                                };
+
+                                body.local_variables.push(variable);
+                            }
+
+                            body.add_placeholder(Attribute::LocalVariableTable(vec![]));
+                        },
+                        &Attribute::LocalVariableTypeTable(ref table) => {
+                            for entry in table {
+                                let variable = LocalVariable {
+                                    start: labels.absolute(&mut body, entry.start_pc as usize, entry.start_pc as usize)?,
+                                    end: labels.absolute(&mut body, entry.start_pc as usize, entry.start_pc as usize + entry.length as usize)?,
+                                    name_index: entry.name_index.clone(),
+                                    descriptor_index: entry.signature_index.clone(),
+                                    index: entry.index
+                                };
+
+                                body.local_variable_types.push(variable);
+                            }
+
+                            body.add_placeholder(Attribute::LocalVariableTypeTable(vec![]));
+                        },
+                        &Attribute::StackMapTable(ref frames) => {
+                            let mut previous: Option<usize> = None;
+
+                            for frame in frames {
+                                let (delta, kind) = match frame {
+                                    &StackMapFrame::SameFrame { tag } => (tag as usize, FrameKind::Same),
+                                    &StackMapFrame::SameLocals1StackItemFrame { tag, ref stack } => (tag as usize - 64, FrameKind::SameLocals1StackItem(labels.frame_value(&mut body, stack)?)),
+                                    &StackMapFrame::SameLocals1StackItemFrameExtended { offset_delta, ref stack } => (offset_delta as usize, FrameKind::SameLocals1StackItem(labels.frame_value(&mut body, stack)?)),
+                                    &StackMapFrame::ChopFrame { tag, offset_delta } => (offset_delta as usize, FrameKind::Chop(251 - tag)),
+                                    &StackMapFrame::SameFrameExtended { offset_delta } => (offset_delta as usize, FrameKind::Same),
+                                    &StackMapFrame::AppendFrame { offset_delta, ref locals, .. } => (offset_delta as usize, FrameKind::Append(labels.frame_values(&mut body, locals)?)),
+                                    &StackMapFrame::FullFrame { offset_delta, ref locals, ref stack } => (offset_delta as usize, FrameKind::Full { locals: labels.frame_values(&mut body, locals)?, stack: labels.frame_values(&mut body, stack)? }),
+                                    // Reserved frame types are rejected by ClassReader, there's nothing to anchor
+                                    &StackMapFrame::FutureUse { .. } => continue
+                                };
+
+                                let pc = previous.map(|previous| previous + delta + 1).unwrap_or(delta);
+                                let label = labels.absolute(&mut body, pc, pc)?;
+
+                                body.frames.push(Frame { label: label, kind: kind });
+                                previous = Some(pc);
+                            }
+
+                            body.add_placeholder(Attribute::StackMapTable(vec![]));
+                        },
+                        other@_ => body.attributes.push(other.clone())
+                    }
+                }
+
+                let mut positions = labels.labels.into_iter().peekable();
+
+                for (pc, item) in items {
+                    while positions.peek().map(|&(position, _)| position <= pc).unwrap_or(false) {
+                        if let Some((_, label)) = positions.next() {
+                            body.items.push(CodeItem::Label(label));
+                        }
+                    }
+
+                    body.items.push(item);
+                }
+
+                // Only the end of the code remains, referred to by exception and local variable ranges
+                for (_, label) in positions {
+                    body.items.push(CodeItem::Label(label));
+                }
+
+                Ok(body)
+            },
+            _ => Err(CodeError::NotCode)
+        }
+    }
+
+    /// Create a new label. The label has to be placed with `CodeItem::Label` before lowering
+    pub fn new_label(&mut self) -> Label {
+        self.next_label += 1;
+        Label(self.next_label - 1)
+    }
+
+    /// Index of the item marking the position of `label`
+    pub fn position_of(&self, label: Label) -> Option<usize> {
+        self.items.iter().position(|item| match item {
+            &CodeItem::Label(l) => l == label,
+            _ => false
+        })
+    }
+
+    /// Insert the given items before the item at `index`
+    pub fn insert(&mut self, index: usize, items: Vec<CodeItem>) {
+        let tail = self.items.split_off(index);
+
+        self.items.extend(items);
+        self.items.extend(tail);
+    }
+
+    pub fn push(&mut self, item: CodeItem) {
+        self.items.push(item);
+    }
+
+    /// Rewrite every conditional jump that doesn't fit in a 16 bit offset into the negated jump
+    /// over a `goto_w`. `lower` does the same, but doing it up front makes the instruction after
+    /// the `goto_w` a labelled branch target that stack map frames can refer to
+    pub fn expand_wide_jumps(&mut self) -> Result<(), CodeError> {
+        let layout = self.layout()?;
+        let original = mem::replace(&mut self.items, vec![]);
+        let mut items: Vec<CodeItem> = Vec::with_capacity(original.len());
+
+        for (item, wide) in original.into_iter().zip(layout.wide.into_iter()) {
+            match item {
+                CodeItem::Jump(jump, target) if wide && jump.negate().is_some() => {
+                    let skip = self.new_label();
+
+                    items.push(CodeItem::Jump(jump.negate().unwrap_or(jump), skip));
+                    items.push(CodeItem::Jump(Jump::GOTO_W, target));
+                    items.push(CodeItem::Label(skip));
+                },
+                other@_ => items.push(other)
+            }
+        }
+
+        self.items = items;
+
+        Ok(())
+    }
+
+    /// Turn this body back into a `Code` attribute, resolving every label to its final offset
+    pub fn lower(&self) -> Result<Attribute, CodeError> {
+        let layout = self.layout()?;
+        let position = |label: Label| layout.labels.get(label.0).and_then(|pc| *pc).ok_or(CodeError::UnplacedLabel(label));
+
+        let mut code: Vec<Instruction> = Vec::with_capacity(self.items.len());
+
+        for (item, (&pc, &wide)) in self.items.iter().zip(layout.pcs.iter().zip(layout.wide.iter())) {
+            match item {
+                &CodeItem::Label(_) => (),
+                &CodeItem::Instruction(ref instruction) => code.push(instruction.clone()),
+                &CodeItem::Jump(jump, target) => {
+                    let offset = position(target)? as i64 - pc as i64;
+
+                    match (jump, wide) {
+                        (Jump::GOTO, true) | (Jump::GOTO_W, _) => code.push(Instruction::GOTO_W(offset as i32)),
+                        (Jump::JSR, true) | (Jump::JSR_W, _) => code.push(Instruction::JSR_W(offset as i32)),
+                        (_, true) => {
+                            // Branch over a goto_w if the condition doesn't hold
+                            code.push(jump.negate().unwrap_or(jump).to_instruction(8));
+                            code.push(Instruction::GOTO_W((offset - 3) as i32));
+                        },
+                        (_, false) => code.push(jump.to_instruction(offset as i16))
+                    }
+                },
+                &CodeItem::TableSwitch { default, low, high, ref targets } => {
+                    let offsets = targets.iter().map(|target| position(*target).map(|target| target as i32 - pc as i32)).collect::<Result<Vec<i32>, CodeError>>()?;
+
+                    code.push(Instruction::TABLESWITCH(position(default)? as i32 - pc as i32, low, high, offsets));
+                },
+                &CodeItem::LookupSwitch { default, ref pairs } => {
+                    let pairs = pairs.iter().map(|&(key, target)| position(target).map(|target| (key, target as i32 - pc as i32))).collect::<Result<Vec<(i32, i32)>, CodeError>>()?;
+
+                    code.push(Instruction::LOOKUPSWITCH(position(default)? as i32 - pc as i32, pairs));
+                }
+            }
+        }
+
+        let range = |start: Label, end: Label| -> Result<(u16, u16), CodeError> {
+            match (position(start)?, position(end)?) {
+                (start_pc, end_pc) if end_pc >= start_pc => Ok((start_pc as u16, (end_pc - start_pc) as u16)),
+                _ => Err(CodeError::InvalidRange { start: start, end: end })
+            }
+        };
+
+        let exception_table = self.try_catch_blocks.iter().map(|block| {
+            range(block.start, block.end).and_then(|(start_pc, length)| position(block.handler).map(|handler_pc| ExceptionHandler {
+                start_pc: start_pc,
+                end_pc: start_pc + length,
+                handler_pc: handler_pc as u16,
+                catch_type: block.catch_type.clone()
+            }))
+        }).collect::<Result<Vec<ExceptionHandler>, CodeError>>()?;
+
+        let line_numbers = self.line_numbers.iter().map(|line| position(line.start).map(|start_pc| LineNumberTable { start_pc: start_pc as u16, line_number: line.line_number })).collect::<Result<Vec<LineNumberTable>, CodeError>>()?;
+
+        let local_variables = self.local_variables.iter().map(|variable| range(variable.start, variable.end).map(|(start_pc, length)| LocalVariableTable {
+            start_pc: start_pc,
+            length: length,
+            name_index: variable.name_index.clone(),
+            descriptor_index: variable.descriptor_index.clone(),
+            index: variable.index
+        })).collect::<Result<Vec<LocalVariableTable>, CodeError>>()?;
+
+        let local_variable_types = self.local_variable_types.iter().map(|variable| range(variable.start, variable.end).map(|(start_pc, length)| LocalVariableTypeTable {
+            start_pc: start_pc,
+            length: length,
+            name_index: variable.name_index.clone(),
+            signature_index: variable.descriptor_index.clone(),
+            index: variable.index
+        })).collect::<Result<Vec<LocalVariableTypeTable>, CodeError>>()?;
+
+        let frames = self.lower_frames(&position)?;
+
+        // Fill in the placeholders, appending tables that were not present in the original code
+        let mut tables = (Some(line_numbers), Some(local_variables), Some(local_variable_types), Some(frames));
+        let mut attributes: Vec<Attribute> = vec![];
+
+        for attribute in self.attributes.iter() {
+            match attribute {
+                &Attribute::LineNumberTable(_) => if let Some(table) = tables.0.take() { attributes.push(Attribute::LineNumberTable(table)) },
+                &Attribute::LocalVariableTable(_) => if let Some(table) = tables.1.take() { attributes.push(Attribute::LocalVariableTable(table)) },
+                &Attribute::LocalVariableTypeTable(_) => if let Some(table) = tables.2.take() { attributes.push(Attribute::LocalVariableTypeTable(table)) },
+                &Attribute::StackMapTable(_) => if let Some(table) = tables.3.take() { attributes.push(Attribute::StackMapTable(table)) },
+                other@_ => attributes.push(other.clone())
+            }
+        }
+
+        tables.0.into_iter().filter(|table| table.len() > 0).for_each(|table| attributes.push(Attribute::LineNumberTable(table)));
+        tables.1.into_iter().filter(|table| table.len() > 0).for_each(|table| attributes.push(Attribute::LocalVariableTable(table)));
+        tables.2.into_iter().filter(|table| table.len() > 0).for_each(|table| attributes.push(Attribute::LocalVariableTypeTable(table)));
+        tables.3.into_iter().filter(|table| table.len() > 0).for_each(|table| attributes.push(Attribute::StackMapTable(table)));
+
+        Ok(Attribute::Code {
+            max_stack: self.max_stack,
+            max_locals: self.max_locals,
+            code: code,
+            exception_table: exception_table,
+            attributes: attributes
+        })
+    }
+
+    fn add_placeholder(&mut self, placeholder: Attribute) {
+        let present = self.attributes.iter().any(|attribute| match (attribute, &placeholder) {
+            (&Attribute::LineNumberTable(_), &Attribute::LineNumberTable(_)) => true,
+            (&Attribute::LocalVariableTable(_), &Attribute::LocalVariableTable(_)) => true,
+            (&Attribute::LocalVariableTypeTable(_), &Attribute::LocalVariableTypeTable(_)) => true,
+            (&Attribute::StackMapTable(_), &Attribute::StackMapTable(_)) => true,
+            _ => false
+        });
+
+        if !present {
+            self.attributes.push(placeholder);
+        }
+    }
+
+    /// Assign an offset to every item. Jumps start out short and are widened until every offset
+    /// fits, widening can only move targets further away so this always terminates
+    fn layout(&self) -> Result<Layout, CodeError> {
+        let mut wide: Vec<bool> = self.items.iter().map(|item| match item {
+            &CodeItem::Jump(jump, _) => jump.is_wide(),
+            _ => false
+        }).collect();
+
+        loop {
+            let mut pcs: Vec<usize> = Vec::with_capacity(self.items.len());
+            let mut labels: Vec<Option<usize>> = vec![None; self.next_label];
+
+            let code_len = self.items.iter().zip(wide.iter()).fold(Ok(0), |acc, (item, &wide)| acc.and_then(|pc| {
+                pcs.push(pc);
+
+                match item {
+                    &CodeItem::Label(label) => {
+                        if label.0 >= labels.len() {
+                            labels.resize(label.0 + 1, None);
+                        }
+
+                        match labels[label.0] {
+                            Some(_) => Err(CodeError::DuplicateLabel(label)),
+                            None => {
+                                labels[label.0] = Some(pc);
+                                Ok(pc)
+                            }
+                        }
+                    },
+                    &CodeItem::Instruction(ref instruction) => Ok(pc + instruction_len(instruction, pc)),
+                    &CodeItem::Jump(jump, _) => Ok(pc + jump.len(wide)),
+                    &CodeItem::TableSwitch { ref targets, .. } => Ok(pc + 1 + switch_padding(pc) + 12 + targets.len() * 4),
+                    &CodeItem::LookupSwitch { ref pairs, .. } => Ok(pc + 1 + switch_padding(pc) + 8 + pairs.len() * 8)
+                }
+            }))?;
+
+            if code_len > 0xFFFF {
+                return Err(CodeError::CodeTooLarge(code_len));
+            }
+
+            let mut changed = false;
+
+            for (idx, item) in self.items.iter().enumerate() {
+                if let &CodeItem::Jump(_, target) = item {
+                    let target_pc = labels.get(target.0).and_then(|pc| *pc).ok_or(CodeError::UnplacedLabel(target))?;
+                    let offset = target_pc as i64 - pcs[idx] as i64;
+
+                    if !wide[idx] && (offset < i16::min_value() as i64 || offset > i16::max_value() as i64) {
+                        wide[idx] = true;
+                        changed = true;
+                    }
+                }
+            }
+
+            if !changed {
+                return Ok(Layout { pcs: pcs, wide: wide, labels: labels });
+            }
+        }
+    }
+
+    fn lower_frames<F>(&self, position: &F) -> Result<Vec<StackMapFrame>, CodeError> where F: Fn(Label) -> Result<usize, CodeError> {
+        let lower_value = |value: &FrameValue| -> Result<VerificationType, CodeError> {
+            Ok(match value {
+                &FrameValue::Top => VerificationType::Top,
+                &FrameValue::Integer => VerificationType::Integer,
+                &FrameValue::Float => VerificationType::Float,
+                &FrameValue::Long => VerificationType::Long,
+                &FrameValue::Double => VerificationType::Double,
+                &FrameValue::Null => VerificationType::Null,
+                &FrameValue::UninitializedThis => VerificationType::UninitializedThis,
+                &FrameValue::Object(ref idx) => VerificationType::Object { cpool_index: idx.clone() },
+                &FrameValue::Uninitialized(label) => VerificationType::Uninitialized { offset: position(label)? as u16 }
+            })
+        };
+
+        let lower_values = |values: &Vec<FrameValue>| values.iter().map(|value| lower_value(value)).collect::<Result<Vec<VerificationType>, CodeError>>();
+
+        let mut frames: Vec<(usize, &Frame)> = self.frames.iter().map(|frame| position(frame.label).map(|pc| (pc, frame))).collect::<Result<Vec<(usize, &Frame)>, CodeError>>()?;
+        frames.sort_by_key(|&(pc, _)| pc);
+
+        let mut previous: Option<usize> = None;
+        let mut lowered: Vec<StackMapFrame> = Vec::with_capacity(frames.len());
+
+        for (pc, frame) in frames {
+            let delta = match previous {
+                None => pc,
+                Some(previous) if pc > previous => pc - previous - 1,
+                Some(_) => return Err(CodeError::InvalidFrameOrder(frame.label))
+            };
+
+            lowered.push(match frame.kind {
+                FrameKind::Same if delta < 64 => StackMapFrame::SameFrame { tag: delta as u8 },
+                FrameKind::Same => StackMapFrame::SameFrameExtended { offset_delta: delta as u16 },
+                FrameKind::SameLocals1StackItem(ref value) if delta < 64 => StackMapFrame::SameLocals1StackItemFrame { tag: 64 + delta as u8, stack: lower_value(value)? },
+                FrameKind::SameLocals1StackItem(ref value) => StackMapFrame::SameLocals1StackItemFrameExtended { offset_delta: delta as u16, stack: lower_value(value)? },
+                FrameKind::Chop(count) => StackMapFrame::ChopFrame { tag: 251 - count, offset_delta: delta as u16 },
+                FrameKind::Append(ref locals) => StackMapFrame::AppendFrame { tag: 251 + locals.len() as u8, offset_delta: delta as u16, locals: lower_values(locals)? },
+                FrameKind::Full { ref locals, ref stack } => StackMapFrame::FullFrame { offset_delta: delta as u16, locals: lower_values(locals)?, stack: lower_values(stack)? }
+            });
+
+            previous = Some(pc);
+        }
+
+        Ok(lowered)
+    }
+}
+
+struct Layout {
+    /// Offset of each item
+    pcs: Vec<usize>,
+    /// Whether the jump at each item is encoded in its wide form
+    wide: Vec<bool>,
+    /// Offset of each label, indexed by label id
+    labels: Vec<Option<usize>>
+}
+
+/// Assigns labels to bytecode offsets while a `Code` attribute is converted
+struct LabelMap {
+    boundaries: HashSet<usize>,
+    code_len: usize,
+    labels: BTreeMap<usize, Label>
+}
+
+impl LabelMap {
+    fn absolute(&mut self, body: &mut CodeBody, pc: usize, target: usize) -> Result<Label, CodeError> {
+        if target != self.code_len && !self.boundaries.contains(&target) {
+            return Err(CodeError::InvalidTarget { pc: pc, target: target as i64 });
+        }
+
+        Ok(*self.labels.entry(target).or_insert_with(|| body.new_label()))
+    }
+
+    fn relative(&mut self, body: &mut CodeBody, pc: usize, offset: i32) -> Result<Label, CodeError> {
+        let target = pc as i64 + offset as i64;
+
+        if target < 0 || target >= self.code_len as i64 {
+            Err(CodeError::InvalidTarget { pc: pc, target: target })
+        } else {
+            self.absolute(body, pc, target as usize)
+        }
+    }
+
+    fn frame_value(&mut self, body: &mut CodeBody, value: &VerificationType) -> Result<FrameValue, CodeError> {
+        Ok(match value {
+            &VerificationType::Top => FrameValue::Top,
+            &VerificationType::Integer => FrameValue::Integer,
+            &VerificationType::Float => FrameValue::Float,
+            &VerificationType::Long => FrameValue::Long,
+            &VerificationType::Double => FrameValue::Double,
+            &VerificationType::Null => FrameValue::Null,
+            &VerificationType::UninitializedThis => FrameValue::UninitializedThis,
+            &VerificationType::Object { ref cpool_index } => FrameValue::Object(cpool_index.clone()),
+            &VerificationType::Uninitialized { offset } => FrameValue::Uninitialized(self.absolute(body, offset as usize, offset as usize)?)
+        })
+    }
+
+    fn frame_values(&mut self, body: &mut CodeBody, values: &Vec<VerificationType>) -> Result<Vec<FrameValue>, CodeError> {
+        values.iter().map(|value| self.frame_value(body, value)).collect()
+    }
+}
+
+/// Number of padding bytes following a switch opcode at `pc`, aligning its operands to 4 bytes
+fn switch_padding(pc: usize) -> usize {
+    (4 - ((pc + 1) % 4)) % 4
+}
+
+/// Encoded length of an instruction at the given offset, as written by `ClassWriter`
+fn instruction_len(instruction: &Instruction, pc: usize) -> usize {
+    let mut target: Vec<u8> = vec![];
+    ClassWriter::new(&mut target).render_instruction(instruction, pc)
+}
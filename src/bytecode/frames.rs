@@ -0,0 +1,1145 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use super::classfile::*;
+use super::code::*;
+use super::source::ClassSource;
+
+const OBJECT: &'static str = "java/lang/Object";
+const THROWABLE: &'static str = "java/lang/Throwable";
+
+///
+/// Answers the only question about the class hierarchy the frame computation has: which class
+/// two reference types merge to where control flow joins.
+pub trait ClassHierarchy {
+    /// The internal name of the closest common superclass of the classes `a` and `b`. Interfaces
+    /// merge to `java/lang/Object`, like they do in the verifier. `None` if the classes can't be
+    /// resolved, as guessing could produce frames that don't verify
+    fn common_superclass(&self, a: &str, b: &str) -> Option<String>;
+}
+
+///
+/// A hierarchy without any class information, distinct classes always merge to `java/lang/Object`.
+/// Frames computed with it only verify if merged values are not used as anything more specific.
+pub struct DefaultHierarchy;
+
+impl ClassHierarchy for DefaultHierarchy {
+    fn common_superclass(&self, a: &str, b: &str) -> Option<String> {
+        Some(if a == b { a.to_string() } else { OBJECT.to_string() })
+    }
+}
+
+///
+/// The part of a class definition a hierarchy walk needs
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassInfo {
+    /// Internal name of the superclass, `None` for `java/lang/Object`
+    pub super_name: Option<String>,
+    pub is_interface: bool
+}
+
+impl ClassInfo {
+    pub fn of(class: &Classfile) -> ClassInfo {
+        ClassInfo {
+            super_name: class_name(&class.constant_pool, class.super_class.idx),
+            is_interface: class.access_flags.has_flag(ClassAccessFlags::Interface as u16)
+        }
+    }
+}
+
+/// Find the common superclass of `a` and `b` by walking their superclass chains, as reported by
+/// `lookup`. Interfaces are taken to extend `java/lang/Object` directly. Returns `None` if a class
+/// on either chain is unknown to `lookup`
+pub fn common_superclass_by<F>(a: &str, b: &str, lookup: F) -> Option<String> where F: Fn(&str) -> Option<ClassInfo> {
+    if a == b {
+        return Some(a.to_string());
+    }
+
+    let chain = |name: &str| -> Option<Vec<String>> {
+        let mut chain = vec![ name.to_string() ];
+
+        while chain.last().map(|last| last != OBJECT).unwrap_or(false) {
+            match chain.last().and_then(|last| lookup(last)) {
+                Some(ClassInfo { is_interface: true, .. }) | Some(ClassInfo { super_name: None, .. }) => chain.push(OBJECT.to_string()),
+                Some(ClassInfo { super_name: Some(super_name), .. }) => {
+                    if chain.contains(&super_name) {
+                        return None;
+                    }
+
+                    chain.push(super_name);
+                },
+                None => return None
+            }
+        }
+
+        Some(chain)
+    };
+
+    match (chain(a), chain(b)) {
+        (Some(a_chain), Some(b_chain)) => a_chain.into_iter().find(|name| b_chain.contains(name)),
+        _ => None
+    }
+}
+
+///
+/// A hierarchy backed by the classes of a `ClassSource`. Classes are parsed on first use and
+/// remembered. Classes missing from the source can't be merged with other classes.
+pub struct SourceHierarchy<'a> {
+    source: &'a ClassSource,
+    classes: RefCell<HashMap<String, Option<ClassInfo>>>
+}
+
+impl<'a> SourceHierarchy<'a> {
+    pub fn new(source: &'a ClassSource) -> SourceHierarchy<'a> {
+        SourceHierarchy { source: source, classes: RefCell::new(HashMap::new()) }
+    }
+
+    /// The superclass and kind of the named class, `None` if the source doesn't have it
+    pub fn class_info(&self, name: &str) -> Option<ClassInfo> {
+        if let Some(info) = self.classes.borrow().get(name) {
+            return info.clone();
+        }
+
+        let info = self.source.find(name).and_then(|entry| self.source.read_class(entry).ok()).map(|class| ClassInfo::of(&class));
+        self.classes.borrow_mut().insert(name.to_string(), info.clone());
+
+        info
+    }
+}
+
+impl<'a> ClassHierarchy for SourceHierarchy<'a> {
+    fn common_superclass(&self, a: &str, b: &str) -> Option<String> {
+        common_superclass_by(a, b, |name| self.class_info(name))
+    }
+}
+
+///
+/// The method a `CodeBody` belongs to, as far as the frame computation is concerned
+#[derive(Debug, Clone)]
+pub struct MethodContext {
+    /// Internal name of the declaring class
+    pub class_name: String,
+    pub method_name: String,
+    pub descriptor: String,
+    pub is_static: bool
+}
+
+impl MethodContext {
+    /// Describe a method of the given class, `None` if its names can't be resolved
+    pub fn of(class: &Classfile, method: &Method) -> Option<MethodContext> {
+        let cp = &class.constant_pool;
+
+        match (class_name(cp, class.this_class.idx), cp.get_utf8_string(method.name_index.idx as u16), cp.get_utf8_string(method.descriptor_index.idx as u16)) {
+            (Some(class_name), Some(method_name), Some(descriptor)) => Some(MethodContext {
+                class_name: class_name,
+                method_name: method_name,
+                descriptor: descriptor,
+                is_static: method.access_flags.has_flag(MethodAccessFlags::Static as u16)
+            }),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FrameError {
+    Code(CodeError),
+    /// The names of the method or its class can't be resolved in the constant pool
+    UnknownMethod,
+    /// The method descriptor or a descriptor referred to by an instruction is malformed
+    InvalidDescriptor(String),
+    /// The instruction at the given item refers to a missing or unexpected constant pool entry
+    InvalidConstant { item: usize, index: usize },
+    /// The instruction at the given item is not a valid instruction
+    InvalidInstruction(usize),
+    /// `jsr` and `ret` are not allowed in code that needs stack map frames
+    Subroutine(usize),
+    /// The instruction at the given item pops more values than the stack holds
+    StackUnderflow(usize),
+    /// Control flow merges with different stack heights at the given item
+    StackMismatch(usize),
+    /// Execution can continue past the last instruction
+    FallsOffEnd,
+    /// The common superclass of the two classes can't be found where control flow merges them
+    UnresolvedMerge(String, String)
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &FrameError::Code(ref err) => write!(f, "{}", err),
+            &FrameError::UnknownMethod => write!(f, "Method or class names can't be resolved"),
+            &FrameError::InvalidDescriptor(ref descriptor) => write!(f, "Invalid descriptor {}", descriptor),
+            &FrameError::InvalidConstant { item, index } => write!(f, "Item {} refers to invalid constant #{}", item, index),
+            &FrameError::InvalidInstruction(item) => write!(f, "Item {} is not a valid instruction", item),
+            &FrameError::Subroutine(item) => write!(f, "Item {} is a subroutine instruction, which can't have stack map frames", item),
+            &FrameError::StackUnderflow(item) => write!(f, "Item {} pops more values than the stack holds", item),
+            &FrameError::StackMismatch(item) => write!(f, "Stack heights differ where control flow merges at item {}", item),
+            &FrameError::FallsOffEnd => write!(f, "Execution falls off the end of the code"),
+            &FrameError::UnresolvedMerge(ref a, ref b) => write!(f, "Classes {} and {} can't be resolved to merge them", a, b)
+        }
+    }
+}
+
+impl Error for FrameError {
+    fn description(&self) -> &str {
+        "Stack map frames can't be computed"
+    }
+}
+
+impl From<CodeError> for FrameError {
+    fn from(err: CodeError) -> FrameError {
+        FrameError::Code(err)
+    }
+}
+
+///
+/// Recompute `max_stack`, `max_locals` and the stack map frames of `body`.
+///
+/// Frames are placed at every branch target and exception handler, and encoded in the most
+/// compact form relative to the previous frame. Unreachable instructions are removed, as the
+/// verifier would require frames for them that no execution could justify, along with exception
+/// handlers that end up protecting no code. Reference types merge according to `hierarchy`, any
+/// class constants needed by the frames are added to `cp`.
+pub fn compute_frames(body: &mut CodeBody, context: &MethodContext, cp: &mut ConstantPool, hierarchy: &ClassHierarchy) -> Result<(), FrameError> {
+    body.expand_wide_jumps()?;
+    label_allocations(body);
+
+    let analysis = Analyzer::new(&body.items, &body.try_catch_blocks, context, cp, hierarchy, false).run()?;
+
+    let mut frames: Vec<Frame> = vec![];
+    let mut targets: Vec<usize> = analysis.states.keys().filter(|idx| analysis.targets[**idx]).map(|idx| *idx).collect();
+    targets.sort();
+
+    let mut previous = frame_locals(&analysis.initial.locals);
+
+    for idx in targets {
+        let state = &analysis.states[&idx];
+        let locals = frame_locals(&state.locals);
+        let to_frame_values = |values: &[Value], cp: &mut ConstantPool| values.iter().map(|value| value.to_frame_value(&analysis.labels, cp)).collect::<Vec<FrameValue>>();
+
+        let kind = if state.stack.is_empty() && locals == previous {
+            FrameKind::Same
+        } else if state.stack.len() == 1 && locals == previous {
+            FrameKind::SameLocals1StackItem(state.stack[0].to_frame_value(&analysis.labels, cp))
+        } else if state.stack.is_empty() && locals.len() > previous.len() && locals.len() - previous.len() <= 3 && locals.starts_with(&previous) {
+            FrameKind::Append(to_frame_values(&locals[previous.len()..], cp))
+        } else if state.stack.is_empty() && previous.len() > locals.len() && previous.len() - locals.len() <= 3 && previous.starts_with(&locals) {
+            FrameKind::Chop((previous.len() - locals.len()) as u8)
+        } else {
+            FrameKind::Full { locals: to_frame_values(&locals, cp), stack: to_frame_values(&state.stack, cp) }
+        };
+
+        match analysis.labels.get(&idx) {
+            Some(label) => frames.push(Frame { label: *label, kind: kind }),
+            None => return Err(FrameError::InvalidInstruction(idx))
+        }
+
+        previous = locals;
+    }
+
+    let original = ::std::mem::replace(&mut body.items, vec![]);
+
+    body.items = original.into_iter().enumerate().filter(|&(idx, ref item)| match item {
+        &CodeItem::Label(_) => true,
+        _ => analysis.reached[idx]
+    }).map(|(_, item)| item).collect();
+
+    let positions = instruction_positions(&body.items);
+
+    body.try_catch_blocks.retain(|block| positions.get(&block.start) != positions.get(&block.end));
+    body.frames = frames;
+    body.max_stack = analysis.max_stack as u16;
+    body.max_locals = max_locals(&body.items, context) as u16;
+
+    Ok(())
+}
+
+///
+/// Recompute `max_stack` and `max_locals` of `body`, leaving its code and frames alone. Unlike
+/// `compute_frames` this accepts subroutines, so it works for code of any class file version.
+pub fn compute_maxs(body: &mut CodeBody, context: &MethodContext, cp: &ConstantPool) -> Result<(), FrameError> {
+    let max_stack = Analyzer::new(&body.items, &body.try_catch_blocks, context, cp, &DefaultHierarchy, true).run()?.max_stack;
+
+    body.max_stack = max_stack as u16;
+    body.max_locals = max_locals(&body.items, context) as u16;
+
+    Ok(())
+}
+
+/// A verification type, as tracked during the analysis. Long and double values take up two
+/// local variable slots, the second one holding `Top`
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Top,
+    Integer,
+    Float,
+    Long,
+    Double,
+    Null,
+    UninitializedThis,
+    /// An object created by the `new` instruction at the given item, not yet initialised
+    Uninitialized(usize),
+    /// A class, by internal name, or an array, by descriptor
+    Reference(String)
+}
+
+impl Value {
+    fn size(&self) -> usize {
+        match self {
+            &Value::Long | &Value::Double => 2,
+            _ => 1
+        }
+    }
+
+    fn to_frame_value(&self, labels: &HashMap<usize, Label>, cp: &mut ConstantPool) -> FrameValue {
+        match self {
+            &Value::Top => FrameValue::Top,
+            &Value::Integer => FrameValue::Integer,
+            &Value::Float => FrameValue::Float,
+            &Value::Long => FrameValue::Long,
+            &Value::Double => FrameValue::Double,
+            &Value::Null => FrameValue::Null,
+            &Value::UninitializedThis => FrameValue::UninitializedThis,
+            // Every new instruction is labelled before the analysis
+            &Value::Uninitialized(idx) => labels.get(&idx).map(|label| FrameValue::Uninitialized(*label)).unwrap_or(FrameValue::Top),
+            &Value::Reference(ref name) => FrameValue::Object(class_constant(cp, name))
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct State {
+    locals: Vec<Value>,
+    stack: Vec<Value>
+}
+
+impl State {
+    fn pop(&mut self, idx: usize) -> Result<Value, FrameError> {
+        self.stack.pop().ok_or(FrameError::StackUnderflow(idx))
+    }
+
+    fn pop_n(&mut self, idx: usize, count: usize) -> Result<(), FrameError> {
+        (0..count).fold(Ok(()), |acc, _| acc.and_then(|_| self.pop(idx).map(|_| ())))
+    }
+
+    fn push(&mut self, value: Value) {
+        self.stack.push(value);
+    }
+
+    fn load(&self, index: usize) -> Value {
+        self.locals.get(index).cloned().unwrap_or(Value::Top)
+    }
+
+    fn store(&mut self, index: usize, value: Value) {
+        let size = value.size();
+
+        if self.locals.len() < index + size {
+            self.locals.resize(index + size, Value::Top);
+        }
+
+        // Overwriting the second half of a long or double invalidates the first half
+        if index > 0 && self.locals[index - 1].size() == 2 {
+            self.locals[index - 1] = Value::Top;
+        }
+
+        self.locals[index] = value;
+
+        if size == 2 {
+            self.locals[index + 1] = Value::Top;
+        }
+    }
+
+    fn stack_size(&self) -> usize {
+        self.stack.iter().map(|value| value.size()).sum()
+    }
+
+    /// Replace every occurrence of an uninitialised value after its constructor has been called
+    fn initialise(&mut self, uninitialised: &Value, initialised: Value) {
+        for value in self.locals.iter_mut().chain(self.stack.iter_mut()) {
+            if *value == *uninitialised {
+                *value = initialised.clone();
+            }
+        }
+    }
+}
+
+struct Analysis {
+    initial: State,
+    /// The state at the start of every branch target and the entry point that has been reached
+    states: HashMap<usize, State>,
+    /// Whether the item at each index is a branch target
+    targets: Vec<bool>,
+    reached: Vec<bool>,
+    /// The first label of every labelled instruction, by item index
+    labels: HashMap<usize, Label>,
+    max_stack: usize
+}
+
+struct Handler {
+    start: usize,
+    end: usize,
+    handler: usize,
+    catch_type: String
+}
+
+/// Where control goes after an instruction: the next instruction and any number of targets
+struct Flow {
+    next: bool,
+    targets: Vec<usize>
+}
+
+impl Flow {
+    fn next() -> Flow { Flow { next: true, targets: vec![] } }
+    fn end() -> Flow { Flow { next: false, targets: vec![] } }
+}
+
+struct Analyzer<'a> {
+    items: &'a Vec<CodeItem>,
+    blocks: &'a Vec<TryCatchBlock>,
+    context: &'a MethodContext,
+    cp: &'a ConstantPool,
+    hierarchy: &'a ClassHierarchy,
+    /// Accept `jsr` and `ret`, which is only good enough for computing the maximum stack size
+    subroutines: bool,
+    positions: HashMap<Label, usize>,
+    handlers: Vec<Handler>,
+    states: HashMap<usize, State>,
+    targets: Vec<bool>,
+    reached: Vec<bool>,
+    max_stack: usize
+}
+
+impl<'a> Analyzer<'a> {
+    fn new(items: &'a Vec<CodeItem>, blocks: &'a Vec<TryCatchBlock>, context: &'a MethodContext, cp: &'a ConstantPool, hierarchy: &'a ClassHierarchy, subroutines: bool) -> Analyzer<'a> {
+        Analyzer {
+            items: items,
+            blocks: blocks,
+            context: context,
+            cp: cp,
+            hierarchy: hierarchy,
+            subroutines: subroutines,
+            positions: HashMap::new(),
+            handlers: vec![],
+            states: HashMap::new(),
+            targets: vec![ false; items.len() + 1 ],
+            reached: vec![ false; items.len() ],
+            max_stack: 0
+        }
+    }
+
+    fn run(mut self) -> Result<Analysis, FrameError> {
+        self.positions = instruction_positions(self.items);
+
+        for block in self.blocks.iter() {
+            let handler = Handler {
+                start: self.position(block.start)?,
+                end: self.position(block.end)?,
+                handler: self.position(block.handler)?,
+                catch_type: if block.catch_type.idx == 0 { Some(THROWABLE.to_string()) } else { class_name(self.cp, block.catch_type.idx) }
+                    .ok_or(FrameError::InvalidConstant { item: 0, index: block.catch_type.idx })?
+            };
+
+            self.targets[handler.handler] = true;
+            self.handlers.push(handler);
+        }
+
+        for item in self.items.iter() {
+            let labels: Vec<Label> = match item {
+                &CodeItem::Jump(_, target) => vec![ target ],
+                &CodeItem::TableSwitch { default, ref targets, .. } => targets.iter().chain(Some(default).iter()).map(|label| *label).collect(),
+                &CodeItem::LookupSwitch { default, ref pairs } => pairs.iter().map(|&(_, label)| label).chain(Some(default)).collect(),
+                _ => vec![]
+            };
+
+            for label in labels {
+                let target = self.position(label)?;
+                self.targets[target] = true;
+            }
+        }
+
+        let initial = self.initial_state()?;
+        let entry = self.first_instruction();
+        let mut worklist: Vec<usize> = vec![ entry ];
+
+        self.max_stack = 0;
+        self.merge(entry, &initial)?;
+
+        let items = self.items;
+
+        while let Some(start) = worklist.pop() {
+            let mut state = self.states[&start].clone();
+            let mut idx = start;
+
+            loop {
+                if idx >= self.items.len() {
+                    return Err(FrameError::FallsOffEnd);
+                }
+
+                if let &CodeItem::Label(_) = &items[idx] {
+                    idx += 1;
+                    continue;
+                }
+
+                if idx != start && self.targets[idx] {
+                    if self.merge(idx, &state)? {
+                        worklist.push(idx);
+                    }
+
+                    break;
+                }
+
+                self.reached[idx] = true;
+                self.merge_handlers(idx, &state, &mut worklist)?;
+
+                let flow = match &items[idx] {
+                    &CodeItem::Jump(Jump::JSR, target) | &CodeItem::Jump(Jump::JSR_W, target) if self.subroutines => {
+                        // The subroutine is assumed to return with the stack it was called with
+                        let target = self.position(target)?;
+                        let mut called = state.clone();
+                        called.push(Value::Top);
+
+                        self.max_stack = self.max_stack.max(called.stack_size());
+
+                        if self.merge(target, &called)? {
+                            worklist.push(target);
+                        }
+
+                        Flow::next()
+                    },
+                    _ => self.execute(idx, &mut state)?
+                };
+
+                self.merge_handlers(idx, &state, &mut worklist)?;
+                self.max_stack = self.max_stack.max(state.stack_size());
+
+                for target in flow.targets {
+                    if self.merge(target, &state)? {
+                        worklist.push(target);
+                    }
+                }
+
+                if !flow.next {
+                    break;
+                }
+
+                idx += 1;
+            }
+        }
+
+        let mut labels: HashMap<usize, Label> = HashMap::new();
+
+        for item in self.items.iter().rev() {
+            if let &CodeItem::Label(label) = item {
+                if let Some(position) = self.positions.get(&label) {
+                    labels.insert(*position, label);
+                }
+            }
+        }
+
+        Ok(Analysis {
+            initial: initial,
+            states: self.states,
+            targets: self.targets,
+            reached: self.reached,
+            labels: labels,
+            max_stack: self.max_stack
+        })
+    }
+
+    fn first_instruction(&self) -> usize {
+        self.items.iter().position(|item| match item {
+            &CodeItem::Label(_) => false,
+            _ => true
+        }).unwrap_or(self.items.len())
+    }
+
+    fn position(&self, label: Label) -> Result<usize, FrameError> {
+        self.positions.get(&label).map(|idx| *idx).ok_or(FrameError::Code(CodeError::UnplacedLabel(label)))
+    }
+
+    fn initial_state(&self) -> Result<State, FrameError> {
+        let (arguments, _) = method_types(&self.context.descriptor).ok_or(FrameError::InvalidDescriptor(self.context.descriptor.clone()))?;
+        let mut state = State { locals: vec![], stack: vec![] };
+
+        if !self.context.is_static {
+            if self.context.method_name == "<init>" && self.context.class_name != OBJECT {
+                state.locals.push(Value::UninitializedThis);
+            } else {
+                state.locals.push(Value::Reference(self.context.class_name.clone()));
+            }
+        }
+
+        for argument in arguments {
+            let index = state.locals.len();
+            state.store(index, argument);
+        }
+
+        let size = max_locals(self.items, self.context);
+        state.locals.resize(size, Value::Top);
+
+        Ok(state)
+    }
+
+    /// Merge a state into the state recorded at `idx`, returning whether the recorded state changed
+    fn merge(&mut self, idx: usize, state: &State) -> Result<bool, FrameError> {
+        if idx >= self.items.len() {
+            return Err(FrameError::FallsOffEnd);
+        }
+
+        let merged = match self.states.get(&idx) {
+            None => state.clone(),
+            Some(current) if current.stack.len() != state.stack.len() => return Err(FrameError::StackMismatch(idx)),
+            Some(current) => {
+                let merge_all = |a: &Vec<Value>, b: &Vec<Value>| a.iter().zip(b.iter()).map(|(a, b)| self.merge_values(a, b)).collect::<Result<Vec<Value>, FrameError>>();
+                let merged = State { locals: merge_all(&current.locals, &state.locals)?, stack: merge_all(&current.stack, &state.stack)? };
+
+                if merged.locals == current.locals && merged.stack == current.stack {
+                    return Ok(false);
+                }
+
+                merged
+            }
+        };
+
+        self.states.insert(idx, merged);
+
+        Ok(true)
+    }
+
+    fn merge_handlers(&mut self, idx: usize, state: &State, worklist: &mut Vec<usize>) -> Result<(), FrameError> {
+        let entries: Vec<(usize, String)> = self.handlers.iter()
+            .filter(|handler| handler.start <= idx && idx < handler.end)
+            .map(|handler| (handler.handler, handler.catch_type.clone()))
+            .collect();
+
+        for (handler, catch_type) in entries {
+            let entry = State { locals: state.locals.clone(), stack: vec![ Value::Reference(catch_type) ] };
+
+            self.max_stack = self.max_stack.max(1);
+
+            if self.merge(handler, &entry)? {
+                worklist.push(handler);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn merge_values(&self, a: &Value, b: &Value) -> Result<Value, FrameError> {
+        Ok(match (a, b) {
+            _ if a == b => a.clone(),
+            (&Value::Null, &Value::Reference(_)) => b.clone(),
+            (&Value::Reference(_), &Value::Null) => a.clone(),
+            (&Value::Reference(ref a), &Value::Reference(ref b)) => Value::Reference(self.merge_references(a, b)?),
+            _ => Value::Top
+        })
+    }
+
+    fn merge_references(&self, a: &str, b: &str) -> Result<String, FrameError> {
+        if a == b {
+            return Ok(a.to_string());
+        }
+
+        match (a.starts_with('['), b.starts_with('[')) {
+            (true, true) => {
+                let (a_component, b_component) = (&a[1..], &b[1..]);
+
+                match (reference_name(a_component), reference_name(b_component)) {
+                    (Some(a_name), Some(b_name)) => Ok(format!("[{}", reference_descriptor(&self.merge_references(a_name, b_name)?))),
+                    _ => Ok(OBJECT.to_string())
+                }
+            },
+            (false, false) => self.hierarchy.common_superclass(a, b).ok_or(FrameError::UnresolvedMerge(a.to_string(), b.to_string())),
+            _ => Ok(OBJECT.to_string())
+        }
+    }
+
+    fn execute(&self, idx: usize, state: &mut State) -> Result<Flow, FrameError> {
+        let instruction = match &self.items[idx] {
+            &CodeItem::Label(_) => return Ok(Flow::next()),
+            &CodeItem::Jump(jump, target) => {
+                let target = self.position(target)?;
+
+                return match jump {
+                    Jump::GOTO | Jump::GOTO_W => Ok(Flow { next: false, targets: vec![ target ] }),
+                    Jump::JSR | Jump::JSR_W => Err(FrameError::Subroutine(idx)),
+                    Jump::IF_ICMPEQ | Jump::IF_ICMPNE | Jump::IF_ICMPLT | Jump::IF_ICMPGE | Jump::IF_ICMPGT | Jump::IF_ICMPLE | Jump::IF_ACMPEQ | Jump::IF_ACMPNE => {
+                        state.pop_n(idx, 2).map(|_| Flow { next: true, targets: vec![ target ] })
+                    },
+                    _ => state.pop(idx).map(|_| Flow { next: true, targets: vec![ target ] })
+                };
+            },
+            &CodeItem::TableSwitch { default, ref targets, .. } => {
+                state.pop(idx)?;
+
+                return targets.iter().chain(Some(default).iter()).map(|label| self.position(*label)).collect::<Result<Vec<usize>, FrameError>>()
+                    .map(|targets| Flow { next: false, targets: targets });
+            },
+            &CodeItem::LookupSwitch { default, ref pairs } => {
+                state.pop(idx)?;
+
+                return pairs.iter().map(|&(_, label)| label).chain(Some(default)).map(|label| self.position(label)).collect::<Result<Vec<usize>, FrameError>>()
+                    .map(|targets| Flow { next: false, targets: targets });
+            },
+            &CodeItem::Instruction(ref instruction) => instruction
+        };
+
+        let invalid_constant = |index: usize| FrameError::InvalidConstant { item: idx, index: index };
+
+        match instruction {
+            &Instruction::NOP => (),
+            &Instruction::ACONST_NULL => state.push(Value::Null),
+            &Instruction::ICONST_M1 | &Instruction::ICONST_0 | &Instruction::ICONST_1 | &Instruction::ICONST_2 | &Instruction::ICONST_3 |
+            &Instruction::ICONST_4 | &Instruction::ICONST_5 | &Instruction::BIPUSH(_) | &Instruction::SIPUSH(_) => state.push(Value::Integer),
+            &Instruction::LCONST_0 | &Instruction::LCONST_1 => state.push(Value::Long),
+            &Instruction::FCONST_0 | &Instruction::FCONST_1 | &Instruction::FCONST_2 => state.push(Value::Float),
+            &Instruction::DCONST_0 | &Instruction::DCONST_1 => state.push(Value::Double),
+            &Instruction::LDC(index) => state.push(self.constant_value(index as usize).ok_or(invalid_constant(index as usize))?),
+            &Instruction::LDC_W(index) | &Instruction::LDC2_W(index) => state.push(self.constant_value(index as usize).ok_or(invalid_constant(index as usize))?),
+
+            &Instruction::ILOAD(_) | &Instruction::ILOAD_0 | &Instruction::ILOAD_1 | &Instruction::ILOAD_2 | &Instruction::ILOAD_3 | &Instruction::ILOAD_W(_) => state.push(Value::Integer),
+            &Instruction::LLOAD(_) | &Instruction::LLOAD_0 | &Instruction::LLOAD_1 | &Instruction::LLOAD_2 | &Instruction::LLOAD_3 | &Instruction::LLOAD_W(_) => state.push(Value::Long),
+            &Instruction::FLOAD(_) | &Instruction::FLOAD_0 | &Instruction::FLOAD_1 | &Instruction::FLOAD_2 | &Instruction::FLOAD_3 | &Instruction::FLOAD_W(_) => state.push(Value::Float),
+            &Instruction::DLOAD(_) | &Instruction::DLOAD_0 | &Instruction::DLOAD_1 | &Instruction::DLOAD_2 | &Instruction::DLOAD_3 | &Instruction::DLOAD_W(_) => state.push(Value::Double),
+            &Instruction::ALOAD(_) | &Instruction::ALOAD_0 | &Instruction::ALOAD_1 | &Instruction::ALOAD_2 | &Instruction::ALOAD_3 | &Instruction::ALOAD_W(_) => {
+                let value = state.load(local_variable(instruction).map(|(index, _)| index).unwrap_or(0));
+                state.push(value);
+            },
+
+            &Instruction::IALOAD | &Instruction::BALOAD | &Instruction::CALOAD | &Instruction::SALOAD => { state.pop_n(idx, 2)?; state.push(Value::Integer); },
+            &Instruction::LALOAD => { state.pop_n(idx, 2)?; state.push(Value::Long); },
+            &Instruction::FALOAD => { state.pop_n(idx, 2)?; state.push(Value::Float); },
+            &Instruction::DALOAD => { state.pop_n(idx, 2)?; state.push(Value::Double); },
+            &Instruction::AALOAD => {
+                state.pop(idx)?;
+
+                let component = match state.pop(idx)? {
+                    Value::Reference(ref array) if array.starts_with('[') => descriptor_value(&array[1..]).unwrap_or(Value::Top),
+                    _ => Value::Null
+                };
+
+                state.push(component);
+            },
+
+            &Instruction::ISTORE(_) | &Instruction::ISTORE_0 | &Instruction::ISTORE_1 | &Instruction::ISTORE_2 | &Instruction::ISTORE_3 | &Instruction::ISTORE_W(_) |
+            &Instruction::LSTORE(_) | &Instruction::LSTORE_0 | &Instruction::LSTORE_1 | &Instruction::LSTORE_2 | &Instruction::LSTORE_3 | &Instruction::LSTORE_W(_) |
+            &Instruction::FSTORE(_) | &Instruction::FSTORE_0 | &Instruction::FSTORE_1 | &Instruction::FSTORE_2 | &Instruction::FSTORE_3 | &Instruction::FSTORE_W(_) |
+            &Instruction::DSTORE(_) | &Instruction::DSTORE_0 | &Instruction::DSTORE_1 | &Instruction::DSTORE_2 | &Instruction::DSTORE_3 | &Instruction::DSTORE_W(_) |
+            &Instruction::ASTORE(_) | &Instruction::ASTORE_0 | &Instruction::ASTORE_1 | &Instruction::ASTORE_2 | &Instruction::ASTORE_3 | &Instruction::ASTORE_W(_) => {
+                let value = state.pop(idx)?;
+                state.store(local_variable(instruction).map(|(index, _)| index).unwrap_or(0), value);
+            },
+            &Instruction::IINC(_, _) | &Instruction::IINC_W(_, _) => (),
+
+            &Instruction::IASTORE | &Instruction::LASTORE | &Instruction::FASTORE | &Instruction::DASTORE |
+            &Instruction::AASTORE | &Instruction::BASTORE | &Instruction::CASTORE | &Instruction::SASTORE => state.pop_n(idx, 3)?,
+
+            &Instruction::POP => { state.pop(idx)?; },
+            &Instruction::POP2 => {
+                if state.pop(idx)?.size() == 1 {
+                    state.pop(idx)?;
+                }
+            },
+            &Instruction::DUP => {
+                let value = state.pop(idx)?;
+                state.push(value.clone());
+                state.push(value);
+            },
+            &Instruction::DUP_X1 => {
+                let (value1, value2) = (state.pop(idx)?, state.pop(idx)?);
+                state.stack.extend(vec![ value1.clone(), value2, value1 ]);
+            },
+            &Instruction::DUP_X2 => {
+                let (value1, value2) = (state.pop(idx)?, state.pop(idx)?);
+
+                if value2.size() == 2 {
+                    state.stack.extend(vec![ value1.clone(), value2, value1 ]);
+                } else {
+                    let value3 = state.pop(idx)?;
+                    state.stack.extend(vec![ value1.clone(), value3, value2, value1 ]);
+                }
+            },
+            &Instruction::DUP2 => {
+                let value1 = state.pop(idx)?;
+
+                if value1.size() == 2 {
+                    state.stack.extend(vec![ value1.clone(), value1 ]);
+                } else {
+                    let value2 = state.pop(idx)?;
+                    state.stack.extend(vec![ value2.clone(), value1.clone(), value2, value1 ]);
+                }
+            },
+            &Instruction::DUP2_X1 => {
+                let (value1, value2) = (state.pop(idx)?, state.pop(idx)?);
+
+                if value1.size() == 2 {
+                    state.stack.extend(vec![ value1.clone(), value2, value1 ]);
+                } else {
+                    let value3 = state.pop(idx)?;
+                    state.stack.extend(vec![ value2.clone(), value1.clone(), value3, value2, value1 ]);
+                }
+            },
+            &Instruction::DUP2_X2 => {
+                let (value1, value2) = (state.pop(idx)?, state.pop(idx)?);
+
+                match (value1.size(), value2.size()) {
+                    (2, 2) => state.stack.extend(vec![ value1.clone(), value2, value1 ]),
+                    (2, _) => {
+                        let value3 = state.pop(idx)?;
+                        state.stack.extend(vec![ value1.clone(), value3, value2, value1 ]);
+                    },
+                    _ => {
+                        let value3 = state.pop(idx)?;
+
+                        if value3.size() == 2 {
+                            state.stack.extend(vec![ value2.clone(), value1.clone(), value3, value2, value1 ]);
+                        } else {
+                            let value4 = state.pop(idx)?;
+                            state.stack.extend(vec![ value2.clone(), value1.clone(), value4, value3, value2, value1 ]);
+                        }
+                    }
+                }
+            },
+            &Instruction::SWAP => {
+                let (value1, value2) = (state.pop(idx)?, state.pop(idx)?);
+                state.stack.extend(vec![ value1, value2 ]);
+            },
+
+            &Instruction::IADD | &Instruction::ISUB | &Instruction::IMUL | &Instruction::IDIV | &Instruction::IREM | &Instruction::IAND |
+            &Instruction::IOR | &Instruction::IXOR | &Instruction::ISHL | &Instruction::ISHR | &Instruction::IUSHR |
+            &Instruction::LCMP | &Instruction::FCMPL | &Instruction::FCMPG | &Instruction::DCMPL | &Instruction::DCMPG => { state.pop_n(idx, 2)?; state.push(Value::Integer); },
+            &Instruction::LADD | &Instruction::LSUB | &Instruction::LMUL | &Instruction::LDIV | &Instruction::LREM | &Instruction::LAND |
+            &Instruction::LOR | &Instruction::LXOR | &Instruction::LSHL | &Instruction::LSHR | &Instruction::LUSHR => { state.pop_n(idx, 2)?; state.push(Value::Long); },
+            &Instruction::FADD | &Instruction::FSUB | &Instruction::FMUL | &Instruction::FDIV | &Instruction::FREM => { state.pop_n(idx, 2)?; state.push(Value::Float); },
+            &Instruction::DADD | &Instruction::DSUB | &Instruction::DMUL | &Instruction::DDIV | &Instruction::DREM => { state.pop_n(idx, 2)?; state.push(Value::Double); },
+
+            &Instruction::INEG | &Instruction::L2I | &Instruction::F2I | &Instruction::D2I | &Instruction::I2B | &Instruction::I2C | &Instruction::I2S => { state.pop(idx)?; state.push(Value::Integer); },
+            &Instruction::LNEG | &Instruction::I2L | &Instruction::F2L | &Instruction::D2L => { state.pop(idx)?; state.push(Value::Long); },
+            &Instruction::FNEG | &Instruction::I2F | &Instruction::L2F | &Instruction::D2F => { state.pop(idx)?; state.push(Value::Float); },
+            &Instruction::DNEG | &Instruction::I2D | &Instruction::L2D | &Instruction::F2D => { state.pop(idx)?; state.push(Value::Double); },
+
+            &Instruction::IRETURN | &Instruction::LRETURN | &Instruction::FRETURN | &Instruction::DRETURN | &Instruction::ARETURN | &Instruction::ATHROW => {
+                state.pop(idx)?;
+                return Ok(Flow::end());
+            },
+            &Instruction::RETURN => return Ok(Flow::end()),
+            &Instruction::RET(_) | &Instruction::RET_W(_) if self.subroutines => return Ok(Flow::end()),
+            &Instruction::RET(_) | &Instruction::RET_W(_) => return Err(FrameError::Subroutine(idx)),
+
+            &Instruction::GETSTATIC(index) | &Instruction::GETFIELD(index) => {
+                let descriptor = member_descriptor(self.cp, index as usize).ok_or(invalid_constant(index as usize))?.1;
+
+                if let &Instruction::GETFIELD(_) = instruction {
+                    state.pop(idx)?;
+                }
+
+                state.push(descriptor_value(&descriptor).ok_or(FrameError::InvalidDescriptor(descriptor.clone()))?);
+            },
+            &Instruction::PUTSTATIC(index) | &Instruction::PUTFIELD(index) => {
+                member_descriptor(self.cp, index as usize).ok_or(invalid_constant(index as usize))?;
+
+                if let &Instruction::PUTFIELD(_) = instruction {
+                    state.pop_n(idx, 2)?;
+                } else {
+                    state.pop(idx)?;
+                }
+            },
+            &Instruction::INVOKEVIRTUAL(index) | &Instruction::INVOKESPECIAL(index) | &Instruction::INVOKESTATIC(index) |
+            &Instruction::INVOKEINTERFACE(index, _) | &Instruction::INVOKEDYNAMIC(index) => {
+                let (name, descriptor) = member_descriptor(self.cp, index as usize).ok_or(invalid_constant(index as usize))?;
+                let (arguments, result) = method_types(&descriptor).ok_or(FrameError::InvalidDescriptor(descriptor.clone()))?;
+
+                state.pop_n(idx, arguments.len())?;
+
+                match instruction {
+                    &Instruction::INVOKESTATIC(_) | &Instruction::INVOKEDYNAMIC(_) => (),
+                    &Instruction::INVOKESPECIAL(_) if name == "<init>" => {
+                        let receiver = state.pop(idx)?;
+
+                        let initialised = match receiver {
+                            Value::UninitializedThis => Some(self.context.class_name.clone()),
+                            Value::Uninitialized(created) => match &self.items[created] {
+                                &CodeItem::Instruction(Instruction::NEW(class_index)) => Some(class_name(self.cp, class_index as usize).ok_or(invalid_constant(class_index as usize))?),
+                                _ => None
+                            },
+                            _ => None
+                        };
+
+                        if let Some(class) = initialised {
+                            state.initialise(&receiver, Value::Reference(class));
+                        }
+                    },
+                    _ => { state.pop(idx)?; }
+                }
+
+                if let Some(result) = result {
+                    state.push(result);
+                }
+            },
+
+            &Instruction::NEW(index) => {
+                class_name(self.cp, index as usize).ok_or(invalid_constant(index as usize))?;
+                state.push(Value::Uninitialized(idx));
+            },
+            &Instruction::NEWARRAY(atype) => {
+                let descriptor = match atype {
+                    4 => "[Z", 5 => "[C", 6 => "[F", 7 => "[D", 8 => "[B", 9 => "[S", 10 => "[I", 11 => "[J",
+                    _ => return Err(FrameError::InvalidInstruction(idx))
+                };
+
+                state.pop(idx)?;
+                state.push(Value::Reference(descriptor.to_string()));
+            },
+            &Instruction::ANEWARRAY(index) => {
+                let component = class_name(self.cp, index as usize).ok_or(invalid_constant(index as usize))?;
+
+                state.pop(idx)?;
+                state.push(Value::Reference(format!("[{}", reference_descriptor(&component))));
+            },
+            &Instruction::MULTIANEWARRAY(index, dimensions) => {
+                let array = class_name(self.cp, index as usize).ok_or(invalid_constant(index as usize))?;
+
+                state.pop_n(idx, dimensions as usize)?;
+                state.push(Value::Reference(array));
+            },
+            &Instruction::ARRAYLENGTH | &Instruction::INSTANCEOF(_) => { state.pop(idx)?; state.push(Value::Integer); },
+            &Instruction::CHECKCAST(index) => {
+                let class = class_name(self.cp, index as usize).ok_or(invalid_constant(index as usize))?;
+
+                state.pop(idx)?;
+                state.push(Value::Reference(class));
+            },
+            &Instruction::MONITORENTER | &Instruction::MONITOREXIT => { state.pop(idx)?; },
+
+            // Jumps and switches are turned into code items by CodeBody
+            _ => return Err(FrameError::InvalidInstruction(idx))
+        }
+
+        Ok(Flow::next())
+    }
+
+    /// The type of the value `ldc` pushes for the given constant
+    fn constant_value(&self, index: usize) -> Option<Value> {
+        match self.cp.constants.get(index) {
+            Some(&Constant::Integer(_)) => Some(Value::Integer),
+            Some(&Constant::Float(_)) => Some(Value::Float),
+            Some(&Constant::Long(_)) => Some(Value::Long),
+            Some(&Constant::Double(_)) => Some(Value::Double),
+            Some(&Constant::String(_)) => Some(Value::Reference(String::from("java/lang/String"))),
+            Some(&Constant::Class(_)) => Some(Value::Reference(String::from("java/lang/Class"))),
+            Some(&Constant::MethodType(_)) => Some(Value::Reference(String::from("java/lang/invoke/MethodType"))),
+            Some(&Constant::MethodHandle { .. }) => Some(Value::Reference(String::from("java/lang/invoke/MethodHandle"))),
+            Some(&Constant::Dynamic { .. }) => member_descriptor(self.cp, index).and_then(|(_, descriptor)| descriptor_value(&descriptor)),
+            _ => None
+        }
+    }
+}
+
+/// Make sure every `new` instruction is labelled, so uninitialised values can refer to it
+fn label_allocations(body: &mut CodeBody) {
+    let original = ::std::mem::replace(&mut body.items, vec![]);
+    let mut labelled = false;
+
+    for item in original {
+        if let CodeItem::Instruction(Instruction::NEW(_)) = item {
+            if !labelled {
+                let label = body.new_label();
+                body.items.push(CodeItem::Label(label));
+            }
+        }
+
+        labelled = match item {
+            CodeItem::Label(_) => true,
+            _ => false
+        };
+
+        body.items.push(item);
+    }
+}
+
+/// The index of the instruction following each label, the number of items for labels at the end
+fn instruction_positions(items: &Vec<CodeItem>) -> HashMap<Label, usize> {
+    let mut positions: HashMap<Label, usize> = HashMap::new();
+    let mut pending: Vec<Label> = vec![];
+
+    for (idx, item) in items.iter().enumerate() {
+        match item {
+            &CodeItem::Label(label) => pending.push(label),
+            _ => for label in pending.drain(..) {
+                positions.insert(label, idx);
+            }
+        }
+    }
+
+    for label in pending {
+        positions.insert(label, items.len());
+    }
+
+    positions
+}
+
+/// Local variables in frame order, where long and double values take up a single entry and
+/// trailing unusable slots are left out
+fn frame_locals(locals: &[Value]) -> Vec<Value> {
+    let mut values: Vec<Value> = vec![];
+    let mut idx = 0;
+
+    while idx < locals.len() {
+        values.push(locals[idx].clone());
+        idx += locals[idx].size();
+    }
+
+    while values.last() == Some(&Value::Top) {
+        values.pop();
+    }
+
+    values
+}
+
+/// Number of local variable slots used by the arguments and the instructions of a method
+fn max_locals(items: &Vec<CodeItem>, context: &MethodContext) -> usize {
+    let receiver = if context.is_static { 0 } else { 1 };
+    let arguments = method_types(&context.descriptor).map(|(arguments, _)| arguments.iter().map(|value| value.size()).sum()).unwrap_or(0);
+
+    items.iter().filter_map(|item| match item {
+        &CodeItem::Instruction(ref instruction) => local_variable(instruction).map(|(index, size)| index + size),
+        _ => None
+    }).fold(receiver + arguments, |max, end| max.max(end))
+}
+
+/// The local variable index and slot count an instruction accesses
+fn local_variable(instruction: &Instruction) -> Option<(usize, usize)> {
+    match instruction {
+        &Instruction::ILOAD(index) | &Instruction::FLOAD(index) | &Instruction::ALOAD(index) |
+        &Instruction::ISTORE(index) | &Instruction::FSTORE(index) | &Instruction::ASTORE(index) |
+        &Instruction::IINC(index, _) | &Instruction::RET(index) => Some((index as usize, 1)),
+        &Instruction::LLOAD(index) | &Instruction::DLOAD(index) | &Instruction::LSTORE(index) | &Instruction::DSTORE(index) => Some((index as usize, 2)),
+        &Instruction::ILOAD_W(index) | &Instruction::FLOAD_W(index) | &Instruction::ALOAD_W(index) |
+        &Instruction::ISTORE_W(index) | &Instruction::FSTORE_W(index) | &Instruction::ASTORE_W(index) |
+        &Instruction::IINC_W(index, _) | &Instruction::RET_W(index) => Some((index as usize, 1)),
+        &Instruction::LLOAD_W(index) | &Instruction::DLOAD_W(index) | &Instruction::LSTORE_W(index) | &Instruction::DSTORE_W(index) => Some((index as usize, 2)),
+        &Instruction::ILOAD_0 | &Instruction::FLOAD_0 | &Instruction::ALOAD_0 | &Instruction::ISTORE_0 | &Instruction::FSTORE_0 | &Instruction::ASTORE_0 => Some((0, 1)),
+        &Instruction::ILOAD_1 | &Instruction::FLOAD_1 | &Instruction::ALOAD_1 | &Instruction::ISTORE_1 | &Instruction::FSTORE_1 | &Instruction::ASTORE_1 => Some((1, 1)),
+        &Instruction::ILOAD_2 | &Instruction::FLOAD_2 | &Instruction::ALOAD_2 | &Instruction::ISTORE_2 | &Instruction::FSTORE_2 | &Instruction::ASTORE_2 => Some((2, 1)),
+        &Instruction::ILOAD_3 | &Instruction::FLOAD_3 | &Instruction::ALOAD_3 | &Instruction::ISTORE_3 | &Instruction::FSTORE_3 | &Instruction::ASTORE_3 => Some((3, 1)),
+        &Instruction::LLOAD_0 | &Instruction::DLOAD_0 | &Instruction::LSTORE_0 | &Instruction::DSTORE_0 => Some((0, 2)),
+        &Instruction::LLOAD_1 | &Instruction::DLOAD_1 | &Instruction::LSTORE_1 | &Instruction::DSTORE_1 => Some((1, 2)),
+        &Instruction::LLOAD_2 | &Instruction::DLOAD_2 | &Instruction::LSTORE_2 | &Instruction::DSTORE_2 => Some((2, 2)),
+        &Instruction::LLOAD_3 | &Instruction::DLOAD_3 | &Instruction::LSTORE_3 | &Instruction::DSTORE_3 => Some((3, 2)),
+        _ => None
+    }
+}
+
+/// The name of the class referred to by a `Class` constant
+pub fn class_name(cp: &ConstantPool, index: usize) -> Option<String> {
+    match cp.constants.get(index) {
+        Some(&Constant::Class(ref name_index)) => cp.get_utf8_string(name_index.idx as u16),
+        _ => None
+    }
+}
+
+/// Find or add the `Class` constant of the given class name
+fn class_constant(cp: &mut ConstantPool, name: &str) -> ConstantPoolIndex {
+    let utf8 = Constant::Utf8(name.as_bytes().to_vec());
+
+    let name_index = match cp.get_constant_index(&utf8) {
+        Some(idx) => idx,
+        None => cp.add_constant(utf8)
+    };
+
+    let class = Constant::Class(name_index);
+
+    match cp.get_constant_index(&class) {
+        Some(idx) => idx,
+        None => cp.add_constant(class)
+    }
+}
+
+/// The name and descriptor of the member or call site referred to by the given constant
+fn member_descriptor(cp: &ConstantPool, index: usize) -> Option<(String, String)> {
+    let name_and_type = match cp.constants.get(index) {
+        Some(&Constant::FieldRef { ref name_and_type_index, .. }) |
+        Some(&Constant::MethodRef { ref name_and_type_index, .. }) |
+        Some(&Constant::InterfaceMethodRef { ref name_and_type_index, .. }) |
+        Some(&Constant::Dynamic { ref name_and_type_index, .. }) |
+        Some(&Constant::InvokeDynamic { ref name_and_type_index, .. }) => name_and_type_index.idx,
+        _ => return None
+    };
+
+    match cp.constants.get(name_and_type) {
+        Some(&Constant::NameAndType { ref name_index, ref descriptor_index }) => match (cp.get_utf8_string(name_index.idx as u16), cp.get_utf8_string(descriptor_index.idx as u16)) {
+            (Some(name), Some(descriptor)) => Some((name, descriptor)),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+/// The argument types and the return type (`None` for void) of a method descriptor
+fn method_types(descriptor: &str) -> Option<(Vec<Value>, Option<Value>)> {
+    if !descriptor.starts_with('(') {
+        return None;
+    }
+
+    let close = descriptor.find(')')?;
+    let mut rest = &descriptor[1..close];
+    let mut arguments: Vec<Value> = vec![];
+
+    while !rest.is_empty() {
+        let len = descriptor_len(rest)?;
+
+        arguments.push(descriptor_value(&rest[..len])?);
+        rest = &rest[len..];
+    }
+
+    match &descriptor[close + 1..] {
+        "V" => Some((arguments, None)),
+        result@_ => descriptor_value(result).map(|result| (arguments, Some(result)))
+    }
+}
+
+/// Length of the field descriptor at the start of `descriptor`
+fn descriptor_len(descriptor: &str) -> Option<usize> {
+    let dimensions = descriptor.chars().take_while(|c| *c == '[').count();
+
+    match descriptor[dimensions..].chars().next() {
+        Some('L') => descriptor.find(';').map(|end| end + 1),
+        Some('B') | Some('C') | Some('D') | Some('F') | Some('I') | Some('J') | Some('S') | Some('Z') => Some(dimensions + 1),
+        _ => None
+    }
+}
+
+/// The verification type of a value of the given field descriptor
+fn descriptor_value(descriptor: &str) -> Option<Value> {
+    if descriptor_len(descriptor) != Some(descriptor.len()) {
+        return None;
+    }
+
+    match descriptor.chars().next() {
+        Some('B') | Some('C') | Some('I') | Some('S') | Some('Z') => Some(Value::Integer),
+        Some('F') => Some(Value::Float),
+        Some('J') => Some(Value::Long),
+        Some('D') => Some(Value::Double),
+        Some('L') => Some(Value::Reference(descriptor[1..descriptor.len() - 1].to_string())),
+        Some('[') => Some(Value::Reference(descriptor.to_string())),
+        _ => None
+    }
+}
+
+/// The class name or array descriptor of a reference field descriptor, `None` for primitives
+fn reference_name(descriptor: &str) -> Option<&str> {
+    if descriptor.starts_with('L') && descriptor.ends_with(';') {
+        Some(&descriptor[1..descriptor.len() - 1])
+    } else if descriptor.starts_with('[') {
+        Some(descriptor)
+    } else {
+        None
+    }
+}
+
+/// The field descriptor of a class name or array descriptor
+fn reference_descriptor(name: &str) -> String {
+    if name.starts_with('[') { name.to_string() } else { format!("L{};", name) }
+}
@@ -0,0 +1,120 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+///
+/// Classifies the reason a class file could not be read
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReadErrorKind {
+    /// The input ended before the structure being read was complete
+    UnexpectedEof,
+    /// The underlying reader failed for a reason other than reaching the end of input
+    Io,
+    /// The class file doesn't start with 0xCAFEBABE
+    InvalidMagic,
+    /// A constant pool entry has an unknown tag or doesn't fit in the pool
+    InvalidConstant,
+    /// A constant pool index points outside the pool or to a constant of the wrong type
+    InvalidIndex,
+    /// An instruction has an unknown or malformed opcode
+    InvalidOpcode,
+    /// A tag, count or other value is outside its permitted range
+    InvalidValue,
+    /// An attribute body is shorter or longer than its declared length
+    LengthMismatch
+}
+
+///
+/// A `ClassReadError` describes why and where `ClassReader` stopped parsing a class file.
+///
+/// `offset` is the absolute byte offset into the class file, `section` is a path to the structure
+/// being read (eg. `methods[2].Code.code`) and `expected`/`found` describe the offending value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassReadError {
+    pub kind: ReadErrorKind,
+    pub offset: usize,
+    pub section: String,
+    pub constant_pool_index: Option<usize>,
+    pub expected: String,
+    pub found: String
+}
+
+impl ClassReadError {
+    pub fn new<E, F>(kind: ReadErrorKind, offset: usize, expected: E, found: F) -> ClassReadError where E: Into<String>, F: Into<String> {
+        ClassReadError {
+            kind: kind,
+            offset: offset,
+            section: String::new(),
+            constant_pool_index: None,
+            expected: expected.into(),
+            found: found.into()
+        }
+    }
+
+    pub fn eof(offset: usize, expected: usize, found: usize) -> ClassReadError {
+        ClassReadError::new(ReadErrorKind::UnexpectedEof, offset, format!("{} bytes", expected), format!("{} bytes", found))
+    }
+
+    pub fn io(offset: usize, err: io::Error) -> ClassReadError {
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof => ClassReadError::new(ReadErrorKind::UnexpectedEof, offset, "more input", "end of input"),
+            _ => ClassReadError::new(ReadErrorKind::Io, offset, "readable input", format!("{}", err))
+        }
+    }
+
+    /// Record the constant pool index the error relates to
+    pub fn at_index(mut self, idx: usize) -> ClassReadError {
+        self.constant_pool_index = Some(idx);
+        self
+    }
+
+    /// Prepend an enclosing section to the section path of this error. Segments starting with `[`
+    /// are treated as indices of the enclosing section and aren't separated by a dot.
+    pub fn within<T>(mut self, section: T) -> ClassReadError where T: Into<String> {
+        let mut outer: String = section.into();
+
+        if !self.section.is_empty() {
+            if !self.section.starts_with("[") {
+                outer.push('.');
+            }
+
+            outer.push_str(self.section.as_str());
+        }
+
+        self.section = outer;
+        self
+    }
+}
+
+impl fmt::Display for ClassReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} at offset {} ({:#x})", self.kind, self.offset, self.offset)?;
+
+        if !self.section.is_empty() {
+            write!(f, " in {}", self.section)?;
+        }
+
+        if let Some(idx) = self.constant_pool_index {
+            write!(f, " (constant pool index #{})", idx)?;
+        }
+
+        write!(f, ": expected {}, found {}", self.expected, self.found)
+    }
+}
+
+impl Error for ClassReadError {
+    fn description(&self) -> &str {
+        "Failed to read class file"
+    }
+}
+
+impl From<ClassReadError> for io::Error {
+    fn from(err: ClassReadError) -> io::Error {
+        let kind = match err.kind {
+            ReadErrorKind::UnexpectedEof => io::ErrorKind::UnexpectedEof,
+            _ => io::ErrorKind::InvalidData
+        };
+
+        io::Error::new(kind, format!("{}", err))
+    }
+}
@@ -1,5 +1,7 @@
+pub use self::error::*;
 pub use self::reader::*;
 pub use self::writer::*;
 
+pub mod error;
 pub mod reader;
 pub mod writer;
@@ -1,33 +1,36 @@
-use std::io::{ Cursor, Read, Error, ErrorKind };
-use std::cell::Cell;
+use std::io::{ Cursor, Read, ErrorKind };
 use super::super::classfile::*;
+use super::error::*;
 
 pub struct ClassReader {
 }
 
+type FragmentReader = fn(&mut BlockReader, &ClassFragment) -> Result<ClassFragment, ClassReadError>;
+
 impl ClassReader {
 
-    pub fn read_class<T>(source: &mut T) -> Result<Classfile, Error> where T: Read {
+    pub fn read_class<T>(source: &mut T) -> Result<Classfile, ClassReadError> where T: Read {
         let mut reader = BlockReader::new(source);
 
-        let fns: Vec<fn(&mut BlockReader, &ClassFragment) -> Result<ClassFragment, Error>> = vec![
-            ClassReader::read_magic_bytes,
-            ClassReader::read_classfile_version,
-            ClassReader::read_constant_pool,
-            ClassReader::read_access_flags,
-            ClassReader::read_this_class,
-            ClassReader::read_super_class,
-            ClassReader::read_interfaces,
-            ClassReader::read_fields,
-            ClassReader::read_methods,
-            ClassReader::read_class_attributes
+        let fns: Vec<(&'static str, FragmentReader)> = vec![
+            ("magic", ClassReader::read_magic_bytes),
+            ("version", ClassReader::read_classfile_version),
+            ("constant_pool", ClassReader::read_constant_pool),
+            ("access_flags", ClassReader::read_access_flags),
+            ("this_class", ClassReader::read_this_class),
+            ("super_class", ClassReader::read_super_class),
+            ("interfaces", ClassReader::read_interfaces),
+            ("fields", ClassReader::read_fields),
+            ("methods", ClassReader::read_methods),
+            ("attributes", ClassReader::read_class_attributes),
+            ("end", ClassReader::read_end_of_class)
         ];
 
-        let result = fns.iter().fold(Ok(ClassFragment::default()), |acc, x| {
+        let result = fns.iter().fold(Ok(ClassFragment::default()), |acc, &(section, x)| {
             match acc {
                 Ok(acc_fragment) => match x(&mut reader, &acc_fragment) {
                     Ok(cur_fragment) => Ok(acc_fragment.merge(cur_fragment)),
-                    err@_ => err
+                    Err(err) => Err(err.within(section))
                 },
                 err@_ => err
             }
@@ -39,311 +42,252 @@ impl ClassReader {
         }
     }
 
-    fn read_magic_bytes(reader: &mut BlockReader, _: &ClassFragment) -> Result<ClassFragment, Error> {
+    fn read_magic_bytes(reader: &mut BlockReader, _: &ClassFragment) -> Result<ClassFragment, ClassReadError> {
         match reader.read_u32() {
             Ok(0xCAFEBABE) => Ok(ClassFragment::default()),
-            _ => Err(Error::new(ErrorKind::InvalidData, "Invalid magic bytes"))
+            Ok(magic) => Err(ClassReadError::new(ReadErrorKind::InvalidMagic, 0, "0xCAFEBABE", format!("{:#010X}", magic))),
+            Err(err) => Err(err)
         }
     }
 
-    fn read_classfile_version(reader: &mut BlockReader, _: &ClassFragment) -> Result<ClassFragment, Error> {
-        match (reader.read_u16(), reader.read_u16()) {
-            (Ok(minor_version), Ok(major_version)) => {
-                Ok(ClassFragment {
-                    version: Some(ClassfileVersion::new(major_version, minor_version)),
-                    ..Default::default()
-                })
-            },
-            _ => Err(Error::new(ErrorKind::UnexpectedEof, "Could not read classfile version number"))
-        }
+    fn read_classfile_version(reader: &mut BlockReader, _: &ClassFragment) -> Result<ClassFragment, ClassReadError> {
+        let minor_version = reader.read_u16()?;
+        let major_version = reader.read_u16()?;
+
+        Ok(ClassFragment {
+            version: Some(ClassfileVersion::new(major_version, minor_version)),
+            ..Default::default()
+        })
     }
 
-    fn read_constant_pool(reader: &mut BlockReader, _: &ClassFragment) -> Result<ClassFragment, Error> {
-        match reader.read_u16() {
-            Ok(cp_len) => {
-                let mut constants: Vec<Constant> = vec![ Constant::Placeholder ];
-
-                for _ in 1..cp_len {
-                    if constants.len() < cp_len as usize {
-                        match ClassReader::read_constant(reader) {
-                            Ok(constant) => {
-                                let constant_size = constant.cp_size();
-
-                                constants.push(constant);
-
-                                for _ in 1..constant_size {
-                                    constants.push(Constant::Placeholder);
-                                }
-                            },
-                            Err(err) => return Err(err)
-                        }
-                    }
-                }
+    fn read_constant_pool(reader: &mut BlockReader, _: &ClassFragment) -> Result<ClassFragment, ClassReadError> {
+        let cp_len = reader.read_u16()? as usize;
+        let mut constants: Vec<Constant> = vec![ Constant::Placeholder ];
 
-                Ok(ClassFragment {
-                    constant_pool: Some(ConstantPool::new(constants)),
-                    ..Default::default()
-                })
-            },
-            Err(err) => Err(err)
+        while constants.len() < cp_len {
+            let idx = constants.len();
+            let offset = reader.offset();
+            let constant = ClassReader::read_constant(reader).map_err(|err| err.within(format!("[{}]", idx)).at_index(idx))?;
+            let constant_size = constant.cp_size();
+
+            // Long and Double constants take up two slots, which must both fit in the pool
+            if idx + constant_size > cp_len {
+                return Err(ClassReadError::new(ReadErrorKind::InvalidConstant, offset, format!("at most {} constant pool slots", cp_len), format!("{} slots", idx + constant_size))
+                    .within(format!("[{}]", idx)).at_index(idx));
+            }
+
+            constants.push(constant);
+
+            for _ in 1..constant_size {
+                constants.push(Constant::Placeholder);
+            }
         }
+
+        Ok(ClassFragment {
+            constant_pool: Some(ConstantPool::new(constants)),
+            ..Default::default()
+        })
     }
 
-    fn read_constant(reader: &mut BlockReader) -> Result<Constant, Error> {
-        let tag = reader.read_u8();
+    fn read_constant(reader: &mut BlockReader) -> Result<Constant, ClassReadError> {
+        let offset = reader.offset();
 
-        match tag {
-            Ok(1) => match reader.read_u16() {
-                Ok(str_len) => match reader.read_n(str_len as usize) {
-                    Ok(bytes) => {
-                        Ok(Constant::Utf8(bytes))
-                    },
-                    Err(err) => Err(err)
-                },
-                Err(err) => Err(err)
+        match reader.read_u8()? {
+            1 => {
+                let str_len = reader.read_u16()?;
+                reader.read_n(str_len as usize).map(|bytes| Constant::Utf8(bytes))
             },
-            Ok(3) => reader.read_u32().map(|value| Constant::Integer(value)),
-            Ok(4) => reader.read_u32().map(|value| Constant::Float(value)),
-            Ok(5) => reader.read_u64().map(|value| Constant::Long(value)),
-            Ok(6) => reader.read_u64().map(|value| Constant::Double(value)),
-            Ok(7) => reader.read_u16().map(|idx| Constant::Class(ConstantPoolIndex::new(idx as usize))),
-            Ok(8) => reader.read_u16().map(|idx| Constant::String(ConstantPoolIndex::new(idx as usize))),
-            Ok(9) => ClassReader::require_n(reader, 4, |mut r| Constant::FieldRef {
-                class_index: ConstantPoolIndex::new(r.get_u16() as usize),
-                name_and_type_index: ConstantPoolIndex::new(r.get_u16() as usize)
+            3 => reader.read_u32().map(|value| Constant::Integer(value)),
+            4 => reader.read_u32().map(|value| Constant::Float(value)),
+            5 => reader.read_u64().map(|value| Constant::Long(value)),
+            6 => reader.read_u64().map(|value| Constant::Double(value)),
+            7 => reader.read_u16().map(|idx| Constant::Class(ConstantPoolIndex::new(idx as usize))),
+            8 => reader.read_u16().map(|idx| Constant::String(ConstantPoolIndex::new(idx as usize))),
+            9 => Ok(Constant::FieldRef {
+                class_index: ClassReader::read_constant_pool_index(reader)?,
+                name_and_type_index: ClassReader::read_constant_pool_index(reader)?
             }),
-            Ok(10) => ClassReader::require_n(reader, 4, |mut r| Constant::MethodRef {
-                class_index: ConstantPoolIndex::new(r.get_u16() as usize),
-                name_and_type_index: ConstantPoolIndex::new(r.get_u16() as usize)
+            10 => Ok(Constant::MethodRef {
+                class_index: ClassReader::read_constant_pool_index(reader)?,
+                name_and_type_index: ClassReader::read_constant_pool_index(reader)?
             }),
-            Ok(11) => ClassReader::require_n(reader, 4, |mut r| Constant::InterfaceMethodRef {
-                class_index: ConstantPoolIndex::new(r.get_u16() as usize),
-                name_and_type_index: ConstantPoolIndex::new(r.get_u16() as usize)
+            11 => Ok(Constant::InterfaceMethodRef {
+                class_index: ClassReader::read_constant_pool_index(reader)?,
+                name_and_type_index: ClassReader::read_constant_pool_index(reader)?
             }),
-            Ok(12) => ClassReader::require_n(reader, 4, |mut r| Constant::NameAndType {
-                    name_index: ConstantPoolIndex::new(r.get_u16() as usize),
-                    descriptor_index: ConstantPoolIndex::new(r.get_u16() as usize)
+            12 => Ok(Constant::NameAndType {
+                name_index: ClassReader::read_constant_pool_index(reader)?,
+                descriptor_index: ClassReader::read_constant_pool_index(reader)?
             }),
-            Ok(15) => ClassReader::require_n(reader, 3, |mut r| Constant::MethodHandle {
-                reference_kind: ReferenceKind::from_u8(r.get_u8()),
-                reference_index: ConstantPoolIndex::new(r.get_u16() as usize)
+            15 => {
+                let kind_offset = reader.offset();
+
+                match ReferenceKind::from_u8(reader.read_u8()?) {
+                    ReferenceKind::Unknown => Err(ClassReadError::new(ReadErrorKind::InvalidValue, kind_offset, "reference kind 1-9", "unknown reference kind")),
+                    kind@_ => Ok(Constant::MethodHandle {
+                        reference_kind: kind,
+                        reference_index: ClassReader::read_constant_pool_index(reader)?
+                    })
+                }
+            },
+            16 => reader.read_u16().map(|idx| Constant::MethodType(ConstantPoolIndex::new(idx as usize))),
+            17 => Ok(Constant::Dynamic {
+                bootstrap_method_attr_index: ClassReader::read_constant_pool_index(reader)?,
+                name_and_type_index: ClassReader::read_constant_pool_index(reader)?
             }),
-            Ok(16) => reader.read_u16().map(|idx| Constant::MethodType(ConstantPoolIndex::new(idx as usize))),
-            Ok(18) => ClassReader::require_n(reader, 4, |mut r| Constant::InvokeDynamic {
-                bootstrap_method_attr_index: ConstantPoolIndex::new(r.get_u16() as usize),
-                name_and_type_index: ConstantPoolIndex::new(r.get_u16() as usize)
+            18 => Ok(Constant::InvokeDynamic {
+                bootstrap_method_attr_index: ClassReader::read_constant_pool_index(reader)?,
+                name_and_type_index: ClassReader::read_constant_pool_index(reader)?
             }),
-            Ok(tag) => Ok(Constant::Unknown(tag)),
-            Err(err) => Err(err)
+            19 => reader.read_u16().map(|idx| Constant::Module(ConstantPoolIndex::new(idx as usize))),
+            20 => reader.read_u16().map(|idx| Constant::Package(ConstantPoolIndex::new(idx as usize))),
+            // The payload length of an unknown constant can't be determined, so there's no way to
+            // continue reading the constant pool without corrupting every subsequent entry
+            tag@_ => Err(ClassReadError::new(ReadErrorKind::InvalidConstant, offset, "constant tag", format!("{}", tag)))
         }
     }
 
-    fn read_access_flags(reader: &mut BlockReader, _: &ClassFragment) -> Result<ClassFragment, Error> {
-        match reader.read_u16() {
-            Ok(val) => Ok(ClassFragment {
-                access_flags: Some(AccessFlags::of(val)),
-                ..Default::default()
-            }),
-            Err(err) => Err(err)
-        }
+    fn read_access_flags(reader: &mut BlockReader, _: &ClassFragment) -> Result<ClassFragment, ClassReadError> {
+        reader.read_u16().map(|val| ClassFragment {
+            access_flags: Some(AccessFlags::of(val)),
+            ..Default::default()
+        })
     }
 
-    fn read_this_class(reader: &mut BlockReader, _: &ClassFragment) -> Result<ClassFragment, Error> {
-        match ClassReader::read_constant_pool_index(reader) {
-            Ok(idx) => Ok(ClassFragment {
-                this_class: Some(idx),
-                ..Default::default()
-            }),
-            Err(err) => Err(err)
-        }
+    fn read_this_class(reader: &mut BlockReader, _: &ClassFragment) -> Result<ClassFragment, ClassReadError> {
+        ClassReader::read_constant_pool_index(reader).map(|idx| ClassFragment {
+            this_class: Some(idx),
+            ..Default::default()
+        })
     }
 
-    fn read_super_class(reader: &mut BlockReader, _: &ClassFragment) -> Result<ClassFragment, Error> {
-        match ClassReader::read_constant_pool_index(reader) {
-            Ok(idx) => Ok(ClassFragment {
-                super_class: Some(idx),
-                ..Default::default()
-            }),
-            Err(err) => Err(err)
-        }
+    fn read_super_class(reader: &mut BlockReader, _: &ClassFragment) -> Result<ClassFragment, ClassReadError> {
+        ClassReader::read_constant_pool_index(reader).map(|idx| ClassFragment {
+            super_class: Some(idx),
+            ..Default::default()
+        })
     }
 
-    fn read_interfaces(reader: &mut BlockReader, _: &ClassFragment) -> Result<ClassFragment, Error> {
-        match reader.read_u16() {
-            Ok(ifs_len) => {
-                (0..ifs_len).fold(Ok(vec![]), |acc, _| {
-                    match acc {
-                        Ok(mut ifs) => match ClassReader::read_constant_pool_index(reader) {
-                            Ok(interface) => {
-                                ifs.push(interface);
-                                Ok(ifs)
-                            },
-                            Err(err) => Err(err)
-                        },
-                        err@_ => err
-                    }
-                })
-            },
-            Err(err) => Err(err)
-        }.map(|ifs| ClassFragment {
+    fn read_interfaces(reader: &mut BlockReader, _: &ClassFragment) -> Result<ClassFragment, ClassReadError> {
+        ClassReader::read_index_table(reader).map(|ifs| ClassFragment {
             interfaces: Some(ifs),
             ..Default::default()
         })
     }
 
-    fn read_fields(reader: &mut BlockReader, cf: &ClassFragment) -> Result<ClassFragment, Error> {
-        match reader.read_u16() {
-            Ok(fields_len) => {
-                (0..fields_len).fold(Ok(vec![]), |acc, _| {
-                    match acc {
-                        Ok(mut fields) => match ClassReader::read_field(reader, cf) {
-                            Ok(field) => {
-                                fields.push(field);
-                                Ok(fields)
-                            },
-                            Err(err) => Err(err)
-                        },
-                        err@_ => err
-                    }
-                })
-            },
-            Err(err) => Err(err)
-        }.map(|fields| ClassFragment {
+    fn read_fields(reader: &mut BlockReader, cf: &ClassFragment) -> Result<ClassFragment, ClassReadError> {
+        let fields_len = reader.read_u16()?;
+
+        ClassReader::read_table(reader, fields_len as usize, |r| ClassReader::read_field(r, cf)).map(|fields| ClassFragment {
             fields: Some(fields),
             ..Default::default()
         })
     }
 
-    fn read_field(reader: &mut BlockReader, cf: &ClassFragment) -> Result<Field, Error> {
-        match ClassReader::require_n(reader, 6, |mut r| { (r.get_u16(), r.get_u16(), r.get_u16()) }) {
-            Ok((flags, n_idx, d_idx)) => match ClassReader::read_attributes(reader, cf) {
-                Ok(attributes) => Ok(Field {
-                    access_flags: AccessFlags::of(flags),
-                    name_index: ConstantPoolIndex::new(n_idx as usize),
-                    descriptor_index: ConstantPoolIndex::new(d_idx as usize),
-                    attributes: attributes
-                }),
-                Err(err) => Err(err)
-            },
-            Err(err) => Err(err)
-        }
+    fn read_field(reader: &mut BlockReader, cf: &ClassFragment) -> Result<Field, ClassReadError> {
+        Ok(Field {
+            access_flags: AccessFlags::of(reader.read_u16()?),
+            name_index: ClassReader::read_constant_pool_index(reader)?,
+            descriptor_index: ClassReader::read_constant_pool_index(reader)?,
+            attributes: ClassReader::read_attributes(reader, cf)?
+        })
     }
 
-    fn read_methods(reader: &mut BlockReader, cf: &ClassFragment) -> Result<ClassFragment, Error> {
-        match reader.read_u16() {
-            Ok(methods_len) => {
-                (0..methods_len).fold(Ok(vec![]), |acc, _| {
-                    match acc {
-                        Ok(mut methods) => match ClassReader::read_method(reader, cf) {
-                            Ok(method) => {
-                                methods.push(method);
-                                Ok(methods)
-                            },
-                            Err(err) => Err(err)
-                        },
-                        err@_ => err
-                    }
-                })
-            },
-            Err(err) => Err(err)
-        }.map(|methods| ClassFragment {
+    fn read_methods(reader: &mut BlockReader, cf: &ClassFragment) -> Result<ClassFragment, ClassReadError> {
+        let methods_len = reader.read_u16()?;
+
+        ClassReader::read_table(reader, methods_len as usize, |r| ClassReader::read_method(r, cf)).map(|methods| ClassFragment {
             methods: Some(methods),
             ..Default::default()
         })
     }
 
-    fn read_method(reader: &mut BlockReader, cf: &ClassFragment) -> Result<Method, Error> {
-        match ClassReader::require_n(reader, 6, |mut r| { (r.get_u16(), r.get_u16(), r.get_u16()) }) {
-            Ok((flags, n_idx, d_idx)) => match ClassReader::read_attributes(reader, cf) {
-                Ok(attributes) => Ok(Method {
-                    access_flags: AccessFlags::of(flags),
-                    name_index: ConstantPoolIndex::new(n_idx as usize),
-                    descriptor_index: ConstantPoolIndex::new(d_idx as usize),
-                    attributes: attributes
-                }),
-                Err(err) => Err(err)
-            },
-            Err(err) => Err(err)
-        }
+    fn read_method(reader: &mut BlockReader, cf: &ClassFragment) -> Result<Method, ClassReadError> {
+        Ok(Method {
+            access_flags: AccessFlags::of(reader.read_u16()?),
+            name_index: ClassReader::read_constant_pool_index(reader)?,
+            descriptor_index: ClassReader::read_constant_pool_index(reader)?,
+            attributes: ClassReader::read_attributes(reader, cf)?
+        })
     }
 
-    fn read_class_attributes(reader: &mut BlockReader, cf: &ClassFragment) -> Result<ClassFragment, Error> {
-        match ClassReader::read_attributes(reader, cf) {
-            Ok(attributes) => Ok(ClassFragment {
-                attributes: Some(attributes),
-                ..Default::default()
-            }),
-            Err(err) => Err(err)
-        }
+    fn read_class_attributes(reader: &mut BlockReader, cf: &ClassFragment) -> Result<ClassFragment, ClassReadError> {
+        ClassReader::read_attributes(reader, cf).map(|attributes| ClassFragment {
+            attributes: Some(attributes),
+            ..Default::default()
+        })
     }
 
-    fn read_attributes(reader: &mut BlockReader, cf: &ClassFragment) -> Result<Vec<Attribute>, Error> {
-        match reader.read_u16() {
-            Ok(attr_len) => (0..attr_len).fold(Ok(vec![]), |acc, _| {
-                match acc {
-                    Ok(mut attributes) => match ClassReader::read_attribute(reader, cf) {
-                        Ok(attribute) => {
-                            attributes.push(attribute);
-                            Ok(attributes)
-                        },
-                        Err(err) => Err(err)
-                    },
-                    err@_ => err
-                }
-            }),
+    fn read_end_of_class(reader: &mut BlockReader, _: &ClassFragment) -> Result<ClassFragment, ClassReadError> {
+        let offset = reader.offset();
+
+        match reader.read_bytes() {
+            Ok(ref bytes) if bytes.is_empty() => Ok(ClassFragment::default()),
+            Ok(bytes) => Err(ClassReadError::new(ReadErrorKind::LengthMismatch, offset, "end of class file", format!("{} trailing bytes", bytes.len()))),
             Err(err) => Err(err)
         }
     }
 
-    fn read_attribute(reader: &mut BlockReader, cf: &ClassFragment) -> Result<Attribute, Error> {
-        match reader.read_u16() {
-            Ok(n_idx) => match reader.read_u32() {
-                Ok(a_len) => match reader.read_n(a_len as usize) {
-                    Ok(mut bytes) => Ok(ClassReader::parse_attribute(n_idx, BlockReader::new(&mut Cursor::new(&mut bytes)), cf)),
-                    Err(err) => Err(err)
-                },
-                Err(err) => Err(err)
+    fn read_attributes(reader: &mut BlockReader, cf: &ClassFragment) -> Result<Vec<Attribute>, ClassReadError> {
+        let attr_len = reader.read_u16()?;
+
+        (0..attr_len).map(|_| ClassReader::read_attribute(reader, cf)).collect()
+    }
+
+    fn read_attribute(reader: &mut BlockReader, cf: &ClassFragment) -> Result<Attribute, ClassReadError> {
+        let name_offset = reader.offset();
+        let n_idx = reader.read_u16()?;
+        let a_len = reader.read_u32()? as usize;
+        let origin = reader.offset();
+        let mut bytes = reader.read_n(a_len)?;
+
+        match cf.constant_pool.as_ref().and_then(|cp| cp.get_utf8_string(n_idx)) {
+            Some(name) => {
+                let mut cursor = Cursor::new(&mut bytes);
+                let mut attr_reader = BlockReader::with_origin(&mut cursor, origin);
+
+                ClassReader::parse_attribute(name.as_str(), n_idx, &mut attr_reader, cf)
+                    .and_then(|attribute| match attr_reader.position() {
+                        read_len if read_len == a_len => Ok(attribute),
+                        read_len@_ => Err(ClassReadError::new(ReadErrorKind::LengthMismatch, origin + read_len, format!("{} bytes", a_len), format!("{} bytes", read_len)))
+                    })
+                    .map_err(|err| err.within(name))
             },
-            Err(err) => Err(err)
+            None => Err(ClassReadError::new(ReadErrorKind::InvalidIndex, name_offset, "Utf8 attribute name", ClassReader::describe_constant(cf.constant_pool.as_ref().and_then(|cp| cp.resolve_index(&ConstantPoolIndex::new(n_idx as usize))))).at_index(n_idx as usize))
         }
     }
 
-    fn parse_code(len: usize, reader: &mut BlockReader) -> Vec<Instruction> {
-        /*
-        let read_bytes: Cell<usize> = Cell::new(0);
+    fn parse_code(len: usize, reader: &mut BlockReader) -> Result<Vec<Instruction>, ClassReadError> {
+        let mut instructions = vec![];
 
-        (0..len).take_while(|_| read_bytes.get() < len).map(|_| {
-            let instruction = ClassReader::parse_instruction(reader, read_bytes.get());
+        while reader.position() < len {
+            let current_offset = reader.position();
 
-            read_bytes.set(read_bytes.get() + instruction.len());
-            instruction
-        }).collect()*/
-        let read_bytes: Cell<usize> = Cell::new(0);
-
-        (0..len).take_while(|_| read_bytes.get() < len).map(|_| {
-            let instruction = ClassReader::parse_instruction(reader, read_bytes.get());
+            match ClassReader::parse_instruction(reader, current_offset) {
+                Ok(instruction) => instructions.push(instruction),
+                Err(err) => return Err(err.within(format!("[{}]", current_offset)))
+            }
+        }
 
-            read_bytes.set(reader.position());
-            instruction
-        }).collect()
+        Ok(instructions)
     }
 
-    fn parse_instruction(reader: &mut BlockReader, current_offset: usize) -> Instruction {
-        let opcode = reader.get_u8();
+    fn parse_instruction(reader: &mut BlockReader, current_offset: usize) -> Result<Instruction, ClassReadError> {
+        let opcode = reader.read_u8()?;
 
         let instruction = match opcode {
             0x32 => Instruction::AALOAD,
             0x53 => Instruction::AASTORE,
             0x01 => Instruction::ACONST_NULL,
-            0x19 => Instruction::ALOAD(reader.get_u8()),
+            0x19 => Instruction::ALOAD(reader.read_u8()?),
             0x2a => Instruction::ALOAD_0,
             0x2b => Instruction::ALOAD_1,
             0x2c => Instruction::ALOAD_2,
             0x2d => Instruction::ALOAD_3,
-            0xbd => Instruction::ANEWARRAY(reader.get_u16()),
+            0xbd => Instruction::ANEWARRAY(reader.read_u16()?),
             0xb0 => Instruction::ARETURN,
             0xbe => Instruction::ARRAYLENGTH,
-            0x3a => Instruction::ASTORE(reader.get_u8()),
+            0x3a => Instruction::ASTORE(reader.read_u8()?),
             0x4b => Instruction::ASTORE_0,
             0x4c => Instruction::ASTORE_1,
             0x4d => Instruction::ASTORE_2,
@@ -351,10 +295,10 @@ impl ClassReader {
             0xbf => Instruction::ATHROW,
             0x33 => Instruction::BALOAD,
             0x54 => Instruction::BASTORE,
-            0x10 => Instruction::BIPUSH(reader.get_u8()),
+            0x10 => Instruction::BIPUSH(reader.read_u8()?),
             0x34 => Instruction::CALOAD,
             0x55 => Instruction::CASTORE,
-            0xc0 => Instruction::CHECKCAST(reader.get_u16()),
+            0xc0 => Instruction::CHECKCAST(reader.read_u16()?),
             0x90 => Instruction::D2F,
             0x8e => Instruction::D2I,
             0x8f => Instruction::D2L,
@@ -366,7 +310,7 @@ impl ClassReader {
             0x0e => Instruction::DCONST_0,
             0x0f => Instruction::DCONST_1,
             0x6f => Instruction::DDIV,
-            0x18 => Instruction::DLOAD(reader.get_u8()),
+            0x18 => Instruction::DLOAD(reader.read_u8()?),
             0x26 => Instruction::DLOAD_0,
             0x27 => Instruction::DLOAD_1,
             0x28 => Instruction::DLOAD_2,
@@ -375,7 +319,7 @@ impl ClassReader {
             0x77 => Instruction::DNEG,
             0x73 => Instruction::DREM,
             0xaf => Instruction::DRETURN,
-            0x39 => Instruction::DSTORE(reader.get_u8()),
+            0x39 => Instruction::DSTORE(reader.read_u8()?),
             0x47 => Instruction::DSTORE_0,
             0x48 => Instruction::DSTORE_1,
             0x49 => Instruction::DSTORE_2,
@@ -399,7 +343,7 @@ impl ClassReader {
             0x0c => Instruction::FCONST_1,
             0x0d => Instruction::FCONST_2,
             0x6e => Instruction::FDIV,
-            0x17 => Instruction::FLOAD(reader.get_u8()),
+            0x17 => Instruction::FLOAD(reader.read_u8()?),
             0x22 => Instruction::FLOAD_0,
             0x23 => Instruction::FLOAD_1,
             0x24 => Instruction::FLOAD_2,
@@ -408,16 +352,16 @@ impl ClassReader {
             0x76 => Instruction::FNEG,
             0x72 => Instruction::FREM,
             0xae => Instruction::FRETURN,
-            0x38 => Instruction::FSTORE(reader.get_u8()),
+            0x38 => Instruction::FSTORE(reader.read_u8()?),
             0x43 => Instruction::FSTORE_0,
             0x44 => Instruction::FSTORE_1,
             0x45 => Instruction::FSTORE_2,
             0x46 => Instruction::FSTORE_3,
             0x66 => Instruction::FSUB,
-            0xb4 => Instruction::GETFIELD(reader.get_u16()),
-            0xb2 => Instruction::GETSTATIC(reader.get_u16()),
-            0xa7 => Instruction::GOTO(reader.get_u16() as i16),
-            0xc8 => Instruction::GOTO_W(reader.get_u32() as i32),
+            0xb4 => Instruction::GETFIELD(reader.read_u16()?),
+            0xb2 => Instruction::GETSTATIC(reader.read_u16()?),
+            0xa7 => Instruction::GOTO(reader.read_u16()? as i16),
+            0xc8 => Instruction::GOTO_W(reader.read_u32()? as i32),
             0x91 => Instruction::I2B,
             0x92 => Instruction::I2C,
             0x87 => Instruction::I2D,
@@ -436,42 +380,42 @@ impl ClassReader {
             0x07 => Instruction::ICONST_4,
             0x08 => Instruction::ICONST_5,
             0x6c => Instruction::IDIV,
-            0xa5 => Instruction::IF_ACMPEQ(reader.get_u16() as i16),
-            0xa6 => Instruction::IF_ACMPNE(reader.get_u16() as i16),
-            0x9f => Instruction::IF_ICMPEQ(reader.get_u16() as i16),
-            0xa0 => Instruction::IF_ICMPNE(reader.get_u16() as i16),
-            0xa1 => Instruction::IF_ICMPLT(reader.get_u16() as i16),
-            0xa2 => Instruction::IF_ICMPGE(reader.get_u16() as i16),
-            0xa3 => Instruction::IF_ICMPGT(reader.get_u16() as i16),
-            0xa4 => Instruction::IF_ICMPLE(reader.get_u16() as i16),
-            0x99 => Instruction::IFEQ(reader.get_u16() as i16),
-            0x9a => Instruction::IFNE(reader.get_u16() as i16),
-            0x9b => Instruction::IFLT(reader.get_u16() as i16),
-            0x9c => Instruction::IFGE(reader.get_u16() as i16),
-            0x9d => Instruction::IFGT(reader.get_u16() as i16),
-            0x9e => Instruction::IFLE(reader.get_u16() as i16),
-            0xc7 => Instruction::IFNONNULL(reader.get_u16() as i16),
-            0xc6 => Instruction::IFNULL(reader.get_u16() as i16),
-            0x84 => Instruction::IINC(reader.get_u8(), reader.get_u8() as i8),
-            0x15 => Instruction::ILOAD(reader.get_u8()),
+            0xa5 => Instruction::IF_ACMPEQ(reader.read_u16()? as i16),
+            0xa6 => Instruction::IF_ACMPNE(reader.read_u16()? as i16),
+            0x9f => Instruction::IF_ICMPEQ(reader.read_u16()? as i16),
+            0xa0 => Instruction::IF_ICMPNE(reader.read_u16()? as i16),
+            0xa1 => Instruction::IF_ICMPLT(reader.read_u16()? as i16),
+            0xa2 => Instruction::IF_ICMPGE(reader.read_u16()? as i16),
+            0xa3 => Instruction::IF_ICMPGT(reader.read_u16()? as i16),
+            0xa4 => Instruction::IF_ICMPLE(reader.read_u16()? as i16),
+            0x99 => Instruction::IFEQ(reader.read_u16()? as i16),
+            0x9a => Instruction::IFNE(reader.read_u16()? as i16),
+            0x9b => Instruction::IFLT(reader.read_u16()? as i16),
+            0x9c => Instruction::IFGE(reader.read_u16()? as i16),
+            0x9d => Instruction::IFGT(reader.read_u16()? as i16),
+            0x9e => Instruction::IFLE(reader.read_u16()? as i16),
+            0xc7 => Instruction::IFNONNULL(reader.read_u16()? as i16),
+            0xc6 => Instruction::IFNULL(reader.read_u16()? as i16),
+            0x84 => Instruction::IINC(reader.read_u8()?, reader.read_u8()? as i8),
+            0x15 => Instruction::ILOAD(reader.read_u8()?),
             0x1a => Instruction::ILOAD_0,
             0x1b => Instruction::ILOAD_1,
             0x1c => Instruction::ILOAD_2,
             0x1d => Instruction::ILOAD_3,
             0x68 => Instruction::IMUL,
             0x74 => Instruction::INEG,
-            0xc1 => Instruction::INSTANCEOF(reader.get_u16()),
-            0xba => (Instruction::INVOKEDYNAMIC(reader.get_u16()), reader.get_u16()).0,
-            0xb9 => (Instruction::INVOKEINTERFACE(reader.get_u16(), reader.get_u8()), reader.get_u8()).0,
-            0xb7 => Instruction::INVOKESPECIAL(reader.get_u16()),
-            0xb8 => Instruction::INVOKESTATIC(reader.get_u16()),
-            0xb6 => Instruction::INVOKEVIRTUAL(reader.get_u16()),
+            0xc1 => Instruction::INSTANCEOF(reader.read_u16()?),
+            0xba => (Instruction::INVOKEDYNAMIC(reader.read_u16()?), reader.read_u16()?).0,
+            0xb9 => (Instruction::INVOKEINTERFACE(reader.read_u16()?, reader.read_u8()?), reader.read_u8()?).0,
+            0xb7 => Instruction::INVOKESPECIAL(reader.read_u16()?),
+            0xb8 => Instruction::INVOKESTATIC(reader.read_u16()?),
+            0xb6 => Instruction::INVOKEVIRTUAL(reader.read_u16()?),
             0x80 => Instruction::IOR,
             0x70 => Instruction::IREM,
             0xac => Instruction::IRETURN,
             0x78 => Instruction::ISHL,
             0x7a => Instruction::ISHR,
-            0x36 => Instruction::ISTORE(reader.get_u8()),
+            0x36 => Instruction::ISTORE(reader.read_u8()?),
             0x3b => Instruction::ISTORE_0,
             0x3c => Instruction::ISTORE_1,
             0x3d => Instruction::ISTORE_2,
@@ -479,8 +423,8 @@ impl ClassReader {
             0x64 => Instruction::ISUB,
             0x7c => Instruction::IUSHR,
             0x82 => Instruction::IXOR,
-            0xa8 => Instruction::JSR(reader.get_u16() as i16),
-            0xc9 => Instruction::JSR_W(reader.get_u32() as i32),
+            0xa8 => Instruction::JSR(reader.read_u16()? as i16),
+            0xc9 => Instruction::JSR_W(reader.read_u32()? as i32),
             0x8a => Instruction::L2D,
             0x89 => Instruction::L2F,
             0x88 => Instruction::L2I,
@@ -491,11 +435,11 @@ impl ClassReader {
             0x94 => Instruction::LCMP,
             0x09 => Instruction::LCONST_0,
             0x0a => Instruction::LCONST_1,
-            0x12 => Instruction::LDC(reader.get_u8()),
-            0x13 => Instruction::LDC_W(reader.get_u16()),
-            0x14 => Instruction::LDC2_W(reader.get_u16()),
+            0x12 => Instruction::LDC(reader.read_u8()?),
+            0x13 => Instruction::LDC_W(reader.read_u16()?),
+            0x14 => Instruction::LDC2_W(reader.read_u16()?),
             0x6d => Instruction::LDIV,
-            0x16 => Instruction::LLOAD(reader.get_u8()),
+            0x16 => Instruction::LLOAD(reader.read_u8()?),
             0x1e => Instruction::LLOAD_0,
             0x1f => Instruction::LLOAD_1,
             0x20 => Instruction::LLOAD_2,
@@ -504,20 +448,18 @@ impl ClassReader {
             0x75 => Instruction::LNEG,
             0xab => {
                 let padding = (4 - ((current_offset + 1) % 4)) % 4;
-                let _ = reader.get_n(padding);
-                let default =  reader.get_u32() as i32;
-                let n = reader.get_u32();
+                let _ = reader.read_n(padding)?;
+                let default =  reader.read_u32()? as i32;
+                let n = reader.read_u32()?;
 
-                Instruction::LOOKUPSWITCH(default, {
-                    (0..n).map(|_| (reader.get_u32() as i32, reader.get_u32() as i32)).collect()
-                })
+                Instruction::LOOKUPSWITCH(default, ClassReader::read_table(reader, n as usize, |r| Ok((r.read_u32()? as i32, r.read_u32()? as i32)))?)
             },
             0x81 => Instruction::LOR,
             0x71 => Instruction::LREM,
             0xad => Instruction::LRETURN,
             0x79 => Instruction::LSHL,
             0x7b => Instruction::LSHR,
-            0x37 => Instruction::LSTORE(reader.get_u8()),
+            0x37 => Instruction::LSTORE(reader.read_u8()?),
             0x3f => Instruction::LSTORE_0,
             0x40 => Instruction::LSTORE_1,
             0x41 => Instruction::LSTORE_2,
@@ -527,33 +469,37 @@ impl ClassReader {
             0x83 => Instruction::LXOR,
             0xc2 => Instruction::MONITORENTER,
             0xc3 => Instruction::MONITOREXIT,
-            0xc5 => Instruction::MULTIANEWARRAY(reader.get_u16(), reader.get_u8()),
-            0xbb => Instruction::NEW(reader.get_u16()),
-            0xbc => Instruction::NEWARRAY(reader.get_u8()),
+            0xc5 => Instruction::MULTIANEWARRAY(reader.read_u16()?, reader.read_u8()?),
+            0xbb => Instruction::NEW(reader.read_u16()?),
+            0xbc => Instruction::NEWARRAY(reader.read_u8()?),
             0x00 => Instruction::NOP,
             0x57 => Instruction::POP,
             0x58 => Instruction::POP2,
-            0xb5 => Instruction::PUTFIELD(reader.get_u16()),
-            0xb3 => Instruction::PUTSTATIC(reader.get_u16()),
-            0xa9 => Instruction::RET(reader.get_u8()),
+            0xb5 => Instruction::PUTFIELD(reader.read_u16()?),
+            0xb3 => Instruction::PUTSTATIC(reader.read_u16()?),
+            0xa9 => Instruction::RET(reader.read_u8()?),
             0xb1 => Instruction::RETURN,
             0x35 => Instruction::SALOAD,
             0x56 => Instruction::SASTORE,
-            0x11 => Instruction::SIPUSH(reader.get_u16()),
+            0x11 => Instruction::SIPUSH(reader.read_u16()?),
             0x5f => Instruction::SWAP,
             0xaa => {
                 let padding = (4 - ((current_offset + 1) % 4)) % 4;
-                let _ = reader.get_n(padding);
+                let _ = reader.read_n(padding)?;
+
+                let default = reader.read_u32()? as i32;
+                let low = reader.read_u32()? as i32;
+                let high = reader.read_u32()? as i32;
 
-                let default = reader.get_u32() as i32;
-                let low = reader.get_u32() as i32;
-                let high = reader.get_u32() as i32;
+                if high < low {
+                    return Err(ClassReadError::new(ReadErrorKind::InvalidValue, reader.offset(), format!("tableswitch high >= {}", low), format!("{}", high)));
+                }
 
-                Instruction::TABLESWITCH(default, low, high, (0..high - low + 1).map(|_| reader.get_u32() as i32).collect())
+                Instruction::TABLESWITCH(default, low, high, ClassReader::read_table(reader, (high as i64 - low as i64 + 1) as usize, |r| r.read_u32().map(|v| v as i32))?)
             },
             0xc4 => {
-                let opcode = reader.get_u8();
-                let index = reader.get_u16();
+                let opcode = reader.read_u8()?;
+                let index = reader.read_u16()?;
 
                 match opcode {
                     0x15 => Instruction::ILOAD_W(index),
@@ -568,295 +514,348 @@ impl ClassReader {
                     0x39 => Instruction::DSTORE_W(index),
                     0xa9 => Instruction::RET_W(index),
                     0x84 => {
-                        let constbyte = reader.get_u16();
+                        let constbyte = reader.read_u16()?;
                         Instruction::IINC_W(index, constbyte as i16)
                     },
-                    // This should not be happening
-                    _ => Instruction::WTF(opcode as u32)
+                    _ => return Err(ClassReadError::new(ReadErrorKind::InvalidOpcode, reader.offset() - 3, "opcode that can be widened", format!("{:#04x}", opcode)))
 
                 }
             },
-            _ => Instruction::WTF(opcode as u32)
+            _ => return Err(ClassReadError::new(ReadErrorKind::InvalidOpcode, reader.offset() - 1, "valid opcode", format!("{:#04x}", opcode)))
         };
 
-        instruction
+        Ok(instruction)
     }
 
-    fn parse_attribute(idx: u16, mut reader: BlockReader, cf: &ClassFragment) -> Attribute {
-        match cf.constant_pool {
-            Some(ref cp) => match cp.get_utf8_string(idx) {
-                Some(ref s) => match s.as_str() {
-                    "ConstantValue" => Some(Attribute::ConstantValue(ConstantPoolIndex::new(reader.get_u16() as usize))),
-                    "Code" => Some(Attribute::Code {
-                        max_stack: reader.get_u16(),
-                        max_locals: reader.get_u16(),
-                        code: {
-                            let n = reader.get_u32() as usize;
-                            ClassReader::parse_code(n, &mut BlockReader::new(&mut Cursor::new(reader.get_n(n as usize))))
-                        },
-                        exception_table: {
-                            let n = reader.get_u16();
-                            (0..n).map(|_| ExceptionHandler { start_pc: reader.get_u16(), end_pc: reader.get_u16(), handler_pc: reader.get_u16(), catch_type: ConstantPoolIndex::new(reader.get_u16() as usize) }).collect()
-                        },
-                        attributes: ClassReader::read_attributes(&mut reader, cf).unwrap_or(vec![])
-                        }),
-                    "StackMapTable" => Some(Attribute::StackMapTable({
-                        let n = reader.get_u16();
-                        (0..n).map(|_| {
-                            let frame_type = reader.get_u8();
-
-                            let read_verification_type = |r: &mut BlockReader| match r.get_u8() {
-                                0 => VerificationType::Top,
-                                1 => VerificationType::Integer,
-                                2 => VerificationType::Float,
-                                3 => VerificationType::Double,
-                                4 => VerificationType::Long,
-                                5 => VerificationType::Null,
-                                6 => VerificationType::UninitializedThis,
-                                7 => VerificationType::Object { cpool_index: ConstantPoolIndex::new(r.get_u16() as usize ) },
-                                8 => VerificationType::Uninitialized { offset: r.get_u16() },
-                                _ => VerificationType::Top
-                            };
-
-                            match frame_type {
-                                tag@0...63 => StackMapFrame::SameFrame { tag: tag },
-                                tag@64...127 => StackMapFrame::SameLocals1StackItemFrame { tag: tag, stack: read_verification_type(&mut reader) },
-                                247 => StackMapFrame::SameLocals1StackItemFrameExtended { offset_delta: reader.get_u16(), stack: read_verification_type(&mut reader) },
-                                tag@248...250 => StackMapFrame::ChopFrame { tag: tag, offset_delta: reader.get_u16() },
-                                251 => StackMapFrame::SameFrameExtended { offset_delta: reader.get_u16() },
-                                tag@252...254 => StackMapFrame::AppendFrame { tag: tag, offset_delta: reader.get_u16(), locals: (0..tag - 251).map(|_| read_verification_type(&mut reader)).collect() },
-                                255 => StackMapFrame::FullFrame { offset_delta: reader.get_u16(), locals: {
-                                    let n = reader.get_u16();
-                                    (0..n).map(|_| read_verification_type(&mut reader)).collect()
-                                }, stack: {
-                                    let n = reader.get_u16();
-                                    (0..n).map(|_| read_verification_type(&mut reader)).collect()
-                                }},
-                                tag@_ => StackMapFrame::FutureUse { tag: tag },
-                            }
-                        }).collect()
-                    })),
-                    "Exceptions" => Some(Attribute::Exceptions({
-                        let n = reader.get_u16();
-                        (0..n).map(|_| ConstantPoolIndex::new(reader.get_u16() as usize)).collect()
-                        })),
-                    "InnerClasses" => Some(Attribute::InnerClasses({
-                        let n = reader.get_u16();
-                        (0..n).map(|_| InnerClass {
-                            inner_class_info_index: ConstantPoolIndex::new(reader.get_u16() as usize),
-                            outer_class_info_index: ConstantPoolIndex::new(reader.get_u16() as usize),
-                            inner_name_index: ConstantPoolIndex::new(reader.get_u16() as usize),
-                            access_flags: AccessFlags::of(reader.get_u16())
-                            }).collect()
-                        })),
-                    "EnclosingMethod" => Some(Attribute::EnclosingMethod { class_index: ConstantPoolIndex::new(reader.get_u16() as usize), method_index: ConstantPoolIndex::new(reader.get_u16() as usize)}),
-                    "Synthetic" => Some(Attribute::Synthetic),
-                    "Signature" => Some(Attribute::Signature(ConstantPoolIndex::new(reader.get_u16() as usize))),
-                    "SourceFile" => Some(Attribute::SourceFile(ConstantPoolIndex::new(reader.get_u16() as usize))),
-                    "SourceDebugExtension" => Some(Attribute::SourceDebugExtension(reader.get_bytes())),
-                    "LineNumberTable" => Some(Attribute::LineNumberTable({
-                        let n = reader.get_u16();
-                        (0..n).map(|_| LineNumberTable {
-                            start_pc: reader.get_u16(),
-                            line_number: reader.get_u16()
-                        }).collect()
-                    })),
-                    "LocalVariableTable" => Some(Attribute::LocalVariableTable({
-                        let n = reader.get_u16();
-                        (0..n).map(|_| LocalVariableTable {
-                            start_pc: reader.get_u16(),
-                            length: reader.get_u16(),
-                            name_index: ConstantPoolIndex::new(reader.get_u16() as usize),
-                            descriptor_index: ConstantPoolIndex::new(reader.get_u16() as usize),
-                            index: reader.get_u16()
-                        }).collect()
-                    })),
-                    "LocalVariableTypeTable" => Some(Attribute::LocalVariableTypeTable({
-                        let n = reader.get_u16();
-                        (0..n).map(|_| LocalVariableTypeTable {
-                            start_pc: reader.get_u16(),
-                            length: reader.get_u16(),
-                            name_index: ConstantPoolIndex::new(reader.get_u16() as usize),
-                            signature_index: ConstantPoolIndex::new(reader.get_u16() as usize),
-                            index: reader.get_u16()
-                        }).collect()
-                    })),
-                    "Deprecated" => Some(Attribute::Deprecated),
-                    "RuntimeVisibleAnnotations" => Some(Attribute::RuntimeVisibleAnnotations({
-                        let n = reader.get_u16();
-                        (0..n).map(|_| ClassReader::read_annotation(&mut reader)).collect()
-                    })),
-                    "RuntimeInvisibleAnnotations" => Some(Attribute::RuntimeInvisibleAnnotations({
-                        let n = reader.get_u16();
-                        (0..n).map(|_| ClassReader::read_annotation(&mut reader)).collect()
-                    })),
-                    "RuntimeVisibleParameterAnnotations" => Some(Attribute::RuntimeVisibleParameterAnnotations({
-                        let n = reader.get_u8();
-
-                        (0..n).map(|_| {
-                            let m = reader.get_u16();
-                            (0..m).map(|_| ClassReader::read_annotation(&mut reader)).collect()
-                        }).collect()
-                    })),
-                    "RuntimeInvisibleParameterAnnotations" => Some(Attribute::RuntimeInvisibleParameterAnnotations({
-                        let n = reader.get_u8();
-
-                        (0..n).map(|_| {
-                            let m = reader.get_u16();
-                            (0..m).map(|_| ClassReader::read_annotation(&mut reader)).collect()
-                        }).collect()
-                    })),
-                    "RuntimeVisibleTypeAnnotations" => Some(Attribute::RuntimeVisibleTypeAnnotations({
-                        let n = reader.get_u16();
-                        (0..n).map(|_| ClassReader::read_type_annotation(&mut reader)).collect()
-                    })),
-                    "AnnotationDefault" => Some(Attribute::AnnotationDefault(ClassReader::read_element_value(&mut reader))),
-                    "BootstrapMethods" => Some(Attribute::BootstrapMethods({
-                        let n = reader.get_u16();
-                        (0..n).map(|_| BootstrapMethod {
-                            bootstrap_method_ref: ConstantPoolIndex::new(reader.get_u16() as usize),
-                            bootstrap_arguments: {
-                                let m = reader.get_u16();
-                                (0..m).map(|_| ConstantPoolIndex::new(reader.get_u16() as usize)).collect()
-                            }
-                        }).collect()
-                    })),
-                    "MethodParameters" => Some(Attribute::MethodParameters({
-                        let n = reader.get_u8();
-                        (0..n).map(|_| MethodParameter {
-                            name_index: ConstantPoolIndex::new(reader.get_u16() as usize),
-                            access_flags: AccessFlags::of(reader.get_u16())
-                        }).collect()
-                    })),
-                    _ => None
+    fn parse_attribute(name: &str, idx: u16, reader: &mut BlockReader, cf: &ClassFragment) -> Result<Attribute, ClassReadError> {
+        match name {
+            "ConstantValue" => Ok(Attribute::ConstantValue(ClassReader::read_constant_pool_index(reader)?)),
+            "Code" => Ok(Attribute::Code {
+                max_stack: reader.read_u16()?,
+                max_locals: reader.read_u16()?,
+                code: {
+                    let n = reader.read_u32()? as usize;
+                    let origin = reader.offset();
+                    let mut bytes = reader.read_n(n)?;
+
+                    ClassReader::parse_code(n, &mut BlockReader::with_origin(&mut Cursor::new(&mut bytes), origin)).map_err(|err| err.within("code"))?
+                },
+                exception_table: {
+                    let n = reader.read_u16()?;
+                    ClassReader::read_table(reader, n as usize, |r| Ok(ExceptionHandler {
+                        start_pc: r.read_u16()?,
+                        end_pc: r.read_u16()?,
+                        handler_pc: r.read_u16()?,
+                        catch_type: ClassReader::read_constant_pool_index(r)?
+                    })).map_err(|err| err.within("exception_table"))?
+                },
+                attributes: ClassReader::read_attributes(reader, cf)?
+            }),
+            "StackMapTable" => {
+                let n = reader.read_u16()?;
+                ClassReader::read_table(reader, n as usize, ClassReader::read_stack_map_frame).map(|frames| Attribute::StackMapTable(frames))
+            },
+            "Exceptions" => ClassReader::read_index_table(reader).map(|table| Attribute::Exceptions(table)),
+            "InnerClasses" => {
+                let n = reader.read_u16()?;
+                ClassReader::read_table(reader, n as usize, |r| Ok(InnerClass {
+                    inner_class_info_index: ClassReader::read_constant_pool_index(r)?,
+                    outer_class_info_index: ClassReader::read_constant_pool_index(r)?,
+                    inner_name_index: ClassReader::read_constant_pool_index(r)?,
+                    access_flags: AccessFlags::of(r.read_u16()?)
+                })).map(|table| Attribute::InnerClasses(table))
+            },
+            "EnclosingMethod" => Ok(Attribute::EnclosingMethod {
+                class_index: ClassReader::read_constant_pool_index(reader)?,
+                method_index: ClassReader::read_constant_pool_index(reader)?
+            }),
+            "Synthetic" => Ok(Attribute::Synthetic),
+            "Signature" => Ok(Attribute::Signature(ClassReader::read_constant_pool_index(reader)?)),
+            "SourceFile" => Ok(Attribute::SourceFile(ClassReader::read_constant_pool_index(reader)?)),
+            "SourceDebugExtension" => reader.read_bytes().map(|bytes| Attribute::SourceDebugExtension(bytes)),
+            "LineNumberTable" => {
+                let n = reader.read_u16()?;
+                ClassReader::read_table(reader, n as usize, |r| Ok(LineNumberTable {
+                    start_pc: r.read_u16()?,
+                    line_number: r.read_u16()?
+                })).map(|table| Attribute::LineNumberTable(table))
+            },
+            "LocalVariableTable" => {
+                let n = reader.read_u16()?;
+                ClassReader::read_table(reader, n as usize, |r| Ok(LocalVariableTable {
+                    start_pc: r.read_u16()?,
+                    length: r.read_u16()?,
+                    name_index: ClassReader::read_constant_pool_index(r)?,
+                    descriptor_index: ClassReader::read_constant_pool_index(r)?,
+                    index: r.read_u16()?
+                })).map(|table| Attribute::LocalVariableTable(table))
+            },
+            "LocalVariableTypeTable" => {
+                let n = reader.read_u16()?;
+                ClassReader::read_table(reader, n as usize, |r| Ok(LocalVariableTypeTable {
+                    start_pc: r.read_u16()?,
+                    length: r.read_u16()?,
+                    name_index: ClassReader::read_constant_pool_index(r)?,
+                    signature_index: ClassReader::read_constant_pool_index(r)?,
+                    index: r.read_u16()?
+                })).map(|table| Attribute::LocalVariableTypeTable(table))
+            },
+            "Deprecated" => Ok(Attribute::Deprecated),
+            "RuntimeVisibleAnnotations" => ClassReader::read_annotations(reader).map(|table| Attribute::RuntimeVisibleAnnotations(table)),
+            "RuntimeInvisibleAnnotations" => ClassReader::read_annotations(reader).map(|table| Attribute::RuntimeInvisibleAnnotations(table)),
+            "RuntimeVisibleParameterAnnotations" => {
+                let n = reader.read_u8()?;
+                ClassReader::read_table(reader, n as usize, ClassReader::read_annotations).map(|table| Attribute::RuntimeVisibleParameterAnnotations(table))
+            },
+            "RuntimeInvisibleParameterAnnotations" => {
+                let n = reader.read_u8()?;
+                ClassReader::read_table(reader, n as usize, ClassReader::read_annotations).map(|table| Attribute::RuntimeInvisibleParameterAnnotations(table))
+            },
+            "RuntimeVisibleTypeAnnotations" => {
+                let n = reader.read_u16()?;
+                ClassReader::read_table(reader, n as usize, ClassReader::read_type_annotation).map(|table| Attribute::RuntimeVisibleTypeAnnotations(table))
+            },
+            "AnnotationDefault" => ClassReader::read_element_value(reader).map(|value| Attribute::AnnotationDefault(value)),
+            "BootstrapMethods" => {
+                let n = reader.read_u16()?;
+                ClassReader::read_table(reader, n as usize, |r| Ok(BootstrapMethod {
+                    bootstrap_method_ref: ClassReader::read_constant_pool_index(r)?,
+                    bootstrap_arguments: ClassReader::read_index_table(r)?
+                })).map(|table| Attribute::BootstrapMethods(table))
+            },
+            "MethodParameters" => {
+                let n = reader.read_u8()?;
+                ClassReader::read_table(reader, n as usize, |r| Ok(MethodParameter {
+                    name_index: ClassReader::read_constant_pool_index(r)?,
+                    access_flags: AccessFlags::of(r.read_u16()?)
+                })).map(|table| Attribute::MethodParameters(table))
+            },
+            "Module" => Ok(Attribute::Module {
+                name_index: ClassReader::read_constant_pool_index(reader)?,
+                flags: AccessFlags::of(reader.read_u16()?),
+                version_index: ClassReader::read_constant_pool_index(reader)?,
+                requires: {
+                    let n = reader.read_u16()?;
+                    ClassReader::read_table(reader, n as usize, |r| Ok(ModuleRequires {
+                        requires_index: ClassReader::read_constant_pool_index(r)?,
+                        requires_flags: AccessFlags::of(r.read_u16()?),
+                        requires_version_index: ClassReader::read_constant_pool_index(r)?
+                    })).map_err(|err| err.within("requires"))?
+                },
+                exports: {
+                    let n = reader.read_u16()?;
+                    ClassReader::read_table(reader, n as usize, |r| Ok(ModuleExports {
+                        exports_index: ClassReader::read_constant_pool_index(r)?,
+                        exports_flags: AccessFlags::of(r.read_u16()?),
+                        exports_to_index: ClassReader::read_index_table(r)?
+                    })).map_err(|err| err.within("exports"))?
                 },
-                _ => None
+                opens: {
+                    let n = reader.read_u16()?;
+                    ClassReader::read_table(reader, n as usize, |r| Ok(ModuleOpens {
+                        opens_index: ClassReader::read_constant_pool_index(r)?,
+                        opens_flags: AccessFlags::of(r.read_u16()?),
+                        opens_to_index: ClassReader::read_index_table(r)?
+                    })).map_err(|err| err.within("opens"))?
+                },
+                uses: ClassReader::read_index_table(reader).map_err(|err| err.within("uses"))?,
+                provides: {
+                    let n = reader.read_u16()?;
+                    ClassReader::read_table(reader, n as usize, |r| Ok(ModuleProvides {
+                        provides_index: ClassReader::read_constant_pool_index(r)?,
+                        provides_with_index: ClassReader::read_index_table(r)?
+                    })).map_err(|err| err.within("provides"))?
+                }
+            }),
+            "ModulePackages" => ClassReader::read_index_table(reader).map(|table| Attribute::ModulePackages(table)),
+            "ModuleMainClass" => Ok(Attribute::ModuleMainClass(ClassReader::read_constant_pool_index(reader)?)),
+            "NestHost" => Ok(Attribute::NestHost(ClassReader::read_constant_pool_index(reader)?)),
+            "NestMembers" => ClassReader::read_index_table(reader).map(|table| Attribute::NestMembers(table)),
+            "Record" => {
+                let n = reader.read_u16()?;
+                ClassReader::read_table(reader, n as usize, |r| Ok(RecordComponent {
+                    name_index: ClassReader::read_constant_pool_index(r)?,
+                    descriptor_index: ClassReader::read_constant_pool_index(r)?,
+                    attributes: ClassReader::read_attributes(r, cf)?
+                })).map(|components| Attribute::Record(components))
             },
-            _ => None
-        }.unwrap_or(Attribute::RawAttribute { name_index: ConstantPoolIndex::new(idx as usize), info: reader.get_bytes() })
+            "PermittedSubclasses" => ClassReader::read_index_table(reader).map(|table| Attribute::PermittedSubclasses(table)),
+            _ => reader.read_bytes().map(|bytes| Attribute::RawAttribute { name_index: ConstantPoolIndex::new(idx as usize), info: bytes })
+        }
     }
 
-    fn read_annotation(reader: &mut BlockReader) -> Annotation {
-        Annotation {
-            type_index: ConstantPoolIndex::new(reader.get_u16() as usize),
-            element_value_pairs: {
-                let en = reader.get_u16();
-                (0..en).map(|_| ElementValuePair {
-                    element_name_index: ConstantPoolIndex::new(reader.get_u16() as usize),
-                    value: ClassReader::read_element_value(reader)
-                }).collect()
-            }
+    fn read_stack_map_frame(reader: &mut BlockReader) -> Result<StackMapFrame, ClassReadError> {
+        let offset = reader.offset();
+
+        match reader.read_u8()? {
+            tag@0...63 => Ok(StackMapFrame::SameFrame { tag: tag }),
+            tag@64...127 => Ok(StackMapFrame::SameLocals1StackItemFrame { tag: tag, stack: ClassReader::read_verification_type(reader)? }),
+            247 => Ok(StackMapFrame::SameLocals1StackItemFrameExtended { offset_delta: reader.read_u16()?, stack: ClassReader::read_verification_type(reader)? }),
+            tag@248...250 => Ok(StackMapFrame::ChopFrame { tag: tag, offset_delta: reader.read_u16()? }),
+            251 => Ok(StackMapFrame::SameFrameExtended { offset_delta: reader.read_u16()? }),
+            tag@252...254 => Ok(StackMapFrame::AppendFrame {
+                tag: tag,
+                offset_delta: reader.read_u16()?,
+                locals: ClassReader::read_table(reader, (tag - 251) as usize, ClassReader::read_verification_type)?
+            }),
+            255 => Ok(StackMapFrame::FullFrame {
+                offset_delta: reader.read_u16()?,
+                locals: {
+                    let n = reader.read_u16()?;
+                    ClassReader::read_table(reader, n as usize, ClassReader::read_verification_type).map_err(|err| err.within("locals"))?
+                },
+                stack: {
+                    let n = reader.read_u16()?;
+                    ClassReader::read_table(reader, n as usize, ClassReader::read_verification_type).map_err(|err| err.within("stack"))?
+                }
+            }),
+            // Frame types 128-246 are reserved, their length is unknown so parsing can't go on
+            tag@_ => Err(ClassReadError::new(ReadErrorKind::InvalidValue, offset, "stack map frame type", format!("reserved type {}", tag)))
+        }
+    }
+
+    fn read_verification_type(reader: &mut BlockReader) -> Result<VerificationType, ClassReadError> {
+        let offset = reader.offset();
+
+        match reader.read_u8()? {
+            0 => Ok(VerificationType::Top),
+            1 => Ok(VerificationType::Integer),
+            2 => Ok(VerificationType::Float),
+            3 => Ok(VerificationType::Double),
+            4 => Ok(VerificationType::Long),
+            5 => Ok(VerificationType::Null),
+            6 => Ok(VerificationType::UninitializedThis),
+            7 => Ok(VerificationType::Object { cpool_index: ClassReader::read_constant_pool_index(reader)? }),
+            8 => Ok(VerificationType::Uninitialized { offset: reader.read_u16()? }),
+            tag@_ => Err(ClassReadError::new(ReadErrorKind::InvalidValue, offset, "verification type tag 0-8", format!("{}", tag)))
         }
     }
 
-    fn read_type_annotation(reader: &mut BlockReader) -> TypeAnnotation {
-        TypeAnnotation {
-            target_info: match reader.get_u8() {
+    fn read_annotations(reader: &mut BlockReader) -> Result<Vec<Annotation>, ClassReadError> {
+        let n = reader.read_u16()?;
+        ClassReader::read_table(reader, n as usize, ClassReader::read_annotation)
+    }
+
+    fn read_annotation(reader: &mut BlockReader) -> Result<Annotation, ClassReadError> {
+        Ok(Annotation {
+            type_index: ClassReader::read_constant_pool_index(reader)?,
+            element_value_pairs: ClassReader::read_element_value_pairs(reader)?
+        })
+    }
+
+    fn read_element_value_pairs(reader: &mut BlockReader) -> Result<Vec<ElementValuePair>, ClassReadError> {
+        let n = reader.read_u16()?;
+        ClassReader::read_table(reader, n as usize, |r| Ok(ElementValuePair {
+            element_name_index: ClassReader::read_constant_pool_index(r)?,
+            value: ClassReader::read_element_value(r)?
+        }))
+    }
+
+    fn read_type_annotation(reader: &mut BlockReader) -> Result<TypeAnnotation, ClassReadError> {
+        let target_offset = reader.offset();
+
+        Ok(TypeAnnotation {
+            target_info: match reader.read_u8()? {
                 // 0x00 type parameter declaration of generic class or interface
                 // 0x01 type parameter declaration of generic method or constructor
-                subtype @ 0x00...0x01 => TargetInfo::TypeParameter { subtype: subtype, idx: reader.get_u8() },
+                subtype @ 0x00...0x01 => TargetInfo::TypeParameter { subtype: subtype, idx: reader.read_u8()? },
                 // type in extends or implements clause of class declaration (including the direct superclass or direct superinterface of an anonymous class declaration), or in extends clause of interface declaration
-                0x10 => TargetInfo::SuperType { idx: reader.get_u16() },
+                0x10 => TargetInfo::SuperType { idx: reader.read_u16()? },
                 // 0x11 type in bound of type parameter declaration of generic class or interface
                 // 0x12 type in bound of type parameter declaration of generic method or constructor
-                subtype @ 0x11...0x12 => TargetInfo::TypeParameterBound { subtype: subtype, param_idx: reader.get_u8(), bound_index: reader.get_u8() },
+                subtype @ 0x11...0x12 => TargetInfo::TypeParameterBound { subtype: subtype, param_idx: reader.read_u8()?, bound_index: reader.read_u8()? },
                 // 0x13 type in field declaration
                 // 0x14 return type of method, or type of newly constructed object
                 // 0x15 receiver type of method or constructor
                 subtype @ 0x13...0x15 => TargetInfo::Empty { subtype: subtype },
                 // type in formal parameter declaration of method, constructor, or lambda expression
-                0x16 => TargetInfo::MethodFormalParameter { idx: reader.get_u8() },
+                0x16 => TargetInfo::MethodFormalParameter { idx: reader.read_u8()? },
                 // type in throws clause of method or constructor
-                0x17 => TargetInfo::Throws { idx: reader.get_u16() },
+                0x17 => TargetInfo::Throws { idx: reader.read_u16()? },
                 // 0x40 type in local variable declaration
                 // 0x41 type in resource variable declaration
                 subtype @ 0x40...0x41 => TargetInfo::LocalVar { subtype: subtype, target: {
-                    let count = reader.get_u16();
+                    let count = reader.read_u16()?;
 
                                         //u2 start_pc;    u2 length;        u2 index;
-                    (0..count).map(|_| (reader.get_u16(), reader.get_u16(), reader.get_u16())).collect()
+                    ClassReader::read_table(reader, count as usize, |r| Ok((r.read_u16()?, r.read_u16()?, r.read_u16()?)))?
                 }},
                 // type in exception parameter declaration
-                0x42 => TargetInfo::Catch { idx: reader.get_u16() },
+                0x42 => TargetInfo::Catch { idx: reader.read_u16()? },
                 // 0x43 type in instanceof expression
                 // 0x44 type in new expression
                 // 0x45 type in method reference expression using ::new
                 // 0x46 type in method reference expression using ::Identifier
-                subtype @ 0x43...0x46 => TargetInfo::Offset { subtype: subtype, idx: reader.get_u16() },
+                subtype @ 0x43...0x46 => TargetInfo::Offset { subtype: subtype, idx: reader.read_u16()? },
                 // 0x48 type argument for generic constructor in new expression or explicit constructor invocation statement
                 // 0x49 type argument for generic method in method invocation expression
                 // 0x4A type argument for generic constructor in method reference expression using ::new
                 // 0x4B type argument for generic method in method reference expression using ::Identifier
-                subtype @ 0x47...0x4b => TargetInfo::TypeArgument { subtype: subtype, offset: reader.get_u16(), type_arg_idx: reader.get_u8() },
-                // TODO replace the below fallback branch with proper error handling
-                _ => TargetInfo::Empty { subtype: 0 }
+                subtype @ 0x47...0x4b => TargetInfo::TypeArgument { subtype: subtype, offset: reader.read_u16()?, type_arg_idx: reader.read_u8()? },
+                target_type@_ => return Err(ClassReadError::new(ReadErrorKind::InvalidValue, target_offset, "type annotation target type", format!("{:#04x}", target_type)))
             },
             target_path: TypePath {
                 path: {
-                    let n = reader.get_u8();
-                    (0..n).map(|_| (match reader.get_u8() {
-                        0 => TypePathKind::Array,
-                        1 => TypePathKind::Nested,
-                        2 => TypePathKind::Wildcard,
-                        3 => TypePathKind::TypeArgument,
-                        // TODO replace the below fallback branch with proper error handling
-                        _ => TypePathKind::Array
-                    }, reader.get_u8())).collect()
+                    let n = reader.read_u8()?;
+                    ClassReader::read_table(reader, n as usize, |r| {
+                        let offset = r.offset();
+
+                        let kind = match r.read_u8()? {
+                            0 => TypePathKind::Array,
+                            1 => TypePathKind::Nested,
+                            2 => TypePathKind::Wildcard,
+                            3 => TypePathKind::TypeArgument,
+                            kind@_ => return Err(ClassReadError::new(ReadErrorKind::InvalidValue, offset, "type path kind 0-3", format!("{}", kind)))
+                        };
+
+                        Ok((kind, r.read_u8()?))
+                    }).map_err(|err| err.within("target_path"))?
                 }
             },
-            type_index: ConstantPoolIndex::new(reader.get_u16() as usize),
-            element_value_pairs: {
-                let n = reader.get_u16();
-                (0..n).map(|_| ElementValuePair {
-                    element_name_index: ConstantPoolIndex::new(reader.get_u16() as usize),
-                    value: ClassReader::read_element_value(reader)
-                }).collect()
-            }
-        }
+            type_index: ClassReader::read_constant_pool_index(reader)?,
+            element_value_pairs: ClassReader::read_element_value_pairs(reader)?
+        })
     }
 
-    fn read_element_value(reader: &mut BlockReader) -> ElementValue {
-        let tag = reader.get_u8();
-
-        match tag {
-            66 /* B */ => ElementValue::ConstantValue(tag, ConstantPoolIndex::new(reader.get_u16() as usize)),
-            67 /* C */ => ElementValue::ConstantValue(tag, ConstantPoolIndex::new(reader.get_u16() as usize)),
-            68 /* D */ => ElementValue::ConstantValue(tag, ConstantPoolIndex::new(reader.get_u16() as usize)),
-            70 /* F */ => ElementValue::ConstantValue(tag, ConstantPoolIndex::new(reader.get_u16() as usize)),
-            73 /* I */ => ElementValue::ConstantValue(tag, ConstantPoolIndex::new(reader.get_u16() as usize)),
-            74 /* J */ => ElementValue::ConstantValue(tag, ConstantPoolIndex::new(reader.get_u16() as usize)),
-            83 /* S */ => ElementValue::ConstantValue(tag, ConstantPoolIndex::new(reader.get_u16() as usize)),
-            90 /* Z */ => ElementValue::ConstantValue(tag, ConstantPoolIndex::new(reader.get_u16() as usize)),
-            115 /* s */ => ElementValue::ConstantValue(tag, ConstantPoolIndex::new(reader.get_u16() as usize)),
-            101 /* e */ => ElementValue::Enum {
-                type_name_index: ConstantPoolIndex::new(reader.get_u16() as usize),
-                const_name_index: ConstantPoolIndex::new(reader.get_u16() as usize) },
-            99 /* c */ => ElementValue::ClassInfo(ConstantPoolIndex::new(reader.get_u16() as usize)),
-            64 /* @ */ => ElementValue::Annotation(ClassReader::read_annotation(reader)),
-            91 /* [ */ => ElementValue::Array({
-                let n = reader.get_u16();
-                (0..n).map(|_| ClassReader::read_element_value(reader)).collect()
-            }),
-            _ => ElementValue::ConstantValue(tag, ConstantPoolIndex::new(0)) // TODO this deserves a better error handling
+    fn read_element_value(reader: &mut BlockReader) -> Result<ElementValue, ClassReadError> {
+        let offset = reader.offset();
+
+        match reader.read_u8()? {
+            tag@66 /* B */ | tag@67 /* C */ | tag@68 /* D */ | tag@70 /* F */ | tag@73 /* I */ |
+            tag@74 /* J */ | tag@83 /* S */ | tag@90 /* Z */ | tag@115 /* s */ => Ok(ElementValue::ConstantValue(tag, ClassReader::read_constant_pool_index(reader)?)),
+            101 /* e */ => Ok(ElementValue::Enum {
+                type_name_index: ClassReader::read_constant_pool_index(reader)?,
+                const_name_index: ClassReader::read_constant_pool_index(reader)? }),
+            99 /* c */ => Ok(ElementValue::ClassInfo(ClassReader::read_constant_pool_index(reader)?)),
+            64 /* @ */ => Ok(ElementValue::Annotation(ClassReader::read_annotation(reader)?)),
+            91 /* [ */ => {
+                let n = reader.read_u16()?;
+                ClassReader::read_table(reader, n as usize, ClassReader::read_element_value).map(|values| ElementValue::Array(values))
+            },
+            tag@_ => Err(ClassReadError::new(ReadErrorKind::InvalidValue, offset, "element value tag", format!("{:?}", tag as char)))
         }
     }
 
-    fn read_constant_pool_index(reader: &mut BlockReader) -> Result<ConstantPoolIndex, Error> {
-        match reader.read_u16() {
-            Ok(idx) => Ok(ConstantPoolIndex::new(idx as usize)),
-            Err(err) => Err(err)
-        }
+    fn read_constant_pool_index(reader: &mut BlockReader) -> Result<ConstantPoolIndex, ClassReadError> {
+        reader.read_u16().map(|idx| ConstantPoolIndex::new(idx as usize))
     }
 
-    fn require_n<T, U>(reader: &mut BlockReader, count: usize, extractor: U) -> Result<T, Error> where U: Fn(BlockReader) -> T {
-        match reader.read_n(count) {
-            Ok(bytes) => {
-                let mut cursor = Cursor::new(bytes);
-                let r = BlockReader::new(&mut cursor);
+    /// Read a u2 length-prefixed table of constant pool indices, a layout shared by many attributes
+    fn read_index_table(reader: &mut BlockReader) -> Result<Vec<ConstantPoolIndex>, ClassReadError> {
+        let n = reader.read_u16()?;
+        ClassReader::read_table(reader, n as usize, ClassReader::read_constant_pool_index)
+    }
 
-                Ok(extractor(r))
-            },
-            Err(err) => Err(err)
+    /// Read `n` consecutive entries using the given extractor. Errors are tagged with the index of
+    /// the entry that failed
+    fn read_table<T, F>(reader: &mut BlockReader, n: usize, mut extractor: F) -> Result<Vec<T>, ClassReadError> where F: FnMut(&mut BlockReader) -> Result<T, ClassReadError> {
+        (0..n).map(|i| extractor(reader).map_err(|err| err.within(format!("[{}]", i)))).collect()
+    }
+
+    fn describe_constant(constant: Option<&Constant>) -> String {
+        match constant {
+            Some(constant) => format!("{:?}", constant),
+            None => String::from("index outside of the constant pool")
         }
     }
 }
@@ -864,114 +863,87 @@ impl ClassReader {
 // TODO remove pub after testing
 pub struct BlockReader<'a> {
     source: &'a mut Read,
+    origin: usize,
     position: usize
 }
 
 impl<'a> BlockReader<'a> {
 
     pub fn new<T>(source: &'a mut T) -> BlockReader where T: Read {
-        BlockReader { source: source, position: 0 }
+        BlockReader::with_origin(source, 0)
     }
 
-    pub fn read_u64(&mut self) -> Result<u64, Error> {
-        let mut buf: [u8; 8] = [0; 8];
+    /// Create a reader for a block that starts at `origin` in the enclosing class file. Positions
+    /// are relative to the block, while error offsets are absolute.
+    pub fn with_origin<T>(source: &'a mut T, origin: usize) -> BlockReader where T: Read {
+        BlockReader { source: source, origin: origin, position: 0 }
+    }
 
-        match self.source.read_exact(&mut buf) {
+    fn fill(&mut self, buf: &mut [u8]) -> Result<(), ClassReadError> {
+        match self.source.read_exact(buf) {
             Ok(_) => {
-                self.position += 8;
-
-                Ok(
-                ((buf[0] as u64) << 56) +
-                ((buf[1] as u64) << 48) +
-                ((buf[2] as u64) << 40) +
-                ((buf[3] as u64) << 32) +
-                ((buf[4] as u64) << 24) +
-                ((buf[5] as u64) << 16) +
-                ((buf[6] as u64) << 8) +
-                buf[7] as u64)
+                self.position += buf.len();
+                Ok(())
             },
-            Err(err) => Err(err)
+            Err(ref err) if err.kind() == ErrorKind::UnexpectedEof => Err(ClassReadError::new(ReadErrorKind::UnexpectedEof, self.offset(), format!("{} bytes", buf.len()), "end of input")),
+            Err(err) => Err(ClassReadError::io(self.offset(), err))
         }
     }
 
-    #[allow(dead_code)]
-    pub fn get_u64(&mut self) -> u64 {
-        self.read_u64().unwrap_or(0)
+    pub fn read_u64(&mut self) -> Result<u64, ClassReadError> {
+        let mut buf: [u8; 8] = [0; 8];
+
+        self.fill(&mut buf).map(|_| {
+            ((buf[0] as u64) << 56) +
+            ((buf[1] as u64) << 48) +
+            ((buf[2] as u64) << 40) +
+            ((buf[3] as u64) << 32) +
+            ((buf[4] as u64) << 24) +
+            ((buf[5] as u64) << 16) +
+            ((buf[6] as u64) << 8) +
+            buf[7] as u64
+        })
     }
 
-    pub fn read_u32(&mut self) -> Result<u32, Error> {
+    pub fn read_u32(&mut self) -> Result<u32, ClassReadError> {
         let mut buf: [u8; 4] = [0; 4];
 
-        match self.source.read_exact(&mut buf) {
-            Ok(_) => {
-                self.position += 4;
-                Ok(
-                ((buf[0] as u32) << 24) +
-                ((buf[1] as u32) << 16) +
-                ((buf[2] as u32) << 8) +
-                buf[3] as u32)
-            },
-            Err(err) => Err(err)
-        }
-    }
-
-    #[allow(dead_code)]
-    pub fn get_u32(&mut self) -> u32 {
-        self.read_u32().unwrap_or(0)
+        self.fill(&mut buf).map(|_| {
+            ((buf[0] as u32) << 24) +
+            ((buf[1] as u32) << 16) +
+            ((buf[2] as u32) << 8) +
+            buf[3] as u32
+        })
     }
 
-    pub fn read_u16(&mut self) -> Result<u16, Error> {
+    pub fn read_u16(&mut self) -> Result<u16, ClassReadError> {
         let mut buf: [u8; 2] = [0; 2];
 
-        match self.source.read_exact(&mut buf) {
-            Ok(_) => {
-                self.position += 2;
-                Ok(((buf[0] as u16) << 8) + buf[1] as u16)
-            },
-            Err(err) => Err(err)
-        }
-    }
-
-    pub fn get_u16(&mut self) -> u16 {
-        self.read_u16().unwrap_or(0)
+        self.fill(&mut buf).map(|_| ((buf[0] as u16) << 8) + buf[1] as u16)
     }
 
-    pub fn read_u8(&mut self) -> Result<u8, Error> {
+    pub fn read_u8(&mut self) -> Result<u8, ClassReadError> {
         let mut buf: [u8; 1] = [0; 1];
 
-        match self.source.read_exact(&mut buf) {
-            Ok(_) => {
-                self.position += 1;
-                Ok(buf[0])
-            },
-            Err(err) => Err(err)
-        }
-    }
-
-    pub fn get_u8(&mut self) -> u8 {
-        self.read_u8().unwrap_or(0)
+        self.fill(&mut buf).map(|_| buf[0])
     }
 
-    pub fn read_n(&mut self, count: usize) -> Result<Vec<u8>, Error> {
-        let mut tmp: Vec<u8> = Vec::with_capacity(count);
+    pub fn read_n(&mut self, count: usize) -> Result<Vec<u8>, ClassReadError> {
+        // Don't preallocate count bytes, it comes straight from the (possibly malicious) input
+        let mut tmp: Vec<u8> = vec![];
+        let offset = self.offset();
 
         match self.source.take(count as u64).read_to_end(&mut tmp) {
-            Ok(_) => {
+            Ok(len) if len == count => {
                 self.position += count;
                 Ok(tmp)
             },
-            Err(err) => Err(err)
-        }
-    }
-
-    pub fn get_n(&mut self, count: usize) -> Vec<u8> {
-        match self.read_n(count) {
-            Ok(bytes) => bytes,
-            Err(_) => vec![]
+            Ok(len) => Err(ClassReadError::eof(offset, count, len)),
+            Err(err) => Err(ClassReadError::io(offset, err))
         }
     }
 
-    pub fn read_bytes(&mut self) -> Result<Vec<u8>, Error> {
+    pub fn read_bytes(&mut self) -> Result<Vec<u8>, ClassReadError> {
         let mut tmp: Vec<u8> = vec![];
 
         match self.source.read_to_end(&mut tmp) {
@@ -979,23 +951,20 @@ impl<'a> BlockReader<'a> {
                 self.position += tmp.len();
                 Ok(tmp)
             },
-            Err(err) => Err(err)
+            Err(err) => Err(ClassReadError::io(self.offset(), err))
         }
     }
 
-    pub fn get_bytes(&mut self) -> Vec<u8> {
-        let mut tmp: Vec<u8> = vec![];
-
-        let _ = self.source.read_to_end(&mut tmp);
-
-        tmp
-    }
-
+    /// Number of bytes read from this block so far
     pub fn position(&self) -> usize {
         self.position
     }
-}
 
+    /// Absolute offset of the next byte in the class file
+    pub fn offset(&self) -> usize {
+        self.origin + self.position
+    }
+}
 
 struct ClassFragment {
     pub version: Option<ClassfileVersion>,
@@ -41,7 +41,7 @@ impl<'a> ClassWriter<'a> {
         })
     }
 
-    fn write_constant(&mut self, constant: &Constant) -> Result<usize, Error> {
+    pub fn write_constant(&mut self, constant: &Constant) -> Result<usize, Error> {
         match constant {
             &Constant::Utf8(ref bytes) => self.write_u8(1).and(self.write_u16(bytes.len() as u16)).and(self.write_n(bytes)),
             &Constant::Integer(ref value) => self.write_u8(3).and(self.write_u32(*value)),
@@ -56,7 +56,10 @@ impl<'a> ClassWriter<'a> {
             &Constant::InterfaceMethodRef { class_index: ref c_idx, name_and_type_index: ref n_idx } => self.write_u8(11).and(self.write_u16(c_idx.idx as u16)).and(self.write_u16(n_idx.idx as u16)),
             &Constant::NameAndType { name_index: ref n_idx, descriptor_index: ref d_idx } => self.write_u8(12).and(self.write_u16(n_idx.idx as u16)).and(self.write_u16(d_idx.idx as u16)),
             &Constant::MethodHandle { reference_kind: ref kind, reference_index: ref r_idx } => self.write_u8(15).and(self.write_u8(kind.to_u8())).and(self.write_u16(r_idx.idx as u16)),
+            &Constant::Dynamic { bootstrap_method_attr_index: ref m_idx, name_and_type_index: ref n_idx } => self.write_u8(17).and(self.write_u16(m_idx.idx as u16)).and(self.write_u16(n_idx.idx as u16)),
             &Constant::InvokeDynamic { bootstrap_method_attr_index: ref m_idx, name_and_type_index: ref n_idx } => self.write_u8(18).and(self.write_u16(m_idx.idx as u16)).and(self.write_u16(n_idx.idx as u16)),
+            &Constant::Module(ref idx) => self.write_u8(19).and(self.write_u16(idx.idx as u16)),
+            &Constant::Package(ref idx) => self.write_u8(20).and(self.write_u16(idx.idx as u16)),
             &Constant::Placeholder => Ok(0),
             _ => Err(Error::new(ErrorKind::InvalidData, "Unknown constant detected"))
         }
@@ -88,7 +91,7 @@ impl<'a> ClassWriter<'a> {
         })
     }
 
-    fn write_field(&mut self, field: &Field, cp: &ConstantPool) -> Result<usize, Error> {
+    pub fn write_field(&mut self, field: &Field, cp: &ConstantPool) -> Result<usize, Error> {
         self.write_access_flags(&field.access_flags)
             .and(self.write_constant_pool_index(&field.name_index))
             .and(self.write_constant_pool_index(&field.descriptor_index))
@@ -104,7 +107,7 @@ impl<'a> ClassWriter<'a> {
         })
     }
 
-    fn write_method(&mut self, method: &Method, cp: &ConstantPool) -> Result<usize, Error> {
+    pub fn write_method(&mut self, method: &Method, cp: &ConstantPool) -> Result<usize, Error> {
         self.write_access_flags(&method.access_flags)
             .and(self.write_constant_pool_index(&method.name_index))
             .and(self.write_constant_pool_index(&method.descriptor_index))
@@ -120,24 +123,25 @@ impl<'a> ClassWriter<'a> {
         })
     }
 
-    fn write_attribute(&mut self, attribute: &Attribute, cp: &ConstantPool) -> Result<usize, Error> {
+    pub fn write_attribute(&mut self, attribute: &Attribute, cp: &ConstantPool) -> Result<usize, Error> {
         match attribute {
             &Attribute::RawAttribute { name_index: ref n_idx, info: ref bytes } => self.write_u16(n_idx.idx as u16).and(self.write_u32(bytes.len() as u32)).and(self.write_n(bytes)),
             &Attribute::ConstantValue(ref idx) => self.write_u16(cp.get_utf8_index("ConstantValue") as u16).and(self.write_u32(2)).and(self.write_u16(idx.idx as u16)),
             &Attribute::Code { max_stack, max_locals, ref code, ref exception_table, ref attributes } => {
                 let mut target: Vec<u8> = vec![];
 
-                {
+                let written = {
                     let mut code_writer = ClassWriter::new(&mut target);
 
-                    let _ = code_writer.write_u16(max_stack)
+                    code_writer.write_u16(max_stack)
                     .and(code_writer.write_u16(max_locals))
                     .and(code_writer.write_instructions(code))
                     .and(code_writer.write_exception_handlers(exception_table))
-                    .and(code_writer.write_attributes(attributes, cp));
-                }
+                    .and(code_writer.write_attributes(attributes, cp))
+                };
 
-                self.write_u16(cp.get_utf8_index("Code") as u16)
+                written
+                .and(self.write_u16(cp.get_utf8_index("Code") as u16))
                 .and(self.write_u32(target.len() as u32))
                 .and(self.write_n(&target))
             },
@@ -250,8 +254,74 @@ impl<'a> ClassWriter<'a> {
             &Attribute::MethodParameters(ref table) => {
                 self.write_u16(cp.get_utf8_index("MethodParameters") as u16)
                 .and(self.write_u32(1 + table.len() as u32 * 4))
+                .and(self.write_u8(table.len() as u8))
                 .and(table.iter().fold(Ok(0), |_, p| self.write_u16(p.name_index.idx as u16).and(self.write_u16(p.access_flags.flags as u16))))
             }
+            &Attribute::Module { ref name_index, ref flags, ref version_index, ref requires, ref exports, ref opens, ref uses, ref provides } => {
+                self.write_u16(cp.get_utf8_index("Module") as u16)
+                // attribute_length
+                .and(self.write_u32(16
+                    + requires.iter().fold(0, |acc, x| acc + x.len() as u32)
+                    + exports.iter().fold(0, |acc, x| acc + x.len() as u32)
+                    + opens.iter().fold(0, |acc, x| acc + x.len() as u32)
+                    + uses.len() as u32 * 2
+                    + provides.iter().fold(0, |acc, x| acc + x.len() as u32)))
+                .and(self.write_u16(name_index.idx as u16))
+                .and(self.write_u16(flags.flags))
+                .and(self.write_u16(version_index.idx as u16))
+                // requires
+                .and(self.write_u16(requires.len() as u16))
+                .and(requires.iter().fold(Ok(0), |_, x| {
+                    self.write_u16(x.requires_index.idx as u16)
+                    .and(self.write_u16(x.requires_flags.flags))
+                    .and(self.write_u16(x.requires_version_index.idx as u16))
+                }))
+                // exports
+                .and(self.write_u16(exports.len() as u16))
+                .and(exports.iter().fold(Ok(0), |_, x| {
+                    self.write_u16(x.exports_index.idx as u16)
+                    .and(self.write_u16(x.exports_flags.flags))
+                    .and(self.write_index_table(&x.exports_to_index))
+                }))
+                // opens
+                .and(self.write_u16(opens.len() as u16))
+                .and(opens.iter().fold(Ok(0), |_, x| {
+                    self.write_u16(x.opens_index.idx as u16)
+                    .and(self.write_u16(x.opens_flags.flags))
+                    .and(self.write_index_table(&x.opens_to_index))
+                }))
+                // uses
+                .and(self.write_index_table(uses))
+                // provides
+                .and(self.write_u16(provides.len() as u16))
+                .and(provides.iter().fold(Ok(0), |_, x| {
+                    self.write_u16(x.provides_index.idx as u16)
+                    .and(self.write_index_table(&x.provides_with_index))
+                }))
+            },
+            &Attribute::ModulePackages(ref table) => self.write_u16(cp.get_utf8_index("ModulePackages") as u16).and(self.write_u32(2 + table.len() as u32 * 2)).and(self.write_index_table(table)),
+            &Attribute::ModuleMainClass(ref idx) => self.write_u16(cp.get_utf8_index("ModuleMainClass") as u16).and(self.write_u32(2)).and(self.write_u16(idx.idx as u16)),
+            &Attribute::NestHost(ref idx) => self.write_u16(cp.get_utf8_index("NestHost") as u16).and(self.write_u32(2)).and(self.write_u16(idx.idx as u16)),
+            &Attribute::NestMembers(ref table) => self.write_u16(cp.get_utf8_index("NestMembers") as u16).and(self.write_u32(2 + table.len() as u32 * 2)).and(self.write_index_table(table)),
+            &Attribute::Record(ref components) => {
+                let mut target: Vec<u8> = vec![];
+
+                let written = {
+                    let mut record_writer = ClassWriter::new(&mut target);
+
+                    components.iter().fold(record_writer.write_u16(components.len() as u16), |acc, x| {
+                        acc.and(record_writer.write_u16(x.name_index.idx as u16))
+                        .and(record_writer.write_u16(x.descriptor_index.idx as u16))
+                        .and(record_writer.write_attributes(&x.attributes, cp))
+                    })
+                };
+
+                written
+                .and(self.write_u16(cp.get_utf8_index("Record") as u16))
+                .and(self.write_u32(target.len() as u32))
+                .and(self.write_n(&target))
+            },
+            &Attribute::PermittedSubclasses(ref table) => self.write_u16(cp.get_utf8_index("PermittedSubclasses") as u16).and(self.write_u32(2 + table.len() as u32 * 2)).and(self.write_index_table(table)),
         }
     }
 
@@ -370,7 +440,7 @@ impl<'a> ClassWriter<'a> {
     }
 
     /// Renders a single instruction into the output stream
-    fn render_instruction(&mut self, instruction: &Instruction, offset: usize) -> usize {
+    pub fn render_instruction(&mut self, instruction: &Instruction, offset: usize) -> usize {
         match instruction {
             &Instruction::AALOAD => self.write_u8(0x32),
             &Instruction::AASTORE => self.write_u8(0x53),
@@ -629,6 +699,10 @@ impl<'a> ClassWriter<'a> {
         }.ok().unwrap_or(0)
     }
 
+    fn write_index_table(&mut self, table: &Vec<ConstantPoolIndex>) -> Result<usize, Error> {
+        table.iter().fold(self.write_u16(table.len() as u16), |acc, x| acc.and(self.write_u16(x.idx as u16)))
+    }
+
     fn write_exception_handlers(&mut self, exception_table: &Vec<ExceptionHandler>) -> Result<usize, Error> {
         self.write_u16(exception_table.len() as u16)
             .and(exception_table.iter().fold(Ok(0), |_, x| {
@@ -658,7 +732,7 @@ impl<'a> ClassWriter<'a> {
             (value & 0xFF) as u8
         ];
 
-        self.target.write(&buf)
+        self.target.write_all(&buf).map(|_| buf.len())
     }
 
     pub fn write_u32(&mut self, value: u32) -> Result<usize, Error> {
@@ -669,16 +743,16 @@ impl<'a> ClassWriter<'a> {
             (value & 0xFF) as u8
         ];
 
-        self.target.write(&buf)
+        self.target.write_all(&buf).map(|_| buf.len())
     }
 
     pub fn write_u16(&mut self, value: u16) -> Result<usize, Error> {
         let buf: [u8; 2] = [((value & 0xFF00) >> 8) as u8, (value & 0xFF) as u8];
 
-        self.target.write(&buf)
+        self.target.write_all(&buf).map(|_| buf.len())
     }
 
     pub fn write_u8(&mut self, value: u8) -> Result<usize, Error> {
-        self.target.write(&[value])
+        self.target.write_all(&[value]).map(|_| 1)
     }
 }
@@ -0,0 +1,179 @@
+use flate2::read::DeflateDecoder;
+use std::fs::File;
+use std::io::{ Read, Error, ErrorKind };
+use std::path::Path;
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x04034b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06054b50;
+const END_OF_CENTRAL_DIRECTORY_LEN: usize = 22;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+
+/// Most memory set aside for an inflated entry up front, as the sizes come from the archive itself
+const MAX_PREALLOCATED_LEN: usize = 1 << 20;
+
+///
+/// A single file stored in a jar archive, as described by the archive's central directory
+#[derive(Debug, Clone)]
+pub struct JarEntry {
+    pub name: String,
+    pub method: u16,
+    pub compressed_size: usize,
+    pub uncompressed_size: usize,
+    local_header_offset: usize
+}
+
+impl JarEntry {
+    pub fn is_directory(&self) -> bool {
+        self.name.ends_with("/")
+    }
+}
+
+///
+/// A `JarArchive` is an in-memory view of a jar (or any zip) file. Only the central directory is
+/// parsed up front, entry contents are extracted on demand. Stored and deflated entries are
+/// supported, encrypted and zip64 archives are rejected.
+pub struct JarArchive {
+    data: Vec<u8>,
+    entries: Vec<JarEntry>
+}
+
+impl JarArchive {
+    pub fn open<P>(path: P) -> Result<JarArchive, Error> where P: AsRef<Path> {
+        let mut data: Vec<u8> = vec![];
+
+        match File::open(path).and_then(|mut file| file.read_to_end(&mut data)) {
+            Ok(_) => JarArchive::from_bytes(data),
+            Err(err) => Err(err)
+        }
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<JarArchive, Error> {
+        match JarArchive::read_central_directory(&data) {
+            Ok(entries) => Ok(JarArchive { data: data, entries: entries }),
+            Err(err) => Err(err)
+        }
+    }
+
+    pub fn entries(&self) -> &Vec<JarEntry> {
+        &self.entries
+    }
+
+    pub fn find_entry(&self, name: &str) -> Option<&JarEntry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+
+    /// Extract the uncompressed contents of the given entry
+    pub fn read_entry(&self, entry: &JarEntry) -> Result<Vec<u8>, Error> {
+        let header = entry.local_header_offset;
+
+        if read_u32(&self.data, header) != Some(LOCAL_HEADER_SIGNATURE) {
+            return Err(invalid_data(format!("Missing local file header for entry {}", entry.name)));
+        }
+
+        let start = match (read_u16(&self.data, header + 26), read_u16(&self.data, header + 28)) {
+            (Some(name_len), Some(extra_len)) => header + 30 + name_len as usize + extra_len as usize,
+            _ => return Err(invalid_data(format!("Truncated local file header for entry {}", entry.name)))
+        };
+
+        let compressed = match self.data.get(start..start + entry.compressed_size) {
+            Some(bytes) => bytes,
+            None => return Err(invalid_data(format!("Truncated data for entry {}", entry.name)))
+        };
+
+        match entry.method {
+            METHOD_STORED => Ok(compressed.to_vec()),
+            METHOD_DEFLATED => {
+                let mut output: Vec<u8> = Vec::with_capacity(entry.uncompressed_size.min(MAX_PREALLOCATED_LEN));
+
+                // Inflating a byte past the expected size is enough to tell the size is wrong
+                DeflateDecoder::new(compressed).take(entry.uncompressed_size as u64 + 1).read_to_end(&mut output).and_then(|len| {
+                    if len == entry.uncompressed_size {
+                        Ok(output)
+                    } else {
+                        Err(invalid_data(format!("Entry {} inflated to {} bytes instead of {}", entry.name, len, entry.uncompressed_size)))
+                    }
+                })
+            },
+            method@_ => Err(Error::new(ErrorKind::Other, format!("Unsupported compression method {} for entry {}", method, entry.name)))
+        }
+    }
+
+    fn read_central_directory(data: &Vec<u8>) -> Result<Vec<JarEntry>, Error> {
+        let eocd = match JarArchive::find_end_of_central_directory(data) {
+            Some(offset) => offset,
+            None => return Err(invalid_data(String::from("Not a jar archive: end of central directory not found")))
+        };
+
+        let (count, cd_offset) = match (read_u16(data, eocd + 10), read_u32(data, eocd + 16)) {
+            (Some(0xFFFF), _) | (_, Some(0xFFFFFFFF)) => return Err(Error::new(ErrorKind::Other, "Zip64 archives are not supported")),
+            (Some(count), Some(cd_offset)) => (count as usize, cd_offset as usize),
+            _ => return Err(invalid_data(String::from("Truncated end of central directory record")))
+        };
+
+        let mut entries = Vec::with_capacity(count);
+        let mut offset = cd_offset;
+
+        for _ in 0..count {
+            if read_u32(data, offset) != Some(CENTRAL_HEADER_SIGNATURE) {
+                return Err(invalid_data(format!("Invalid central directory header at offset {}", offset)));
+            }
+
+            let fields = (read_u16(data, offset + 8), read_u16(data, offset + 10), read_u32(data, offset + 20), read_u32(data, offset + 24),
+                          read_u16(data, offset + 28), read_u16(data, offset + 30), read_u16(data, offset + 32), read_u32(data, offset + 42));
+
+            match fields {
+                (Some(flags), Some(method), Some(compressed_size), Some(uncompressed_size), Some(name_len), Some(extra_len), Some(comment_len), Some(local_header_offset)) => {
+                    let name = match data.get(offset + 46..offset + 46 + name_len as usize) {
+                        Some(bytes) => String::from_utf8_lossy(bytes).into_owned(),
+                        None => return Err(invalid_data(format!("Truncated central directory header at offset {}", offset)))
+                    };
+
+                    if flags & 0x0001 != 0 {
+                        return Err(Error::new(ErrorKind::Other, format!("Entry {} is encrypted", name)));
+                    }
+
+                    entries.push(JarEntry {
+                        name: name,
+                        method: method,
+                        compressed_size: compressed_size as usize,
+                        uncompressed_size: uncompressed_size as usize,
+                        local_header_offset: local_header_offset as usize
+                    });
+
+                    offset += 46 + name_len as usize + extra_len as usize + comment_len as usize;
+                },
+                _ => return Err(invalid_data(format!("Truncated central directory header at offset {}", offset)))
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// The end of central directory record is followed by a variable length comment, so it has to
+    /// be searched for backwards from the end of the archive
+    fn find_end_of_central_directory(data: &Vec<u8>) -> Option<usize> {
+        if data.len() < END_OF_CENTRAL_DIRECTORY_LEN {
+            return None;
+        }
+
+        let last = data.len() - END_OF_CENTRAL_DIRECTORY_LEN;
+        let first = last.saturating_sub(0xFFFF);
+
+        (first..last + 1).rev().find(|offset| read_u32(data, *offset) == Some(END_OF_CENTRAL_DIRECTORY_SIGNATURE))
+    }
+}
+
+fn invalid_data(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+fn read_u16(data: &Vec<u8>, offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2).map(|b| (b[0] as u16) | ((b[1] as u16) << 8))
+}
+
+fn read_u32(data: &Vec<u8>, offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4).map(|b| (b[0] as u32) | ((b[1] as u32) << 8) | ((b[2] as u32) << 16) | ((b[3] as u32) << 24))
+}
@@ -2,8 +2,13 @@ pub use self::classfile::*;
 pub use self::io::*;
 
 pub mod classfile;
+pub mod code;
+pub mod frames;
 pub mod io;
+pub mod jar;
 pub mod printer;
+pub mod source;
+pub mod verify;
 
 /*
 
@@ -22,6 +22,11 @@ impl ClassfilePrinter {
             .map(|line| lines.push(line))
             .collect();
 
+        let _: Vec<()> = classfile.attributes.iter()
+            .flat_map(|attribute| ClassfilePrinter::render_attribute(attribute, &classfile.constant_pool))
+            .map(|line| lines.push(line))
+            .collect();
+
         let _: Vec<()> = ClassfilePrinter::render_methods(&classfile).iter()
             .map(|method| {
                 format!("{}", method)
@@ -56,25 +61,33 @@ impl ClassfilePrinter {
 
     pub fn render_constant(constant: &Constant, pool: &ConstantPool) -> String {
         match constant {
-            &Constant::Utf8(ref content) => format!("Utf8               {}", String::from_utf8_lossy(content.as_slice())),
-            &Constant::Integer(value) => format!("Integer            {}", value),
-            &Constant::Float(value) => format!("Float               {}", value),
-            &Constant::Long(value) => format!("Long               {}", value),
-            &Constant::Double(value) => format!("Double              {}", value),
-            &Constant::Class(ref index) => format!("Class              #{:<14}// {}", index.idx, ClassfilePrinter::resolve_utf8(index, pool)),
-            &Constant::FieldRef { class_index: ref ci, name_and_type_index: ref ni } => format!("FieldRef           {:<14} // {}.{}", format!("#{}.#{}", ci.idx, ni.idx), ClassfilePrinter::resolve_class(ci, pool), ClassfilePrinter::resolve_name_and_type(ni, &pool)),
-            &Constant::MethodRef { class_index: ref ci, name_and_type_index: ref ni } => format!("MethodRef          {:<14} // {}.{}", format!("#{}.#{}", ci.idx, ni.idx), ClassfilePrinter::resolve_class(ci, pool), ClassfilePrinter::resolve_name_and_type(ni, pool)),
-            &Constant::InterfaceMethodRef { class_index: ref ci, name_and_type_index: ref ni } => format!("InterfaceMethodRef {:<14} // {}.{}", format!("#{}.#{}", ci.idx, ni.idx), ClassfilePrinter::resolve_class(ci, pool), ClassfilePrinter::resolve_name_and_type(ni, pool)),
-            &Constant::String(ref cpi) => format!("String             #{:<14}// {}", cpi.idx, ClassfilePrinter::resolve_utf8(cpi, pool)),
-            &Constant::NameAndType { name_index: ref ni, descriptor_index: ref dp } => format!("NameAndType        {:<14} // {}:{}", format!("#{}:#{}", ni.idx, dp.idx), ClassfilePrinter::resolve_utf8(ni, pool), ClassfilePrinter::resolve_utf8(dp, pool)),
-            &Constant::MethodHandle { reference_kind: ref kind, reference_index: ref ri } => format!("MethodHandle       {} #{}", ClassfilePrinter::resolve_reference_kind(kind), ri.idx),
-            &Constant::MethodType(ref cpi) => format!("MethodType         #{}", cpi.idx),
-            &Constant::InvokeDynamic { bootstrap_method_attr_index: ref bi, name_and_type_index: ref ni } => format!("InvokeDynamic      #{}.{}", bi.idx, ClassfilePrinter::resolve_name_and_type(ni, pool)),
-            &Constant::Unknown(value) => format!("Unknown constant        {}", value),
+            &Constant::Utf8(ref content) => ClassfilePrinter::constant_line("Utf8", String::from_utf8_lossy(content.as_slice()).to_string()),
+            &Constant::Integer(value) => ClassfilePrinter::constant_line("Integer", value.to_string()),
+            &Constant::Float(value) => ClassfilePrinter::constant_line("Float", value.to_string()),
+            &Constant::Long(value) => ClassfilePrinter::constant_line("Long", value.to_string()),
+            &Constant::Double(value) => ClassfilePrinter::constant_line("Double", value.to_string()),
+            &Constant::Class(ref index) => ClassfilePrinter::constant_line("Class", format!("#{:<14}// {}", index.idx, ClassfilePrinter::resolve_utf8(index, pool))),
+            &Constant::FieldRef { class_index: ref ci, name_and_type_index: ref ni } => ClassfilePrinter::constant_line("FieldRef", format!("{:<14} // {}.{}", format!("#{}.#{}", ci.idx, ni.idx), ClassfilePrinter::resolve_class(ci, pool), ClassfilePrinter::resolve_name_and_type(ni, &pool))),
+            &Constant::MethodRef { class_index: ref ci, name_and_type_index: ref ni } => ClassfilePrinter::constant_line("MethodRef", format!("{:<14} // {}.{}", format!("#{}.#{}", ci.idx, ni.idx), ClassfilePrinter::resolve_class(ci, pool), ClassfilePrinter::resolve_name_and_type(ni, pool))),
+            &Constant::InterfaceMethodRef { class_index: ref ci, name_and_type_index: ref ni } => ClassfilePrinter::constant_line("InterfaceMethodRef", format!("{:<14} // {}.{}", format!("#{}.#{}", ci.idx, ni.idx), ClassfilePrinter::resolve_class(ci, pool), ClassfilePrinter::resolve_name_and_type(ni, pool))),
+            &Constant::String(ref cpi) => ClassfilePrinter::constant_line("String", format!("#{:<14}// {}", cpi.idx, ClassfilePrinter::resolve_utf8(cpi, pool))),
+            &Constant::NameAndType { name_index: ref ni, descriptor_index: ref dp } => ClassfilePrinter::constant_line("NameAndType", format!("{:<14} // {}:{}", format!("#{}:#{}", ni.idx, dp.idx), ClassfilePrinter::resolve_utf8(ni, pool), ClassfilePrinter::resolve_utf8(dp, pool))),
+            &Constant::MethodHandle { reference_kind: ref kind, reference_index: ref ri } => ClassfilePrinter::constant_line("MethodHandle", format!("{} #{}", ClassfilePrinter::resolve_reference_kind(kind), ri.idx)),
+            &Constant::MethodType(ref cpi) => ClassfilePrinter::constant_line("MethodType", format!("#{}", cpi.idx)),
+            &Constant::Dynamic { bootstrap_method_attr_index: ref bi, name_and_type_index: ref ni } => ClassfilePrinter::constant_line("Dynamic", format!("#{}.{}", bi.idx, ClassfilePrinter::resolve_name_and_type(ni, pool))),
+            &Constant::InvokeDynamic { bootstrap_method_attr_index: ref bi, name_and_type_index: ref ni } => ClassfilePrinter::constant_line("InvokeDynamic", format!("#{}.{}", bi.idx, ClassfilePrinter::resolve_name_and_type(ni, pool))),
+            &Constant::Module(ref index) => ClassfilePrinter::constant_line("Module", format!("#{:<14}// {}", index.idx, ClassfilePrinter::resolve_utf8(index, pool))),
+            &Constant::Package(ref index) => ClassfilePrinter::constant_line("Package", format!("#{:<14}// {}", index.idx, ClassfilePrinter::resolve_utf8(index, pool))),
+            &Constant::Unknown(value) => ClassfilePrinter::constant_line("Unknown", value.to_string()),
             &Constant::Placeholder => format!("Placeholder")
         }
     }
 
+    /// A constant pool entry, its tag padded so that the details of every entry line up
+    fn constant_line(tag: &str, details: String) -> String {
+        format!("{:<19}{}", tag, details)
+    }
+
     pub fn render_utf8(index: &ConstantPoolIndex, cp: &ConstantPool) -> Option<String> {
         cp.get_utf8_string(index.idx as u16)
     }
@@ -90,6 +103,20 @@ impl ClassfilePrinter {
         }).unwrap_or(String::from("<Not found>"))
     }
 
+    pub fn resolve_module(index: &ConstantPoolIndex, cp: &ConstantPool) -> String {
+        cp.resolve_index(index).map(|constant| match constant {
+            &Constant::Module(ref idx) => ClassfilePrinter::resolve_utf8(idx, cp),
+            _ => String::from("<Not a module>")
+        }).unwrap_or(String::from("<Not found>"))
+    }
+
+    pub fn resolve_package(index: &ConstantPoolIndex, cp: &ConstantPool) -> String {
+        cp.resolve_index(index).map(|constant| match constant {
+            &Constant::Package(ref idx) => ClassfilePrinter::resolve_utf8(idx, cp),
+            _ => String::from("<Not a package>")
+        }).unwrap_or(String::from("<Not found>"))
+    }
+
     pub fn resolve_name_and_type(nandt: &ConstantPoolIndex, cp: &ConstantPool) -> String {
         cp.resolve_index(nandt).map(|constant| match constant {
             &Constant::NameAndType { name_index: ref ni, descriptor_index: ref di } => format!("{}:{}", ClassfilePrinter::resolve_utf8(ni, cp), ClassfilePrinter::resolve_utf8(di, cp)),
@@ -383,6 +410,39 @@ impl ClassfilePrinter {
                 let _: Vec<()> = table.iter().map(|var_type| ClassfilePrinter::render_local_variable_type(var_type)).map(|line| lines.push(format!("    {}", line))).collect();
             },
             &Attribute::Deprecated => { lines.push(format!("    Deprecated")); },
+            &Attribute::SourceDebugExtension(ref bytes) => {
+                lines.push(String::from("    SourceDebugExtension"));
+                let _: Vec<()> = String::from_utf8_lossy(bytes.as_slice()).lines().map(|line| lines.push(format!("      {}", line))).collect();
+            },
+            &Attribute::Module { ref name_index, ref flags, ref version_index, ref requires, ref exports, ref opens, ref uses, ref provides } => {
+                lines.push(format!("    Module #{:<14}// {} {:#06x} {}", name_index.idx, ClassfilePrinter::resolve_module(name_index, cp), flags.flags, ClassfilePrinter::render_utf8(version_index, cp).unwrap_or(String::new())));
+                let _: Vec<()> = requires.iter().map(|r| lines.push(format!("      requires {} {:#06x} {}", ClassfilePrinter::resolve_module(&r.requires_index, cp), r.requires_flags.flags, ClassfilePrinter::render_utf8(&r.requires_version_index, cp).unwrap_or(String::new())))).collect();
+                let _: Vec<()> = exports.iter().map(|e| lines.push(format!("      exports {} {:#06x}{}", ClassfilePrinter::resolve_package(&e.exports_index, cp), e.exports_flags.flags, ClassfilePrinter::render_targets(&e.exports_to_index, cp)))).collect();
+                let _: Vec<()> = opens.iter().map(|o| lines.push(format!("      opens {} {:#06x}{}", ClassfilePrinter::resolve_package(&o.opens_index, cp), o.opens_flags.flags, ClassfilePrinter::render_targets(&o.opens_to_index, cp)))).collect();
+                let _: Vec<()> = uses.iter().map(|u| lines.push(format!("      uses {}", ClassfilePrinter::resolve_class(u, cp)))).collect();
+                let _: Vec<()> = provides.iter().map(|p| lines.push(format!("      provides {} with {}", ClassfilePrinter::resolve_class(&p.provides_index, cp), p.provides_with_index.iter().map(|w| ClassfilePrinter::resolve_class(w, cp)).collect::<Vec<String>>().join(", ")))).collect();
+            },
+            &Attribute::ModulePackages(ref table) => {
+                lines.push(String::from("    ModulePackages"));
+                let _: Vec<()> = table.iter().map(|package| lines.push(format!("      {}", ClassfilePrinter::resolve_package(package, cp)))).collect();
+            },
+            &Attribute::ModuleMainClass(ref cpi) => { lines.push(format!("    ModuleMainClass #{:<14}// {}", cpi.idx, ClassfilePrinter::resolve_class(cpi, cp))); },
+            &Attribute::NestHost(ref cpi) => { lines.push(format!("    NestHost #{:<14}// {}", cpi.idx, ClassfilePrinter::resolve_class(cpi, cp))); },
+            &Attribute::NestMembers(ref table) => {
+                lines.push(String::from("    NestMembers"));
+                let _: Vec<()> = table.iter().map(|member| lines.push(format!("      {}", ClassfilePrinter::resolve_class(member, cp)))).collect();
+            },
+            &Attribute::Record(ref components) => {
+                lines.push(String::from("    Record"));
+                let _: Vec<()> = components.iter().map(|component| {
+                    lines.push(format!("      {} {}", ClassfilePrinter::resolve_utf8(&component.descriptor_index, cp), ClassfilePrinter::resolve_utf8(&component.name_index, cp)));
+                    let _: Vec<()> = component.attributes.iter().flat_map(|att| ClassfilePrinter::render_attribute(att, cp)).map(|line| lines.push(format!("    {}", line))).collect();
+                }).collect();
+            },
+            &Attribute::PermittedSubclasses(ref table) => {
+                lines.push(String::from("    PermittedSubclasses"));
+                let _: Vec<()> = table.iter().map(|subclass| lines.push(format!("      {}", ClassfilePrinter::resolve_class(subclass, cp)))).collect();
+            },
             _ => {
                 lines.push(format!("RandomAttribute"));
                 ()
@@ -414,6 +474,14 @@ impl ClassfilePrinter {
         format!("{}", variable_type.index)
     }
 
+    pub fn render_targets(targets: &Vec<ConstantPoolIndex>, cp: &ConstantPool) -> String {
+        if targets.is_empty() {
+            String::new()
+        } else {
+            format!(" to {}", targets.iter().map(|target| ClassfilePrinter::resolve_module(target, cp)).collect::<Vec<String>>().join(", "))
+        }
+    }
+
     pub fn render_line_number_table(table: &Vec<LineNumberTable>) -> Vec<String> {
         table.iter().map(|line| format!("line {}: {}", line.line_number, line.start_pc)).collect()
     }
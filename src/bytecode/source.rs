@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io::{ Cursor, Error, Read };
+use std::path::{ Path, PathBuf };
+use super::classfile::*;
+use super::io::*;
+use super::jar::*;
+
+const VERSIONS_PREFIX: &'static str = "META-INF/versions/";
+const CLASS_ROOTS: [&'static str; 2] = [ "WEB-INF/classes/", "BOOT-INF/classes/" ];
+
+#[derive(Debug, Clone)]
+enum Origin {
+    File(PathBuf),
+    Archive(usize, JarEntry)
+}
+
+///
+/// A single class file available from a `ClassSource`. Entries are only located when the source
+/// is opened, their contents are read and parsed on demand.
+#[derive(Debug, Clone)]
+pub struct ClassEntry {
+    /// Path of the class file relative to the root of its directory or archive, eg.
+    /// `com/example/Foo.class`. Multi-release overlays are reported under their base name
+    pub name: String,
+    /// Human readable location of the class file, eg. `lib/app.jar!/META-INF/versions/11/com/example/Foo.class`
+    pub location: String,
+    /// The multi-release version this entry was taken from, or `None` for base entries
+    pub release: Option<u32>,
+    origin: Origin
+}
+
+impl ClassEntry {
+    /// The internal name of the class, eg. `com/example/Foo`. Web application and Spring Boot
+    /// class roots are stripped
+    pub fn class_name(&self) -> &str {
+        let name = CLASS_ROOTS.iter().fold(self.name.as_str(), |name, root| if name.starts_with(root) { &name[root.len()..] } else { name });
+
+        if name.ends_with(".class") { &name[..name.len() - 6] } else { name }
+    }
+
+    /// The file this entry was found in, unless it is stored in an archive
+    pub fn file_path(&self) -> Option<&Path> {
+        match self.origin {
+            Origin::File(ref path) => Some(path.as_path()),
+            Origin::Archive(_, _) => None
+        }
+    }
+}
+
+///
+/// A `ClassSource` enumerates the class files found in a single class file, a directory tree or
+/// a jar archive. Jar (and zip) archives found in a directory or nested in another archive, like
+/// `WEB-INF/lib` or `BOOT-INF/lib`, are included as well.
+///
+/// Multi-release archives are resolved the way the JVM does it: an entry under
+/// `META-INF/versions/N/` replaces the base entry of the same name if `N` is the highest version
+/// not above the target release.
+pub struct ClassSource {
+    release: Option<u32>,
+    archives: Vec<JarArchive>,
+    entries: Vec<ClassEntry>
+}
+
+impl ClassSource {
+    /// Open the given path, resolving multi-release archives to their latest versions
+    pub fn open<P>(path: P) -> Result<ClassSource, Error> where P: AsRef<Path> {
+        ClassSource::open_release(path, None)
+    }
+
+    /// Open the given path, resolving multi-release archives for the given Java release. A
+    /// release of `None` selects the latest version of every entry
+    pub fn open_release<P>(path: P, release: Option<u32>) -> Result<ClassSource, Error> where P: AsRef<Path> {
+        let path = path.as_ref();
+        let mut source = ClassSource { release: release, archives: vec![], entries: vec![] };
+
+        if path.is_dir() {
+            source.add_directory(path, path).map(|_| source)
+        } else if is_archive(&path.to_string_lossy()) {
+            JarArchive::open(path).and_then(|archive| source.add_archive(archive, path.to_string_lossy().into_owned())).map(|_| source)
+        } else if path.is_file() {
+            source.add_file(path, path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or(String::new()));
+            Ok(source)
+        } else {
+            File::open(path).map(|_| source)
+        }
+    }
+
+    /// Create a source from an in-memory archive
+    pub fn from_archive(archive: JarArchive, location: &str, release: Option<u32>) -> Result<ClassSource, Error> {
+        let mut source = ClassSource { release: release, archives: vec![], entries: vec![] };
+
+        source.add_archive(archive, location.to_string()).map(|_| source)
+    }
+
+    pub fn entries(&self) -> &Vec<ClassEntry> {
+        &self.entries
+    }
+
+    /// Find the entry of the class with the given internal name, eg. `com/example/Foo`
+    pub fn find(&self, class_name: &str) -> Option<&ClassEntry> {
+        self.entries.iter().find(|entry| entry.class_name() == class_name)
+    }
+
+    pub fn read_bytes(&self, entry: &ClassEntry) -> Result<Vec<u8>, Error> {
+        match entry.origin {
+            Origin::File(ref path) => {
+                let mut bytes: Vec<u8> = vec![];
+
+                File::open(path).and_then(|mut file| file.read_to_end(&mut bytes)).map(|_| bytes)
+            },
+            Origin::Archive(idx, ref jar_entry) => self.archives[idx].read_entry(jar_entry)
+        }
+    }
+
+    pub fn read_class(&self, entry: &ClassEntry) -> Result<Classfile, ClassReadError> {
+        match self.read_bytes(entry) {
+            Ok(bytes) => ClassReader::read_class(&mut Cursor::new(bytes)),
+            Err(err) => Err(ClassReadError::io(0, err))
+        }
+    }
+
+    /// Iterate over all entries, parsing each class as it is reached
+    pub fn classes<'a>(&'a self) -> Classes<'a> {
+        Classes { source: self, position: 0 }
+    }
+
+    fn add_file(&mut self, path: &Path, name: String) {
+        self.entries.push(ClassEntry {
+            name: name,
+            location: path.to_string_lossy().into_owned(),
+            release: None,
+            origin: Origin::File(path.to_path_buf())
+        });
+    }
+
+    fn add_directory(&mut self, root: &Path, dir: &Path) -> Result<(), Error> {
+        let mut children: Vec<PathBuf> = fs::read_dir(dir)?.filter_map(|entry| entry.ok().map(|e| e.path())).collect();
+        children.sort();
+
+        for child in children {
+            let location = child.to_string_lossy().into_owned();
+
+            if child.is_dir() {
+                self.add_directory(root, &child)?;
+            } else if location.ends_with(".class") {
+                let name = child.strip_prefix(root).unwrap_or(&child).components()
+                    .map(|component| component.as_os_str().to_string_lossy().into_owned())
+                    .collect::<Vec<String>>()
+                    .join("/");
+
+                self.add_file(&child, name);
+            } else if is_archive(&location) {
+                let archive = JarArchive::open(&child)?;
+                self.add_archive(archive, location)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn add_archive(&mut self, archive: JarArchive, location: String) -> Result<(), Error> {
+        let archive_idx = self.archives.len();
+        let multi_release = is_multi_release(&archive);
+
+        let mut nested: Vec<(String, Vec<u8>)> = vec![];
+        let mut classes: Vec<ClassEntry> = vec![];
+        let mut index: HashMap<String, usize> = HashMap::new();
+
+        for entry in archive.entries().iter().filter(|entry| !entry.is_directory()) {
+            if is_archive(&entry.name) {
+                nested.push((format!("{}!/{}", location, entry.name), archive.read_entry(entry)?));
+            } else if entry.name.ends_with(".class") {
+                let (name, release) = match versioned_name(&entry.name) {
+                    Some((_, version)) if multi_release && self.release.map(|max| version > max).unwrap_or(false) => continue,
+                    Some((name, version)) if multi_release => (name, Some(version)),
+                    _ => (entry.name.clone(), None)
+                };
+
+                let class_entry = ClassEntry {
+                    name: name,
+                    location: format!("{}!/{}", location, entry.name),
+                    release: release,
+                    origin: Origin::Archive(archive_idx, entry.clone())
+                };
+
+                match index.get(&class_entry.name).map(|idx| *idx) {
+                    Some(idx) => if classes[idx].release < class_entry.release {
+                        classes[idx] = class_entry;
+                    },
+                    None => {
+                        index.insert(class_entry.name.clone(), classes.len());
+                        classes.push(class_entry);
+                    }
+                }
+            }
+        }
+
+        self.archives.push(archive);
+        self.entries.extend(classes);
+
+        nested.into_iter().fold(Ok(()), |acc, (location, bytes)| acc.and_then(|_| {
+            JarArchive::from_bytes(bytes).and_then(|archive| self.add_archive(archive, location))
+        }))
+    }
+}
+
+///
+/// Iterator over the parsed classes of a `ClassSource`
+pub struct Classes<'a> {
+    source: &'a ClassSource,
+    position: usize
+}
+
+impl<'a> Iterator for Classes<'a> {
+    type Item = (&'a ClassEntry, Result<Classfile, ClassReadError>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.source.entries.get(self.position).map(|entry| {
+            self.position += 1;
+            (entry, self.source.read_class(entry))
+        })
+    }
+}
+
+fn is_archive(name: &str) -> bool {
+    name.ends_with(".jar") || name.ends_with(".zip")
+}
+
+/// Split `META-INF/versions/N/name` into the base name and version `N`
+fn versioned_name(name: &str) -> Option<(String, u32)> {
+    if name.starts_with(VERSIONS_PREFIX) {
+        let rest = &name[VERSIONS_PREFIX.len()..];
+
+        rest.find('/').and_then(|split| rest[..split].parse::<u32>().ok().map(|version| (rest[split + 1..].to_string(), version)))
+    } else {
+        None
+    }
+}
+
+fn is_multi_release(archive: &JarArchive) -> bool {
+    archive.find_entry("META-INF/MANIFEST.MF")
+        .and_then(|entry| archive.read_entry(entry).ok())
+        .map(|manifest| String::from_utf8_lossy(&manifest).lines().any(|line| {
+            let mut parts = line.splitn(2, ':');
+
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) => key.trim().eq_ignore_ascii_case("Multi-Release") && value.trim().eq_ignore_ascii_case("true"),
+                _ => false
+            }
+        }))
+        .unwrap_or(false)
+}
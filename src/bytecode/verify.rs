@@ -0,0 +1,213 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::io::Cursor;
+use super::classfile::*;
+use super::io::*;
+
+///
+/// Describes the first byte where a rewritten class file differs from the original one
+#[derive(Debug, PartialEq)]
+pub struct Mismatch {
+    /// Offset of the first differing byte
+    pub offset: usize,
+    /// The original byte at `offset`, or `None` if the original class is shorter
+    pub original: Option<u8>,
+    /// The rewritten byte at `offset`, or `None` if the rewritten class is shorter
+    pub rewritten: Option<u8>,
+    /// The class file structure `offset` falls into, eg. `methods[1] (main) Code instruction #3 (pc 5) IFEQ(7)`
+    pub location: String
+}
+
+#[derive(Debug)]
+pub enum RoundTripError {
+    Read(ClassReadError),
+    Write(io::Error),
+    Mismatch(Mismatch)
+}
+
+impl fmt::Display for RoundTripError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let render_byte = |byte: Option<u8>| byte.map(|b| format!("{:#04x}", b)).unwrap_or(String::from("EOF"));
+
+        match self {
+            &RoundTripError::Read(ref err) => write!(f, "Read failed: {}", err),
+            &RoundTripError::Write(ref err) => write!(f, "Write failed: {}", err),
+            &RoundTripError::Mismatch(ref m) => write!(f, "First difference at offset {} ({:#x}) in {}: original {} rewritten {}",
+                                                       m.offset, m.offset, m.location, render_byte(m.original), render_byte(m.rewritten))
+        }
+    }
+}
+
+impl Error for RoundTripError {
+    fn description(&self) -> &str {
+        "Class file round trip failed"
+    }
+}
+
+///
+/// Reads the given class with `ClassReader`, writes it back with `ClassWriter` and checks that the
+/// output is identical to the input, byte by byte.
+pub fn verify_round_trip(original: &Vec<u8>) -> Result<(), RoundTripError> {
+    let class = match ClassReader::read_class(&mut Cursor::new(original)) {
+        Ok(class) => class,
+        Err(err) => return Err(RoundTripError::Read(err))
+    };
+
+    let mut rewritten: Vec<u8> = vec![];
+
+    if let Err(err) = ClassWriter::new(&mut rewritten).write_class(&class) {
+        return Err(RoundTripError::Write(err));
+    }
+
+    match original.iter().zip(rewritten.iter()).position(|(a, b)| a != b) {
+        None if original.len() == rewritten.len() => Ok(()),
+        position@_ => {
+            let offset = position.unwrap_or(original.len().min(rewritten.len()));
+
+            Err(RoundTripError::Mismatch(Mismatch {
+                offset: offset,
+                original: original.get(offset).map(|b| *b),
+                rewritten: rewritten.get(offset).map(|b| *b),
+                location: locate(&class, offset)
+            }))
+        }
+    }
+}
+
+///
+/// Maps an offset in the serialised form of `class` to the structure containing it. Lengths are
+/// taken from `ClassWriter`, so the result is exact for every byte up to the first point where a
+/// written class differs from the one it was read from.
+pub fn locate(class: &Classfile, offset: usize) -> String {
+    let cp = &class.constant_pool;
+    let mut locator = Locator { target: offset, position: 0 };
+
+    if locator.skip(4) { return String::from("magic"); }
+    if locator.skip(4) { return String::from("version"); }
+    if locator.skip(2) { return String::from("constant_pool count"); }
+
+    for (idx, constant) in cp.constants.iter().enumerate() {
+        if locator.skip(measure(|w| w.write_constant(constant))) {
+            return format!("constant #{} {:?}", idx, constant);
+        }
+    }
+
+    if locator.skip(2) { return String::from("access_flags"); }
+    if locator.skip(2) { return String::from("this_class"); }
+    if locator.skip(2) { return String::from("super_class"); }
+    if locator.skip(2 + class.interfaces.len() * 2) { return String::from("interfaces"); }
+
+    if locator.skip(2) { return String::from("fields count"); }
+
+    for (idx, field) in class.fields.iter().enumerate() {
+        let name = format!("fields[{}] ({})", idx, cp.get_utf8_string(field.name_index.idx as u16).unwrap_or(String::from("?")));
+
+        if let Some(location) = locator.member(&field.attributes, cp) {
+            return format!("{} {}", name, location);
+        }
+    }
+
+    if locator.skip(2) { return String::from("methods count"); }
+
+    for (idx, method) in class.methods.iter().enumerate() {
+        let name = format!("methods[{}] ({})", idx, cp.get_utf8_string(method.name_index.idx as u16).unwrap_or(String::from("?")));
+
+        if let Some(location) = locator.member(&method.attributes, cp) {
+            return format!("{} {}", name, location);
+        }
+    }
+
+    match locator.attributes(&class.attributes, cp) {
+        Some(location) => format!("attributes {}", location),
+        None => String::from("end of class")
+    }
+}
+
+/// Number of bytes the given write operation produces
+fn measure<F>(write: F) -> usize where F: Fn(&mut ClassWriter) -> Result<usize, io::Error> {
+    let mut target: Vec<u8> = vec![];
+    let _ = write(&mut ClassWriter::new(&mut target));
+    target.len()
+}
+
+struct Locator {
+    target: usize,
+    position: usize
+}
+
+impl Locator {
+    /// Skip over a structure of the given length, returning true if the target offset is in it
+    fn skip(&mut self, len: usize) -> bool {
+        if self.target < self.position + len {
+            true
+        } else {
+            self.position += len;
+            false
+        }
+    }
+
+    fn member(&mut self, attributes: &Vec<Attribute>, cp: &ConstantPool) -> Option<String> {
+        if self.skip(6) {
+            Some(String::from("header"))
+        } else {
+            self.attributes(attributes, cp)
+        }
+    }
+
+    fn attributes(&mut self, attributes: &Vec<Attribute>, cp: &ConstantPool) -> Option<String> {
+        if self.skip(2) {
+            return Some(String::from("attributes count"));
+        }
+
+        for attribute in attributes {
+            let start = self.position;
+            let len = measure(|w| w.write_attribute(attribute, cp));
+
+            if self.skip(len) {
+                let name = Locator::attribute_name(attribute, cp);
+
+                return Some(match attribute {
+                    &Attribute::Code { ref code, ref exception_table, ref attributes, .. } => {
+                        self.position = start;
+                        self.code(code, exception_table, attributes, cp).map(|location| format!("{} {}", name, location)).unwrap_or(name)
+                    },
+                    _ => format!("{} +{}", name, self.target - start)
+                });
+            }
+        }
+
+        None
+    }
+
+    fn code(&mut self, code: &Vec<Instruction>, exception_table: &Vec<ExceptionHandler>, attributes: &Vec<Attribute>, cp: &ConstantPool) -> Option<String> {
+        if self.skip(6) { return Some(String::from("header")); }
+        if self.skip(4) { return Some(String::from("max_stack/max_locals")); }
+        if self.skip(4) { return Some(String::from("code_length")); }
+
+        let mut pc = 0;
+
+        for (idx, instruction) in code.iter().enumerate() {
+            let len = measure(|w| Ok(w.render_instruction(instruction, pc)));
+
+            if self.skip(len) {
+                return Some(format!("instruction #{} (pc {}) {:?}", idx, pc, instruction));
+            }
+
+            pc += len;
+        }
+
+        if self.skip(2 + exception_table.len() * 8) {
+            return Some(String::from("exception_table"));
+        }
+
+        self.attributes(attributes, cp)
+    }
+
+    fn attribute_name(attribute: &Attribute, cp: &ConstantPool) -> String {
+        match attribute {
+            &Attribute::RawAttribute { ref name_index, .. } => cp.get_utf8_string(name_index.idx as u16).unwrap_or(String::from("RawAttribute")),
+            _ => format!("{:?}", attribute).split(|c: char| !c.is_alphanumeric()).next().unwrap_or("").to_string()
+        }
+    }
+}
@@ -234,9 +234,9 @@ impl Capabilities {
 
         let native_merged = jvmtiCapabilities {
                 _bindgen_bitfield_1_: native1._bindgen_bitfield_1_ & native2._bindgen_bitfield_1_,
-                _bindgen_bitfield_2_: native1._bindgen_bitfield_1_ & native2._bindgen_bitfield_2_,
-                _bindgen_bitfield_3_: native1._bindgen_bitfield_1_ & native2._bindgen_bitfield_3_,
-                _bindgen_bitfield_4_: native1._bindgen_bitfield_1_ & native2._bindgen_bitfield_4_
+                _bindgen_bitfield_2_: native1._bindgen_bitfield_2_ & native2._bindgen_bitfield_2_,
+                _bindgen_bitfield_3_: native1._bindgen_bitfield_3_ & native2._bindgen_bitfield_3_,
+                _bindgen_bitfield_4_: native1._bindgen_bitfield_4_ & native2._bindgen_bitfield_4_
         };
 
         Capabilities::from_native(&native_merged)
@@ -77,10 +77,12 @@ impl<'a> JavaType<'a> {
 ///
 /// Represents a JNI local reference to a Java class
 ///
+#[derive(Clone, Debug)]
 pub struct ClassId {
     pub native_id: JavaClass
 }
 
+#[derive(Clone, Debug)]
 pub struct ClassSignature {
     pub package: String, // eq Class.getPackage() : java.lang
     pub name: String, //eq Class.getName() : java.lang.String
@@ -2,6 +2,7 @@ use super::config::Config;
 use super::thread::ThreadId;
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use time::Duration;
 use time::Tm;
 use time::now;
@@ -25,14 +26,17 @@ pub fn static_context() -> &'static AgentContext {
 
 pub struct AgentContext {
     context: Arc<RwLock<Context>>,
-    pub config: Arc<RwLock<Config>>
+    pub config: Arc<RwLock<Config>>,
+    /// Bumped whenever the probe patterns may have changed, for the filters built from them to be rebuilt
+    probe_patterns_version: AtomicUsize
 }
 
 impl AgentContext {
     pub fn new() -> AgentContext {
         AgentContext {
             context: Arc::new(RwLock::new(Context::new())),
-            config: Arc::new(RwLock::new(Config::default()))
+            config: Arc::new(RwLock::new(Config::default())),
+            probe_patterns_version: AtomicUsize::new(0)
         }
     }
 
@@ -43,6 +47,8 @@ impl AgentContext {
             },
             Err(_) => { /* TODO */ }
         }
+
+        self.probe_patterns_version.fetch_add(1, Ordering::SeqCst);
     }
 
     pub fn set_trace_enable(&self, enable: bool) {
@@ -53,6 +59,24 @@ impl AgentContext {
         self.config.read().unwrap().trace_enable
     }
 
+    /// Replace the entry points and active classes that select the methods to inject probes into
+    pub fn set_probe_patterns(&self, entry_points: Vec<String>, active_classes: Vec<String>) {
+        match self.config.write() {
+            Ok(mut cfg) => {
+                cfg.entry_points = entry_points;
+                cfg.active_classes = active_classes;
+            },
+            Err(_) => { /* TODO */ }
+        }
+
+        self.probe_patterns_version.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Changes whenever `set_config` or `set_probe_patterns` is called
+    pub fn probe_patterns_version(&self) -> usize {
+        self.probe_patterns_version.load(Ordering::SeqCst)
+    }
+
     pub fn thread_start(&self, thread_id: &ThreadId) {
         match self.context.write() {
             Ok(mut ctx) => {
@@ -141,7 +165,7 @@ impl AgentContext {
 
                 match (*ctx).method_times.get_mut(thread_id) {
                     Some(ref mut thread_stack) => match thread_stack.pop() {
-                        Some(time) => Some(time - now),
+                        Some(time) => Some(now - time),
                         None => None
                     },
                     None => None
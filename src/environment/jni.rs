@@ -1,7 +1,10 @@
-use super::super::native::{JavaObject, JNIEnvPtr};
+use super::super::native::{JavaObject, JavaVMPtr, JNIEnvPtr};
 use super::super::class::ClassId;
-use native::jvmti_native::{jclass, jmethodID};
+use super::super::field::FieldId;
+use native::jvmti_native::{jclass, jmethodID, JNINativeMethod};
+use libc::c_void;
 use std::ffi::CString;
+use std::ptr;
 use native::{JavaMethod, JavaClass, JavaThread, JavaLong};
 
 ///
@@ -17,6 +20,57 @@ pub trait JNI {
     fn get_method_id(&self, clazz: JavaClass, method_name: &str, method_sig: &str ) -> JavaMethod;
 
     fn call_long_method(&self, thread: JavaThread, method_id: JavaMethod) -> JavaLong;
+
+    /// Look up an instance or static field of the given class by name and type signature, eg.
+    /// `timeout`, `J`. Returns `None` and leaves a `NoSuchFieldError` pending if there's no such field.
+    fn get_field_id(&self, class_id: &ClassId, field_name: &str, field_sig: &str, is_static: bool) -> Option<FieldId>;
+
+    /// Look a class up by its binary name, eg. `java.lang.String`, among the classes the given class
+    /// loader is an initiating loader of, with `ClassLoader.findLoadedClass`. Classes are never
+    /// loaded on behalf of the caller, `None` is returned for those that aren't loaded.
+    fn find_loaded_class(&self, loader: &JavaObject, class_name: &str) -> Option<ClassId>;
+
+    /// Return the superclass of the given class, `None` for `java.lang.Object` and interfaces.
+    fn get_superclass(&self, class_id: &ClassId) -> Option<ClassId>;
+
+    /// Clear the exception pending in the current thread, returning whether there was one.
+    fn clear_exception(&self) -> bool;
+
+    /// Delete a local reference once it's no longer needed.
+    fn delete_local_ref(&self, object: JavaObject);
+
+    /// Whether two references refer to the same object
+    fn is_same_object(&self, a: &JavaObject, b: &JavaObject) -> bool;
+
+    /// Create a global reference to an object, valid on any thread until it's deleted
+    fn new_global_ref(&self, object: &JavaObject) -> JavaObject;
+
+    /// Delete a global reference once it's no longer needed
+    fn delete_global_ref(&self, object: JavaObject);
+
+    /// Create a weak global reference to an object, valid on any thread but not keeping the object
+    /// from being collected. It refers to null once it is.
+    fn new_weak_global_ref(&self, object: &JavaObject) -> JavaObject;
+
+    /// Delete a weak global reference once it's no longer needed
+    fn delete_weak_global_ref(&self, object: JavaObject);
+
+    /// Start a new frame of local references, with room for at least `capacity` of them. Returns
+    /// false if the JVM ran out of memory.
+    fn push_local_frame(&self, capacity: usize) -> bool;
+
+    /// Free the local references created since the matching `push_local_frame`
+    fn pop_local_frame(&self);
+
+    /// Define a class from its class file bytes in the given class loader, a null loader meaning
+    /// the bootstrap class loader. Returns `None` if the JVM refused the class.
+    fn define_class(&self, class_name: &str, loader: JavaObject, bytes: &[u8]) -> Option<ClassId>;
+
+    /// Bind a native method of the given class to `function`, returning whether it succeeded.
+    fn register_native(&self, class_id: &ClassId, method_name: &str, method_sig: &str, function: *mut c_void) -> bool;
+
+    /// Return the virtual machine this environment belongs to, which other threads can attach to.
+    fn get_java_vm(&self) -> Option<JavaVMPtr>;
 }
 
 ///
@@ -71,4 +125,151 @@ impl JNI for JNIEnvironment {
             value
         }
     }
+
+    fn get_field_id(&self, class_id: &ClassId, field_name: &str, field_sig: &str, is_static: bool) -> Option<FieldId> {
+        unsafe {
+            let field_name = CString::new(field_name.to_string()).expect("CString::new failed");
+            let field_sig = CString::new(field_sig.to_string()).expect("CString::new failed");
+            let field_id = if is_static {
+                (**self.jni).GetStaticFieldID.unwrap()(self.jni, class_id.native_id, field_name.as_ptr(), field_sig.as_ptr())
+            } else {
+                (**self.jni).GetFieldID.unwrap()(self.jni, class_id.native_id, field_name.as_ptr(), field_sig.as_ptr())
+            };
+
+            if field_id.is_null() { None } else { Some(FieldId { native_id: field_id }) }
+        }
+    }
+
+    fn find_loaded_class(&self, loader: &JavaObject, class_name: &str) -> Option<ClassId> {
+        let loader_class = self.find_class("java/lang/ClassLoader");
+
+        if loader_class.native_id.is_null() {
+            self.clear_exception();
+            return None;
+        }
+
+        let method_id = self.get_method_id(loader_class.native_id, "findLoadedClass", "(Ljava/lang/String;)Ljava/lang/Class;");
+
+        self.delete_local_ref(loader_class.native_id);
+
+        if method_id.is_null() {
+            self.clear_exception();
+            return None;
+        }
+
+        unsafe {
+            let class_name = CString::new(class_name.to_string()).expect("CString::new failed");
+            let name = (**self.jni).NewStringUTF.unwrap()(self.jni, class_name.as_ptr());
+
+            if name.is_null() {
+                self.clear_exception();
+                return None;
+            }
+
+            let class_id = (**self.jni).CallObjectMethod.unwrap()(self.jni, *loader, method_id, name);
+
+            self.delete_local_ref(name);
+
+            if self.clear_exception() || class_id.is_null() { None } else { Some(ClassId { native_id: class_id }) }
+        }
+    }
+
+    fn get_superclass(&self, class_id: &ClassId) -> Option<ClassId> {
+        unsafe {
+            let super_id = (**self.jni).GetSuperclass.unwrap()(self.jni, class_id.native_id);
+
+            if super_id.is_null() { None } else { Some(ClassId { native_id: super_id }) }
+        }
+    }
+
+    fn clear_exception(&self) -> bool {
+        unsafe {
+            let pending = (**self.jni).ExceptionCheck.unwrap()(self.jni) != 0;
+
+            if pending {
+                (**self.jni).ExceptionClear.unwrap()(self.jni);
+            }
+
+            pending
+        }
+    }
+
+    fn delete_local_ref(&self, object: JavaObject) {
+        unsafe {
+            (**self.jni).DeleteLocalRef.unwrap()(self.jni, object);
+        }
+    }
+
+    fn is_same_object(&self, a: &JavaObject, b: &JavaObject) -> bool {
+        unsafe {
+            (**self.jni).IsSameObject.unwrap()(self.jni, *a, *b) != 0
+        }
+    }
+
+    fn new_global_ref(&self, object: &JavaObject) -> JavaObject {
+        unsafe {
+            (**self.jni).NewGlobalRef.unwrap()(self.jni, *object)
+        }
+    }
+
+    fn delete_global_ref(&self, object: JavaObject) {
+        unsafe {
+            (**self.jni).DeleteGlobalRef.unwrap()(self.jni, object);
+        }
+    }
+
+    fn new_weak_global_ref(&self, object: &JavaObject) -> JavaObject {
+        unsafe {
+            (**self.jni).NewWeakGlobalRef.unwrap()(self.jni, *object)
+        }
+    }
+
+    fn delete_weak_global_ref(&self, object: JavaObject) {
+        unsafe {
+            (**self.jni).DeleteWeakGlobalRef.unwrap()(self.jni, object);
+        }
+    }
+
+    fn push_local_frame(&self, capacity: usize) -> bool {
+        unsafe {
+            (**self.jni).PushLocalFrame.unwrap()(self.jni, capacity as i32) == 0
+        }
+    }
+
+    fn pop_local_frame(&self) {
+        unsafe {
+            (**self.jni).PopLocalFrame.unwrap()(self.jni, ptr::null_mut());
+        }
+    }
+
+    fn define_class(&self, class_name: &str, loader: JavaObject, bytes: &[u8]) -> Option<ClassId> {
+        unsafe {
+            let class_name = CString::new(class_name.to_string()).expect("CString::new failed");
+            let class_id = (**self.jni).DefineClass.unwrap()(self.jni, class_name.as_ptr(), loader, bytes.as_ptr() as *const i8, bytes.len() as i32);
+
+            if class_id.is_null() { None } else { Some(ClassId { native_id: class_id }) }
+        }
+    }
+
+    fn register_native(&self, class_id: &ClassId, method_name: &str, method_sig: &str, function: *mut c_void) -> bool {
+        unsafe {
+            let method_name = CString::new(method_name.to_string()).expect("CString::new failed");
+            let method_sig = CString::new(method_sig.to_string()).expect("CString::new failed");
+            let method = JNINativeMethod {
+                name: method_name.as_ptr() as *mut i8,
+                signature: method_sig.as_ptr() as *mut i8,
+                fnPtr: function
+            };
+
+            (**self.jni).RegisterNatives.unwrap()(self.jni, class_id.native_id, &method, 1) == 0
+        }
+    }
+
+    fn get_java_vm(&self) -> Option<JavaVMPtr> {
+        unsafe {
+            let mut vm: JavaVMPtr = ptr::null_mut();
+
+            if (**self.jni).GetJavaVM.unwrap()(self.jni, &mut vm) == 0 && !vm.is_null() { Some(vm) } else { None }
+        }
+    }
 }
@@ -1,7 +1,7 @@
 use super::super::native::{JavaVMPtr, JVMTIEnvPtr};
 use super::super::native::jvmti_native::JVMTI_VERSION;
 use super::super::environment::jvmti::{JVMTI, JVMTIEnvironment};
-use super::super::error::{wrap_error, NativeError};
+use super::super::error::{wrap_error, wrap_jni_error, JNIError, NativeError};
 use libc::c_void;
 use std::ptr;
 use native::jvmti_native::JavaVMAttachArgs;
@@ -12,6 +12,8 @@ use environment::jni::{JNIEnvironment, JNI};
 pub trait JVMF {
     fn get_environment(&self) -> Result<Box<JVMTI>, NativeError>;
     fn attach(&self, thread_name: &str) -> Result<Box<JNI>, NativeError> { Result::Err(wrap_error(999999)) }
+    /// Detach the current thread, which must have been attached with `attach`, from the JVM
+    fn detach(&self) -> Result<(), NativeError> { Result::Err(wrap_error(999999)) }
     fn destroy(&self) -> Result<(), NativeError>;
 }
 ///
@@ -37,15 +39,15 @@ impl JVMF for JVMAgent {
         unsafe {
             let mut void_ptr: *mut c_void = ptr::null_mut() as *mut c_void;
             let penv_ptr: *mut *mut c_void = &mut void_ptr as *mut *mut c_void;
-            let result = wrap_error((**self.vm).GetEnv.unwrap()(self.vm, penv_ptr, JVMTI_VERSION) as u32);
+            let result = wrap_jni_error((**self.vm).GetEnv.unwrap()(self.vm, penv_ptr, JVMTI_VERSION));
 
             match result {
-                NativeError::NoError => {
+                JNIError::NoError => {
                     let env_ptr: JVMTIEnvPtr = *penv_ptr as JVMTIEnvPtr;
                     let env = JVMTIEnvironment::new(env_ptr);
                     return Result::Ok(Box::new(env));
                 },
-                err @ _ => Result::Err(wrap_error(err as u32))
+                err @ _ => Result::Err(NativeError::from(err))
             }
         }
     }
@@ -67,28 +69,34 @@ impl JVMF for JVMAgent {
                 group: std::ptr::null_mut()
             };
             let mut args_ptr =  &mut args as *mut JavaVMAttachArgs;
-            let result = wrap_error((**self.vm).AttachCurrentThreadAsDaemon.unwrap()(self.vm, penv_ptr, args_ptr as *mut c_void) as u32);
+            let result = wrap_jni_error((**self.vm).AttachCurrentThreadAsDaemon.unwrap()(self.vm, penv_ptr, args_ptr as *mut c_void));
             match result {
-                NativeError::NoError => {
+                JNIError::NoError => {
                     //Note: use env_ptr from AttachCurrentThreadAsDaemon will get crash on call jvmti method, but call GetEnv one more time will work fine!
                     let env_ptr: JNIEnvPtr = *penv_ptr as JNIEnvPtr;
                     let env = JNIEnvironment::new(env_ptr);
                     return Result::Ok(Box::new(env));
 //                    return self.get_environment();
                 },
-                err @ _ => Result::Err(wrap_error(err as u32))
+                err @ _ => Result::Err(NativeError::from(err))
             }
         }
     }
 
-    fn destroy(&self) -> Result<(), NativeError> {
+    fn detach(&self) -> Result<(), NativeError> {
         unsafe {
-            let error = (**self.vm).DestroyJavaVM.unwrap()(self.vm) as u32;
+            match wrap_jni_error((**self.vm).DetachCurrentThread.unwrap()(self.vm)) {
+                JNIError::NoError => Ok(()),
+                err @ _ => Err(NativeError::from(err))
+            }
+        }
+    }
 
-            if error == 0 {
-                Ok(())
-            } else {
-                Err(wrap_error(error))
+    fn destroy(&self) -> Result<(), NativeError> {
+        unsafe {
+            match wrap_jni_error((**self.vm).DestroyJavaVM.unwrap()(self.vm)) {
+                JNIError::NoError => Ok(()),
+                err @ _ => Err(NativeError::from(err))
             }
         }
     }
@@ -1,18 +1,21 @@
 use super::super::capabilities::Capabilities;
 use super::super::class::{ClassId, ClassSignature, JavaType};
 use super::super::error::{wrap_error, NativeError};
-use super::super::event::{EventCallbacks, VMEvent};
+use super::super::event::VMEvent;
 use super::super::event_handler::*;
+use super::super::field::{FieldId, FieldSignature};
+use super::super::heap::{heap_iteration_callback, heap_reference_callback, FnHeapIteration, FnHeapReference, HeapFilter};
 use super::super::mem::MemoryAllocation;
-use super::super::method::{MethodId, MethodSignature};
-use super::super::thread::{ThreadId, Thread};
+use super::super::monitor::{MonitorUsage, OwnedMonitor};
+use super::super::method::{LineNumberEntry, LocalVariableEntry, MethodId, MethodSignature};
+use super::super::thread::{ThreadId, Thread, ThreadState};
 use super::super::util::stringify;
 use super::super::version::VersionNumber;
 use super::super::native::{MutString, MutByteArray, JavaClass, JavaObject, JavaInstance, JavaLong, JavaThread, JVMTIEnvPtr, JavaInt};
-use super::super::native::jvmti_native::{Struct__jvmtiThreadInfo, jvmtiCapabilities, jint, jvmtiStackInfo, jthread, jvmtiFrameInfo, jlong, jvmtiTimerInfo};
+use super::super::native::jvmti_native::{Struct__jvmtiThreadInfo, Struct__jvmtiThreadGroupInfo, jvmtiCapabilities, jint, jvmtiStackInfo, jthread, jvmtiFrameInfo, jlong, jvmtiTimerInfo};
 use std::ptr;
 use native::jvmti_native::*;
-use std::os::raw::{c_char, c_uchar};
+use std::os::raw::{c_char, c_uchar, c_void};
 use native::{JavaMethod, JNIEnvPtr};
 
 
@@ -39,17 +42,164 @@ pub trait JVMTI {
     /// are sent before this function is called. When an entry is None no event is sent.
     /// An event must be enabled and have a callback in order to be sent--the order in which this
     /// function and set_event_notification_mode are called does not affect the result.
-    fn set_event_callbacks(&mut self, callbacks: EventCallbacks) -> Option<NativeError>;
+    /// Install the native event callbacks, which dispatch events to the handlers subscribed in
+    /// `event_handler::CALLBACK_TABLE`.
+    fn set_event_callbacks(&mut self) -> Option<NativeError>;
     fn set_event_notification_mode(&mut self, event: VMEvent, mode: bool) -> Option<NativeError>;
+    /// Enable or disable an event for a single thread. An event is sent to a thread when it's
+    /// enabled either globally or for that thread.
+    fn set_thread_event_notification_mode(&mut self, event: VMEvent, thread_id: &JavaThread, mode: bool) -> Result<(), NativeError>;
+    /// Send the CompiledMethodLoad or DynamicCodeGenerated events of the code generated before
+    /// the event was enabled, such as before the agent was attached.
+    fn generate_events(&self, event: VMEvent) -> Result<(), NativeError>;
     fn get_thread_info(&self, thread_id: &JavaThread) -> Result<Thread, NativeError>;
     fn get_method_declaring_class(&self, method_id: &MethodId) -> Result<ClassId, NativeError>;
     fn get_method_name(&self, method_id: &MethodId) -> Result<MethodSignature, NativeError>;
     fn get_class_signature(&self, class_id: &ClassId) -> Result<ClassSignature, NativeError>;
+    /// Return the name of the source file a class was compiled from, without the path. Requires
+    /// the can_get_source_file_name capability.
+    fn get_source_file_name(&self, class_id: &ClassId) -> Result<String, NativeError>;
+    /// Return a local reference to the class loader that defined a class, `None` for the bootstrap
+    /// class loader.
+    fn get_class_loader(&self, class_id: &ClassId) -> Result<Option<JavaObject>, NativeError>;
+    /// Return the identity hash code of an object, which doesn't change for as long as the object lives.
+    fn get_object_hash_code(&self, object: &JavaObject) -> Result<JavaInt, NativeError>;
+    /// Return the tag of an object, zero if it isn't tagged. Requires the can_tag_objects capability.
+    fn get_tag(&self, object: &JavaObject) -> Result<JavaLong, NativeError>;
+    /// Tag an object, zero removing its tag. Tags are kept by the JVM for as long as the object
+    /// lives. Requires the can_tag_objects capability.
+    fn set_tag(&self, object: &JavaObject, tag: JavaLong) -> Result<(), NativeError>;
+    /// Return the objects tagged with any of the given tags, as local references, along with their
+    /// tags. Requires the can_tag_objects capability.
+    fn get_objects_with_tags(&self, tags: &Vec<JavaLong>) -> Result<Vec<(JavaObject, JavaLong)>, NativeError>;
+    /// Return the size of an object in bytes, an approximation that may differ between JVMs.
+    fn get_object_size(&self, object: &JavaObject) -> Result<JavaLong, NativeError>;
+    /// Call `callback` with every object on the heap, reachable or not, that passes the filter and is
+    /// an instance of `class_id` if given. Requires the can_tag_objects capability.
+    fn iterate_through_heap(&self, filter: HeapFilter, class_id: Option<&ClassId>, callback: &mut FnHeapIteration) -> Result<(), NativeError>;
+    /// Call `callback` with every reference reachable from `initial_object`, or from the heap roots if
+    /// none is given, following references for as long as the callback returns
+    /// `HeapVisit::Continue`. The filter and `class_id` only select the objects reported, they
+    /// don't stop references from being followed. Requires the can_tag_objects capability.
+    fn follow_references(&self, filter: HeapFilter, class_id: Option<&ClassId>, initial_object: Option<&JavaObject>, callback: &mut FnHeapReference) -> Result<(), NativeError>;
+    /// Return the line number table of a method, which maps bytecode locations to source lines.
+    /// Requires the can_get_line_numbers capability.
+    fn get_line_number_table(&self, method_id: &MethodId) -> Result<Vec<LineNumberEntry>, NativeError>;
+    /// Return the local variable table of a method. Requires the can_access_local_variables capability
+    /// and classes compiled with debug information.
+    fn get_local_variable_table(&self, method_id: &MethodId) -> Result<Vec<LocalVariableEntry>, NativeError>;
+    /// Return the name and type signature of a field of the given class.
+    fn get_field_name(&self, class_id: &ClassId, field_id: &FieldId) -> Result<FieldSignature, NativeError>;
+    /// Generate a FieldAccess event whenever the given field is read by Java code. Requires the
+    /// can_generate_field_access_events capability.
+    fn set_field_access_watch(&self, class_id: &ClassId, field_id: &FieldId) -> Result<(), NativeError>;
+    fn clear_field_access_watch(&self, class_id: &ClassId, field_id: &FieldId) -> Result<(), NativeError>;
+    /// Generate a FieldModification event whenever the given field is written by Java code.
+    /// Requires the can_generate_field_modification_events capability.
+    fn set_field_modification_watch(&self, class_id: &ClassId, field_id: &FieldId) -> Result<(), NativeError>;
+    fn clear_field_modification_watch(&self, class_id: &ClassId, field_id: &FieldId) -> Result<(), NativeError>;
+    /// Generate a Breakpoint event whenever the given bytecode location of a method is about to be
+    /// executed. Requires the can_generate_breakpoint_events capability.
+    fn set_breakpoint(&self, method_id: &MethodId, location: JavaLong) -> Result<(), NativeError>;
+    fn clear_breakpoint(&self, method_id: &MethodId, location: JavaLong) -> Result<(), NativeError>;
+    /// Generate a FramePop event when the frame at the given depth of the stack of a thread returns
+    /// or is popped by an exception. The thread must be suspended or be the current thread.
+    /// Requires the can_generate_frame_pop_events capability.
+    fn notify_frame_pop(&self, thread_id: &JavaThread, depth: usize) -> Result<(), NativeError>;
+    /// Determines whether a class object reference represents an interface.
+    fn is_interface(&self, class_id: &ClassId) -> Result<bool, NativeError>;
     fn allocate(&self, len: usize) -> Result<MemoryAllocation, NativeError>;
     fn deallocate(&self, ptr: *mut i8);
+    /// Return all classes loaded in the virtual machine, as local references. Array classes are
+    /// included, primitive classes are not.
+    fn get_loaded_classes(&self) -> Result<Vec<ClassId>, NativeError>;
+    /// Return the methods declared by a class, constructors and static initializers included,
+    /// inherited methods excluded.
+    fn get_class_methods(&self, class_id: &ClassId) -> Result<Vec<MethodId>, NativeError>;
+    /// Determines whether a class can be retransformed or redefined. Primitive and array classes
+    /// never can, and neither can some system classes.
+    fn is_modifiable_class(&self, class_id: &ClassId) -> Result<bool, NativeError>;
+    /// Run the given classes through the ClassFileLoadHook again, starting from the class file
+    /// bytes they were originally loaded from. Requires the can_retransform_classes capability.
+    fn retransform_classes(&self, classes: &Vec<ClassId>) -> Result<(), NativeError>;
+    /// Replace the definitions of the given classes with new class file bytes. Threads keep
+    /// running the old versions of methods already on their stack. Requires the
+    /// can_redefine_classes capability.
+    fn redefine_classes(&self, definitions: &Vec<ClassDefinition>) -> Result<(), NativeError>;
 
-    fn get_all_stacktraces(&self) -> Result<Vec<JavaStackTrace>, NativeError>;
+    /// Return the stack traces of all live threads, at most `max_frame_count` frames of each,
+    /// starting with the current frames.
+    fn get_all_stacktraces(&self, max_frame_count: usize) -> Result<Vec<JavaStackTrace>, NativeError>;
+    /// Return the stack traces of the given threads, at most `max_frame_count` frames of each,
+    /// starting with the current frames.
+    fn get_thread_list_stack_traces(&self, threads: &Vec<JavaThread>, max_frame_count: usize) -> Result<Vec<JavaStackTrace>, NativeError>;
+    /// Return at most `max_frame_count` frames of the stack of a thread, starting at `start_depth`.
+    /// A zero depth is the current frame, a negative one counts from the bottom of the stack, eg.
+    /// -1 returns the root frame only.
+    fn get_stack_trace(&self, thread_id: &JavaThread, start_depth: JavaInt, max_frame_count: usize) -> Result<Vec<JavaStackFrame>, NativeError>;
+    /// Return the number of frames on the stack of a thread.
+    fn get_frame_count(&self, thread_id: &JavaThread) -> Result<usize, NativeError>;
+    /// Return the method and the bytecode location being executed at the given depth of the stack
+    /// of a thread, the current frame being at depth zero.
+    fn get_frame_location(&self, thread_id: &JavaThread, depth: usize) -> Result<JavaStackFrame, NativeError>;
+    /// Return the object `this` of the method executing at the given depth of the stack of a thread,
+    /// as a local reference. Fails for static and native methods. Requires the
+    /// can_access_local_variables capability, like the other local variable functions, which also
+    /// require the thread to be suspended or to be the current thread.
+    fn get_local_instance(&self, thread_id: &JavaThread, depth: usize) -> Result<JavaObject, NativeError>;
+    /// Return the value of a local variable of type object, the slot being given by the local variable table. The object is returned as a local reference.
+    fn get_local_object(&self, thread_id: &JavaThread, depth: usize, slot: u32) -> Result<JavaObject, NativeError>;
+    /// Return the value of a local variable of type int, the slot being given by the local variable table.
+    fn get_local_int(&self, thread_id: &JavaThread, depth: usize, slot: u32) -> Result<JavaInt, NativeError>;
+    /// Return the value of a local variable of type long, the slot being given by the local variable table.
+    fn get_local_long(&self, thread_id: &JavaThread, depth: usize, slot: u32) -> Result<JavaLong, NativeError>;
+    /// Return the value of a local variable of type float, the slot being given by the local variable table.
+    fn get_local_float(&self, thread_id: &JavaThread, depth: usize, slot: u32) -> Result<f32, NativeError>;
+    /// Return the value of a local variable of type double, the slot being given by the local variable table.
+    fn get_local_double(&self, thread_id: &JavaThread, depth: usize, slot: u32) -> Result<f64, NativeError>;
+    /// Change the value of a local variable of type object
+    fn set_local_object(&self, thread_id: &JavaThread, depth: usize, slot: u32, value: JavaObject) -> Result<(), NativeError>;
+    /// Change the value of a local variable of type int
+    fn set_local_int(&self, thread_id: &JavaThread, depth: usize, slot: u32, value: JavaInt) -> Result<(), NativeError>;
+    /// Change the value of a local variable of type long
+    fn set_local_long(&self, thread_id: &JavaThread, depth: usize, slot: u32, value: JavaLong) -> Result<(), NativeError>;
+    /// Change the value of a local variable of type float
+    fn set_local_float(&self, thread_id: &JavaThread, depth: usize, slot: u32, value: f32) -> Result<(), NativeError>;
+    /// Change the value of a local variable of type double
+    fn set_local_double(&self, thread_id: &JavaThread, depth: usize, slot: u32, value: f64) -> Result<(), NativeError>;
     fn get_all_threads(&self) -> Result<Vec<ThreadId>, NativeError>;
+    /// Return the name of the thread group a thread belongs to. The thread group and its parent
+    /// are left as local references in the current local frame.
+    fn get_thread_group_name(&self, thread_id: &JavaThread) -> Result<String, NativeError>;
+    /// Return the state of a thread.
+    fn get_thread_state(&self, thread_id: &JavaThread) -> Result<ThreadState, NativeError>;
+    /// Suspend a thread until it's resumed. Suspending the current thread only returns once another
+    /// thread resumes it. Requires the can_suspend capability, like the other suspend and resume
+    /// functions.
+    fn suspend_thread(&self, thread_id: &JavaThread) -> Result<(), NativeError>;
+    /// Resume a suspended thread.
+    fn resume_thread(&self, thread_id: &JavaThread) -> Result<(), NativeError>;
+    /// Suspend a list of threads, returning the result of suspending each of them, in order.
+    fn suspend_thread_list(&self, threads: &Vec<JavaThread>) -> Result<Vec<Result<(), NativeError>>, NativeError>;
+    /// Resume a list of threads, returning the result of resuming each of them, in order.
+    fn resume_thread_list(&self, threads: &Vec<JavaThread>) -> Result<Vec<Result<(), NativeError>>, NativeError>;
+    /// Interrupt a thread, like `Thread.interrupt()`. Requires the can_signal_thread capability.
+    fn interrupt_thread(&self, thread_id: &JavaThread) -> Result<(), NativeError>;
+    /// Make a thread throw the given exception object asynchronously, like `Thread.stop()`.
+    /// Requires the can_signal_thread capability.
+    fn stop_thread(&self, thread_id: &JavaThread, exception: &JavaObject) -> Result<(), NativeError>;
+    /// Return the monitors owned by a thread, as local references. Requires the
+    /// can_get_owned_monitor_info capability.
+    fn get_owned_monitor_info(&self, thread_id: &JavaThread) -> Result<Vec<JavaObject>, NativeError>;
+    /// Return the monitors owned by a thread along with the depth of the frame that locked each of
+    /// them. Requires the can_get_owned_monitor_stack_depth_info capability.
+    fn get_owned_monitor_stack_depth_info(&self, thread_id: &JavaThread) -> Result<Vec<OwnedMonitor>, NativeError>;
+    /// Return the monitor a thread is waiting to enter, or to enter again in `Object.wait()`, as a
+    /// local reference. Requires the can_get_current_contended_monitor capability.
+    fn get_current_contended_monitor(&self, thread_id: &JavaThread) -> Result<Option<JavaObject>, NativeError>;
+    /// Return the owner of the monitor of an object and the threads waiting for it. Requires the
+    /// can_get_monitor_info capability.
+    fn get_object_monitor_usage(&self, object: &JavaObject) -> Result<MonitorUsage, NativeError>;
     fn get_thread_cpu_time(&self, thread_id: &JavaThread) -> Result<JavaLong, NativeError>;
     fn get_thread_cpu_timer_info(&self) -> Result<jvmtiTimerInfo, NativeError>;
 
@@ -65,6 +215,49 @@ impl JVMTIEnvironment {
     pub fn new(env_ptr: JVMTIEnvPtr) -> JVMTIEnvironment {
         JVMTIEnvironment { jvmti: env_ptr }
     }
+
+    /// Copy the stack traces returned by the JVM, deallocating them
+    unsafe fn read_stack_traces(&self, stack_info_ptr: *mut jvmtiStackInfo, thread_count: jint) -> Vec<JavaStackTrace> {
+        let stack_traces = std::slice::from_raw_parts(stack_info_ptr, thread_count as usize).iter()
+            .map(|stack_info| JavaStackTrace {
+                thread: stack_info.thread,
+                state: stack_info.state,
+                frame_buffer: read_frames(stack_info.frame_buffer, stack_info.frame_count)
+            })
+            .collect();
+
+        self.deallocate(stack_info_ptr as *mut i8);
+        stack_traces
+    }
+}
+
+/// Convert the per-thread errors of the thread list functions
+fn read_thread_list_results(results: &Vec<jvmtiError>) -> Vec<Result<(), NativeError>> {
+    results.iter()
+        .map(|result| match wrap_error(*result) {
+            NativeError::NoError => Ok(()),
+            err @ _ => Err(err)
+        })
+        .collect()
+}
+
+/// Copy an array of objects returned by the JVM, which may be null if it's empty
+unsafe fn read_objects(objects_ptr: *const jobject, object_count: jint) -> Vec<JavaObject> {
+    if objects_ptr.is_null() {
+        vec![]
+    } else {
+        std::slice::from_raw_parts(objects_ptr, object_count as usize).to_vec()
+    }
+}
+
+unsafe fn read_frames(frame_buffer: *const jvmtiFrameInfo, frame_count: jint) -> Vec<JavaStackFrame> {
+    if frame_buffer.is_null() {
+        return vec![];
+    }
+
+    std::slice::from_raw_parts(frame_buffer, frame_count as usize).iter()
+        .map(|frame| JavaStackFrame { method: frame.method, location: frame.location })
+        .collect()
 }
 
 impl JVMTI for JVMTIEnvironment {
@@ -115,28 +308,9 @@ impl JVMTI for JVMTIEnvironment {
         }
     }
 
-    fn set_event_callbacks(&mut self, callbacks: EventCallbacks) -> Option<NativeError> {
-        register_vm_init_callback(callbacks.vm_init);
-        register_vm_start_callback(callbacks.vm_start);
-        register_vm_death_callback(callbacks.vm_death);
-        register_vm_object_alloc_callback(callbacks.vm_object_alloc);
-        register_method_entry_callback(callbacks.method_entry);
-        register_method_exit_callback(callbacks.method_exit);
-        register_thread_start_callback(callbacks.thread_start);
-        register_thread_end_callback(callbacks.thread_end);
-        register_exception_callback(callbacks.exception);
-        register_exception_catch_callback(callbacks.exception_catch);
-        register_monitor_wait_callback(callbacks.monitor_wait);
-        register_monitor_waited_callback(callbacks.monitor_waited);
-        register_monitor_contended_enter_callback(callbacks.monitor_contended_enter);
-        register_monitor_contended_endered_callback(callbacks.monitor_contended_entered);
-        register_field_access_callback(callbacks.field_access);
-        register_field_modification_callback(callbacks.field_modification);
-        register_garbage_collection_start(callbacks.garbage_collection_start);
-        register_garbage_collection_finish(callbacks.garbage_collection_finish);
-        register_class_file_load_hook(callbacks.class_file_load_hook);
-
+    fn set_event_callbacks(&mut self) -> Option<NativeError> {
         let (native_callbacks, callbacks_size) = registered_callbacks();
+        set_event_environment(self.jvmti);
 
         unsafe {
             match wrap_error((**self.jvmti).SetEventCallbacks.unwrap()(self.jvmti, &native_callbacks, callbacks_size)) {
@@ -162,6 +336,26 @@ impl JVMTI for JVMTIEnvironment {
         }
     }
 
+    fn set_thread_event_notification_mode(&mut self, event: VMEvent, thread_id: &JavaThread, mode: bool) -> Result<(), NativeError> {
+        let mode_i = match mode { true => 1, false => 0 };
+
+        unsafe {
+            match wrap_error((**self.jvmti).SetEventNotificationMode.unwrap()(self.jvmti, mode_i, event as u32, *thread_id)) {
+                NativeError::NoError => Ok(()),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn generate_events(&self, event: VMEvent) -> Result<(), NativeError> {
+        unsafe {
+            match wrap_error((**self.jvmti).GenerateEvents.unwrap()(self.jvmti, event as u32)) {
+                NativeError::NoError => Ok(()),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
     fn get_thread_info(&self, thread_id: &JavaThread) -> Result<Thread, NativeError> {
         let mut info = Struct__jvmtiThreadInfo { name: ptr::null_mut(), priority: 0, is_daemon: 0, thread_group: ptr::null_mut(), context_class_loader: ptr::null_mut()};
         let mut info_ptr = &mut info;
@@ -245,6 +439,269 @@ impl JVMTI for JVMTIEnvironment {
         }
     }
 
+    fn get_source_file_name(&self, class_id: &ClassId) -> Result<String, NativeError> {
+        let mut source_name: MutString = ptr::null_mut();
+
+        unsafe {
+            match wrap_error((**self.jvmti).GetSourceFileName.unwrap()(self.jvmti, class_id.native_id, &mut source_name)) {
+                NativeError::NoError => {
+                    let file_name = stringify(source_name);
+                    self.deallocate(source_name);
+                    Ok(file_name)
+                },
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn get_class_loader(&self, class_id: &ClassId) -> Result<Option<JavaObject>, NativeError> {
+        let mut loader: JavaObject = ptr::null_mut();
+
+        unsafe {
+            match wrap_error((**self.jvmti).GetClassLoader.unwrap()(self.jvmti, class_id.native_id, &mut loader)) {
+                NativeError::NoError => Ok(if loader.is_null() { None } else { Some(loader) }),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn get_object_hash_code(&self, object: &JavaObject) -> Result<JavaInt, NativeError> {
+        let mut hash_code: JavaInt = 0;
+
+        unsafe {
+            match wrap_error((**self.jvmti).GetObjectHashCode.unwrap()(self.jvmti, *object, &mut hash_code)) {
+                NativeError::NoError => Ok(hash_code),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn get_tag(&self, object: &JavaObject) -> Result<JavaLong, NativeError> {
+        let mut tag: JavaLong = 0;
+
+        unsafe {
+            match wrap_error((**self.jvmti).GetTag.unwrap()(self.jvmti, *object, &mut tag)) {
+                NativeError::NoError => Ok(tag),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn set_tag(&self, object: &JavaObject, tag: JavaLong) -> Result<(), NativeError> {
+        unsafe {
+            match wrap_error((**self.jvmti).SetTag.unwrap()(self.jvmti, *object, tag)) {
+                NativeError::NoError => Ok(()),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn get_objects_with_tags(&self, tags: &Vec<JavaLong>) -> Result<Vec<(JavaObject, JavaLong)>, NativeError> {
+        let mut object_count: jint = 0;
+        let mut objects_ptr: *mut jobject = ptr::null_mut();
+        let mut tags_ptr: *mut jlong = ptr::null_mut();
+
+        unsafe {
+            match wrap_error((**self.jvmti).GetObjectsWithTags.unwrap()(self.jvmti, tags.len() as jint, tags.as_ptr(), &mut object_count, &mut objects_ptr, &mut tags_ptr)) {
+                NativeError::NoError => {
+                    let objects = std::slice::from_raw_parts(objects_ptr, object_count as usize).iter().cloned()
+                        .zip(std::slice::from_raw_parts(tags_ptr, object_count as usize).iter().cloned())
+                        .collect();
+
+                    self.deallocate(objects_ptr as *mut i8);
+                    self.deallocate(tags_ptr as *mut i8);
+                    Ok(objects)
+                },
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn get_object_size(&self, object: &JavaObject) -> Result<JavaLong, NativeError> {
+        let mut size: jlong = 0;
+
+        unsafe {
+            match wrap_error((**self.jvmti).GetObjectSize.unwrap()(self.jvmti, *object, &mut size)) {
+                NativeError::NoError => Ok(size),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn iterate_through_heap(&self, filter: HeapFilter, class_id: Option<&ClassId>, callback: &mut FnHeapIteration) -> Result<(), NativeError> {
+        let mut callbacks = jvmtiHeapCallbacks::default();
+        callbacks.heap_iteration_callback = Some(heap_iteration_callback);
+        let mut callback = callback;
+        let klass = class_id.map(|class_id| class_id.native_id).unwrap_or(ptr::null_mut());
+
+        unsafe {
+            match wrap_error((**self.jvmti).IterateThroughHeap.unwrap()(self.jvmti, filter.bits() as jint, klass, &callbacks, &mut callback as *mut &mut FnHeapIteration as *const c_void)) {
+                NativeError::NoError => Ok(()),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn follow_references(&self, filter: HeapFilter, class_id: Option<&ClassId>, initial_object: Option<&JavaObject>, callback: &mut FnHeapReference) -> Result<(), NativeError> {
+        let mut callbacks = jvmtiHeapCallbacks::default();
+        callbacks.heap_reference_callback = Some(heap_reference_callback);
+        let mut callback = callback;
+        let klass = class_id.map(|class_id| class_id.native_id).unwrap_or(ptr::null_mut());
+        let initial_object = initial_object.map(|object| *object).unwrap_or(ptr::null_mut());
+
+        unsafe {
+            match wrap_error((**self.jvmti).FollowReferences.unwrap()(self.jvmti, filter.bits() as jint, klass, initial_object, &callbacks, &mut callback as *mut &mut FnHeapReference as *const c_void)) {
+                NativeError::NoError => Ok(()),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn get_line_number_table(&self, method_id: &MethodId) -> Result<Vec<LineNumberEntry>, NativeError> {
+        let mut entry_count: jint = 0;
+        let mut table_ptr: *mut jvmtiLineNumberEntry = ptr::null_mut();
+
+        unsafe {
+            match wrap_error((**self.jvmti).GetLineNumberTable.unwrap()(self.jvmti, method_id.native_id, &mut entry_count, &mut table_ptr)) {
+                NativeError::NoError => {
+                    let table = std::slice::from_raw_parts(table_ptr, entry_count as usize).iter()
+                        .map(|entry| LineNumberEntry { start_location: entry.start_location, line_number: entry.line_number as u32 })
+                        .collect();
+
+                    self.deallocate(table_ptr as *mut i8);
+                    Ok(table)
+                },
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn get_local_variable_table(&self, method_id: &MethodId) -> Result<Vec<LocalVariableEntry>, NativeError> {
+        let mut entry_count: jint = 0;
+        let mut table_ptr: *mut jvmtiLocalVariableEntry = ptr::null_mut();
+
+        unsafe {
+            match wrap_error((**self.jvmti).GetLocalVariableTable.unwrap()(self.jvmti, method_id.native_id, &mut entry_count, &mut table_ptr)) {
+                NativeError::NoError => {
+                    let table = std::slice::from_raw_parts(table_ptr, entry_count as usize).iter()
+                        .map(|entry| {
+                            let variable = LocalVariableEntry {
+                                start_location: entry.start_location,
+                                length: entry.length as u32,
+                                name: stringify(entry.name),
+                                signature: stringify(entry.signature),
+                                generic: if entry.generic_signature.is_null() { None } else { Some(stringify(entry.generic_signature)) },
+                                slot: entry.slot as u32
+                            };
+
+                            self.deallocate(entry.name);
+                            self.deallocate(entry.signature);
+                            self.deallocate(entry.generic_signature);
+                            variable
+                        })
+                        .collect();
+
+                    self.deallocate(table_ptr as *mut i8);
+                    Ok(table)
+                },
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn get_field_name(&self, class_id: &ClassId, field_id: &FieldId) -> Result<FieldSignature, NativeError> {
+        let mut field_name: MutString = ptr::null_mut();
+        let mut signature: MutString = ptr::null_mut();
+        let mut generic_sig: MutString = ptr::null_mut();
+
+        unsafe {
+            match wrap_error((**self.jvmti).GetFieldName.unwrap()(self.jvmti, class_id.native_id, field_id.native_id, &mut field_name, &mut signature, &mut generic_sig)) {
+                NativeError::NoError => {
+                    let field_signature = FieldSignature::new(stringify(field_name), stringify(signature), stringify(generic_sig));
+                    self.deallocate(field_name);
+                    self.deallocate(signature);
+                    self.deallocate(generic_sig);
+                    Ok(field_signature)
+                },
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn set_field_access_watch(&self, class_id: &ClassId, field_id: &FieldId) -> Result<(), NativeError> {
+        unsafe {
+            match wrap_error((**self.jvmti).SetFieldAccessWatch.unwrap()(self.jvmti, class_id.native_id, field_id.native_id)) {
+                NativeError::NoError => Ok(()),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn clear_field_access_watch(&self, class_id: &ClassId, field_id: &FieldId) -> Result<(), NativeError> {
+        unsafe {
+            match wrap_error((**self.jvmti).ClearFieldAccessWatch.unwrap()(self.jvmti, class_id.native_id, field_id.native_id)) {
+                NativeError::NoError => Ok(()),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn set_field_modification_watch(&self, class_id: &ClassId, field_id: &FieldId) -> Result<(), NativeError> {
+        unsafe {
+            match wrap_error((**self.jvmti).SetFieldModificationWatch.unwrap()(self.jvmti, class_id.native_id, field_id.native_id)) {
+                NativeError::NoError => Ok(()),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn clear_field_modification_watch(&self, class_id: &ClassId, field_id: &FieldId) -> Result<(), NativeError> {
+        unsafe {
+            match wrap_error((**self.jvmti).ClearFieldModificationWatch.unwrap()(self.jvmti, class_id.native_id, field_id.native_id)) {
+                NativeError::NoError => Ok(()),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn set_breakpoint(&self, method_id: &MethodId, location: JavaLong) -> Result<(), NativeError> {
+        unsafe {
+            match wrap_error((**self.jvmti).SetBreakpoint.unwrap()(self.jvmti, method_id.native_id, location)) {
+                NativeError::NoError => Ok(()),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn clear_breakpoint(&self, method_id: &MethodId, location: JavaLong) -> Result<(), NativeError> {
+        unsafe {
+            match wrap_error((**self.jvmti).ClearBreakpoint.unwrap()(self.jvmti, method_id.native_id, location)) {
+                NativeError::NoError => Ok(()),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn notify_frame_pop(&self, thread_id: &JavaThread, depth: usize) -> Result<(), NativeError> {
+        unsafe {
+            match wrap_error((**self.jvmti).NotifyFramePop.unwrap()(self.jvmti, *thread_id, depth as jint)) {
+                NativeError::NoError => Ok(()),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn is_interface(&self, class_id: &ClassId) -> Result<bool, NativeError> {
+        let mut is_interface: jboolean = 0;
+
+        unsafe {
+            match wrap_error((**self.jvmti).IsInterface.unwrap()(self.jvmti, class_id.native_id, &mut is_interface)) {
+                NativeError::NoError => Ok(is_interface != 0),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
     fn allocate(&self, len: usize) -> Result<MemoryAllocation, NativeError> {
         let size: JavaLong = len as JavaLong;
         let mut ptr: MutByteArray = ptr::null_mut();
@@ -264,36 +721,85 @@ impl JVMTI for JVMTIEnvironment {
         }
     }
 
-    fn get_all_stacktraces(&self) -> Result<Vec<JavaStackTrace>, NativeError> {
-        let max_frame_count:jint = 100;
-        let mut thread_count:jint = 0;
-        let mut stack_info_ptr: *mut jvmtiStackInfo = ptr::null_mut();
-        let mut stack_traces_list: Vec<JavaStackTrace> = vec![];
+    fn get_loaded_classes(&self) -> Result<Vec<ClassId>, NativeError> {
+        let mut class_count: jint = 0;
+        let mut classes_ptr: *mut jclass = ptr::null_mut();
+
         unsafe {
-            match wrap_error((**self.jvmti).GetAllStackTraces.unwrap()(self.jvmti, max_frame_count, &mut stack_info_ptr, &mut thread_count )){
+            match wrap_error((**self.jvmti).GetLoadedClasses.unwrap()(self.jvmti, &mut class_count, &mut classes_ptr)) {
                 NativeError::NoError => {
-                    let count: usize = thread_count as usize;
-                    let stack_info_array = unsafe { std::slice::from_raw_parts(stack_info_ptr, count ) };
-                    //enumerate thread stacks
-                    for i in 0..count {
-                        let stack_info = stack_info_array[i];
-                        let mut stack_trace = JavaStackTrace{
-                            thread: stack_info.thread,
-                            state: stack_info.state,
-                            frame_buffer: vec![]
-                        };
-
-                        let stack_frames = unsafe { std::slice::from_raw_parts(stack_info.frame_buffer,stack_info.frame_count as usize) };
-                        for n in 0..stack_info.frame_count as usize {
-                            let stack_frame = stack_frames[n];
-                            stack_trace.frame_buffer.push( JavaStackFrame{ method: stack_frame.method, location: stack_frame.location } );
-                        }
-                        stack_traces_list.push(stack_trace);
-                    }
-                    self.deallocate(stack_info_ptr as *mut i8);
-                    Ok(stack_traces_list)
+                    let classes = std::slice::from_raw_parts(classes_ptr, class_count as usize).iter().map(|class| ClassId { native_id: *class }).collect();
+
+                    self.deallocate(classes_ptr as *mut i8);
+                    Ok(classes)
                 },
-                err@ _ => {
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn get_class_methods(&self, class_id: &ClassId) -> Result<Vec<MethodId>, NativeError> {
+        let mut method_count: jint = 0;
+        let mut methods_ptr: *mut jmethodID = ptr::null_mut();
+
+        unsafe {
+            match wrap_error((**self.jvmti).GetClassMethods.unwrap()(self.jvmti, class_id.native_id, &mut method_count, &mut methods_ptr)) {
+                NativeError::NoError => {
+                    let methods = std::slice::from_raw_parts(methods_ptr, method_count as usize).iter().map(|method| MethodId { native_id: *method }).collect();
+
+                    self.deallocate(methods_ptr as *mut i8);
+                    Ok(methods)
+                },
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn is_modifiable_class(&self, class_id: &ClassId) -> Result<bool, NativeError> {
+        let mut is_modifiable: jboolean = 0;
+
+        unsafe {
+            match wrap_error((**self.jvmti).IsModifiableClass.unwrap()(self.jvmti, class_id.native_id, &mut is_modifiable)) {
+                NativeError::NoError => Ok(is_modifiable != 0),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn retransform_classes(&self, classes: &Vec<ClassId>) -> Result<(), NativeError> {
+        let class_ids: Vec<jclass> = classes.iter().map(|class| class.native_id).collect();
+
+        unsafe {
+            match wrap_error((**self.jvmti).RetransformClasses.unwrap()(self.jvmti, class_ids.len() as jint, class_ids.as_ptr())) {
+                NativeError::NoError => Ok(()),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn redefine_classes(&self, definitions: &Vec<ClassDefinition>) -> Result<(), NativeError> {
+        let native_definitions: Vec<jvmtiClassDefinition> = definitions.iter().map(|definition| jvmtiClassDefinition {
+            klass: definition.class_id.native_id,
+            class_byte_count: definition.class_bytes.len() as jint,
+            class_bytes: definition.class_bytes.as_ptr()
+        }).collect();
+
+        unsafe {
+            match wrap_error((**self.jvmti).RedefineClasses.unwrap()(self.jvmti, native_definitions.len() as jint, native_definitions.as_ptr())) {
+                NativeError::NoError => Ok(()),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn get_all_stacktraces(&self, max_frame_count: usize) -> Result<Vec<JavaStackTrace>, NativeError> {
+        let mut thread_count: jint = 0;
+        let mut stack_info_ptr: *mut jvmtiStackInfo = ptr::null_mut();
+
+        unsafe {
+            match wrap_error((**self.jvmti).GetAllStackTraces.unwrap()(self.jvmti, max_frame_count as jint, &mut stack_info_ptr, &mut thread_count)) {
+                NativeError::NoError => Ok(self.read_stack_traces(stack_info_ptr, thread_count)),
+                err @ _ => {
                     println!("GetAllStackTraces error: {:?}", err);
                     Err(err)
                 }
@@ -301,6 +807,163 @@ impl JVMTI for JVMTIEnvironment {
         }
     }
 
+    fn get_thread_list_stack_traces(&self, threads: &Vec<JavaThread>, max_frame_count: usize) -> Result<Vec<JavaStackTrace>, NativeError> {
+        let mut stack_info_ptr: *mut jvmtiStackInfo = ptr::null_mut();
+
+        unsafe {
+            match wrap_error((**self.jvmti).GetThreadListStackTraces.unwrap()(self.jvmti, threads.len() as jint, threads.as_ptr(), max_frame_count as jint, &mut stack_info_ptr)) {
+                NativeError::NoError => Ok(self.read_stack_traces(stack_info_ptr, threads.len() as jint)),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn get_stack_trace(&self, thread_id: &JavaThread, start_depth: JavaInt, max_frame_count: usize) -> Result<Vec<JavaStackFrame>, NativeError> {
+        let mut frame_buffer: Vec<jvmtiFrameInfo> = Vec::with_capacity(max_frame_count);
+        let mut frame_count: jint = 0;
+
+        unsafe {
+            match wrap_error((**self.jvmti).GetStackTrace.unwrap()(self.jvmti, *thread_id, start_depth, max_frame_count as jint, frame_buffer.as_mut_ptr(), &mut frame_count)) {
+                NativeError::NoError => Ok(read_frames(frame_buffer.as_ptr(), frame_count)),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn get_frame_count(&self, thread_id: &JavaThread) -> Result<usize, NativeError> {
+        let mut frame_count: jint = 0;
+
+        unsafe {
+            match wrap_error((**self.jvmti).GetFrameCount.unwrap()(self.jvmti, *thread_id, &mut frame_count)) {
+                NativeError::NoError => Ok(frame_count as usize),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn get_frame_location(&self, thread_id: &JavaThread, depth: usize) -> Result<JavaStackFrame, NativeError> {
+        let mut method: JavaMethod = ptr::null_mut();
+        let mut location: JavaLong = 0;
+
+        unsafe {
+            match wrap_error((**self.jvmti).GetFrameLocation.unwrap()(self.jvmti, *thread_id, depth as jint, &mut method, &mut location)) {
+                NativeError::NoError => Ok(JavaStackFrame { method: method, location: location }),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn get_local_instance(&self, thread_id: &JavaThread, depth: usize) -> Result<JavaObject, NativeError> {
+        let mut value: JavaObject = ptr::null_mut();
+
+        unsafe {
+            match wrap_error((**self.jvmti).GetLocalInstance.unwrap()(self.jvmti, *thread_id, depth as jint, &mut value)) {
+                NativeError::NoError => Ok(value),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn get_local_object(&self, thread_id: &JavaThread, depth: usize, slot: u32) -> Result<JavaObject, NativeError> {
+        let mut value: JavaObject = ptr::null_mut();
+
+        unsafe {
+            match wrap_error((**self.jvmti).GetLocalObject.unwrap()(self.jvmti, *thread_id, depth as jint, slot as jint, &mut value)) {
+                NativeError::NoError => Ok(value),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn get_local_int(&self, thread_id: &JavaThread, depth: usize, slot: u32) -> Result<JavaInt, NativeError> {
+        let mut value: JavaInt = 0;
+
+        unsafe {
+            match wrap_error((**self.jvmti).GetLocalInt.unwrap()(self.jvmti, *thread_id, depth as jint, slot as jint, &mut value)) {
+                NativeError::NoError => Ok(value),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn get_local_long(&self, thread_id: &JavaThread, depth: usize, slot: u32) -> Result<JavaLong, NativeError> {
+        let mut value: JavaLong = 0;
+
+        unsafe {
+            match wrap_error((**self.jvmti).GetLocalLong.unwrap()(self.jvmti, *thread_id, depth as jint, slot as jint, &mut value)) {
+                NativeError::NoError => Ok(value),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn get_local_float(&self, thread_id: &JavaThread, depth: usize, slot: u32) -> Result<f32, NativeError> {
+        let mut value: f32 = 0.0;
+
+        unsafe {
+            match wrap_error((**self.jvmti).GetLocalFloat.unwrap()(self.jvmti, *thread_id, depth as jint, slot as jint, &mut value)) {
+                NativeError::NoError => Ok(value),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn get_local_double(&self, thread_id: &JavaThread, depth: usize, slot: u32) -> Result<f64, NativeError> {
+        let mut value: f64 = 0.0;
+
+        unsafe {
+            match wrap_error((**self.jvmti).GetLocalDouble.unwrap()(self.jvmti, *thread_id, depth as jint, slot as jint, &mut value)) {
+                NativeError::NoError => Ok(value),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn set_local_object(&self, thread_id: &JavaThread, depth: usize, slot: u32, value: JavaObject) -> Result<(), NativeError> {
+        unsafe {
+            match wrap_error((**self.jvmti).SetLocalObject.unwrap()(self.jvmti, *thread_id, depth as jint, slot as jint, value)) {
+                NativeError::NoError => Ok(()),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn set_local_int(&self, thread_id: &JavaThread, depth: usize, slot: u32, value: JavaInt) -> Result<(), NativeError> {
+        unsafe {
+            match wrap_error((**self.jvmti).SetLocalInt.unwrap()(self.jvmti, *thread_id, depth as jint, slot as jint, value)) {
+                NativeError::NoError => Ok(()),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn set_local_long(&self, thread_id: &JavaThread, depth: usize, slot: u32, value: JavaLong) -> Result<(), NativeError> {
+        unsafe {
+            match wrap_error((**self.jvmti).SetLocalLong.unwrap()(self.jvmti, *thread_id, depth as jint, slot as jint, value)) {
+                NativeError::NoError => Ok(()),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn set_local_float(&self, thread_id: &JavaThread, depth: usize, slot: u32, value: f32) -> Result<(), NativeError> {
+        unsafe {
+            match wrap_error((**self.jvmti).SetLocalFloat.unwrap()(self.jvmti, *thread_id, depth as jint, slot as jint, value)) {
+                NativeError::NoError => Ok(()),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn set_local_double(&self, thread_id: &JavaThread, depth: usize, slot: u32, value: f64) -> Result<(), NativeError> {
+        unsafe {
+            match wrap_error((**self.jvmti).SetLocalDouble.unwrap()(self.jvmti, *thread_id, depth as jint, slot as jint, value)) {
+                NativeError::NoError => Ok(()),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
     fn get_all_threads(&self) -> Result<Vec<ThreadId>, NativeError> {
         let mut thread_count:jint = 0;
         let mut threads_ptr : *mut jthread = ptr::null_mut();
@@ -323,6 +986,165 @@ impl JVMTI for JVMTIEnvironment {
         }
     }
 
+    fn get_thread_group_name(&self, thread_id: &JavaThread) -> Result<String, NativeError> {
+        let mut thread_info = Struct__jvmtiThreadInfo { name: ptr::null_mut(), priority: 0, is_daemon: 0, thread_group: ptr::null_mut(), context_class_loader: ptr::null_mut() };
+        let mut group_info = Struct__jvmtiThreadGroupInfo { parent: ptr::null_mut(), name: ptr::null_mut(), max_priority: 0, is_daemon: 0 };
+
+        unsafe {
+            match wrap_error((**self.jvmti).GetThreadInfo.unwrap()(self.jvmti, *thread_id, &mut thread_info)) {
+                NativeError::NoError => self.deallocate(thread_info.name),
+                err @ _ => return Err(err)
+            }
+
+            match wrap_error((**self.jvmti).GetThreadGroupInfo.unwrap()(self.jvmti, thread_info.thread_group, &mut group_info)) {
+                NativeError::NoError => {
+                    let group_name = stringify(group_info.name);
+                    self.deallocate(group_info.name);
+                    Ok(group_name)
+                },
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn get_thread_state(&self, thread_id: &JavaThread) -> Result<ThreadState, NativeError> {
+        let mut state: jint = 0;
+
+        unsafe {
+            match wrap_error((**self.jvmti).GetThreadState.unwrap()(self.jvmti, *thread_id, &mut state)) {
+                NativeError::NoError => Ok(ThreadState::from_bits(state as u32)),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn suspend_thread(&self, thread_id: &JavaThread) -> Result<(), NativeError> {
+        unsafe {
+            match wrap_error((**self.jvmti).SuspendThread.unwrap()(self.jvmti, *thread_id)) {
+                NativeError::NoError => Ok(()),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn resume_thread(&self, thread_id: &JavaThread) -> Result<(), NativeError> {
+        unsafe {
+            match wrap_error((**self.jvmti).ResumeThread.unwrap()(self.jvmti, *thread_id)) {
+                NativeError::NoError => Ok(()),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn suspend_thread_list(&self, threads: &Vec<JavaThread>) -> Result<Vec<Result<(), NativeError>>, NativeError> {
+        let mut results: Vec<jvmtiError> = vec![0; threads.len()];
+
+        unsafe {
+            match wrap_error((**self.jvmti).SuspendThreadList.unwrap()(self.jvmti, threads.len() as jint, threads.as_ptr(), results.as_mut_ptr())) {
+                NativeError::NoError => Ok(read_thread_list_results(&results)),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn resume_thread_list(&self, threads: &Vec<JavaThread>) -> Result<Vec<Result<(), NativeError>>, NativeError> {
+        let mut results: Vec<jvmtiError> = vec![0; threads.len()];
+
+        unsafe {
+            match wrap_error((**self.jvmti).ResumeThreadList.unwrap()(self.jvmti, threads.len() as jint, threads.as_ptr(), results.as_mut_ptr())) {
+                NativeError::NoError => Ok(read_thread_list_results(&results)),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn interrupt_thread(&self, thread_id: &JavaThread) -> Result<(), NativeError> {
+        unsafe {
+            match wrap_error((**self.jvmti).InterruptThread.unwrap()(self.jvmti, *thread_id)) {
+                NativeError::NoError => Ok(()),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn stop_thread(&self, thread_id: &JavaThread, exception: &JavaObject) -> Result<(), NativeError> {
+        unsafe {
+            match wrap_error((**self.jvmti).StopThread.unwrap()(self.jvmti, *thread_id, *exception)) {
+                NativeError::NoError => Ok(()),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn get_owned_monitor_info(&self, thread_id: &JavaThread) -> Result<Vec<JavaObject>, NativeError> {
+        let mut monitor_count: jint = 0;
+        let mut monitors_ptr: *mut jobject = ptr::null_mut();
+
+        unsafe {
+            match wrap_error((**self.jvmti).GetOwnedMonitorInfo.unwrap()(self.jvmti, *thread_id, &mut monitor_count, &mut monitors_ptr)) {
+                NativeError::NoError => {
+                    let monitors = read_objects(monitors_ptr, monitor_count);
+
+                    self.deallocate(monitors_ptr as *mut i8);
+                    Ok(monitors)
+                },
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn get_owned_monitor_stack_depth_info(&self, thread_id: &JavaThread) -> Result<Vec<OwnedMonitor>, NativeError> {
+        let mut monitor_count: jint = 0;
+        let mut monitors_ptr: *mut jvmtiMonitorStackDepthInfo = ptr::null_mut();
+
+        unsafe {
+            match wrap_error((**self.jvmti).GetOwnedMonitorStackDepthInfo.unwrap()(self.jvmti, *thread_id, &mut monitor_count, &mut monitors_ptr)) {
+                NativeError::NoError => {
+                    let monitors = std::slice::from_raw_parts(monitors_ptr, monitor_count as usize).iter()
+                        .map(|info| OwnedMonitor { monitor: info.monitor, stack_depth: if info.stack_depth < 0 { None } else { Some(info.stack_depth as usize) } })
+                        .collect();
+
+                    self.deallocate(monitors_ptr as *mut i8);
+                    Ok(monitors)
+                },
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn get_current_contended_monitor(&self, thread_id: &JavaThread) -> Result<Option<JavaObject>, NativeError> {
+        let mut monitor: jobject = ptr::null_mut();
+
+        unsafe {
+            match wrap_error((**self.jvmti).GetCurrentContendedMonitor.unwrap()(self.jvmti, *thread_id, &mut monitor)) {
+                NativeError::NoError => Ok(if monitor.is_null() { None } else { Some(monitor) }),
+                err @ _ => Err(err)
+            }
+        }
+    }
+
+    fn get_object_monitor_usage(&self, object: &JavaObject) -> Result<MonitorUsage, NativeError> {
+        let mut usage: jvmtiMonitorUsage = Default::default();
+
+        unsafe {
+            match wrap_error((**self.jvmti).GetObjectMonitorUsage.unwrap()(self.jvmti, *object, &mut usage)) {
+                NativeError::NoError => {
+                    let monitor_usage = MonitorUsage {
+                        owner: if usage.owner.is_null() { None } else { Some(usage.owner) },
+                        entry_count: usage.entry_count as u32,
+                        waiters: read_objects(usage.waiters, usage.waiter_count),
+                        notify_waiters: read_objects(usage.notify_waiters, usage.notify_waiter_count)
+                    };
+
+                    self.deallocate(usage.waiters as *mut i8);
+                    self.deallocate(usage.notify_waiters as *mut i8);
+                    Ok(monitor_usage)
+                },
+                err @ _ => Err(err)
+            }
+        }
+    }
+
     fn get_thread_cpu_time(&self, thread_id: &JavaThread) -> Result<JavaLong, NativeError> {
         let mut nanos: JavaLong = 0;
         unsafe {
@@ -367,12 +1189,28 @@ impl JVMTI for JVMTIEnvironment {
 }
 
 
+///
+/// The new definition of a class, as passed to `redefine_classes`
+pub struct ClassDefinition {
+    pub class_id: ClassId,
+    pub class_bytes: Vec<u8>
+}
+
 pub struct JavaStackTrace {
     pub thread: JavaThread,
     pub state: JavaInt,
     pub frame_buffer: Vec<JavaStackFrame>
 }
 
+impl JavaStackTrace {
+
+    /// The state the thread was in when the stack trace was taken
+    pub fn thread_state(&self) -> ThreadState {
+        ThreadState::from_bits(self.state as u32)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
 pub struct JavaStackFrame {
     pub method: JavaMethod,
     pub location: JavaLong,
@@ -3,17 +3,21 @@ use self::jni::{JNI, JNIEnvironment};
 use super::capabilities::Capabilities;
 use super::class::{ClassId, ClassSignature};
 use super::error::NativeError;
-use super::event::{EventCallbacks, VMEvent};
+use super::event::VMEvent;
+use super::field::{FieldId, FieldSignature};
+use super::heap::{FnHeapIteration, FnHeapReference, HeapFilter};
 use super::mem::MemoryAllocation;
-use super::method::{MethodId, MethodSignature};
-use super::native::{JavaObject, JavaThread};
-use super::thread::Thread;
+use super::monitor::{MonitorUsage, OwnedMonitor};
+use super::method::{LineNumberEntry, LocalVariableEntry, MethodId, MethodSignature};
+use super::native::{JavaObject, JavaThread, JavaVMPtr};
+use super::thread::{Thread, ThreadState};
 use super::version::VersionNumber;
-use native::{JavaClass, JavaMethod, JavaLong, JNIEnvPtr};
-use environment::jvmti::JavaStackTrace;
+use native::{JavaClass, JavaMethod, JavaLong, JavaInt, JNIEnvPtr};
+use environment::jvmti::{ClassDefinition, JavaStackFrame, JavaStackTrace};
 use thread::ThreadId;
 use native::jvmti_native::jvmtiTimerInfo;
 use std::cell::Cell;
+use libc::c_void;
 
 pub mod jni;
 pub mod jvm;
@@ -72,14 +76,22 @@ impl JVMTI for Environment {
         self.jvmti.get_potential_capabilities()
     }
 
-    fn set_event_callbacks(&mut self, callbacks: EventCallbacks) -> Option<NativeError> {
-        self.jvmti.set_event_callbacks(callbacks)
+    fn set_event_callbacks(&mut self) -> Option<NativeError> {
+        self.jvmti.set_event_callbacks()
     }
 
     fn set_event_notification_mode(&mut self, event: VMEvent, mode: bool) -> Option<NativeError> {
         self.jvmti.set_event_notification_mode(event, mode)
     }
 
+    fn set_thread_event_notification_mode(&mut self, event: VMEvent, thread_id: &JavaThread, mode: bool) -> Result<(), NativeError> {
+        self.jvmti.set_thread_event_notification_mode(event, thread_id, mode)
+    }
+
+    fn generate_events(&self, event: VMEvent) -> Result<(), NativeError> {
+        self.jvmti.generate_events(event)
+    }
+
     fn get_thread_info(&self, thread_id: &JavaThread) -> Result<Thread, NativeError> {
         let mut thread_info = self.jvmti.get_thread_info(thread_id).unwrap();
         let java_thread_id = self.get_thread_id(&thread_id);
@@ -99,6 +111,86 @@ impl JVMTI for Environment {
         self.jvmti.get_class_signature(class_id)
     }
 
+    fn get_source_file_name(&self, class_id: &ClassId) -> Result<String, NativeError> {
+        self.jvmti.get_source_file_name(class_id)
+    }
+
+    fn get_class_loader(&self, class_id: &ClassId) -> Result<Option<JavaObject>, NativeError> {
+        self.jvmti.get_class_loader(class_id)
+    }
+
+    fn get_object_hash_code(&self, object: &JavaObject) -> Result<JavaInt, NativeError> {
+        self.jvmti.get_object_hash_code(object)
+    }
+
+    fn get_tag(&self, object: &JavaObject) -> Result<JavaLong, NativeError> {
+        self.jvmti.get_tag(object)
+    }
+
+    fn set_tag(&self, object: &JavaObject, tag: JavaLong) -> Result<(), NativeError> {
+        self.jvmti.set_tag(object, tag)
+    }
+
+    fn get_objects_with_tags(&self, tags: &Vec<JavaLong>) -> Result<Vec<(JavaObject, JavaLong)>, NativeError> {
+        self.jvmti.get_objects_with_tags(tags)
+    }
+
+    fn get_object_size(&self, object: &JavaObject) -> Result<JavaLong, NativeError> {
+        self.jvmti.get_object_size(object)
+    }
+
+    fn iterate_through_heap(&self, filter: HeapFilter, class_id: Option<&ClassId>, callback: &mut FnHeapIteration) -> Result<(), NativeError> {
+        self.jvmti.iterate_through_heap(filter, class_id, callback)
+    }
+
+    fn follow_references(&self, filter: HeapFilter, class_id: Option<&ClassId>, initial_object: Option<&JavaObject>, callback: &mut FnHeapReference) -> Result<(), NativeError> {
+        self.jvmti.follow_references(filter, class_id, initial_object, callback)
+    }
+
+    fn get_line_number_table(&self, method_id: &MethodId) -> Result<Vec<LineNumberEntry>, NativeError> {
+        self.jvmti.get_line_number_table(method_id)
+    }
+
+    fn get_local_variable_table(&self, method_id: &MethodId) -> Result<Vec<LocalVariableEntry>, NativeError> {
+        self.jvmti.get_local_variable_table(method_id)
+    }
+
+    fn get_field_name(&self, class_id: &ClassId, field_id: &FieldId) -> Result<FieldSignature, NativeError> {
+        self.jvmti.get_field_name(class_id, field_id)
+    }
+
+    fn set_field_access_watch(&self, class_id: &ClassId, field_id: &FieldId) -> Result<(), NativeError> {
+        self.jvmti.set_field_access_watch(class_id, field_id)
+    }
+
+    fn clear_field_access_watch(&self, class_id: &ClassId, field_id: &FieldId) -> Result<(), NativeError> {
+        self.jvmti.clear_field_access_watch(class_id, field_id)
+    }
+
+    fn set_field_modification_watch(&self, class_id: &ClassId, field_id: &FieldId) -> Result<(), NativeError> {
+        self.jvmti.set_field_modification_watch(class_id, field_id)
+    }
+
+    fn clear_field_modification_watch(&self, class_id: &ClassId, field_id: &FieldId) -> Result<(), NativeError> {
+        self.jvmti.clear_field_modification_watch(class_id, field_id)
+    }
+
+    fn set_breakpoint(&self, method_id: &MethodId, location: JavaLong) -> Result<(), NativeError> {
+        self.jvmti.set_breakpoint(method_id, location)
+    }
+
+    fn clear_breakpoint(&self, method_id: &MethodId, location: JavaLong) -> Result<(), NativeError> {
+        self.jvmti.clear_breakpoint(method_id, location)
+    }
+
+    fn notify_frame_pop(&self, thread_id: &JavaThread, depth: usize) -> Result<(), NativeError> {
+        self.jvmti.notify_frame_pop(thread_id, depth)
+    }
+
+    fn is_interface(&self, class_id: &ClassId) -> Result<bool, NativeError> {
+        self.jvmti.is_interface(class_id)
+    }
+
     fn allocate(&self, len: usize) -> Result<MemoryAllocation, NativeError> {
         self.jvmti.allocate(len)
     }
@@ -107,14 +199,142 @@ impl JVMTI for Environment {
         self.jvmti.deallocate(ptr)
     }
 
-    fn get_all_stacktraces(&self) -> Result<Vec<JavaStackTrace>, NativeError> {
-        self.jvmti.get_all_stacktraces()
+    fn get_loaded_classes(&self) -> Result<Vec<ClassId>, NativeError> {
+        self.jvmti.get_loaded_classes()
+    }
+
+    fn get_class_methods(&self, class_id: &ClassId) -> Result<Vec<MethodId>, NativeError> {
+        self.jvmti.get_class_methods(class_id)
+    }
+
+    fn is_modifiable_class(&self, class_id: &ClassId) -> Result<bool, NativeError> {
+        self.jvmti.is_modifiable_class(class_id)
+    }
+
+    fn retransform_classes(&self, classes: &Vec<ClassId>) -> Result<(), NativeError> {
+        self.jvmti.retransform_classes(classes)
+    }
+
+    fn redefine_classes(&self, definitions: &Vec<ClassDefinition>) -> Result<(), NativeError> {
+        self.jvmti.redefine_classes(definitions)
+    }
+
+    fn get_all_stacktraces(&self, max_frame_count: usize) -> Result<Vec<JavaStackTrace>, NativeError> {
+        self.jvmti.get_all_stacktraces(max_frame_count)
+    }
+
+    fn get_thread_list_stack_traces(&self, threads: &Vec<JavaThread>, max_frame_count: usize) -> Result<Vec<JavaStackTrace>, NativeError> {
+        self.jvmti.get_thread_list_stack_traces(threads, max_frame_count)
+    }
+
+    fn get_stack_trace(&self, thread_id: &JavaThread, start_depth: JavaInt, max_frame_count: usize) -> Result<Vec<JavaStackFrame>, NativeError> {
+        self.jvmti.get_stack_trace(thread_id, start_depth, max_frame_count)
+    }
+
+    fn get_frame_count(&self, thread_id: &JavaThread) -> Result<usize, NativeError> {
+        self.jvmti.get_frame_count(thread_id)
+    }
+
+    fn get_frame_location(&self, thread_id: &JavaThread, depth: usize) -> Result<JavaStackFrame, NativeError> {
+        self.jvmti.get_frame_location(thread_id, depth)
+    }
+
+    fn get_local_instance(&self, thread_id: &JavaThread, depth: usize) -> Result<JavaObject, NativeError> {
+        self.jvmti.get_local_instance(thread_id, depth)
+    }
+
+    fn get_local_object(&self, thread_id: &JavaThread, depth: usize, slot: u32) -> Result<JavaObject, NativeError> {
+        self.jvmti.get_local_object(thread_id, depth, slot)
+    }
+
+    fn get_local_int(&self, thread_id: &JavaThread, depth: usize, slot: u32) -> Result<JavaInt, NativeError> {
+        self.jvmti.get_local_int(thread_id, depth, slot)
+    }
+
+    fn get_local_long(&self, thread_id: &JavaThread, depth: usize, slot: u32) -> Result<JavaLong, NativeError> {
+        self.jvmti.get_local_long(thread_id, depth, slot)
+    }
+
+    fn get_local_float(&self, thread_id: &JavaThread, depth: usize, slot: u32) -> Result<f32, NativeError> {
+        self.jvmti.get_local_float(thread_id, depth, slot)
+    }
+
+    fn get_local_double(&self, thread_id: &JavaThread, depth: usize, slot: u32) -> Result<f64, NativeError> {
+        self.jvmti.get_local_double(thread_id, depth, slot)
+    }
+
+    fn set_local_object(&self, thread_id: &JavaThread, depth: usize, slot: u32, value: JavaObject) -> Result<(), NativeError> {
+        self.jvmti.set_local_object(thread_id, depth, slot, value)
+    }
+
+    fn set_local_int(&self, thread_id: &JavaThread, depth: usize, slot: u32, value: JavaInt) -> Result<(), NativeError> {
+        self.jvmti.set_local_int(thread_id, depth, slot, value)
+    }
+
+    fn set_local_long(&self, thread_id: &JavaThread, depth: usize, slot: u32, value: JavaLong) -> Result<(), NativeError> {
+        self.jvmti.set_local_long(thread_id, depth, slot, value)
+    }
+
+    fn set_local_float(&self, thread_id: &JavaThread, depth: usize, slot: u32, value: f32) -> Result<(), NativeError> {
+        self.jvmti.set_local_float(thread_id, depth, slot, value)
+    }
+
+    fn set_local_double(&self, thread_id: &JavaThread, depth: usize, slot: u32, value: f64) -> Result<(), NativeError> {
+        self.jvmti.set_local_double(thread_id, depth, slot, value)
     }
 
     fn get_all_threads(&self) -> Result<Vec<ThreadId>, NativeError> {
         self.jvmti.get_all_threads()
     }
 
+    fn get_thread_group_name(&self, thread_id: &JavaThread) -> Result<String, NativeError> {
+        self.jvmti.get_thread_group_name(thread_id)
+    }
+
+    fn get_thread_state(&self, thread_id: &JavaThread) -> Result<ThreadState, NativeError> {
+        self.jvmti.get_thread_state(thread_id)
+    }
+
+    fn get_owned_monitor_info(&self, thread_id: &JavaThread) -> Result<Vec<JavaObject>, NativeError> {
+        self.jvmti.get_owned_monitor_info(thread_id)
+    }
+
+    fn get_owned_monitor_stack_depth_info(&self, thread_id: &JavaThread) -> Result<Vec<OwnedMonitor>, NativeError> {
+        self.jvmti.get_owned_monitor_stack_depth_info(thread_id)
+    }
+
+    fn get_current_contended_monitor(&self, thread_id: &JavaThread) -> Result<Option<JavaObject>, NativeError> {
+        self.jvmti.get_current_contended_monitor(thread_id)
+    }
+
+    fn get_object_monitor_usage(&self, object: &JavaObject) -> Result<MonitorUsage, NativeError> {
+        self.jvmti.get_object_monitor_usage(object)
+    }
+
+    fn suspend_thread(&self, thread_id: &JavaThread) -> Result<(), NativeError> {
+        self.jvmti.suspend_thread(thread_id)
+    }
+
+    fn resume_thread(&self, thread_id: &JavaThread) -> Result<(), NativeError> {
+        self.jvmti.resume_thread(thread_id)
+    }
+
+    fn suspend_thread_list(&self, threads: &Vec<JavaThread>) -> Result<Vec<Result<(), NativeError>>, NativeError> {
+        self.jvmti.suspend_thread_list(threads)
+    }
+
+    fn resume_thread_list(&self, threads: &Vec<JavaThread>) -> Result<Vec<Result<(), NativeError>>, NativeError> {
+        self.jvmti.resume_thread_list(threads)
+    }
+
+    fn interrupt_thread(&self, thread_id: &JavaThread) -> Result<(), NativeError> {
+        self.jvmti.interrupt_thread(thread_id)
+    }
+
+    fn stop_thread(&self, thread_id: &JavaThread, exception: &JavaObject) -> Result<(), NativeError> {
+        self.jvmti.stop_thread(thread_id, exception)
+    }
+
     fn get_thread_cpu_time(&self, thread_id: &JavaThread) -> Result<JavaLong, NativeError> {
         self.jvmti.get_thread_cpu_time(thread_id)
     }
@@ -145,4 +365,64 @@ impl JNI for Environment {
     fn call_long_method(&self, thread: JavaThread, method_id: JavaMethod) -> JavaLong {
         self.jni.call_long_method(thread, method_id)
     }
+
+    fn get_field_id(&self, class_id: &ClassId, field_name: &str, field_sig: &str, is_static: bool) -> Option<FieldId> {
+        self.jni.get_field_id(class_id, field_name, field_sig, is_static)
+    }
+
+    fn find_loaded_class(&self, loader: &JavaObject, class_name: &str) -> Option<ClassId> {
+        self.jni.find_loaded_class(loader, class_name)
+    }
+
+    fn get_superclass(&self, class_id: &ClassId) -> Option<ClassId> {
+        self.jni.get_superclass(class_id)
+    }
+
+    fn clear_exception(&self) -> bool {
+        self.jni.clear_exception()
+    }
+
+    fn delete_local_ref(&self, object: JavaObject) {
+        self.jni.delete_local_ref(object)
+    }
+
+    fn is_same_object(&self, a: &JavaObject, b: &JavaObject) -> bool {
+        self.jni.is_same_object(a, b)
+    }
+
+    fn new_global_ref(&self, object: &JavaObject) -> JavaObject {
+        self.jni.new_global_ref(object)
+    }
+
+    fn delete_global_ref(&self, object: JavaObject) {
+        self.jni.delete_global_ref(object)
+    }
+
+    fn new_weak_global_ref(&self, object: &JavaObject) -> JavaObject {
+        self.jni.new_weak_global_ref(object)
+    }
+
+    fn delete_weak_global_ref(&self, object: JavaObject) {
+        self.jni.delete_weak_global_ref(object)
+    }
+
+    fn push_local_frame(&self, capacity: usize) -> bool {
+        self.jni.push_local_frame(capacity)
+    }
+
+    fn pop_local_frame(&self) {
+        self.jni.pop_local_frame()
+    }
+
+    fn define_class(&self, class_name: &str, loader: JavaObject, bytes: &[u8]) -> Option<ClassId> {
+        self.jni.define_class(class_name, loader, bytes)
+    }
+
+    fn register_native(&self, class_id: &ClassId, method_name: &str, method_sig: &str, function: *mut c_void) -> bool {
+        self.jni.register_native(class_id, method_name, method_sig, function)
+    }
+
+    fn get_java_vm(&self) -> Option<JavaVMPtr> {
+        self.jni.get_java_vm()
+    }
 }
@@ -1,28 +1,185 @@
+use std::error::Error;
+use std::fmt;
 
 /// A type-safe representation of possible errors
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum NativeError {
-    NoError = 0,
-    NotAvailable = 98,
-    MustPossessCapability = 99,
-    NullPointer = 100,
-    OutOfMemory = 110,
-    NotEnabled = 111,
-    WrongPhase = 112,
-    UnexpectedInternalError = 113,
-    ThreadNotAttached = 115,
-    Disconnected = 116,
-    NotImplemented = 999999, // <- now this is a "temporary" hack until the library is under heavy development
-    UnknownError
+    NoError,
+    InvalidThread,
+    InvalidThreadGroup,
+    InvalidPriority,
+    ThreadNotSuspended,
+    ThreadSuspended,
+    ThreadNotAlive,
+    InvalidObject,
+    InvalidClass,
+    ClassNotPrepared,
+    InvalidMethodId,
+    InvalidLocation,
+    InvalidFieldId,
+    InvalidModule,
+    NoMoreFrames,
+    OpaqueFrame,
+    TypeMismatch,
+    InvalidSlot,
+    Duplicate,
+    NotFound,
+    InvalidMonitor,
+    NotMonitorOwner,
+    Interrupt,
+    InvalidClassFormat,
+    CircularClassDefinition,
+    FailsVerification,
+    UnsupportedRedefinitionMethodAdded,
+    UnsupportedRedefinitionSchemaChanged,
+    InvalidTypeState,
+    UnsupportedRedefinitionHierarchyChanged,
+    UnsupportedRedefinitionMethodDeleted,
+    UnsupportedVersion,
+    NamesDontMatch,
+    UnsupportedRedefinitionClassModifiersChanged,
+    UnsupportedRedefinitionMethodModifiersChanged,
+    UnsupportedRedefinitionClassAttributeChanged,
+    UnsupportedOperation,
+    UnmodifiableClass,
+    UnmodifiableModule,
+    NotAvailable,
+    MustPossessCapability,
+    NullPointer,
+    AbsentInformation,
+    InvalidEventType,
+    IllegalArgument,
+    NativeMethod,
+    ClassLoaderUnsupported,
+    OutOfMemory,
+    NotEnabled,
+    WrongPhase,
+    UnexpectedInternalError,
+    ThreadNotAttached,
+    Disconnected,
+    NotImplemented, // <- now this is a "temporary" hack until the library is under heavy development
+    /// A JNI invocation interface function failed
+    JNIError(JNIError),
+    /// An error code this library doesn't know about
+    UnknownError(u32)
+}
+
+impl NativeError {
+
+    /// The native error code this error was wrapped from. JNI return codes are negative, which is
+    /// why it's wider than the JVMTI error codes.
+    pub fn code(&self) -> i64 {
+        match *self {
+            NativeError::NoError => 0,
+            NativeError::InvalidThread => 10,
+            NativeError::InvalidThreadGroup => 11,
+            NativeError::InvalidPriority => 12,
+            NativeError::ThreadNotSuspended => 13,
+            NativeError::ThreadSuspended => 14,
+            NativeError::ThreadNotAlive => 15,
+            NativeError::InvalidObject => 20,
+            NativeError::InvalidClass => 21,
+            NativeError::ClassNotPrepared => 22,
+            NativeError::InvalidMethodId => 23,
+            NativeError::InvalidLocation => 24,
+            NativeError::InvalidFieldId => 25,
+            NativeError::InvalidModule => 26,
+            NativeError::NoMoreFrames => 31,
+            NativeError::OpaqueFrame => 32,
+            NativeError::TypeMismatch => 34,
+            NativeError::InvalidSlot => 35,
+            NativeError::Duplicate => 40,
+            NativeError::NotFound => 41,
+            NativeError::InvalidMonitor => 50,
+            NativeError::NotMonitorOwner => 51,
+            NativeError::Interrupt => 52,
+            NativeError::InvalidClassFormat => 60,
+            NativeError::CircularClassDefinition => 61,
+            NativeError::FailsVerification => 62,
+            NativeError::UnsupportedRedefinitionMethodAdded => 63,
+            NativeError::UnsupportedRedefinitionSchemaChanged => 64,
+            NativeError::InvalidTypeState => 65,
+            NativeError::UnsupportedRedefinitionHierarchyChanged => 66,
+            NativeError::UnsupportedRedefinitionMethodDeleted => 67,
+            NativeError::UnsupportedVersion => 68,
+            NativeError::NamesDontMatch => 69,
+            NativeError::UnsupportedRedefinitionClassModifiersChanged => 70,
+            NativeError::UnsupportedRedefinitionMethodModifiersChanged => 71,
+            NativeError::UnsupportedRedefinitionClassAttributeChanged => 72,
+            NativeError::UnsupportedOperation => 73,
+            NativeError::UnmodifiableClass => 79,
+            NativeError::UnmodifiableModule => 80,
+            NativeError::NotAvailable => 98,
+            NativeError::MustPossessCapability => 99,
+            NativeError::NullPointer => 100,
+            NativeError::AbsentInformation => 101,
+            NativeError::InvalidEventType => 102,
+            NativeError::IllegalArgument => 103,
+            NativeError::NativeMethod => 104,
+            NativeError::ClassLoaderUnsupported => 106,
+            NativeError::OutOfMemory => 110,
+            NativeError::NotEnabled => 111,
+            NativeError::WrongPhase => 112,
+            NativeError::UnexpectedInternalError => 113,
+            NativeError::ThreadNotAttached => 115,
+            NativeError::Disconnected => 116,
+            NativeError::NotImplemented => 999999,
+            NativeError::JNIError(error) => error.code() as i64,
+            NativeError::UnknownError(code) => code as i64
+        }
+    }
 }
 
 /// Turn a native error code into a type-safe error
 pub fn wrap_error(code: u32) -> NativeError {
     match code {
         0 => NativeError::NoError,
+        10 => NativeError::InvalidThread,
+        11 => NativeError::InvalidThreadGroup,
+        12 => NativeError::InvalidPriority,
+        13 => NativeError::ThreadNotSuspended,
+        14 => NativeError::ThreadSuspended,
+        15 => NativeError::ThreadNotAlive,
+        20 => NativeError::InvalidObject,
+        21 => NativeError::InvalidClass,
+        22 => NativeError::ClassNotPrepared,
+        23 => NativeError::InvalidMethodId,
+        24 => NativeError::InvalidLocation,
+        25 => NativeError::InvalidFieldId,
+        26 => NativeError::InvalidModule,
+        31 => NativeError::NoMoreFrames,
+        32 => NativeError::OpaqueFrame,
+        34 => NativeError::TypeMismatch,
+        35 => NativeError::InvalidSlot,
+        40 => NativeError::Duplicate,
+        41 => NativeError::NotFound,
+        50 => NativeError::InvalidMonitor,
+        51 => NativeError::NotMonitorOwner,
+        52 => NativeError::Interrupt,
+        60 => NativeError::InvalidClassFormat,
+        61 => NativeError::CircularClassDefinition,
+        62 => NativeError::FailsVerification,
+        63 => NativeError::UnsupportedRedefinitionMethodAdded,
+        64 => NativeError::UnsupportedRedefinitionSchemaChanged,
+        65 => NativeError::InvalidTypeState,
+        66 => NativeError::UnsupportedRedefinitionHierarchyChanged,
+        67 => NativeError::UnsupportedRedefinitionMethodDeleted,
+        68 => NativeError::UnsupportedVersion,
+        69 => NativeError::NamesDontMatch,
+        70 => NativeError::UnsupportedRedefinitionClassModifiersChanged,
+        71 => NativeError::UnsupportedRedefinitionMethodModifiersChanged,
+        72 => NativeError::UnsupportedRedefinitionClassAttributeChanged,
+        73 => NativeError::UnsupportedOperation,
+        79 => NativeError::UnmodifiableClass,
+        80 => NativeError::UnmodifiableModule,
         98 => NativeError::NotAvailable,
         99 => NativeError::MustPossessCapability,
         100 => NativeError::NullPointer,
+        101 => NativeError::AbsentInformation,
+        102 => NativeError::InvalidEventType,
+        103 => NativeError::IllegalArgument,
+        104 => NativeError::NativeMethod,
+        106 => NativeError::ClassLoaderUnsupported,
         110 => NativeError::OutOfMemory,
         111 => NativeError::NotEnabled,
         112 => NativeError::WrongPhase,
@@ -30,7 +187,7 @@ pub fn wrap_error(code: u32) -> NativeError {
         115 => NativeError::ThreadNotAttached,
         116 => NativeError::Disconnected,
         999999 => NativeError::NotImplemented,
-        _ => { println!("Unknown error code was detected: {}", code); NativeError::UnknownError }
+        _ => NativeError::UnknownError(code)
     }
 }
 
@@ -38,9 +195,52 @@ pub fn wrap_error(code: u32) -> NativeError {
 pub fn translate_error(code: &NativeError) -> String {
     match code {
         &NativeError::NoError => "No error has occurred.",
+        &NativeError::InvalidThread => "The passed thread is not a valid thread.",
+        &NativeError::InvalidThreadGroup => "Thread group invalid.",
+        &NativeError::InvalidPriority => "Invalid priority.",
+        &NativeError::ThreadNotSuspended => "Thread was not suspended.",
+        &NativeError::ThreadSuspended => "Thread already suspended.",
+        &NativeError::ThreadNotAlive => "This operation requires the thread to be alive--that is, it must be started and not yet have died.",
+        &NativeError::InvalidObject => "Invalid object.",
+        &NativeError::InvalidClass => "Invalid class.",
+        &NativeError::ClassNotPrepared => "The class has been loaded but not yet prepared.",
+        &NativeError::InvalidMethodId => "Invalid method.",
+        &NativeError::InvalidLocation => "Invalid location.",
+        &NativeError::InvalidFieldId => "Invalid field.",
+        &NativeError::InvalidModule => "Invalid module.",
+        &NativeError::NoMoreFrames => "There are no Java programming language or JNI stack frames at the specified depth.",
+        &NativeError::OpaqueFrame => "Information about the frame is not available (e.g. for native frames).",
+        &NativeError::TypeMismatch => "The variable is not an appropriate type for the function used.",
+        &NativeError::InvalidSlot => "Invalid slot.",
+        &NativeError::Duplicate => "Item already set.",
+        &NativeError::NotFound => "Desired element (e.g. field or breakpoint) not found.",
+        &NativeError::InvalidMonitor => "Invalid raw monitor.",
+        &NativeError::NotMonitorOwner => "This thread doesn't own the raw monitor.",
+        &NativeError::Interrupt => "The call has been interrupted before completion.",
+        &NativeError::InvalidClassFormat => "A new class file is malformed (the VM would return a ClassFormatError).",
+        &NativeError::CircularClassDefinition => "The new class file definitions would lead to a circular definition (the VM would return a ClassCircularityError).",
+        &NativeError::FailsVerification => "The class bytes fail verification.",
+        &NativeError::UnsupportedRedefinitionMethodAdded => "A new class file would require adding a method.",
+        &NativeError::UnsupportedRedefinitionSchemaChanged => "A new class version changes a field.",
+        &NativeError::InvalidTypeState => "The state of the thread has been modified, and is now inconsistent.",
+        &NativeError::UnsupportedRedefinitionHierarchyChanged => "A direct superclass is different for the new class version, or the set of directly implemented interfaces is different.",
+        &NativeError::UnsupportedRedefinitionMethodDeleted => "A new class version does not declare a method declared in the old class version.",
+        &NativeError::UnsupportedVersion => "A new class file has a version number not supported by this VM.",
+        &NativeError::NamesDontMatch => "The class name defined in the new class file is different from the name in the old class object.",
+        &NativeError::UnsupportedRedefinitionClassModifiersChanged => "A new class version has different modifiers.",
+        &NativeError::UnsupportedRedefinitionMethodModifiersChanged => "A method in the new class version has different modifiers than its counterpart in the old class version.",
+        &NativeError::UnsupportedRedefinitionClassAttributeChanged => "A new class version has unsupported differences in class attributes.",
+        &NativeError::UnsupportedOperation => "Functionality is unsupported in this implementation.",
+        &NativeError::UnmodifiableClass => "The class cannot be modified.",
+        &NativeError::UnmodifiableModule => "The module cannot be modified.",
         &NativeError::NotAvailable => "The functionality is not available in this virtual machine.",
         &NativeError::MustPossessCapability => "The capability being used is false in this environment.",
         &NativeError::NullPointer => "Pointer is unexpectedly NULL.",
+        &NativeError::AbsentInformation => "The requested information is not available.",
+        &NativeError::InvalidEventType => "The specified event type ID is not recognized.",
+        &NativeError::IllegalArgument => "Illegal argument.",
+        &NativeError::NativeMethod => "The requested information is not available for native method.",
+        &NativeError::ClassLoaderUnsupported => "The class loader does not support this operation.",
         &NativeError::OutOfMemory => "The function attempted to allocate memory and no more memory was available for allocation.",
         &NativeError::NotEnabled => "The desired functionality has not been enabled in this virtual machine.",
         &NativeError::WrongPhase => "The desired functionality is not available in the current phase. Always returned if the virtual machine has completed running.",
@@ -48,6 +248,91 @@ pub fn translate_error(code: &NativeError) -> String {
         &NativeError::ThreadNotAttached => "The thread being used to call this function is not attached to the virtual machine. Calls must be made from attached threads.",
         &NativeError::Disconnected => "The JVM TI environment provided is no longer connected or is not an environment.",
         &NativeError::NotImplemented => "This function is not implemented yet",
-        &NativeError::UnknownError => "Unknown error."
+        &NativeError::JNIError(ref error) => return translate_jni_error(error),
+        &NativeError::UnknownError(code) => return format!("Unknown error code: {}.", code)
     }.to_string()
 }
+
+impl fmt::Display for NativeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (error {})", translate_error(self), self.code())
+    }
+}
+
+impl Error for NativeError {}
+
+///
+/// The return codes of the JNI invocation interface functions, such as `GetEnv` and
+/// `AttachCurrentThread`
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JNIError {
+    NoError,
+    GenericError,
+    Detached,
+    UnsupportedVersion,
+    OutOfMemory,
+    AlreadyCreated,
+    InvalidArguments,
+    /// A return code this library doesn't know about
+    UnknownError(i32)
+}
+
+impl JNIError {
+
+    /// The JNI return code this error was wrapped from
+    pub fn code(&self) -> i32 {
+        match *self {
+            JNIError::NoError => 0,
+            JNIError::GenericError => -1,
+            JNIError::Detached => -2,
+            JNIError::UnsupportedVersion => -3,
+            JNIError::OutOfMemory => -4,
+            JNIError::AlreadyCreated => -5,
+            JNIError::InvalidArguments => -6,
+            JNIError::UnknownError(code) => code
+        }
+    }
+}
+
+/// Turn a JNI return code into a type-safe error
+pub fn wrap_jni_error(code: i32) -> JNIError {
+    match code {
+        0 => JNIError::NoError,
+        -1 => JNIError::GenericError,
+        -2 => JNIError::Detached,
+        -3 => JNIError::UnsupportedVersion,
+        -4 => JNIError::OutOfMemory,
+        -5 => JNIError::AlreadyCreated,
+        -6 => JNIError::InvalidArguments,
+        _ => JNIError::UnknownError(code)
+    }
+}
+
+/// Turn JNI return codes into meaningful and user-readable strings
+pub fn translate_jni_error(code: &JNIError) -> String {
+    match code {
+        &JNIError::NoError => "No error has occurred.",
+        &JNIError::GenericError => "Unknown JNI error.",
+        &JNIError::Detached => "The thread is not attached to the virtual machine.",
+        &JNIError::UnsupportedVersion => "The requested JNI or JVM TI version is not supported.",
+        &JNIError::OutOfMemory => "Not enough memory.",
+        &JNIError::AlreadyCreated => "A virtual machine has already been created.",
+        &JNIError::InvalidArguments => "Invalid arguments.",
+        &JNIError::UnknownError(code) => return format!("Unknown JNI return code: {}.", code)
+    }.to_string()
+}
+
+impl fmt::Display for JNIError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (JNI error {})", translate_jni_error(self), self.code())
+    }
+}
+
+impl Error for JNIError {}
+
+impl From<JNIError> for NativeError {
+    fn from(error: JNIError) -> NativeError {
+        NativeError::JNIError(error)
+    }
+}
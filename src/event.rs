@@ -1,38 +1,42 @@
+use super::event_handler::unsubscribe;
 use super::native::jvmti_native::*;
 use super::runtime::*;
 use super::thread::Thread;
+use std::sync::Arc;
 
-pub type FnMethodEntry = fn(event: MethodInvocationEvent) -> ();
-pub type FnMethodExit = fn(event: MethodInvocationEvent) -> ();
-pub type FnVMInit = fn() -> ();
-pub type FnVMDeath = fn() -> ();
-pub type FnVMStart = fn() -> ();
-pub type FnVMObjectAlloc = fn(event: ObjectAllocationEvent) -> ();
-pub type FnVMObjectFree = fn() -> ();
-pub type FnThreadStart = fn(thread: Thread) -> ();
-pub type FnThreadEnd = fn(thread: Thread) -> ();
-pub type FnException = fn() -> ();
-pub type FnExceptionCatch = fn() -> ();
-pub type FnMonitorWait = fn(thread: Thread) -> ();
-pub type FnMonitorWaited = fn(thread: Thread) -> ();
-pub type FnMonitorContendedEnter = fn(thread: Thread) -> ();
-pub type FnMonitorContendedEntered = fn(thread: Thread) -> ();
-pub type FnFieldAccess = fn() -> ();
-pub type FnFieldModification = fn() -> ();
-pub type FnGarbageCollectionStart = fn() -> ();
-pub type FnGarbageCollectionFinish = fn() -> ();
-pub type FnClassFileLoad = fn(event: ClassFileLoadEvent) -> Option<Vec<u8>>;
-pub type FnClassLoad = fn() -> ();
-pub type FnClassPrepare = fn() -> ();
-pub type FnSingleStep = fn() -> ();
-pub type FnFramePop = fn() -> ();
-pub type FnBreakpoint = fn() -> ();
-pub type FnNativeMethodBind = fn() -> ();
-pub type FnCompiledMethodLoad = fn() -> ();
-pub type FnCompiledMethodUnload = fn() -> ();
-pub type FnDynamicCodeGenerated = fn() -> ();
-pub type FnResourceExhausted = fn() -> ();
-pub type FnDataDumpRequest = fn() -> ();
+pub type FnMethodEntry = Fn(MethodInvocationEvent) + Send + Sync;
+pub type FnMethodExit = Fn(MethodInvocationEvent) + Send + Sync;
+pub type FnVMInit = Fn() + Send + Sync;
+pub type FnVMDeath = Fn() + Send + Sync;
+pub type FnVMStart = Fn() + Send + Sync;
+pub type FnVMObjectAlloc = Fn(ObjectAllocationEvent) + Send + Sync;
+pub type FnVMObjectFree = Fn() + Send + Sync;
+pub type FnThreadStart = Fn(Thread) + Send + Sync;
+pub type FnThreadEnd = Fn(Thread) + Send + Sync;
+pub type FnException = Fn(ExceptionEvent) + Send + Sync;
+pub type FnExceptionCatch = Fn(ExceptionCatchEvent) + Send + Sync;
+pub type FnMonitorWait = Fn(MonitorWaitEvent) + Send + Sync;
+pub type FnMonitorWaited = Fn(MonitorWaitedEvent) + Send + Sync;
+pub type FnMonitorContendedEnter = Fn(MonitorContendedEnterEvent) + Send + Sync;
+pub type FnMonitorContendedEntered = Fn(MonitorContendedEnteredEvent) + Send + Sync;
+pub type FnFieldAccess = Fn(FieldAccessEvent) + Send + Sync;
+pub type FnFieldModification = Fn(FieldModificationEvent) + Send + Sync;
+pub type FnGarbageCollectionStart = Fn() + Send + Sync;
+pub type FnGarbageCollectionFinish = Fn() + Send + Sync;
+pub type FnClassFileLoad = Fn(ClassFileLoadEvent) -> Option<Vec<u8>> + Send + Sync;
+pub type FnClassLoad = Fn(ClassLoadEvent) + Send + Sync;
+pub type FnClassPrepare = Fn(ClassPrepareEvent) + Send + Sync;
+/// Called while the JVM is freeing the class, so the handler must not call back into the JVM
+pub type FnClassUnload = Fn(ClassUnloadEvent) + Send + Sync;
+pub type FnSingleStep = Fn(SingleStepEvent) + Send + Sync;
+pub type FnFramePop = Fn(FramePopEvent) + Send + Sync;
+pub type FnBreakpoint = Fn(BreakpointEvent) + Send + Sync;
+pub type FnNativeMethodBind = Fn() + Send + Sync;
+pub type FnCompiledMethodLoad = Fn(CompiledMethodLoadEvent) + Send + Sync;
+pub type FnCompiledMethodUnload = Fn(CompiledMethodUnloadEvent) + Send + Sync;
+pub type FnDynamicCodeGenerated = Fn(DynamicCodeGeneratedEvent) + Send + Sync;
+pub type FnResourceExhausted = Fn() + Send + Sync;
+pub type FnDataDumpRequest = Fn() + Send + Sync;
 
 ///
 /// `VMEvent` represents events that can occur in JVM applications. These events can be handled
@@ -75,42 +79,88 @@ pub enum VMEvent {
 }
 
 ///
-/// The `EventCallbacks` structure is used to define a set of event handlers that the JVM will call
-/// when an event fires.
+/// The handlers subscribed to a single event, in the order they were subscribed in. Handlers are
+/// shared, so they can be called without holding on to the table they're kept in.
+pub struct Handlers<F: ?Sized> {
+    entries: Vec<(usize, Arc<F>)>
+}
+
+impl<F: ?Sized> Handlers<F> {
+
+    pub fn new() -> Handlers<F> {
+        Handlers { entries: vec![] }
+    }
+
+    pub fn add(&mut self, id: usize, handler: Box<F>) {
+        self.entries.push((id, Arc::from(handler)));
+    }
+
+    /// Remove the handler subscribed with the given identifier, returning whether there was one
+    pub fn remove(&mut self, id: usize) -> bool {
+        let len = self.entries.len();
+
+        self.entries.retain(|&(entry_id, _)| entry_id != id);
+        self.entries.len() != len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// The handlers to call for an event
+    pub fn snapshot(&self) -> Vec<Arc<F>> {
+        self.entries.iter().map(|&(_, ref handler)| handler.clone()).collect()
+    }
+}
+
+impl<F: ?Sized> Default for Handlers<F> {
+    fn default() -> Handlers<F> {
+        Handlers::new()
+    }
+}
+
+///
+/// The `EventCallbacks` structure holds the handlers the JVM events are dispatched to, any number
+/// of them per event.
 ///
-#[derive(Default, Clone)]
+#[derive(Default)]
 pub struct EventCallbacks {
-    pub vm_init: Option<FnVMInit>,
-    pub vm_death: Option<FnVMDeath>,
-    pub vm_object_alloc: Option<FnVMObjectAlloc>,
-    pub vm_object_free: Option<FnVMObjectFree>,
-    pub vm_start: Option<FnVMStart>,
-    pub method_entry: Option<FnMethodEntry>,
-    pub method_exit: Option<FnMethodExit>,
-    pub thread_start: Option<FnThreadStart>,
-    pub thread_end: Option<FnThreadEnd>,
-    pub exception: Option<FnException>,
-    pub exception_catch: Option<FnExceptionCatch>,
-    pub monitor_wait: Option<FnMonitorWait>,
-    pub monitor_waited: Option<FnMonitorWaited>,
-    pub monitor_contended_enter: Option<FnMonitorContendedEnter>,
-    pub monitor_contended_entered: Option<FnMonitorContendedEntered>,
-    pub field_access: Option<FnFieldAccess>,
-    pub field_modification: Option<FnFieldModification>,
-    pub garbage_collection_start: Option<FnGarbageCollectionStart>,
-    pub garbage_collection_finish: Option<FnGarbageCollectionFinish>,
-    pub class_file_load_hook: Option<FnClassFileLoad>,
-    pub class_load: Option<FnClassLoad>,
-    pub class_prepare: Option<FnClassPrepare>,
-    pub single_step: Option<FnSingleStep>,
-    pub frame_pop: Option<FnFramePop>,
-    pub breakpoint: Option<FnBreakpoint>,
-    pub native_method_bind: Option<FnNativeMethodBind>,
-    pub compiled_method_load: Option<FnCompiledMethodLoad>,
-    pub compiled_method_unload: Option<FnCompiledMethodUnload>,
-    pub dynamic_code_generated: Option<FnDynamicCodeGenerated>,
-    pub data_dump_request: Option<FnDataDumpRequest>,
-    pub resource_exhausted: Option<FnResourceExhausted>
+    pub vm_init: Handlers<FnVMInit>,
+    pub vm_death: Handlers<FnVMDeath>,
+    pub vm_object_alloc: Handlers<FnVMObjectAlloc>,
+    pub vm_object_free: Handlers<FnVMObjectFree>,
+    pub vm_start: Handlers<FnVMStart>,
+    pub method_entry: Handlers<FnMethodEntry>,
+    pub method_exit: Handlers<FnMethodExit>,
+    pub thread_start: Handlers<FnThreadStart>,
+    pub thread_end: Handlers<FnThreadEnd>,
+    pub exception: Handlers<FnException>,
+    pub exception_catch: Handlers<FnExceptionCatch>,
+    pub monitor_wait: Handlers<FnMonitorWait>,
+    pub monitor_waited: Handlers<FnMonitorWaited>,
+    pub monitor_contended_enter: Handlers<FnMonitorContendedEnter>,
+    pub monitor_contended_entered: Handlers<FnMonitorContendedEntered>,
+    pub field_access: Handlers<FnFieldAccess>,
+    pub field_modification: Handlers<FnFieldModification>,
+    pub garbage_collection_start: Handlers<FnGarbageCollectionStart>,
+    pub garbage_collection_finish: Handlers<FnGarbageCollectionFinish>,
+    pub class_file_load_hook: Handlers<FnClassFileLoad>,
+    pub class_load: Handlers<FnClassLoad>,
+    pub class_prepare: Handlers<FnClassPrepare>,
+    pub class_unload: Handlers<FnClassUnload>,
+    pub single_step: Handlers<FnSingleStep>,
+    pub frame_pop: Handlers<FnFramePop>,
+    pub breakpoint: Handlers<FnBreakpoint>,
+    pub native_method_bind: Handlers<FnNativeMethodBind>,
+    pub compiled_method_load: Handlers<FnCompiledMethodLoad>,
+    pub compiled_method_unload: Handlers<FnCompiledMethodUnload>,
+    pub dynamic_code_generated: Handlers<FnDynamicCodeGenerated>,
+    pub data_dump_request: Handlers<FnDataDumpRequest>,
+    pub resource_exhausted: Handlers<FnResourceExhausted>
 }
 
 impl EventCallbacks {
@@ -118,4 +168,78 @@ impl EventCallbacks {
     pub fn new() -> EventCallbacks {
         EventCallbacks { ..Default::default() }
     }
+
+    /// Whether each event needs to be generated for the handlers subscribed to it, class unload
+    /// handlers relying on the class prepare and object free events
+    pub fn notifications(&self) -> Vec<(VMEvent, bool)> {
+        vec![
+            (VMEvent::VMObjectAlloc, !self.vm_object_alloc.is_empty()),
+            (VMEvent::VMObjectFree, !self.vm_object_free.is_empty() || !self.class_unload.is_empty()),
+            (VMEvent::VMStart, !self.vm_start.is_empty()),
+            (VMEvent::VMInit, !self.vm_init.is_empty()),
+            (VMEvent::VMDeath, !self.vm_death.is_empty()),
+            (VMEvent::MethodEntry, !self.method_entry.is_empty()),
+            (VMEvent::MethodExit, !self.method_exit.is_empty()),
+            (VMEvent::ThreadStart, !self.thread_start.is_empty()),
+            (VMEvent::ThreadEnd, !self.thread_end.is_empty()),
+            (VMEvent::Exception, !self.exception.is_empty()),
+            (VMEvent::ExceptionCatch, !self.exception_catch.is_empty()),
+            (VMEvent::MonitorWait, !self.monitor_wait.is_empty()),
+            (VMEvent::MonitorWaited, !self.monitor_waited.is_empty()),
+            (VMEvent::MonitorContendedEnter, !self.monitor_contended_enter.is_empty()),
+            (VMEvent::MonitorContendedEntered, !self.monitor_contended_entered.is_empty()),
+            (VMEvent::FieldAccess, !self.field_access.is_empty()),
+            (VMEvent::FieldModification, !self.field_modification.is_empty()),
+            (VMEvent::GarbageCollectionStart, !self.garbage_collection_start.is_empty()),
+            (VMEvent::GarbageCollectionFinish, !self.garbage_collection_finish.is_empty()),
+            (VMEvent::ClassFileLoadHook, !self.class_file_load_hook.is_empty()),
+            (VMEvent::ClassLoad, !self.class_load.is_empty()),
+            (VMEvent::ClassPrepare, !self.class_prepare.is_empty() || !self.class_unload.is_empty()),
+            (VMEvent::CompiledMethodLoad, !self.compiled_method_load.is_empty()),
+            (VMEvent::CompiledMethodUnload, !self.compiled_method_unload.is_empty()),
+            (VMEvent::DynamicCodeGenerated, !self.dynamic_code_generated.is_empty()),
+            (VMEvent::Breakpoint, !self.breakpoint.is_empty()),
+            (VMEvent::FramePop, !self.frame_pop.is_empty())
+        ]
+    }
+
+    /// Remove the handler subscribed with the given identifier from whichever event it handles
+    pub fn remove(&mut self, id: usize) -> bool {
+        self.vm_init.remove(id) || self.vm_death.remove(id) || self.vm_object_alloc.remove(id) ||
+            self.vm_object_free.remove(id) || self.vm_start.remove(id) || self.method_entry.remove(id) ||
+            self.method_exit.remove(id) || self.thread_start.remove(id) || self.thread_end.remove(id) ||
+            self.exception.remove(id) || self.exception_catch.remove(id) || self.monitor_wait.remove(id) ||
+            self.monitor_waited.remove(id) || self.monitor_contended_enter.remove(id) || self.monitor_contended_entered.remove(id) ||
+            self.field_access.remove(id) || self.field_modification.remove(id) || self.garbage_collection_start.remove(id) ||
+            self.garbage_collection_finish.remove(id) || self.class_file_load_hook.remove(id) || self.class_load.remove(id) ||
+            self.class_prepare.remove(id) || self.class_unload.remove(id) || self.single_step.remove(id) || self.frame_pop.remove(id) ||
+            self.breakpoint.remove(id) || self.native_method_bind.remove(id) || self.compiled_method_load.remove(id) ||
+            self.compiled_method_unload.remove(id) || self.dynamic_code_generated.remove(id) || self.data_dump_request.remove(id) ||
+            self.resource_exhausted.remove(id)
+    }
+}
+
+///
+/// A handle to a subscribed event handler, returned by the `Agent::on_*` methods
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Subscription {
+    id: usize
+}
+
+impl Subscription {
+
+    pub fn new(id: usize) -> Subscription {
+        Subscription { id: id }
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Stop delivering events to the handler. Events keep being generated as long as other
+    /// handlers are subscribed to them, or until the `Agent` is updated. Returns whether the
+    /// handler was still subscribed.
+    pub fn unsubscribe(self) -> bool {
+        unsubscribe(self)
+    }
 }
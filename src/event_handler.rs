@@ -1,131 +1,119 @@
+use super::class::{ClassId, ClassSignature};
 use super::environment::Environment;
 use super::environment::jni::{JNI, JNIEnvironment};
 use super::environment::jvmti::{JVMTI, JVMTIEnvironment};
 use super::error::{translate_error, NativeError};
 use super::event::*;
-use super::method::MethodId;
+use super::field::FieldId;
+use super::method::{line_number_at, MethodId};
 use super::native::*;
+use super::pipeline;
+use super::pipeline::{EventRecord, MonitorRecord};
 use super::native::jvmti_native::*;
+use super::native::jvmticmlr::*;
 use super::runtime::*;
+use super::snapshot::take_snapshot;
+use super::thread::Thread;
+use super::value::JavaValue;
 use libc::{c_char, c_uchar, c_void};
+use std::cell::Cell;
+use std::collections::HashMap;
 use std::mem::size_of;
 use std::ptr;
+use std::slice;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use std::time::Instant;
 use super::util::stringify;
-use super::bytecode::*;
-use std::io::{ Cursor };
 
-pub static mut CALLBACK_TABLE: EventCallbacks = EventCallbacks {
-    vm_init: None,
-    vm_death: None,
-    vm_object_alloc: None,
-    vm_object_free: None,
-    vm_start: None,
-    method_entry: None,
-    method_exit: None,
-    exception: None,
-    exception_catch: None,
-    monitor_wait: None,
-    monitor_waited: None,
-    monitor_contended_enter: None,
-    monitor_contended_entered: None,
-    thread_start: None,
-    thread_end: None,
-    field_access: None,
-    field_modification: None,
-    garbage_collection_start: None,
-    garbage_collection_finish: None,
-    class_file_load_hook: None,
-    class_load: None,
-    class_prepare: None,
-    single_step: None,
-    frame_pop: None,
-    breakpoint: None,
-    native_method_bind: None,
-    compiled_method_load: None,
-    compiled_method_unload: None,
-    dynamic_code_generated: None,
-    data_dump_request: None,
-    resource_exhausted: None
-};
-
-pub fn register_vm_init_callback(callback: Option<FnVMInit>) {
-    unsafe { CALLBACK_TABLE.vm_init = callback; }
-}
-
-pub fn register_vm_death_callback(callback: Option<FnVMDeath>) {
-    unsafe { CALLBACK_TABLE.vm_death = callback; }
-}
-
-pub fn register_vm_object_alloc_callback(callback: Option<FnVMObjectAlloc>) {
-    unsafe { CALLBACK_TABLE.vm_object_alloc = callback; }
-}
-
-pub fn register_vm_object_free_callback(callback: Option<FnVMObjectFree>) {
-    unsafe { CALLBACK_TABLE.vm_object_free = callback; }
+lazy_static! {
+    /// The handlers events are dispatched to. The native callbacks only hold the lock while taking
+    /// a snapshot of the handlers, so handlers are free to subscribe and unsubscribe.
+    pub static ref CALLBACK_TABLE: RwLock<EventCallbacks> = RwLock::new(EventCallbacks::new());
 }
 
-pub fn register_vm_start_callback(callback: Option<FnVMStart>) {
-    unsafe { CALLBACK_TABLE.vm_start = callback; }
+lazy_static! {
+    /// The classes that are tagged for their unloading to be dispatched, by tag
+    static ref UNLOAD_TRACKED_CLASSES: Mutex<HashMap<JavaLong, ClassUnloadEvent>> = Mutex::new(HashMap::new());
 }
 
-pub fn register_method_entry_callback(callback: Option<FnMethodEntry>) {
-    unsafe { CALLBACK_TABLE.method_entry = callback; }
-}
+/// The environment the callbacks were registered with, which events are disabled in on unsubscribing
+static EVENT_ENVIRONMENT: AtomicPtr<jvmtiEnv> = AtomicPtr::new(0 as JVMTIEnvPtr);
+static NEXT_SUBSCRIPTION: AtomicUsize = AtomicUsize::new(0);
+static NEXT_CLASS_TAG: AtomicUsize = AtomicUsize::new(1);
+static EXCEPTION_FRAMES: AtomicUsize = AtomicUsize::new(0);
+static MONITOR_FRAMES: AtomicUsize = AtomicUsize::new(0);
+static BREAKPOINT_FRAMES: AtomicUsize = AtomicUsize::new(0);
 
-pub fn register_method_exit_callback(callback: Option<FnMethodExit>) {
-    unsafe { CALLBACK_TABLE.method_exit = callback; }
+thread_local! {
+    /// When the current thread started blocking on entering a monitor
+    static CONTENDED_SINCE: Cell<Option<Instant>> = Cell::new(None);
 }
 
-pub fn register_exception_callback(callback: Option<FnException>) {
-    unsafe { CALLBACK_TABLE.exception = callback; }
-}
+/// Add a handler to the handlers of the event selected by `select`, eg. `|callbacks| &mut callbacks.method_entry`
+pub fn subscribe<F: ?Sized, S>(select: S, handler: Box<F>) -> Subscription where S: FnOnce(&mut EventCallbacks) -> &mut Handlers<F> {
+    let id = NEXT_SUBSCRIPTION.fetch_add(1, Ordering::SeqCst);
 
-pub fn register_exception_catch_callback(callback: Option<FnExceptionCatch>) {
-    unsafe { CALLBACK_TABLE.exception_catch = callback; }
-}
+    match CALLBACK_TABLE.write() {
+        Ok(mut callbacks) => select(&mut callbacks).add(id, handler),
+        Err(_) => println!("Couldn't subscribe event handler, the callback table is poisoned")
+    }
 
-pub fn register_monitor_wait_callback(callback: Option<FnMonitorWait>) {
-    unsafe { CALLBACK_TABLE.monitor_wait = callback; }
+    Subscription::new(id)
 }
 
-pub fn register_monitor_waited_callback(callback: Option<FnMonitorWaited>) {
-    unsafe { CALLBACK_TABLE.monitor_waited = callback; }
-}
+/// Remove a subscribed handler, returning whether it was still subscribed. Events left without
+/// handlers are disabled.
+pub fn unsubscribe(subscription: Subscription) -> bool {
+    let (removed, unhandled) = match CALLBACK_TABLE.write() {
+        Ok(mut callbacks) => {
+            let handled = callbacks.notifications();
+            let removed = callbacks.remove(subscription.id());
+            let unhandled: Vec<VMEvent> = handled.into_iter().zip(callbacks.notifications())
+                .filter(|&((_, was_handled), (_, is_handled))| was_handled && !is_handled)
+                .map(|((event, _), _)| event)
+                .collect();
 
-pub fn register_monitor_contended_enter_callback(callback: Option<FnMonitorContendedEnter>) {
-    unsafe { CALLBACK_TABLE.monitor_contended_enter = callback; }
-}
+            (removed, unhandled)
+        },
+        Err(_) => (false, vec![])
+    };
 
-pub fn register_monitor_contended_endered_callback(callback: Option<FnMonitorContendedEntered>) {
-    unsafe { CALLBACK_TABLE.monitor_contended_entered = callback; }
-}
+    let env_ptr = EVENT_ENVIRONMENT.load(Ordering::SeqCst);
 
-pub fn register_thread_start_callback(callback: Option<FnThreadStart>) {
-    unsafe { CALLBACK_TABLE.thread_start = callback; }
-}
+    if !unhandled.is_empty() && !env_ptr.is_null() {
+        let mut jvmti = JVMTIEnvironment::new(env_ptr);
 
-pub fn register_thread_end_callback(callback: Option<FnThreadEnd>) {
-    unsafe { CALLBACK_TABLE.thread_end = callback; }
-}
+        for event in unhandled {
+            jvmti.set_event_notification_mode(event, false);
+        }
+    }
 
-pub fn register_field_access_callback(callback: Option<FnFieldAccess>) {
-    unsafe { CALLBACK_TABLE.field_access = callback; }
+    removed
 }
 
-pub fn register_field_modification_callback(callback: Option<FnFieldModification>) {
-    unsafe { CALLBACK_TABLE.field_modification = callback; }
+/// Remember the environment the callbacks are registered with
+pub fn set_event_environment(env_ptr: JVMTIEnvPtr) {
+    EVENT_ENVIRONMENT.store(env_ptr, Ordering::SeqCst);
 }
 
-pub fn register_garbage_collection_start(callback: Option<FnGarbageCollectionStart>) {
-    unsafe { CALLBACK_TABLE.garbage_collection_start = callback;}
+/// The handlers of the event selected by `select`
+pub fn subscribed<F: ?Sized, S>(select: S) -> Vec<Arc<F>> where S: FnOnce(&EventCallbacks) -> &Handlers<F> {
+    match CALLBACK_TABLE.read() {
+        Ok(callbacks) => select(&callbacks).snapshot(),
+        Err(_) => vec![]
+    }
 }
 
-pub fn register_garbage_collection_finish(callback: Option<FnGarbageCollectionFinish>) {
-    unsafe { CALLBACK_TABLE.garbage_collection_finish = callback; }
-}
+/// Call each handler with its own copy of the event
+pub fn dispatch<E: Clone>(handlers: Vec<Arc<Fn(E) + Send + Sync>>, event: E) {
+    if let Some((last, rest)) = handlers.split_last() {
+        for handler in rest {
+            handler(event.clone());
+        }
 
-pub fn register_class_file_load_hook(callback: Option<FnClassFileLoad>) {
-    unsafe { CALLBACK_TABLE.class_file_load_hook = callback; }
+        last(event);
+    }
 }
 
 pub fn registered_callbacks() -> (jvmtiEventCallbacks, i32) {
@@ -141,8 +129,7 @@ pub fn local_event_callbacks() -> jvmtiEventCallbacks {
         VMDeath: Some(local_cb_vm_death), //jvmtiEventVMDeath,
         ThreadStart: Some(local_cb_thread_start), //jvmtiEventThreadStart,
         ThreadEnd: Some(local_cb_thread_end), //jvmtiEventThreadEnd,
-        //ClassFileLoadHook: Some(local_cb_class_file_load_hook), //jvmtiEventClassFileLoadHook,
-        ClassFileLoadHook: None, //jvmtiEventClassFileLoadHook,
+        ClassFileLoadHook: Some(local_cb_class_file_load_hook), //jvmtiEventClassFileLoadHook,
         ClassLoad: Some(local_cb_class_load), //jvmtiEventClassLoad,
         ClassPrepare: Some(local_cb_class_prepare), //jvmtiEventClassPrepare,
         VMStart: Some(local_cb_vm_start), //jvmtiEventVMStart,
@@ -179,340 +166,619 @@ pub fn local_event_callbacks() -> jvmtiEventCallbacks {
 
 #[allow(unused_variables)]
 unsafe extern "C" fn local_cb_vm_object_alloc(jvmti_env: *mut jvmtiEnv, jni_env: *mut JNIEnv, thread: JavaThread, object: JavaObject, object_klass: JavaClass, size: jlong) -> () {
-    match CALLBACK_TABLE.vm_object_alloc {
-        Some(function) => {
-            let env = Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env));
-            match env.get_thread_info(&thread) {
-                Ok(current_thread) => {
-                    let class_id = env.get_object_class(&object);
-
-                    function(ObjectAllocationEvent { class_id: class_id, size: size as i64, thread: current_thread })
-                },
-                Err(err) => {
-                    match err {
-                        NativeError::WrongPhase => { /* we're in the wrong phase, just ignore this */ },
-                        _ => println!("Couldn't get thread info: {}", translate_error(&err))
-                    }
+    let handlers = subscribed(|callbacks| &callbacks.vm_object_alloc);
+
+    if !handlers.is_empty() {
+        let env = Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env));
+        match env.get_thread_info(&thread) {
+            Ok(current_thread) => {
+                let class_id = env.get_object_class(&object);
+
+                dispatch(handlers, ObjectAllocationEvent { class_id: class_id, size: size as i64, thread: current_thread })
+            },
+            Err(err) => {
+                match err {
+                    NativeError::WrongPhase => { /* we're in the wrong phase, just ignore this */ },
+                    _ => println!("Couldn't get thread info: {}", translate_error(&err))
                 }
             }
-        },
-        None => println!("No dynamic callback method was found for VM object allocation")
+        }
     }
 }
 
 #[allow(unused_variables)]
 unsafe extern "C" fn local_cb_method_entry(jvmti_env: *mut jvmtiEnv, jni_env: *mut JNIEnv, thread: JavaThread, method: JavaMethod) -> () {
-    match CALLBACK_TABLE.method_entry {
-        Some(function) => {
-            let env = Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env));
-            match env.get_thread_info(&thread) {
-                Ok(current_thread) => {
-                    let method_id = MethodId { native_id : method };
-                    let class_id = env.get_method_declaring_class(&method_id).ok().unwrap();
-                    let class_sig = env.get_class_signature(&class_id).ok().unwrap();
-                    let method_sig = env.get_method_name(&method_id).ok().unwrap();
-
-                    function(MethodInvocationEvent { method_id: method_id, method_sig: method_sig, class_sig: class_sig, thread: current_thread })
+    if pipeline::is_enabled() {
+        pipeline::record(EventRecord::MethodEntry(MethodId { native_id: method }), || pipeline::buffered_thread(&Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env)), &thread));
+        return;
+    }
 
-                },
-                Err(err) => {
-                    match err {
-                        NativeError::WrongPhase => { /* we're in the wrong phase, just ignore this */ },
-                        _ => println!("Couldn't get thread info: {}", translate_error(&err))
-                    }
+    let handlers = subscribed(|callbacks| &callbacks.method_entry);
+
+    if !handlers.is_empty() {
+        let env = Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env));
+        match env.get_thread_info(&thread) {
+            Ok(current_thread) => {
+                let method_id = MethodId { native_id : method };
+                let class_id = env.get_method_declaring_class(&method_id).ok().unwrap();
+                let class_sig = env.get_class_signature(&class_id).ok().unwrap();
+                let method_sig = env.get_method_name(&method_id).ok().unwrap();
+
+                dispatch(handlers, MethodInvocationEvent { method_id: method_id, method_sig: method_sig, class_sig: class_sig, thread: current_thread })
+
+            },
+            Err(err) => {
+                match err {
+                    NativeError::WrongPhase => { /* we're in the wrong phase, just ignore this */ },
+                    _ => println!("Couldn't get thread info: {}", translate_error(&err))
                 }
             }
-        },
-        None => println!("No dynamic callback method was found for method entry")
+        }
     }
 }
 
 #[allow(unused_variables)]
 unsafe extern "C" fn local_cb_method_exit(jvmti_env: *mut jvmtiEnv, jni_env: *mut JNIEnv, thread: jthread, method: jmethodID, was_popped_by_exception: jboolean, return_value: jvalue) -> () {
-    match CALLBACK_TABLE.method_exit {
-        Some(function) => {
-            let env = Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env));
-            match env.get_thread_info(&thread) {
-                Ok(current_thread) => {
-                    let method_id = MethodId { native_id : method };
-                    let class_id = env.get_method_declaring_class(&method_id).ok().unwrap();
-                    let class_sig = env.get_class_signature(&class_id).ok().unwrap();
-                    let method_sig = env.get_method_name(&method_id).ok().unwrap();
-
-                    function(MethodInvocationEvent { method_id: method_id, method_sig: method_sig, class_sig: class_sig, thread: current_thread })
+    if pipeline::is_enabled() {
+        pipeline::record(EventRecord::MethodExit(MethodId { native_id: method }), || pipeline::buffered_thread(&Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env)), &thread));
+        return;
+    }
 
-                },
-                Err(err) => {
-                    match err {
-                        NativeError::WrongPhase => { /* we're in the wrong phase, just ignore this */ },
-                        _ => println!("Couldn't get thread info: {}", translate_error(&err))
-                    }
+    let handlers = subscribed(|callbacks| &callbacks.method_exit);
+
+    if !handlers.is_empty() {
+        let env = Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env));
+        match env.get_thread_info(&thread) {
+            Ok(current_thread) => {
+                let method_id = MethodId { native_id : method };
+                let class_id = env.get_method_declaring_class(&method_id).ok().unwrap();
+                let class_sig = env.get_class_signature(&class_id).ok().unwrap();
+                let method_sig = env.get_method_name(&method_id).ok().unwrap();
+
+                dispatch(handlers, MethodInvocationEvent { method_id: method_id, method_sig: method_sig, class_sig: class_sig, thread: current_thread })
+
+            },
+            Err(err) => {
+                match err {
+                    NativeError::WrongPhase => { /* we're in the wrong phase, just ignore this */ },
+                    _ => println!("Couldn't get thread info: {}", translate_error(&err))
                 }
             }
         }
-        None => println!("No dynamic callback method was found for method exit")
     }
 }
 
 #[allow(unused_variables)]
 unsafe extern "C" fn local_cb_exception(jvmti_env: *mut jvmtiEnv, jni_env: *mut JNIEnv, thread: jthread, method: jmethodID, location: jlocation, exception: JavaObject, catch_method: jmethodID, catch_location: jlocation) -> () {
-    match CALLBACK_TABLE.exception {
-        Some(function) => {
-            function();
-            let env = Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env));
-            let exception_class = env.get_object_class(&exception);
-
-            function()
-        },
-        None => println!("No dynamic callback method was found for exception")
+    let handlers = subscribed(|callbacks| &callbacks.exception);
+
+    if !handlers.is_empty() {
+        let env = Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env));
+        match env.get_thread_info(&thread) {
+            Ok(current_thread) => {
+                let throw_location = resolve_location(&env, method, location);
+                let exception_class = resolve_object_class(&env, &exception);
+                let catch_location = if catch_method.is_null() { None } else { resolve_location(&env, catch_method, catch_location) };
+                let frames = match EXCEPTION_FRAMES.load(Ordering::SeqCst) {
+                    0 => vec![],
+                    max_frames => take_snapshot(&env, &thread, max_frames).unwrap_or(vec![])
+                };
+
+                match (throw_location, exception_class) {
+                    (Some(throw_location), Some(exception_class)) => dispatch(handlers, ExceptionEvent { thread: current_thread, location: throw_location, exception_class: exception_class, catch_location: catch_location, frames: frames }),
+                    _ => println!("Couldn't resolve the location of exception")
+                }
+            },
+            Err(err) => {
+                match err {
+                    NativeError::WrongPhase => { /* we're in the wrong phase, just ignore this */ },
+                    _ => println!("Couldn't get thread info: {}", translate_error(&err))
+                }
+            }
+        }
     }
 }
 
 #[allow(unused_variables)]
 unsafe extern "C" fn local_cb_exception_catch(jvmti_env: *mut jvmtiEnv, jni_env: *mut JNIEnv, thread: jthread, method: jmethodID, location: jlocation, exception: jobject) -> () {
-    match CALLBACK_TABLE.exception_catch {
-        Some(function) => {
-            function();
-            /*
-            let env = Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env));
-            let current_thread = env.get_thread_info(&thread).ok().unwrap();
-
-            function()
-            */
-        },
-        None => println!("No dynamic callback method was found for exception catch")
+    let handlers = subscribed(|callbacks| &callbacks.exception_catch);
+
+    if !handlers.is_empty() {
+        let env = Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env));
+        match env.get_thread_info(&thread) {
+            Ok(current_thread) => {
+                match (resolve_location(&env, method, location), resolve_object_class(&env, &exception)) {
+                    (Some(catch_location), Some(exception_class)) => dispatch(handlers, ExceptionCatchEvent { thread: current_thread, location: catch_location, exception_class: exception_class }),
+                    _ => println!("Couldn't resolve the location of exception catch")
+                }
+            },
+            Err(err) => {
+                match err {
+                    NativeError::WrongPhase => { /* we're in the wrong phase, just ignore this */ },
+                    _ => println!("Couldn't get thread info: {}", translate_error(&err))
+                }
+            }
+        }
+    }
+}
+
+/// Resolve the method and the source line of a bytecode location. The line is left out if the
+/// class has no line number information or the line number capability wasn't granted
+fn resolve_location(env: &Environment, method: jmethodID, location: jlocation) -> Option<CodeLocation> {
+    let method_id = MethodId { native_id: method };
+    let class_id = env.get_method_declaring_class(&method_id).ok()?;
+    let class_sig = env.get_class_signature(&class_id).ok();
+
+    env.delete_local_ref(class_id.native_id);
+
+    let method_sig = env.get_method_name(&method_id).ok()?;
+    let line_number = env.get_line_number_table(&method_id).ok().and_then(|table| line_number_at(&table, location));
+
+    Some(CodeLocation { method_id: method_id, method_sig: method_sig, class_sig: class_sig?, location: location, line_number: line_number })
+}
+
+fn resolve_object_class(env: &Environment, object: &JavaObject) -> Option<ClassSignature> {
+    let class_id = env.get_object_class(object);
+    let class_sig = env.get_class_signature(&class_id).ok();
+
+    env.delete_local_ref(class_id.native_id);
+    class_sig
+}
+
+fn resolve_monitor(env: &Environment, object: &JavaObject) -> Option<MonitorObject> {
+    match (resolve_object_class(env, object), env.get_object_hash_code(object)) {
+        (Some(class_sig), Ok(object_hash)) => Some(MonitorObject { class_sig: class_sig, object_hash: object_hash }),
+        _ => None
+    }
+}
+
+/// Record a monitor event for the drain thread, releasing the reference to the class of the monitor
+/// if the event couldn't be buffered
+fn record_monitor<F>(env: &Environment, thread: &JavaThread, object: &JavaObject, record: F) where F: FnOnce(MonitorRecord) -> EventRecord {
+    if let Some(monitor) = MonitorRecord::new(env, object) {
+        if !pipeline::record(record(monitor), || pipeline::buffered_thread(env, thread)) {
+            monitor.release(env);
+        }
+    }
+}
+
+fn dispatch_monitor_event<E: Clone>(handlers: Vec<Arc<Fn(E) + Send + Sync>>, env: &Environment, thread: &JavaThread, object: &JavaObject, event: &Fn(Thread, MonitorObject) -> E) {
+    match (env.get_thread_info(thread), resolve_monitor(env, object)) {
+        (Ok(current_thread), Some(monitor)) => dispatch(handlers, event(current_thread, monitor)),
+        (Err(NativeError::WrongPhase), _) => { /* we're in the wrong phase, just ignore this */ },
+        (Err(err), _) => println!("Couldn't get thread info: {}", translate_error(&err)),
+        (Ok(_), None) => println!("Couldn't resolve the monitor")
     }
 }
 
 #[allow(unused_variables)]
 unsafe extern "C" fn local_cb_monitor_wait(jvmti_env: *mut jvmtiEnv, jni_env: *mut JNIEnv, thread: jthread, object: jobject, timeout: jlong) -> () {
-    match CALLBACK_TABLE.monitor_wait {
-        Some(function) => {
-            let env = Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env));
-            match env.get_thread_info(&thread) {
-                Ok(current_thread) => function(current_thread),
-                Err(err) => {
-                    match err {
-                        NativeError::WrongPhase => { /* we're in the wrong phase, just ignore this */ },
-                        _ => println!("Couldn't get thread info: {}", translate_error(&err))
-                    }
-                }
-            }
-        },
-        None => println!("No dynamic callback method was found for monitor wait")
+    let env = Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env));
+
+    if pipeline::is_enabled() {
+        return record_monitor(&env, &thread, &object, |monitor| EventRecord::MonitorWait(monitor, timeout));
+    }
+
+    let handlers = subscribed(|callbacks| &callbacks.monitor_wait);
+
+    if !handlers.is_empty() {
+        dispatch_monitor_event(handlers, &env, &thread, &object, &|thread, monitor| MonitorWaitEvent { thread: thread, monitor: monitor, timeout: timeout });
     }
 }
 
 #[allow(unused_variables)]
 unsafe extern "C" fn local_cb_monitor_waited(jvmti_env: *mut jvmtiEnv, jni_env: *mut JNIEnv, thread: jthread, object: jobject, timed_out: jboolean) -> () {
-    match CALLBACK_TABLE.monitor_waited {
-        Some(function) => {
-            let env = Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env));
-            match env.get_thread_info(&thread) {
-                Ok(current_thread) => function(current_thread),
-                Err(err) => {
-                    match err {
-                        NativeError::WrongPhase => { /* we're in the wrong phase, just ignore this */ },
-                        _ => println!("Couldn't get thread info: {}", translate_error(&err))
-                    }
-                }
-            }
-        },
-        None => println!("No dynamic callback method was found for monitor entered")
+    let env = Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env));
+
+    if pipeline::is_enabled() {
+        return record_monitor(&env, &thread, &object, |monitor| EventRecord::MonitorWaited(monitor, timed_out != 0));
+    }
+
+    let handlers = subscribed(|callbacks| &callbacks.monitor_waited);
+
+    if !handlers.is_empty() {
+        dispatch_monitor_event(handlers, &env, &thread, &object, &|thread, monitor| MonitorWaitedEvent { thread: thread, monitor: monitor, timed_out: timed_out != 0 });
     }
 }
 
 #[allow(unused_variables)]
 unsafe extern "C" fn local_cb_monitor_contended_enter(jvmti_env: *mut jvmtiEnv, jni_env: *mut JNIEnv, thread: jthread, object: jobject) -> () {
-    match CALLBACK_TABLE.monitor_contended_enter {
-        Some(function) => {
-            let env = Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env));
-            match env.get_thread_info(&thread) {
-                Ok(current_thread) => function(current_thread),
-                Err(err) => {
-                    match err {
-                        NativeError::WrongPhase => { /* we're in the wrong phase, just ignore this */ },
-                        _ => println!("Couldn't get thread info: {}", translate_error(&err))
-                    }
-                }
-            }
-        },
-        None => println!("No dynamic callback method was found for monitor contended enter")
+    CONTENDED_SINCE.with(|since| since.set(Some(Instant::now())));
+
+    let env = Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env));
+
+    if pipeline::is_enabled() {
+        return record_monitor(&env, &thread, &object, |monitor| EventRecord::MonitorContendedEnter(monitor));
+    }
+
+    let handlers = subscribed(|callbacks| &callbacks.monitor_contended_enter);
+
+    if !handlers.is_empty() {
+        dispatch_monitor_event(handlers, &env, &thread, &object, &|thread, monitor| MonitorContendedEnterEvent { thread: thread, monitor: monitor });
     }
 }
 
 #[allow(unused_variables)]
 unsafe extern "C" fn local_cb_monitor_contended_entered(jvmti_env: *mut jvmtiEnv, jni_env: *mut JNIEnv, thread: jthread, object: jobject) -> () {
-    match CALLBACK_TABLE.monitor_contended_entered {
-        Some(function) => {
-            let env = Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env));
-            match env.get_thread_info(&thread) {
-                Ok(current_thread) => function(current_thread),
-                Err(err) => {
-                    match err {
-                        NativeError::WrongPhase => { /* we're in the wrong phase, just ignore this */ },
-                        _ => println!("Couldn't get thread info: {}", translate_error(&err))
-                    }
-                }
-            }
-        },
-        None => println!("No dynamic callback method was found for monitor contended entered")
+    let blocked_nanos = CONTENDED_SINCE.with(|since| since.take()).map(|since| {
+        let blocked = since.elapsed();
+        blocked.as_secs() as JavaLong * 1_000_000_000 + blocked.subsec_nanos() as JavaLong
+    });
+
+    let env = Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env));
+
+    if pipeline::is_enabled() {
+        return record_monitor(&env, &thread, &object, |monitor| EventRecord::MonitorContendedEntered(monitor, blocked_nanos));
+    }
+
+    let handlers = subscribed(|callbacks| &callbacks.monitor_contended_entered);
+
+    if !handlers.is_empty() {
+        let frames = match MONITOR_FRAMES.load(Ordering::SeqCst) {
+            0 => vec![],
+            max_frames => env.get_stack_trace(&thread, 0, max_frames).unwrap_or(vec![])
+        };
+
+        dispatch_monitor_event(handlers, &env, &thread, &object, &|thread, monitor| MonitorContendedEnteredEvent { thread: thread, monitor: monitor, blocked_nanos: blocked_nanos, frames: frames.clone() });
     }
 }
 
 #[allow(unused_variables)]
 unsafe extern "C" fn local_cb_thread_start(jvmti_env: *mut jvmtiEnv, jni_env: *mut JNIEnv, thread: jthread) -> () {
-    match CALLBACK_TABLE.thread_start {
-        Some(function) => {
-            let env = Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env));
-            match env.get_thread_info(&thread) {
-                Ok(current_thread) => function(current_thread),
-                Err(err) => {
-                    match err {
-                        NativeError::WrongPhase => { /* we're in the wrong phase, just ignore this */ },
-                        _ => println!("Couldn't get thread info: {}", translate_error(&err))
-                    }
+    if pipeline::is_enabled() {
+        pipeline::record(EventRecord::ThreadStart, || pipeline::buffered_thread(&Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env)), &thread));
+        return;
+    }
+
+    let handlers = subscribed(|callbacks| &callbacks.thread_start);
+
+    if !handlers.is_empty() {
+        let env = Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env));
+        match env.get_thread_info(&thread) {
+            Ok(current_thread) => dispatch(handlers, current_thread),
+            Err(err) => {
+                match err {
+                    NativeError::WrongPhase => { /* we're in the wrong phase, just ignore this */ },
+                    _ => println!("Couldn't get thread info: {}", translate_error(&err))
                 }
             }
-        },
-        None => println!("No dynamic callback method was found for thread start events")
+        }
     }
 
 }
 
 #[allow(unused_variables)]
 unsafe extern "C" fn local_cb_thread_end(jvmti_env: *mut jvmtiEnv, jni_env: *mut JNIEnv, thread: jthread) -> () {
-    match CALLBACK_TABLE.thread_end {
-        Some(function) => {
-            let env = Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env));
-            match env.get_thread_info(&thread) {
-                Ok(current_thread) => function(current_thread),
-                Err(err) => {
-                    match err {
-                        NativeError::WrongPhase => { /* wrong phase, just ignore this */ },
-                        _ => println!("Couldn't get thread info: {}", translate_error(&err))
-                    }
+    if pipeline::is_enabled() {
+        pipeline::record(EventRecord::ThreadEnd, || pipeline::buffered_thread(&Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env)), &thread));
+        return;
+    }
+
+    let handlers = subscribed(|callbacks| &callbacks.thread_end);
+
+    if !handlers.is_empty() {
+        let env = Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env));
+        match env.get_thread_info(&thread) {
+            Ok(current_thread) => dispatch(handlers, current_thread),
+            Err(err) => {
+                match err {
+                    NativeError::WrongPhase => { /* wrong phase, just ignore this */ },
+                    _ => println!("Couldn't get thread info: {}", translate_error(&err))
                 }
             }
-        },
-        None => println!("No dynamic callback method was found for thread end events")
+        }
     }
 }
 
 #[allow(unused_variables)]
 unsafe extern "C" fn local_cb_garbage_collection_start(jvmti_env: *mut jvmtiEnv) -> () {
-    match CALLBACK_TABLE.garbage_collection_start {
-        Some(function) => {
-            function();
-
-        },
-        None => println!("No dynamic callback method was found for garbage collection start events")
+    if pipeline::is_enabled() {
+        pipeline::record(EventRecord::GarbageCollectionStart, || None);
+        return;
     }
 
+    for handler in subscribed(|callbacks| &callbacks.garbage_collection_start) {
+        handler();
+    }
 }
 
 #[allow(unused_variables)]
 unsafe extern "C" fn local_cb_garbage_collection_finish(jvmti_env: *mut jvmtiEnv) -> () {
-    match CALLBACK_TABLE.garbage_collection_finish {
-        Some(function) => {
-            function();
-
-        },
-        None => println!("No dynamic callback method was found for garbage collection finish events")
+    if pipeline::is_enabled() {
+        pipeline::record(EventRecord::GarbageCollectionFinish, || None);
+        return;
     }
 
+    for handler in subscribed(|callbacks| &callbacks.garbage_collection_finish) {
+        handler();
+    }
 }
 
 #[allow(unused_variables)]
 unsafe extern "C" fn local_cb_breakpoint(jvmti_env: *mut jvmtiEnv, jni_env: *mut JNIEnv, thread: jthread, method: jmethodID, location: jlocation) -> () {
-
+    let handlers = subscribed(|callbacks| &callbacks.breakpoint);
+
+    if !handlers.is_empty() {
+        let env = Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env));
+        match env.get_thread_info(&thread) {
+            Ok(current_thread) => {
+                let frames = match BREAKPOINT_FRAMES.load(Ordering::SeqCst) {
+                    0 => vec![],
+                    max_frames => take_snapshot(&env, &thread, max_frames).unwrap_or(vec![])
+                };
+
+                match resolve_location(&env, method, location) {
+                    // Each handler gets an environment of its own, the events not being cloneable
+                    Some(breakpoint_location) => for handler in handlers {
+                        handler(BreakpointEvent { thread: current_thread.clone(), location: breakpoint_location.clone(), frames: frames.clone(),
+                                                  environment: Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env)) });
+                    },
+                    None => println!("Couldn't resolve the location of breakpoint")
+                }
+            },
+            Err(err) => {
+                match err {
+                    NativeError::WrongPhase => { /* we're in the wrong phase, just ignore this */ },
+                    _ => println!("Couldn't get thread info: {}", translate_error(&err))
+                }
+            }
+        }
+    }
 }
 
 #[allow(unused_variables)]
 unsafe extern "C" fn local_cb_class_file_load_hook(jvmti_env: JVMTIEnvPtr, jni_env: JNIEnvPtr, class_being_redefined: JavaClass, loader: JavaObject,
                                                    name: *const c_char, protection_domain: JavaObject, class_data_len: jint, class_data: *const c_uchar,
                                                    new_class_data_len: *mut jint, new_class_data: *mut *mut c_uchar) -> () {
-    match CALLBACK_TABLE.class_file_load_hook {
-        Some(function) => {
-            let env = Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env));
-
-            let mut raw_data: Vec<u8> = Vec::with_capacity(class_data_len as usize);
-            let data_ptr = raw_data.as_mut_ptr();
-
-            ptr::copy_nonoverlapping(class_data, data_ptr, class_data_len as usize);
-            raw_data.set_len(class_data_len as usize);
-
-            if let Ok(classfile) = parse_class(&raw_data) {
-                match function(ClassFileLoadEvent { class_name: stringify(name), class: classfile }) {
-                    Some(transformed) => {
-                        println!("Transformed class {}", stringify(name));
-
-                        match env.allocate(transformed.len()) {
-                            Ok(allocation) => {
-                                ptr::copy_nonoverlapping(transformed.as_ptr(), allocation.ptr, allocation.len);
-                                *new_class_data_len = allocation.len as i32;
-                                *new_class_data = allocation.ptr;
-                            },
-                            Err(err) => {
-                                println!("Failed to allocate memory")
-                            }
-                        }
-                    },
-                    None => ()
+    let handlers = subscribed(|callbacks| &callbacks.class_file_load_hook);
+
+    if !handlers.is_empty() {
+        let env = Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env));
+        let class_name = stringify(name);
+
+        // Handlers only parse the classes they transform, so the class data isn't copied up front
+        let raw_data = slice::from_raw_parts(class_data, class_data_len as usize);
+
+        // Each handler gets the class as transformed by the handlers subscribed before it
+        let mut transformed: Option<Vec<u8>> = None;
+
+        for handler in handlers {
+            let result = handler(ClassFileLoadEvent { class_name: class_name.clone(), class_data: transformed.as_ref().map(|data| data.as_slice()).unwrap_or(raw_data),
+                                                      class_loader: if loader.is_null() { None } else { Some(loader) },
+                                                      environment: Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env)) });
+
+            if result.is_some() {
+                transformed = result;
+            }
+        }
+
+        if let Some(transformed) = transformed {
+            match env.allocate(transformed.len()) {
+                Ok(allocation) => {
+                    ptr::copy_nonoverlapping(transformed.as_ptr(), allocation.ptr, allocation.len);
+                    *new_class_data_len = allocation.len as i32;
+                    *new_class_data = allocation.ptr;
+                },
+                Err(err) => {
+                    println!("Failed to allocate memory")
                 }
+            }
+        }
+
+        debug!("Loading class {} with length {}", class_name, class_data_len);
+    }
+}
+
+#[allow(unused_variables)]
+unsafe extern "C" fn local_cb_class_load(jvmti_env: *mut jvmtiEnv, jni_env: *mut JNIEnv, thread: jthread, klass: jclass) -> () {
+    let handlers = subscribed(|callbacks| &callbacks.class_load);
+
+    if !handlers.is_empty() {
+        let env = Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env));
+        match env.get_thread_info(&thread) {
+            Ok(current_thread) => {
+                let class_id = ClassId { native_id: klass };
 
-            } else {
-                println!("Coult not parse class file");
+                match env.get_class_signature(&class_id) {
+                    Ok(class_sig) => {
+                        let class_loader = resolve_class_loader(&env, &class_id);
+                        let source_file = env.get_source_file_name(&class_id).ok();
+
+                        dispatch(handlers, ClassLoadEvent { thread: current_thread, class_id: class_id, class_sig: class_sig, class_loader: class_loader, source_file: source_file })
+                    },
+                    Err(err) => println!("Couldn't resolve loaded class: {}", translate_error(&err))
+                }
+            },
+            Err(err) => {
+                match err {
+                    NativeError::WrongPhase => { /* we're in the wrong phase, just ignore this */ },
+                    _ => println!("Couldn't get thread info: {}", translate_error(&err))
+                }
             }
+        }
+    }
+}
+
+#[allow(unused_variables)]
+unsafe extern "C" fn local_cb_class_prepare(jvmti_env: *mut jvmtiEnv, jni_env: *mut JNIEnv, thread: jthread, klass: jclass) -> () {
+    let handlers = subscribed(|callbacks| &callbacks.class_prepare);
+    let track_unload = !subscribed(|callbacks| &callbacks.class_unload).is_empty();
+
+    if !handlers.is_empty() || track_unload {
+        let env = Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env));
+        let class_id = ClassId { native_id: klass };
+
+        match env.get_class_signature(&class_id) {
+            Ok(class_sig) => {
+                let class_loader = resolve_class_loader(&env, &class_id);
+
+                if track_unload {
+                    track_class_unload(&env, &class_id, &class_sig, class_loader);
+                }
+
+                if !handlers.is_empty() {
+                    match env.get_thread_info(&thread) {
+                        Ok(current_thread) => {
+                            let source_file = env.get_source_file_name(&class_id).ok();
+
+                            dispatch(handlers, ClassPrepareEvent { thread: current_thread, class_id: class_id, class_sig: class_sig, class_loader: class_loader, source_file: source_file })
+                        },
+                        Err(err) => {
+                            match err {
+                                NativeError::WrongPhase => { /* we're in the wrong phase, just ignore this */ },
+                                _ => println!("Couldn't get thread info: {}", translate_error(&err))
+                            }
+                        }
+                    }
+                }
+            },
+            Err(err) => println!("Couldn't resolve prepared class: {}", translate_error(&err))
+        }
+    }
+}
 
+/// The identity hash code of the class loader that defined a class, `None` for the bootstrap
+/// class loader
+fn resolve_class_loader(env: &Environment, class_id: &ClassId) -> Option<JavaInt> {
+    match env.get_class_loader(class_id) {
+        Ok(Some(loader)) => {
+            let hash_code = env.get_object_hash_code(&loader).ok();
 
-            println!("Loading class {} with length {}", stringify(name), class_data_len);
+            env.delete_local_ref(loader);
+            hash_code
         },
-        None => println!("No dynamic callback method was found for class file load events")
+        _ => None
     }
 }
 
-fn parse_class(data: &Vec<u8>) -> Result<Classfile, ::std::io::Error> {
-    let mut cursor = Cursor::new(data);
+/// Capture the top `max_frames` frames of the throwing thread along with Exception events, zero
+/// turning the capture off
+pub fn capture_exception_frames(max_frames: usize) {
+    EXCEPTION_FRAMES.store(max_frames, Ordering::SeqCst);
+}
+
+/// Capture the top `max_frames` frames of the thread entering a monitor along with
+/// MonitorContendedEntered events, zero turning the capture off. Frames aren't captured when the
+/// events are dispatched asynchronously.
+pub fn capture_monitor_frames(max_frames: usize) {
+    MONITOR_FRAMES.store(max_frames, Ordering::SeqCst);
+}
+
+/// Capture the top `max_frames` frames of the thread hitting a breakpoint along with Breakpoint
+/// events, zero turning the capture off
+pub fn capture_breakpoint_frames(max_frames: usize) {
+    BREAKPOINT_FRAMES.store(max_frames, Ordering::SeqCst);
+}
+
+/// Tag a class so that a ClassUnload event is dispatched once the JVM frees it. Classes are given
+/// negative tags, leaving the positive ones to other uses of object tagging. Requires the
+/// can_tag_objects and can_generate_object_free_events capabilities, and the ObjectFree event to
+/// be enabled. Returns whether the class is tracked.
+pub fn track_class_unload(env: &Environment, class_id: &ClassId, class_sig: &ClassSignature, class_loader: Option<JavaInt>) -> bool {
+    match env.get_tag(&class_id.native_id) {
+        Ok(0) => {
+            let tag = -(NEXT_CLASS_TAG.fetch_add(1, Ordering::SeqCst) as JavaLong);
 
-    //let class_result = ClassReader::read_class(&mut cursor);
-    ClassReader::read_class(&mut cursor)
+            match (env.set_tag(&class_id.native_id, tag), UNLOAD_TRACKED_CLASSES.lock()) {
+                (Ok(_), Ok(mut classes)) => {
+                    let methods = env.get_class_methods(class_id).unwrap_or(vec![]);
 
-/*
-    match class_result {
-        Ok(classfile) => {
-            let output_class: Vec<u8> = vec![];
-            let mut write_cursor = Cursor::new(output_class);
-            let mut writer = ClassWriter::new(&mut write_cursor);
-            match writer.write_class(&classfile) {
-                Ok(len) => (),
-                Err(error) => ()
+                    classes.insert(tag, ClassUnloadEvent { class_sig: class_sig.clone(), class_loader: class_loader, methods: methods });
+                    true
+                },
+                _ => false
             }
         },
-        Err(error) => ()
+        Ok(tag) => tag < 0,
+        Err(_) => false
     }
-    */
 }
 
-#[allow(unused_variables)]
-unsafe extern "C" fn local_cb_class_load(jvmti_env: *mut jvmtiEnv, jni_env: *mut JNIEnv, thread: jthread, klass: jclass) -> () {
+/// Track the unloading of every class loaded so far, except array classes, which are unloaded
+/// along with their element classes. Returns the number of classes tracked.
+pub fn track_loaded_classes(env: &Environment) -> Result<usize, NativeError> {
+    let mut tracked = 0;
 
-}
+    for class_id in env.get_loaded_classes()? {
+        match env.get_class_signature(&class_id) {
+            Ok(ref class_sig) if !class_sig.name.ends_with("[]") => {
+                if track_class_unload(env, &class_id, class_sig, resolve_class_loader(env, &class_id)) {
+                    tracked += 1;
+                }
+            },
+            _ => ()
+        }
 
-#[allow(unused_variables)]
-unsafe extern "C" fn local_cb_class_prepare(jvmti_env: *mut jvmtiEnv, jni_env: *mut JNIEnv, thread: jthread, klass: jclass) -> () {
+        env.delete_local_ref(class_id.native_id);
+    }
 
+    Ok(tracked)
 }
 
 #[allow(unused_variables)]
 unsafe extern "C" fn local_cb_compiled_method_load(jvmti_env: *mut jvmtiEnv, method: jmethodID, code_size: jint, code_addr: *const c_void, map_length: jint,
                                                    map: *const jvmtiAddrLocationMap, compile_info: *const c_void) -> () {
+    let handlers = subscribed(|callbacks| &callbacks.compiled_method_load);
+
+    if !handlers.is_empty() {
+        // There's no JNI environment here, the JVM releases the local references made by the
+        // handlers when they return
+        let jvmti = JVMTIEnvironment::new(jvmti_env);
+        let method_id = MethodId { native_id: method };
+        let class_sig = jvmti.get_method_declaring_class(&method_id).and_then(|class_id| jvmti.get_class_signature(&class_id));
+
+        match (class_sig, jvmti.get_method_name(&method_id)) {
+            (Ok(class_sig), Ok(method_sig)) => {
+                let location_map = if map.is_null() { vec![] } else {
+                    slice::from_raw_parts(map, map_length as usize).iter()
+                        .map(|entry| AddressLocation { address: entry.start_address as usize, location: entry.location })
+                        .collect()
+                };
+
+                dispatch(handlers, CompiledMethodLoadEvent { method_id: method_id, method_sig: method_sig, class_sig: class_sig, code_address: code_addr as usize,
+                                                             code_size: code_size as usize, location_map: location_map, inlining: read_inline_records(compile_info) })
+            },
+            _ => println!("Couldn't resolve compiled method")
+        }
+    }
+}
+
+/// Read the inlined frames out of the compile info records of a CompiledMethodLoad event
+unsafe fn read_inline_records(compile_info: *const c_void) -> Vec<InlinedFrames> {
+    let mut inlining = vec![];
+    let mut record = compile_info as *const jvmtiCompiledMethodLoadRecordHeader;
+
+    while !record.is_null() {
+        if (*record).kind == JVMTI_CMLR_INLINE_INFO {
+            let inline_record = &*(record as *const jvmtiCompiledMethodLoadInlineRecord);
 
+            if !inline_record.pcinfo.is_null() {
+                for pc_info in slice::from_raw_parts(inline_record.pcinfo, inline_record.numpcs as usize) {
+                    let frames = if pc_info.methods.is_null() || pc_info.bcis.is_null() { vec![] } else {
+                        let methods = slice::from_raw_parts(pc_info.methods, pc_info.numstackframes as usize);
+                        let bcis = slice::from_raw_parts(pc_info.bcis, pc_info.numstackframes as usize);
+
+                        methods.iter().zip(bcis).map(|(method, bci)| (MethodId { native_id: *method }, *bci)).collect()
+                    };
+
+                    inlining.push(InlinedFrames { address: pc_info.pc as usize, frames: frames });
+                }
+            }
+        }
+
+        record = (*record).next;
+    }
+
+    inlining
 }
 
 #[allow(unused_variables)]
 unsafe extern "C" fn local_cb_compiled_method_unload(jvmti_env: *mut jvmtiEnv, method: jmethodID, code_addr: *const c_void) -> () {
+    let handlers = subscribed(|callbacks| &callbacks.compiled_method_unload);
 
+    if !handlers.is_empty() {
+        dispatch(handlers, CompiledMethodUnloadEvent { method_id: MethodId { native_id: method }, code_address: code_addr as usize });
+    }
 }
 
 #[allow(unused_variables)]
@@ -522,34 +788,100 @@ unsafe extern "C" fn local_cb_data_dump_request(jvmti_env: *mut jvmtiEnv) -> ()
 
 #[allow(unused_variables)]
 unsafe extern "C" fn local_cb_dynamic_code_generated(jvmti_env: *mut jvmtiEnv, name: *const c_char, address: *const c_void, length: jint) -> () {
+    let handlers = subscribed(|callbacks| &callbacks.dynamic_code_generated);
 
+    if !handlers.is_empty() {
+        dispatch(handlers, DynamicCodeGeneratedEvent { name: stringify(name), address: address as usize, length: length as usize });
+    }
 }
 
 #[allow(unused_variables)]
 unsafe extern "C" fn local_cb_field_access(jvmti_env: *mut jvmtiEnv, jni_env: *mut JNIEnv, thread: jthread, method: jmethodID, location: jlocation,
                                                    field_klass: jclass, object: jobject, field: jfieldID) -> () {
-    match CALLBACK_TABLE.field_access {
-        Some(function) => {
-            function();
-        },
-        None => println!("No dynamic callback method was found for field access events")
+    let handlers = subscribed(|callbacks| &callbacks.field_access);
+
+    if !handlers.is_empty() {
+        let env = Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env));
+        match env.get_thread_info(&thread) {
+            Ok(current_thread) => {
+                let field_id = FieldId { native_id: field };
+                let field_class_id = ClassId { native_id: field_klass };
+
+                match (resolve_location(&env, method, location), env.get_class_signature(&field_class_id), env.get_field_name(&field_class_id, &field_id)) {
+                    (Some(access_location), Ok(field_class), Ok(field_sig)) => dispatch(handlers, FieldAccessEvent { thread: current_thread, location: access_location, field_id: field_id, field_class: field_class, field: field_sig, object: object }),
+                    _ => println!("Couldn't resolve the field or location of field access")
+                }
+            },
+            Err(err) => {
+                match err {
+                    NativeError::WrongPhase => { /* we're in the wrong phase, just ignore this */ },
+                    _ => println!("Couldn't get thread info: {}", translate_error(&err))
+                }
+            }
+        }
     }
 }
 
 #[allow(unused_variables)]
 unsafe extern "C" fn local_cb_field_modification(jvmti_env: *mut jvmtiEnv, jni_env: *mut JNIEnv, thread: jthread, method: jmethodID, location: jlocation,
                                                    field_klass: jclass, object: jobject, field: jfieldID, signature_type: c_char, new_value: jvalue) -> () {
-    match CALLBACK_TABLE.field_modification {
-        Some(function) => {
-            function();
-        },
-        None => println!("No dynamic callback method was found for field modification events")
+    let handlers = subscribed(|callbacks| &callbacks.field_modification);
+
+    if !handlers.is_empty() {
+        let env = Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env));
+        match env.get_thread_info(&thread) {
+            Ok(current_thread) => {
+                let field_id = FieldId { native_id: field };
+                let field_class_id = ClassId { native_id: field_klass };
+                let value = JavaValue::from_jvalue(signature_type as u8 as char, new_value);
+
+                match (resolve_location(&env, method, location), env.get_class_signature(&field_class_id), env.get_field_name(&field_class_id, &field_id), value) {
+                    (Some(modification_location), Ok(field_class), Ok(field_sig), Some(value)) => dispatch(handlers, FieldModificationEvent { thread: current_thread, location: modification_location, field_id: field_id, field_class: field_class, field: field_sig, object: object, new_value: value }),
+                    _ => println!("Couldn't resolve the field or location of field modification")
+                }
+            },
+            Err(err) => {
+                match err {
+                    NativeError::WrongPhase => { /* we're in the wrong phase, just ignore this */ },
+                    _ => println!("Couldn't get thread info: {}", translate_error(&err))
+                }
+            }
+        }
     }
 }
 
 #[allow(unused_variables)]
 unsafe extern "C" fn local_cb_frame_pop(jvmti_env: *mut jvmtiEnv, jni_env: *mut JNIEnv, thread: jthread, method: jmethodID, was_popped_by_exception: jboolean) -> () {
-
+    let handlers = subscribed(|callbacks| &callbacks.frame_pop);
+
+    if !handlers.is_empty() {
+        let env = Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env));
+        match env.get_thread_info(&thread) {
+            Ok(current_thread) => {
+                let method_id = MethodId { native_id: method };
+                let class_sig = env.get_method_declaring_class(&method_id).ok().and_then(|class_id| {
+                    let class_sig = env.get_class_signature(&class_id).ok();
+
+                    env.delete_local_ref(class_id.native_id);
+                    class_sig
+                });
+
+                match (class_sig, env.get_method_name(&method_id)) {
+                    (Some(class_sig), Ok(method_sig)) => for handler in handlers {
+                        handler(FramePopEvent { thread: current_thread.clone(), method_id: method_id, method_sig: method_sig.clone(), class_sig: class_sig.clone(),
+                                                popped_by_exception: was_popped_by_exception != 0, environment: Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env)) });
+                    },
+                    _ => println!("Couldn't resolve the method of frame pop")
+                }
+            },
+            Err(err) => {
+                match err {
+                    NativeError::WrongPhase => { /* we're in the wrong phase, just ignore this */ },
+                    _ => println!("Couldn't get thread info: {}", translate_error(&err))
+                }
+            }
+        }
+    }
 }
 
 #[allow(unused_variables)]
@@ -560,7 +892,22 @@ unsafe extern "C" fn local_cb_native_method_bind(jvmti_env: *mut jvmtiEnv, jni_e
 
 #[allow(unused_variables)]
 unsafe extern "C" fn local_cb_object_free(jvmti_env: *mut jvmtiEnv, tag: jlong) -> () {
-
+    // The freed object is gone already, classes are told apart by the tags they were given
+    let unloaded = if tag < 0 {
+        match UNLOAD_TRACKED_CLASSES.lock() {
+            Ok(mut classes) => classes.remove(&tag),
+            Err(_) => None
+        }
+    } else {
+        None
+    };
+
+    match unloaded {
+        Some(event) => dispatch(subscribed(|callbacks| &callbacks.class_unload), event),
+        None => for handler in subscribed(|callbacks| &callbacks.vm_object_free) {
+            handler();
+        }
+    }
 }
 
 #[allow(unused_variables)]
@@ -570,37 +917,49 @@ unsafe extern "C" fn local_cb_resource_exhausted(jvmti_env: *mut jvmtiEnv, jni_e
 
 #[allow(unused_variables)]
 unsafe extern "C" fn local_cb_single_step(jvmti_env: *mut jvmtiEnv, jni_env: *mut JNIEnv, thread: jthread, method: jmethodID, location: jlocation) -> () {
-
+    let handlers = subscribed(|callbacks| &callbacks.single_step);
+
+    if !handlers.is_empty() {
+        let env = Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env));
+        match env.get_thread_info(&thread) {
+            Ok(current_thread) => {
+                match resolve_location(&env, method, location) {
+                    Some(step_location) => for handler in handlers {
+                        handler(SingleStepEvent { thread: current_thread.clone(), location: step_location.clone(),
+                                                  environment: Environment::new(JVMTIEnvironment::new(jvmti_env), JNIEnvironment::new(jni_env)) });
+                    },
+                    None => println!("Couldn't resolve the location of single step")
+                }
+            },
+            Err(err) => {
+                match err {
+                    NativeError::WrongPhase => { /* we're in the wrong phase, just ignore this */ },
+                    _ => println!("Couldn't get thread info: {}", translate_error(&err))
+                }
+            }
+        }
+    }
 }
 
 #[allow(unused_variables)]
 unsafe extern "C" fn local_cb_vm_death(jvmti_env: *mut jvmtiEnv, jni_env: *mut JNIEnv) -> () {
 
-    match CALLBACK_TABLE.vm_death {
-        Some(function) => {
-            function();
-        },
-        None => println!("No dynamic callback method was found for VM death events")
+    for handler in subscribed(|callbacks| &callbacks.vm_death) {
+        handler();
     }
 }
 
 #[allow(unused_variables)]
 unsafe extern "C" fn local_cb_vm_init(jvmti_env: *mut jvmtiEnv, jni_env: *mut JNIEnv, thread: jthread) -> () {
 
-    match CALLBACK_TABLE.vm_init {
-        Some(function) => {
-            function();
-        },
-        None => println!("No dynamic callback method was found for VM init events")
+    for handler in subscribed(|callbacks| &callbacks.vm_init) {
+        handler();
     }
 }
 
 #[allow(unused_variables)]
 unsafe extern "C" fn local_cb_vm_start(jvmti_env: *mut jvmtiEnv, jni_env: *mut JNIEnv) -> () {
-    match CALLBACK_TABLE.vm_start {
-        Some(function) => {
-            function();
-        },
-        None => println!("No dynamic callback method was found for VM start events")
+    for handler in subscribed(|callbacks| &callbacks.vm_start) {
+        handler();
     }
 }
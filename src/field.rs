@@ -0,0 +1,22 @@
+use super::native::JavaField;
+
+#[derive(Hash, Eq, PartialEq, Clone, Copy, Debug)]
+pub struct FieldId {
+    pub native_id: JavaField
+}
+
+unsafe impl std::marker::Send for FieldId { }
+
+#[derive(Clone, Debug)]
+pub struct FieldSignature {
+    pub name: String,
+    pub signature: String,
+    pub generic: String
+}
+
+impl FieldSignature {
+
+    pub fn new(raw_name: String, raw_signature: String, raw_generic: String) -> FieldSignature {
+        FieldSignature { name: raw_name, signature: raw_signature, generic: raw_generic }
+    }
+}
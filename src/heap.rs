@@ -0,0 +1,303 @@
+use super::class::ClassId;
+use super::environment::Environment;
+use super::environment::jni::JNI;
+use super::environment::jvmti::JVMTI;
+use super::error::NativeError;
+use super::native::{JavaInt, JavaLong, JavaMethod};
+use super::native::jvmti_native::*;
+use libc::c_void;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::ops::BitOr;
+
+///
+/// Flags leaving objects out of heap iterations, by whether they or their classes are tagged.
+/// `HeapFilter::NONE` reports every object.
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct HeapFilter {
+    bits: u32
+}
+
+impl HeapFilter {
+
+    pub const NONE: HeapFilter = HeapFilter { bits: 0 };
+    pub const TAGGED: HeapFilter = HeapFilter { bits: JVMTI_HEAP_FILTER_TAGGED };
+    pub const UNTAGGED: HeapFilter = HeapFilter { bits: JVMTI_HEAP_FILTER_UNTAGGED };
+    pub const CLASS_TAGGED: HeapFilter = HeapFilter { bits: JVMTI_HEAP_FILTER_CLASS_TAGGED };
+    pub const CLASS_UNTAGGED: HeapFilter = HeapFilter { bits: JVMTI_HEAP_FILTER_CLASS_UNTAGGED };
+
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+}
+
+impl BitOr for HeapFilter {
+    type Output = HeapFilter;
+
+    fn bitor(self, other: HeapFilter) -> HeapFilter {
+        HeapFilter { bits: self.bits | other.bits }
+    }
+}
+
+///
+/// What a heap callback tells the JVM to do next. `Skip` leaves the objects referenced by the
+/// current object out of `follow_references`, and is the same as `Continue` otherwise.
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HeapVisit {
+    Continue,
+    Skip,
+    Abort
+}
+
+impl HeapVisit {
+
+    pub fn to_native(&self) -> jint {
+        match self {
+            &HeapVisit::Continue => JVMTI_VISIT_OBJECTS as jint,
+            &HeapVisit::Skip => 0,
+            &HeapVisit::Abort => JVMTI_VISIT_ABORT as jint
+        }
+    }
+}
+
+///
+/// An object reported by a heap iteration. Objects are only known by their tags and the tags of
+/// their classes, zero if untagged. Changing `tag` tags the object.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HeapObject {
+    pub class_tag: JavaLong,
+    pub size: JavaLong,
+    pub tag: JavaLong,
+    /// The length of arrays, `None` for other objects
+    pub length: Option<JavaInt>
+}
+
+///
+/// The kind of a reference followed by `follow_references`, with the details the JVM gives about
+/// it. The last kinds, from `JniGlobal` on, are references from the heap roots.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReferenceKind {
+    Class,
+    Field(JavaInt),
+    ArrayElement(JavaInt),
+    ClassLoader,
+    Signers,
+    ProtectionDomain,
+    Interface,
+    StaticField(JavaInt),
+    ConstantPool(JavaInt),
+    Superclass,
+    JniGlobal,
+    SystemClass,
+    Monitor,
+    StackLocal { thread_tag: JavaLong, thread_id: JavaLong, depth: JavaInt, method: JavaMethod, location: JavaLong, slot: JavaInt },
+    JniLocal { thread_tag: JavaLong, thread_id: JavaLong, depth: JavaInt, method: JavaMethod },
+    Thread,
+    Other,
+    Unknown(u32)
+}
+
+impl ReferenceKind {
+
+    /// Whether the reference is from a heap root rather than from another object
+    pub fn is_root(&self) -> bool {
+        match self {
+            &ReferenceKind::JniGlobal | &ReferenceKind::SystemClass | &ReferenceKind::Monitor | &ReferenceKind::StackLocal { .. } |
+            &ReferenceKind::JniLocal { .. } | &ReferenceKind::Thread | &ReferenceKind::Other => true,
+            _ => false
+        }
+    }
+}
+
+///
+/// A reference followed by `follow_references`. Root references have no referrer.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HeapReference {
+    pub kind: ReferenceKind,
+    pub referrer_class_tag: JavaLong,
+    pub referrer_tag: Option<JavaLong>
+}
+
+pub type FnHeapIteration<'a> = FnMut(&mut HeapObject) -> HeapVisit + 'a;
+pub type FnHeapReference<'a> = FnMut(&HeapReference, &mut HeapObject) -> HeapVisit + 'a;
+
+/// Native heap iteration callback, calling the closure passed in `user_data`
+pub unsafe extern "C" fn heap_iteration_callback(class_tag: jlong, size: jlong, tag_ptr: *mut jlong, length: jint, user_data: *mut c_void) -> jint {
+    let callback = &mut *(user_data as *mut &mut FnHeapIteration);
+    let mut object = HeapObject { class_tag: class_tag, size: size, tag: *tag_ptr, length: if length < 0 { None } else { Some(length) } };
+    let visit = callback(&mut object);
+
+    *tag_ptr = object.tag;
+    visit.to_native()
+}
+
+/// Native heap reference callback, calling the closure passed in `user_data`
+pub unsafe extern "C" fn heap_reference_callback(reference_kind: jvmtiHeapReferenceKind, reference_info: *const jvmtiHeapReferenceInfo, class_tag: jlong,
+                                                 referrer_class_tag: jlong, size: jlong, tag_ptr: *mut jlong, referrer_tag_ptr: *mut jlong, length: jint,
+                                                 user_data: *mut c_void) -> jint {
+    let callback = &mut *(user_data as *mut &mut FnHeapReference);
+    let reference = HeapReference {
+        kind: read_reference_kind(reference_kind, reference_info),
+        referrer_class_tag: referrer_class_tag,
+        referrer_tag: if referrer_tag_ptr.is_null() { None } else { Some(*referrer_tag_ptr) }
+    };
+    let mut object = HeapObject { class_tag: class_tag, size: size, tag: *tag_ptr, length: if length < 0 { None } else { Some(length) } };
+    let visit = callback(&reference, &mut object);
+
+    *tag_ptr = object.tag;
+    visit.to_native()
+}
+
+unsafe fn read_reference_kind(reference_kind: jvmtiHeapReferenceKind, reference_info: *const jvmtiHeapReferenceInfo) -> ReferenceKind {
+    let mut info = if reference_info.is_null() { jvmtiHeapReferenceInfo::default() } else { *reference_info };
+
+    match reference_kind as u32 {
+        JVMTI_HEAP_REFERENCE_CLASS => ReferenceKind::Class,
+        JVMTI_HEAP_REFERENCE_FIELD => ReferenceKind::Field((*info.field()).index),
+        JVMTI_HEAP_REFERENCE_ARRAY_ELEMENT => ReferenceKind::ArrayElement((*info.array()).index),
+        JVMTI_HEAP_REFERENCE_CLASS_LOADER => ReferenceKind::ClassLoader,
+        JVMTI_HEAP_REFERENCE_SIGNERS => ReferenceKind::Signers,
+        JVMTI_HEAP_REFERENCE_PROTECTION_DOMAIN => ReferenceKind::ProtectionDomain,
+        JVMTI_HEAP_REFERENCE_INTERFACE => ReferenceKind::Interface,
+        JVMTI_HEAP_REFERENCE_STATIC_FIELD => ReferenceKind::StaticField((*info.field()).index),
+        JVMTI_HEAP_REFERENCE_CONSTANT_POOL => ReferenceKind::ConstantPool((*info.constant_pool()).index),
+        JVMTI_HEAP_REFERENCE_SUPERCLASS => ReferenceKind::Superclass,
+        JVMTI_HEAP_REFERENCE_JNI_GLOBAL => ReferenceKind::JniGlobal,
+        JVMTI_HEAP_REFERENCE_SYSTEM_CLASS => ReferenceKind::SystemClass,
+        JVMTI_HEAP_REFERENCE_MONITOR => ReferenceKind::Monitor,
+        JVMTI_HEAP_REFERENCE_STACK_LOCAL => {
+            let local = *info.stack_local();
+            ReferenceKind::StackLocal { thread_tag: local.thread_tag, thread_id: local.thread_id, depth: local.depth, method: local.method, location: local.location, slot: local.slot }
+        },
+        JVMTI_HEAP_REFERENCE_JNI_LOCAL => {
+            let local = *info.jni_local();
+            ReferenceKind::JniLocal { thread_tag: local.thread_tag, thread_id: local.thread_id, depth: local.depth, method: local.method }
+        },
+        JVMTI_HEAP_REFERENCE_THREAD => ReferenceKind::Thread,
+        JVMTI_HEAP_REFERENCE_OTHER => ReferenceKind::Other,
+        kind @ _ => ReferenceKind::Unknown(kind)
+    }
+}
+
+///
+/// The number of instances of a class on the heap and their shallow size
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClassHistogramEntry {
+    pub class_name: String,
+    pub instances: u64,
+    pub bytes: u64
+}
+
+///
+/// The instances on the heap counted by class, like `jmap -histo` prints them
+///
+pub struct ClassHistogram {
+    classes: HashMap<String, ClassHistogramEntry>
+}
+
+impl ClassHistogram {
+
+    pub fn new() -> ClassHistogram {
+        ClassHistogram { classes: HashMap::new() }
+    }
+
+    /// Count an instance of the given class, of `size` bytes
+    pub fn add(&mut self, class_name: &str, size: u64) {
+        let entry = self.classes.entry(class_name.to_string()).or_insert_with(|| ClassHistogramEntry { class_name: class_name.to_string(), instances: 0, bytes: 0 });
+
+        entry.instances += 1;
+        entry.bytes += size;
+    }
+
+    /// The classes counted, the ones taking the most bytes first
+    pub fn entries(&self) -> Vec<&ClassHistogramEntry> {
+        let mut entries: Vec<&ClassHistogramEntry> = self.classes.values().collect();
+
+        entries.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.class_name.cmp(&b.class_name)));
+        entries
+    }
+
+    pub fn total_instances(&self) -> u64 {
+        self.classes.values().map(|entry| entry.instances).sum()
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.classes.values().map(|entry| entry.bytes).sum()
+    }
+
+    pub fn write(&self, writer: &mut Write) -> io::Result<()> {
+        writeln!(writer, " num     #instances         #bytes  class name")?;
+        writeln!(writer, "----------------------------------------------")?;
+
+        for (i, entry) in self.entries().iter().enumerate() {
+            writeln!(writer, "{:4}: {:14} {:14}  {}", i + 1, entry.instances, entry.bytes, entry.class_name)?;
+        }
+
+        writeln!(writer, "Total {:14} {:14}", self.total_instances(), self.total_bytes())
+    }
+}
+
+/// The class name objects of classes loaded during `class_histogram` are counted under
+pub const UNKNOWN_CLASS: &'static str = "<unknown>";
+
+/// Count the objects on the heap by class. Loaded classes are tagged for the duration of the count,
+/// with positive tags as classes tracked for unloading are given negative ones, and the tags are
+/// removed again afterwards. Requires the can_tag_objects capability.
+pub fn class_histogram(env: &Environment) -> Result<ClassHistogram, NativeError> {
+    let classes = env.get_loaded_classes()?;
+    let mut tagged = vec![];
+
+    let result = count_by_class(env, &classes, &mut tagged);
+
+    // the tags are removed whether the objects were counted or not
+    let untagged = tagged.iter().fold(Ok(()), |untagged, class_id| untagged.and(env.set_tag(&class_id.native_id, 0)));
+
+    for class_id in &classes {
+        env.delete_local_ref(class_id.native_id);
+    }
+
+    result.and_then(|histogram| untagged.map(|_| histogram))
+}
+
+/// Tag the untagged classes, adding them to `tagged`, and count the objects by the tags of their classes
+fn count_by_class(env: &Environment, classes: &Vec<ClassId>, tagged: &mut Vec<ClassId>) -> Result<ClassHistogram, NativeError> {
+    let mut class_tags = vec![];
+
+    for class_id in classes {
+        class_tags.push(env.get_tag(&class_id.native_id)?);
+    }
+
+    // classes may already be tagged, new tags must not clash with theirs
+    let mut next_tag = class_tags.iter().cloned().max().unwrap_or(0).max(0) + 1;
+    let mut class_names: HashMap<JavaLong, String> = HashMap::new();
+
+    for (class_id, tag) in classes.iter().zip(class_tags) {
+        let tag = if tag != 0 {
+            tag
+        } else {
+            env.set_tag(&class_id.native_id, next_tag)?;
+            tagged.push(class_id.clone());
+            next_tag += 1;
+            next_tag - 1
+        };
+
+        if let Ok(class_sig) = env.get_class_signature(class_id) {
+            class_names.insert(tag, class_sig.name);
+        }
+    }
+
+    let mut histogram = ClassHistogram::new();
+
+    env.iterate_through_heap(HeapFilter::NONE, None, &mut |object| {
+        histogram.add(class_names.get(&object.class_tag).map(|name| name.as_str()).unwrap_or(UNKNOWN_CLASS), object.size as u64);
+        HeapVisit::Continue
+    })?;
+
+    Ok(histogram)
+}
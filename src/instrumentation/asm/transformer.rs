@@ -1,19 +1,161 @@
 use super::super::super::bytecode::*;
+use super::super::super::bytecode::code::*;
+use super::super::super::bytecode::frames::*;
+
+/// The first class file version that requires stack map frames
+const FRAMES_MAJOR_VERSION: u16 = 50;
 
 pub struct Transformer<'a> {
 
-    class: &'a mut Classfile
+    class: &'a mut Classfile,
+    hierarchy: &'a ClassHierarchy
 }
 
 impl<'a> Transformer<'a> {
 
-    pub fn new(class: &mut Classfile) -> Transformer {
+    /// Create a transformer that merges reference types in recomputed frames according to `hierarchy`
+    pub fn new(class: &'a mut Classfile, hierarchy: &'a ClassHierarchy) -> Transformer<'a> {
         Transformer {
-            class: class
+            class: class,
+            hierarchy: hierarchy
         }
     }
 
     pub fn ensure_constant(&mut self, constant: Constant) -> ConstantPoolIndex {
-        self.class.constant_pool.get_constant_index(&constant).unwrap_or(self.class.constant_pool.add_constant(constant))
+        match self.class.constant_pool.get_constant_index(&constant) {
+            Some(idx) => idx,
+            None => self.class.constant_pool.add_constant(constant)
+        }
+    }
+
+    /// Find or add the `MethodRef` constant of the given method, eg. `java/lang/Object`, `hashCode`, `()I`
+    pub fn ensure_method_ref(&mut self, class_name: &str, method_name: &str, descriptor: &str) -> ConstantPoolIndex {
+        let class_name_index = self.ensure_constant(Constant::Utf8(class_name.to_string().into_bytes()));
+        let class_index = self.ensure_constant(Constant::Class(class_name_index));
+        let name_index = self.ensure_constant(Constant::Utf8(method_name.to_string().into_bytes()));
+        let descriptor_index = self.ensure_constant(Constant::Utf8(descriptor.to_string().into_bytes()));
+        let name_and_type_index = self.ensure_constant(Constant::NameAndType { name_index: name_index, descriptor_index: descriptor_index });
+
+        self.ensure_constant(Constant::MethodRef { class_index: class_index, name_and_type_index: name_and_type_index })
+    }
+
+    /// Wrap the code of the method at `method_idx` in `enter` and `exit`, the way a `try`/`finally`
+    /// block would. `enter` runs before the original code, `exit` runs before every return and in
+    /// a catch-all handler that rethrows whatever the original code threw. The handler doesn't
+    /// cover the `exit` sequences themselves, so `exit` runs once even if it throws. Both sequences
+    /// have to leave the operand stack as they found it. Constructors are not supported, as the
+    /// handler would cover the call to the superclass constructor
+    pub fn wrap_method(&mut self, method_idx: usize, enter: &Vec<Instruction>, exit: &Vec<Instruction>) -> Result<(), FrameError> {
+        self.edit_code(method_idx, |body, _| {
+            let handler = body.new_label();
+            let original = ::std::mem::replace(&mut body.items, vec![]);
+            // The protected ranges, from the start of the code or a return to the next return
+            let mut ranges: Vec<(Label, Label)> = vec![];
+            let mut start = body.new_label();
+            let mut covers_code = false;
+
+            body.items.extend(enter.iter().map(|instruction| CodeItem::Instruction(instruction.clone())));
+            body.items.push(CodeItem::Label(start));
+
+            for item in original {
+                if is_return(&item) {
+                    if covers_code {
+                        let end = body.new_label();
+
+                        body.items.push(CodeItem::Label(end));
+                        ranges.push((start, end));
+                    }
+
+                    body.items.extend(exit.iter().map(|instruction| CodeItem::Instruction(instruction.clone())));
+                    body.items.push(item);
+
+                    start = body.new_label();
+                    covers_code = false;
+                    body.items.push(CodeItem::Label(start));
+                } else {
+                    covers_code = covers_code || !is_label(&item);
+                    body.items.push(item);
+                }
+            }
+
+            if covers_code {
+                let end = body.new_label();
+
+                body.items.push(CodeItem::Label(end));
+                ranges.push((start, end));
+            }
+
+            body.items.push(CodeItem::Label(handler));
+            body.items.extend(exit.iter().map(|instruction| CodeItem::Instruction(instruction.clone())));
+            body.items.push(CodeItem::Instruction(Instruction::ATHROW));
+
+            // Appended last, so the handlers of the original code take precedence
+            for (start, end) in ranges {
+                body.try_catch_blocks.push(TryCatchBlock { start: start, end: end, handler: handler, catch_type: ConstantPoolIndex::new(0) });
+            }
+        })
+    }
+
+    /// Edit the code of the method at `method_idx` through its label based representation. The
+    /// maximum stack size and local variable count of the edited code are recomputed, and so are
+    /// its stack map frames if the class file version requires them. The method is left untouched
+    /// if its code can't be converted or the edited code can't be analysed or lowered
+    pub fn edit_code<F>(&mut self, method_idx: usize, edit: F) -> Result<(), FrameError> where F: FnOnce(&mut CodeBody, &mut ConstantPool) {
+        let code_idx = match self.class.methods.get(method_idx).and_then(|method| method.attributes.iter().position(|attribute| match attribute {
+            &Attribute::Code { .. } => true,
+            _ => false
+        })) {
+            Some(idx) => idx,
+            None => return Err(FrameError::Code(CodeError::NotCode))
+        };
+
+        let context = match MethodContext::of(&self.class, &self.class.methods[method_idx]) {
+            Some(context) => context,
+            None => return Err(FrameError::UnknownMethod)
+        };
+
+        let mut body = CodeBody::from_code(&self.class.methods[method_idx].attributes[code_idx])?;
+
+        edit(&mut body, &mut self.class.constant_pool);
+
+        if self.class.version.major_version >= FRAMES_MAJOR_VERSION {
+            compute_frames(&mut body, &context, &mut self.class.constant_pool, self.hierarchy)?;
+        } else {
+            compute_maxs(&mut body, &context, &self.class.constant_pool)?;
+        }
+
+        let lowered = body.lower()?;
+
+        // Tables the original code didn't have need their attribute names in the constant pool
+        if let &Attribute::Code { ref attributes, .. } = &lowered {
+            for attribute in attributes {
+                match attribute {
+                    &Attribute::LineNumberTable(_) => { self.ensure_constant(Constant::Utf8(String::from("LineNumberTable").into_bytes())); },
+                    &Attribute::LocalVariableTable(_) => { self.ensure_constant(Constant::Utf8(String::from("LocalVariableTable").into_bytes())); },
+                    &Attribute::LocalVariableTypeTable(_) => { self.ensure_constant(Constant::Utf8(String::from("LocalVariableTypeTable").into_bytes())); },
+                    &Attribute::StackMapTable(_) => { self.ensure_constant(Constant::Utf8(String::from("StackMapTable").into_bytes())); },
+                    _ => ()
+                }
+            }
+        }
+
+        self.class.methods[method_idx].attributes[code_idx] = lowered;
+
+        Ok(())
+    }
+}
+
+fn is_label(item: &CodeItem) -> bool {
+    match item {
+        &CodeItem::Label(_) => true,
+        _ => false
+    }
+}
+
+fn is_return(item: &CodeItem) -> bool {
+    match item {
+        &CodeItem::Instruction(Instruction::IRETURN) | &CodeItem::Instruction(Instruction::LRETURN) | &CodeItem::Instruction(Instruction::FRETURN) |
+        &CodeItem::Instruction(Instruction::DRETURN) | &CodeItem::Instruction(Instruction::ARETURN) | &CodeItem::Instruction(Instruction::RETURN) => true,
+        _ => false
     }
 }
@@ -0,0 +1,84 @@
+use super::super::bytecode::classfile::Classfile;
+use super::super::bytecode::frames::*;
+use super::super::class::ClassId;
+use super::super::environment::Environment;
+use super::super::environment::jni::JNI;
+use super::super::environment::jvmti::JVMTI;
+use super::super::native::JavaObject;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+///
+/// A `ClassHierarchy` that looks classes up in the running JVM, one at a time as merges need them.
+/// Classes are resolved among those the defining class loader of the transformed class has loaded
+/// or asked its parents for. Nothing is loaded on its behalf, so classes that aren't loaded yet
+/// can't be merged, and neither can any class when the transformed class is defined by the
+/// bootstrap class loader. The class being transformed is described by its class file, as looking
+/// it up while it's being loaded would fail.
+pub struct RuntimeHierarchy<'a> {
+    env: &'a Environment,
+    loader: Option<JavaObject>,
+    classes: RefCell<HashMap<String, Option<ClassInfo>>>
+}
+
+impl<'a> RuntimeHierarchy<'a> {
+    pub fn new(env: &'a Environment, loader: Option<JavaObject>, class: &Classfile) -> RuntimeHierarchy<'a> {
+        let mut classes: HashMap<String, Option<ClassInfo>> = HashMap::new();
+
+        if let Some(name) = class_name(&class.constant_pool, class.this_class.idx) {
+            classes.insert(name, Some(ClassInfo::of(class)));
+        }
+
+        RuntimeHierarchy { env: env, loader: loader, classes: RefCell::new(classes) }
+    }
+
+    fn class_info(&self, name: &str) -> Option<ClassInfo> {
+        if let Some(info) = self.classes.borrow().get(name) {
+            return info.clone();
+        }
+
+        match self.loader.as_ref().and_then(|loader| self.env.find_loaded_class(loader, &name.replace('/', "."))) {
+            Some(class_id) => {
+                let info = self.describe(name, &class_id);
+
+                self.env.delete_local_ref(class_id.native_id);
+                info
+            },
+            None => {
+                self.classes.borrow_mut().insert(name.to_string(), None);
+                None
+            }
+        }
+    }
+
+    /// Remember the superclass and kind of a class, along with those of its superclasses
+    fn describe(&self, name: &str, class_id: &ClassId) -> Option<ClassInfo> {
+        let super_name = self.env.get_superclass(class_id).map(|super_id| {
+            let super_name = self.env.get_class_signature(&super_id).ok().map(|signature| signature.name.replace('.', "/"));
+
+            if let Some(ref super_name) = super_name {
+                if !self.classes.borrow().contains_key(super_name) {
+                    self.describe(super_name, &super_id);
+                }
+            }
+
+            self.env.delete_local_ref(super_id.native_id);
+            super_name
+        });
+
+        let info = match (super_name, self.env.is_interface(class_id)) {
+            (Some(None), _) | (_, Err(_)) => None,
+            (super_name, Ok(is_interface)) => Some(ClassInfo { super_name: super_name.and_then(|name| name), is_interface: is_interface })
+        };
+
+        self.classes.borrow_mut().insert(name.to_string(), info.clone());
+
+        info
+    }
+}
+
+impl<'a> ClassHierarchy for RuntimeHierarchy<'a> {
+    fn common_superclass(&self, a: &str, b: &str) -> Option<String> {
+        common_superclass_by(a, b, |name| self.class_info(name))
+    }
+}
@@ -1,6 +1,8 @@
 use super::bytecode::classfile::*;
 
 pub mod asm;
+pub mod hierarchy;
+pub mod probe;
 
 pub enum JavaType {
     Boolean,
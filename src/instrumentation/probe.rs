@@ -0,0 +1,276 @@
+use super::super::bytecode::classfile::*;
+use super::super::bytecode::frames::{ class_name, ClassHierarchy };
+use super::super::bytecode::io::ClassWriter;
+use super::super::config::Config;
+use super::super::context::static_context;
+use super::super::environment::Environment;
+use super::super::environment::jni::JNI;
+use super::super::native::{JavaClass, JavaInt, JavaThread, JNIEnvPtr};
+use super::super::thread::ThreadId;
+use super::asm::transformer::Transformer;
+use libc::c_void;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ptr;
+use std::rc::Rc;
+use std::sync::{Mutex, RwLock};
+
+/// The internal name of the class declaring the native probe methods
+pub const PROBE_CLASS: &'static str = "jvmti/Probe";
+
+const ENTER_METHOD: &'static str = "enter";
+const EXIT_METHOD: &'static str = "exit";
+/// Probes take the identifier of the probed method, as returned by `register_method`
+const PROBE_DESCRIPTOR: &'static str = "(I)V";
+
+lazy_static! {
+    static ref PROBED_METHODS: RwLock<ProbedMethods> = RwLock::new(ProbedMethods { names: vec![], ids: HashMap::new() });
+    static ref PROBE_CLASS_DEFINED: Mutex<bool> = Mutex::new(false);
+}
+
+thread_local! {
+    /// The filter last built on this thread, with the version of the probe patterns it was built from
+    static CACHED_FILTER: RefCell<Option<(usize, Rc<ProbeFilter>)>> = RefCell::new(None);
+}
+
+///
+/// Selects the methods that get probes. Entry points name single methods, eg.
+/// `com.example.Foo.bar`, active classes select every method of a class, eg. `com.example.Foo`.
+/// Internal names are accepted as well, and a trailing `*` matches any suffix, eg. `com.example.*`
+/// or `com.example.Foo.get*`.
+pub struct ProbeFilter {
+    entry_points: Vec<(String, String)>,
+    active_classes: Vec<String>
+}
+
+impl ProbeFilter {
+    pub fn new(entry_points: &Vec<String>, active_classes: &Vec<String>) -> ProbeFilter {
+        ProbeFilter {
+            entry_points: entry_points.iter().filter_map(|entry_point| {
+                let entry_point = normalise(entry_point);
+
+                entry_point.rfind('.').map(|split| (entry_point[..split].to_string(), entry_point[split + 1..].to_string()))
+            }).collect(),
+            active_classes: active_classes.iter().map(|class| normalise(class)).collect()
+        }
+    }
+
+    pub fn from_config(config: &Config) -> ProbeFilter {
+        ProbeFilter::new(&config.entry_points, &config.active_classes)
+    }
+
+    /// Whether any method of the class with the given name may get probes
+    pub fn matches_class(&self, class_name: &str) -> bool {
+        let class_name = normalise(class_name);
+
+        class_name != normalise(PROBE_CLASS) &&
+            (self.active_classes.iter().any(|pattern| matches(pattern, &class_name)) ||
+             self.entry_points.iter().any(|&(ref pattern, _)| matches(pattern, &class_name)))
+    }
+
+    pub fn matches_method(&self, class_name: &str, method_name: &str) -> bool {
+        let class_name = normalise(class_name);
+
+        class_name != normalise(PROBE_CLASS) &&
+            (self.active_classes.iter().any(|pattern| matches(pattern, &class_name)) ||
+             self.entry_points.iter().any(|&(ref class_pattern, ref method_pattern)| matches(class_pattern, &class_name) && matches(method_pattern, method_name)))
+    }
+}
+
+/// The filter of the configured probe patterns. It's checked on every class load, so it's only
+/// rebuilt, and the configuration only locked, once the patterns change.
+pub fn probe_filter() -> Rc<ProbeFilter> {
+    let version = static_context().probe_patterns_version();
+
+    CACHED_FILTER.with(|cached| {
+        if let Some((cached_version, ref filter)) = *cached.borrow() {
+            if cached_version == version {
+                return filter.clone();
+            }
+        }
+
+        let filter = Rc::new(match static_context().config.read() {
+            Ok(cfg) => ProbeFilter::from_config(&*cfg),
+            Err(_) => ProbeFilter::new(&vec![], &vec![])
+        });
+
+        *cached.borrow_mut() = Some((version, filter.clone()));
+        filter
+    })
+}
+
+/// Convert internal names to binary names, dropping the leading dot `ClassSignature` puts before
+/// classes of the default package
+fn normalise(name: &str) -> String {
+    name.trim_start_matches('.').replace('/', ".")
+}
+
+fn matches(pattern: &str, name: &str) -> bool {
+    if pattern.ends_with('*') {
+        name.starts_with(&pattern[..pattern.len() - 1])
+    } else {
+        pattern == name
+    }
+}
+
+/// The names of the methods given probes, by identifier, and their identifiers by name
+struct ProbedMethods {
+    names: Vec<String>,
+    ids: HashMap<String, u32>
+}
+
+/// Assign an identifier to the given method name, to be passed to the probes. A method keeps its
+/// identifier when its class is loaded or retransformed again.
+pub fn register_method(name: String) -> u32 {
+    match PROBED_METHODS.write() {
+        Ok(mut methods) => {
+            if let Some(id) = methods.ids.get(&name) {
+                return *id;
+            }
+
+            let id = methods.names.len() as u32;
+            methods.names.push(name.clone());
+            methods.ids.insert(name, id);
+            id
+        },
+        Err(_) => 0
+    }
+}
+
+/// The name of the method registered with the given identifier
+pub fn probed_method(id: u32) -> Option<String> {
+    PROBED_METHODS.read().ok().and_then(|methods| methods.names.get(id as usize).cloned())
+}
+
+///
+/// Inject entry and exit probes into every method of `class` selected by `filter`. Abstract and
+/// native methods, constructors and static initialisers are left alone, and so is every method
+/// whose code can't be rewritten. Returns the number of methods that got probes.
+pub fn inject_probes(class: &mut Classfile, filter: &ProbeFilter, hierarchy: &ClassHierarchy) -> usize {
+    let class_name = match class_name(&class.constant_pool, class.this_class.idx) {
+        Some(name) => normalise(&name),
+        None => return 0
+    };
+
+    let candidates: Vec<(usize, String)> = class.methods.iter().enumerate().filter_map(|(idx, method)| {
+        let name = class.constant_pool.get_utf8_string(method.name_index.idx as u16).unwrap_or(String::new());
+        let descriptor = class.constant_pool.get_utf8_string(method.descriptor_index.idx as u16).unwrap_or(String::new());
+        let has_code = !method.access_flags.has_flag(MethodAccessFlags::Abstract as u16) && !method.access_flags.has_flag(MethodAccessFlags::Native as u16);
+
+        if has_code && !name.starts_with('<') && filter.matches_method(&class_name, &name) {
+            Some((idx, format!("{}.{}{}", class_name, name, descriptor)))
+        } else {
+            None
+        }
+    }).collect();
+
+    if candidates.is_empty() {
+        return 0;
+    }
+
+    let mut transformer = Transformer::new(class, hierarchy);
+    let enter_index = transformer.ensure_method_ref(PROBE_CLASS, ENTER_METHOD, PROBE_DESCRIPTOR);
+    let exit_index = transformer.ensure_method_ref(PROBE_CLASS, EXIT_METHOD, PROBE_DESCRIPTOR);
+
+    candidates.into_iter().filter(|&(idx, ref name)| {
+        let id_index = transformer.ensure_constant(Constant::Integer(register_method(name.clone())));
+        let enter = vec![ Instruction::LDC_W(id_index.idx as u16), Instruction::INVOKESTATIC(enter_index.idx as u16) ];
+        let exit = vec![ Instruction::LDC_W(id_index.idx as u16), Instruction::INVOKESTATIC(exit_index.idx as u16) ];
+
+        match transformer.wrap_method(idx, &enter, &exit) {
+            Ok(_) => true,
+            Err(err) => {
+                warn!("Could not inject probes into {}: {}", name, err);
+                false
+            }
+        }
+    }).count()
+}
+
+///
+/// The class declaring the probes, `public final class jvmti.Probe` with the native methods
+/// `public static void enter(int)` and `public static void exit(int)`.
+pub fn probe_class() -> Classfile {
+    let mut class = Classfile::new();
+
+    {
+        let cp = &mut class.constant_pool;
+        let this_name = cp.add_constant(Constant::Utf8(PROBE_CLASS.to_string().into_bytes()));
+        let super_name = cp.add_constant(Constant::Utf8(String::from("java/lang/Object").into_bytes()));
+        let descriptor = cp.add_constant(Constant::Utf8(PROBE_DESCRIPTOR.to_string().into_bytes()));
+
+        class.this_class = cp.add_constant(Constant::Class(this_name));
+        class.super_class = cp.add_constant(Constant::Class(super_name));
+
+        for name in &[ ENTER_METHOD, EXIT_METHOD ] {
+            class.methods.push(Method {
+                access_flags: AccessFlags::of(MethodAccessFlags::Public as u16 | MethodAccessFlags::Static as u16 | MethodAccessFlags::Native as u16),
+                name_index: cp.add_constant(Constant::Utf8(name.to_string().into_bytes())),
+                descriptor_index: descriptor.clone(),
+                attributes: vec![]
+            });
+        }
+    }
+
+    class.access_flags = AccessFlags::of(ClassAccessFlags::Public as u16 | ClassAccessFlags::Final as u16 | ClassAccessFlags::Super as u16);
+    class
+}
+
+///
+/// Define the probe class in the bootstrap class loader, which makes it visible to every class,
+/// and bind its native methods. The class is only defined once, later calls just report whether
+/// it was. Probes must not be injected until this succeeds.
+pub fn define_probe_class(env: &Environment) -> bool {
+    let mut defined = match PROBE_CLASS_DEFINED.lock() {
+        Ok(defined) => defined,
+        Err(_) => return false
+    };
+
+    if !*defined {
+        let mut bytes: Vec<u8> = vec![];
+
+        if ClassWriter::new(&mut bytes).write_class(&probe_class()).is_err() {
+            return false;
+        }
+
+        match env.define_class(PROBE_CLASS, ptr::null_mut(), &bytes) {
+            Some(class_id) => {
+                *defined = env.register_native(&class_id, ENTER_METHOD, PROBE_DESCRIPTOR, probe_enter as *mut c_void) &&
+                    env.register_native(&class_id, EXIT_METHOD, PROBE_DESCRIPTOR, probe_exit as *mut c_void);
+
+                env.delete_local_ref(class_id.native_id);
+            },
+            None => {
+                env.clear_exception();
+            }
+        }
+    }
+
+    *defined
+}
+
+/// A JNI environment belongs to a single thread for as long as the thread lives, which makes it a
+/// stable key for the method timings of the thread, unlike local references to the thread object
+fn current_thread(jni_env: JNIEnvPtr) -> ThreadId {
+    ThreadId { native_id: jni_env as JavaThread }
+}
+
+#[allow(unused_variables)]
+unsafe extern "C" fn probe_enter(jni_env: JNIEnvPtr, class: JavaClass, method: JavaInt) {
+    if static_context().is_trace_enable() {
+        static_context().method_enter(&current_thread(jni_env));
+        debug!("method_enter [{}]", probed_method(method as u32).unwrap_or(format!("#{}", method)));
+    }
+}
+
+#[allow(unused_variables)]
+unsafe extern "C" fn probe_exit(jni_env: JNIEnvPtr, class: JavaClass, method: JavaInt) {
+    if static_context().is_trace_enable() {
+        let method_name = probed_method(method as u32).unwrap_or(format!("#{}", method));
+
+        match static_context().method_exit(&current_thread(jni_env)) {
+            Some(duration) => debug!("method_exit [{}] after {}", method_name, duration),
+            None => debug!("method_no_start [{}]", method_name)
+        }
+    }
+}
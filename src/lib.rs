@@ -9,34 +9,40 @@ extern crate toml;
 extern crate serde_derive;
 #[macro_use] extern crate log;
 extern crate env_logger;
+extern crate flate2;
 extern crate serde_json;
 extern crate serde;
 //extern crate jni;
 //extern crate jvmti_sys;
 
 use agent::Agent;
-use bytecode::printer::ClassfilePrinter;
-use bytecode::classfile::Constant;
 use bytecode::io::ClassWriter;
 use config::Config;
 use context::static_context;
-use instrumentation::asm::transformer::Transformer;
+use instrumentation::hierarchy::RuntimeHierarchy;
+use instrumentation::probe::{define_probe_class, inject_probes, probe_filter};
 use native::{JavaVMPtr, MutString, VoidPtr, ReturnValue};
 use options::Options;
+use perf_map::PerfMap;
 use runtime::*;
 use std::io::{Cursor, Write};
-use thread::Thread;
+use thread::{Thread, ThreadFilter};
 use util::stringify;
 use std::time::*;
 extern crate chrono;
 use chrono::Local;
 use std::sync::{Mutex,Arc,RwLock};
+use std::sync::mpsc::{channel, Receiver, Sender};
 use time::{Duration,Tm};
 use environment::jvm::{JVMF, JVMAgent};
-use environment::jvmti::{JVMTI, JVMTIEnvironment};
+use environment::jvmti::{JVMTI, JVMTIEnvironment, JavaStackTrace};
+use profile::contention::ContentionProfiler;
 use profile::sample::*;
 use environment::Environment;
-use environment::jni::JNIEnvironment;
+use environment::jni::{JNI, JNIEnvironment};
+use error::{translate_error, NativeError};
+use monitor::{find_deadlocks, Deadlock};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::path::Path;
 
 pub mod agent;
@@ -50,16 +56,23 @@ pub mod environment;
 pub mod error;
 pub mod event;
 pub mod event_handler;
+pub mod field;
+pub mod heap;
 pub mod instrumentation;
 pub mod mem;
+pub mod monitor;
 pub mod method;
 pub mod native;
 pub mod options;
+pub mod perf_map;
+pub mod pipeline;
+pub mod profile;
 pub mod runtime;
+pub mod snapshot;
 pub mod thread;
 pub mod util;
+pub mod value;
 pub mod version;
-mod profile;
 
 /*
  * TODO The functions below are essentially parts of an actual client implementation. Because this
@@ -71,8 +84,27 @@ lazy_static! {
     //static ref TREE_ARENA: Mutex<TreeArena> = Mutex::new(TreeArena::new());
     //static ref TRACE_ENABLE: Mutex<bool> = Mutex::new(false);
     static ref SAMPLER: Mutex<Sampler> = Mutex::new(Sampler::new());
+    static ref CONTENTION: Mutex<ContentionProfiler> = Mutex::new(ContentionProfiler::new());
+    /// Classes unloaded since the sampler thread last looked. Sending doesn't take a lock, so the
+    /// GC may send while the sampler thread holds `SAMPLER` waiting for a safepoint.
+    static ref UNLOADED_CLASSES: (Sender<ClassUnloadEvent>, Mutex<Receiver<ClassUnloadEvent>>) = {
+        let (sender, receiver) = channel();
+        (sender, Mutex::new(receiver))
+    };
 }
 
+/// Whether the time blocked entering monitors is profiled, see `ContentionProfiler`
+static CONTENTION_PROFILING: AtomicBool = AtomicBool::new(false);
+
+/// Set when a thread blocks on a monitor, threads can only deadlock on monitors after that
+static MONITOR_CONTENDED: AtomicBool = AtomicBool::new(true);
+
+/// Set while the trace thread runs, until it restored the classes it instrumented
+static TRACE_RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// How long the JVM is held up on exit for the trace thread to stop
+const VM_DEATH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
 fn is_trace_enable() -> bool {
 //    *TRACE_ENABLE.lock().unwrap()
     SAMPLER.lock().unwrap().is_enable()
@@ -149,85 +181,147 @@ fn on_thread_end(thread: Thread) {
     }
 }
 
-fn on_monitor_wait(thread: Thread) {
+fn on_monitor_wait(event: MonitorWaitEvent) {
     if !is_trace_enable() {
         return;
     }
-    println!("[{}] [W1-{}]", nowTime(), thread.name);
+    println!("[{}] [W1-{}] {}@{:x} timeout: {}ms", nowTime(), event.thread.name, event.monitor.class_sig.name, event.monitor.object_hash, event.timeout);
 }
 
-fn on_monitor_waited(thread: Thread) {
+fn on_monitor_waited(event: MonitorWaitedEvent) {
     if !is_trace_enable() {
         return;
     }
-    println!("[{}] [W2-{}]", nowTime(), thread.name);
+    println!("[{}] [W2-{}] {}@{:x}{}", nowTime(), event.thread.name, event.monitor.class_sig.name, event.monitor.object_hash, if event.timed_out { " timed out" } else { "" });
 }
 
-fn on_monitor_contended_enter(thread: Thread) {
+fn on_monitor_contended_enter(event: MonitorContendedEnterEvent) {
     if !is_trace_enable() {
         return;
     }
-    println!("[{}] [C1-{}]", nowTime(), thread.name);
+    println!("[{}] [C1-{}] {}@{:x}", nowTime(), event.thread.name, event.monitor.class_sig.name, event.monitor.object_hash);
 
-    static_context().monitor_enter(&thread.id);
+    MONITOR_CONTENDED.store(true, Ordering::SeqCst);
 }
 
-fn on_monitor_contended_entered(thread: Thread) {
+fn on_monitor_contended_entered(event: MonitorContendedEnteredEvent) {
     if !is_trace_enable() {
         return;
     }
-    println!("[{}] [C2-{}]", nowTime(), thread.name);
+    println!("[{}] [C2-{}] {}@{:x}", nowTime(), event.thread.name, event.monitor.class_sig.name, event.monitor.object_hash);
+
+    match event.blocked_nanos {
+        Some(blocked_nanos) => {
+            println!("[{}] Thread {} waited {}ms", nowTime(), event.thread.name, blocked_nanos as f64 / 1000_000.0);
 
-    match static_context().monitor_entered(&thread.id) {
-        Some(duration) => println!("[{}] Thread {} waited {}", nowTime(), thread.name, duration),
-        None => println!("[{}] Thread {} has never waited", nowTime(), thread.name)
+            if CONTENTION_PROFILING.load(Ordering::SeqCst) {
+                CONTENTION.lock().unwrap().add(&event.monitor.class_sig.name, &event.frames, blocked_nanos);
+            }
+        },
+        None => println!("[{}] Thread {} has never waited", nowTime(), event.thread.name)
     }
 }
 
-fn on_class_file_load(mut event: ClassFileLoadEvent) -> Option<Vec<u8>> {
-    if !is_trace_enable() { return None; }
-    let shall_transform = match static_context().config.read() {
-        Ok(cfg) => (*cfg).entry_points.iter().any(|item| item.starts_with(event.class_name.as_str())), //event.class_name.as_str() == item),
-        _ => false
+fn on_breakpoint(event: BreakpointEvent) {
+    if !is_trace_enable() {
+        return;
+    }
+    let location = &event.location;
+
+    match location.line_number {
+        Some(line_number) => println!("[{}] [B-{}] {}.{}:{}", nowTime(), event.thread.name, location.class_sig.name, location.method_sig.name, line_number),
+        None => println!("[{}] [B-{}] {}.{}@{}", nowTime(), event.thread.name, location.class_sig.name, location.method_sig.name, location.location)
+    }
+
+    for frame in &event.frames {
+        println!("  at {}", frame);
+    }
+
+    // Report when the method of the logpoint returns, the thread being the current one. Logpoints
+    // in loops are hit again before the frame is popped
+    match event.environment.notify_frame_pop(&event.thread.id.native_id, 0) {
+        Ok(_) | Err(NativeError::Duplicate) => (),
+        Err(err) => println!("[{}] Could not follow the frame of {}: {}", nowTime(), event.thread.name, translate_error(&err))
+    }
+}
+
+fn on_frame_pop(event: FramePopEvent) {
+    if !is_trace_enable() {
+        return;
+    }
+    println!("[{}] [P-{}] {}.{}{}", nowTime(), event.thread.name, event.class_sig.name, event.method_sig.name, if event.popped_by_exception { " threw" } else { " returned" });
+}
+
+fn on_class_file_load(event: ClassFileLoadEvent) -> Option<Vec<u8>> {
+    let filter = probe_filter();
+
+    // The probe class is defined from within this hook, so it has to be filtered out first
+    if !filter.matches_class(&event.class_name) || !define_probe_class(&event.environment) {
+        return None;
+    }
+
+    let mut class = match event.parse_class() {
+        Ok(class) => class,
+        Err(err) => {
+            warn!("[{}] Could not parse class file {}: {}", nowTime(), event.class_name, err);
+            return None;
+        }
+    };
+
+    let probed = {
+        let hierarchy = RuntimeHierarchy::new(&event.environment, event.class_loader, &class);
+        inject_probes(&mut class, &filter, &hierarchy)
     };
 
-    if shall_transform {
-        {
-            let mut transformer = Transformer::new(&mut event.class);
-            let result = transformer.ensure_constant(Constant::Utf8(String::from("Cde").into_bytes()));
+    if probed == 0 {
+        return None;
+    }
 
-            println!("Result: {:?}", result);
+    debug!("[{}] Injected probes into {} methods of {}", nowTime(), probed, event.class_name);
+
+    let mut output: Vec<u8> = vec![];
+
+    match ClassWriter::new(&mut output).write_class(&class) {
+        Ok(_) => Some(output),
+        Err(err) => {
+            println!("[{}] Could not write transformed class {}: {}", nowTime(), event.class_name, err);
+            None
         }
-        let _: Vec<()> = ClassfilePrinter::render_lines(&event.class).iter().map(|line| println!("{}", line)).collect();
     }
-/*
-    let output_class: Vec<u8> = vec![];
-    let mut write_cursor = Cursor::new(output_class);
-
-    let mut new_class = event.class;
-
-    new_class.constant_pool.constants = new_class.constant_pool.constants.into_iter().map(|constant| {
-        match constant {
-            Constant::Utf8(bytes) => String::from_utf8(bytes.clone()).map(|string| match string.as_str() {
-                "Hello World" => Constant::Utf8(String::from("Lofasz").into_bytes()),
-                _ => Constant::Utf8(string.into_bytes())
-            }).unwrap_or(Constant::Utf8(bytes)),
-            other @ _ => other
+}
+
+fn on_class_unload(event: ClassUnloadEvent) {
+    debug!("[{}] Class unloaded: {}", nowTime(), event.class_sig.name);
+    let _ = UNLOADED_CLASSES.0.send(event);
+}
+
+/// Let the sampler and the contention profile forget the methods of the classes unloaded since,
+/// called on the sampler thread
+fn forget_unloaded_classes() {
+    if let Ok(receiver) = UNLOADED_CLASSES.1.lock() {
+        let mut sampler = SAMPLER.lock().unwrap();
+
+        for event in receiver.try_iter() {
+            sampler.on_class_unload(&event.class_sig);
+            CONTENTION.lock().unwrap().on_class_unload(&event.methods);
         }
-    }).collect();
+    }
+}
 
-    let result = {
-        let mut writer = ClassWriter::new(&mut write_cursor);
-        writer.write_class(&new_class)
-    };
+/// Stop tracing before the JVM exits, as the trace thread restores the instrumented classes on its
+/// way out, which it can't once the JVM is gone
+fn on_vm_death() {
+    if !TRACE_RUNNING.load(Ordering::SeqCst) {
+        return;
+    }
 
-    if let Ok(_) = result {
-        Some(write_cursor.into_inner())
-    } else {
-        None
+    set_trace_enable(false);
+
+    let started = Instant::now();
+
+    while TRACE_RUNNING.load(Ordering::SeqCst) && started.elapsed() < VM_DEATH_TIMEOUT {
+        std::thread::sleep(std::time::Duration::from_millis(10));
     }
-    */
-    None
 }
 
 fn on_garbage_collection_start() {
@@ -292,6 +386,69 @@ struct JavaVMPtrVo {
 unsafe impl Send for JavaVMPtrVo {
 }
 
+const DEFAULT_MAX_DEPTH: usize = 100;
+const LOCAL_FRAME_CAPACITY: usize = 256;
+/// Samples taken between looking for deadlocks, when threads blocked on monitors in the meantime
+const DEADLOCK_CHECK_INTERVAL: u32 = 50;
+
+/// Parse a logpoint like `com.example.Foo:120` into the class name and the line number
+fn parse_logpoint(value: &str) -> Option<(String, u32)> {
+    let mut parts = value.rsplitn(2, ':');
+
+    match (parts.next().and_then(|line| line.parse().ok()), parts.next()) {
+        (Some(line_number), Some(class_name)) if !class_name.is_empty() => Some((class_name.to_string(), line_number)),
+        _ => None
+    }
+}
+
+fn split_list(value: Option<&String>) -> Vec<String> {
+    value.map(|value| value.split('|').filter(|item| !item.is_empty()).map(|item| item.to_string()).collect()).unwrap_or(vec![])
+}
+
+/// Print the deadlocks found that weren't reported yet, see `monitor::find_deadlocks`
+fn report_deadlocks(jvmti: &Environment, reported: &mut Vec<Deadlock>) {
+    let local_frame = jvmti.push_local_frame(LOCAL_FRAME_CAPACITY);
+
+    match find_deadlocks(jvmti) {
+        Ok(deadlocks) => {
+            for deadlock in deadlocks.iter().filter(|deadlock| !reported.contains(deadlock)) {
+                println!("[{}] {}", nowTime(), deadlock);
+            }
+
+            *reported = deadlocks;
+        },
+        Err(err) => println!("[{}] Could not look for deadlocks: {}", nowTime(), translate_error(&err))
+    }
+
+    if local_frame {
+        jvmti.pop_local_frame();
+    }
+}
+
+/// Sample the stack traces of the threads selected by `filter`, at most `max_depth` frames deep
+fn sample_stack_traces(jvmti: &Environment, filter: &ThreadFilter, max_depth: usize) -> Result<Vec<JavaStackTrace>, NativeError> {
+    if filter.selects_all() {
+        return jvmti.get_all_stacktraces(max_depth);
+    }
+
+    let mut selected = vec![];
+
+    for thread_id in jvmti.get_all_threads()? {
+        let name = jvmti.get_thread_info(&thread_id.native_id).map(|thread| thread.name).unwrap_or(String::new());
+        let group = jvmti.get_thread_group_name(&thread_id.native_id).unwrap_or(String::new());
+
+        if filter.matches(&name, &group) {
+            selected.push(thread_id.native_id);
+        }
+    }
+
+    if selected.is_empty() {
+        Ok(vec![])
+    } else {
+        jvmti.get_thread_list_stack_traces(&selected, max_depth)
+    }
+}
+
 ///
 /// `Agent_OnAttach` is the actual entry point of the agent code and it is called by the
 /// Java Virtual Machine directly.
@@ -313,6 +470,47 @@ pub extern fn Agent_OnAttach(vm: JavaVMPtr, options: MutString, reserved: VoidPt
 
 
 
+    // Write the instances on the heap counted by class to the given file, see `ClassHistogram`
+    if let Some(path) = options.custom_args.get("histogram") {
+        let vm_ptr = vm as usize;
+        let path = path.clone();
+
+        std::thread::spawn(move || {
+            let mut agent = Agent::new_attach(vm_ptr as JavaVMPtr, "Flare-Histogram");
+
+            match agent.class_histogram() {
+                Ok(histogram) => {
+                    match std::fs::File::create(&path).and_then(|mut file| histogram.write(&mut file)) {
+                        Ok(_) => println!("[{}] Wrote histogram of {} classes to {}", nowTime(), histogram.entries().len(), path),
+                        Err(err) => println!("[{}] Could not write histogram to {}: {}", nowTime(), path, err)
+                    }
+                },
+                Err(err) => println!("[{}] Could not count the objects on the heap: {}", nowTime(), translate_error(&err))
+            }
+        });
+    }
+
+    // Write a graph of the objects reachable from the heap roots to the given file, to be analysed
+    // offline with `jvmti heap <file>`, see `HeapSnapshot`
+    if let Some(path) = options.custom_args.get("heap_snapshot") {
+        let vm_ptr = vm as usize;
+        let path = path.clone();
+
+        std::thread::spawn(move || {
+            let mut agent = Agent::new_attach(vm_ptr as JavaVMPtr, "Flare-HeapSnapshot");
+
+            match agent.heap_snapshot() {
+                Ok(snapshot) => {
+                    match snapshot.write(Path::new(&path)) {
+                        Ok(_) => println!("[{}] Wrote heap snapshot of {} objects to {}", nowTime(), snapshot.objects.len(), path),
+                        Err(err) => println!("[{}] Could not write heap snapshot to {}: {}", nowTime(), path, err)
+                    }
+                },
+                Err(err) => println!("[{}] Could not take a heap snapshot: {}", nowTime(), translate_error(&err))
+            }
+        });
+    }
+
     if let Some(val) = options.custom_args.get("trace") {
         match val.as_ref() {
             "on" => {
@@ -330,7 +528,34 @@ pub extern fn Agent_OnAttach(vm: JavaVMPtr, options: MutString, reserved: VoidPt
 //                jvmti.get_all_stacktraces();
 
                 let vm_ptr = vm as usize;
+                // Leave the callbacks to buffering events, processing them on a thread of their own
+                let async_events = options.custom_args.get("async_events").map(|value| value == "on").unwrap_or(false);
+                // Name the JIT compiled code for perf, see `PerfMap`
+                let perf_map = options.custom_args.get("perf_map").map(|value| value == "on").unwrap_or(false);
+                // Deepest frames sampled of each stack trace
+                let max_depth = options.custom_args.get("max_depth").and_then(|value| value.parse().ok()).unwrap_or(DEFAULT_MAX_DEPTH);
+                // Sample only the threads named by, or belonging to the thread groups of, these `|` separated lists
+                let thread_filter = ThreadFilter::new(split_list(options.custom_args.get("threads")), split_list(options.custom_args.get("thread_groups")));
+                // Tell apart the calls made from different lines of a method, naming frames `Foo.bar(Foo.java:123)`
+                let line_numbers = options.custom_args.get("line_numbers").map(|value| value == "on").unwrap_or(false);
+                // Look for threads deadlocked on monitors while sampling
+                let deadlocks = options.custom_args.get("deadlocks").map(|value| value == "on").unwrap_or(false);
+                SAMPLER.lock().unwrap().set_line_numbers(line_numbers);
+                // Profile the time blocked entering monitors by monitor class and call site
+                let contention = options.custom_args.get("contention").map(|value| value == "on").unwrap_or(false);
+                // Buffered events don't carry the frames the contention profile is built from
+                let async_events = if async_events && contention {
+                    println!("[{}] Asynchronous events leave out the frames of contended monitors, dispatching events from the callbacks for the contention profile", nowTime());
+                    false
+                } else {
+                    async_events
+                };
+                CONTENTION.lock().unwrap().set_line_numbers(line_numbers);
+                // Print the stack and local variables of the threads reaching a source line, eg. `com.example.Foo:120`, without stopping them
+                let logpoint = options.custom_args.get("logpoint").and_then(|value| parse_logpoint(value));
                 //TODO how to pass vm or agent to thread safely?
+                TRACE_RUNNING.store(true, Ordering::SeqCst);
+
                 let handle = std::thread::spawn( move||{
                     println!("Trace agent is running ...");
                     let vm = vm_ptr as JavaVMPtr;
@@ -338,18 +563,93 @@ pub extern fn Agent_OnAttach(vm: JavaVMPtr, options: MutString, reserved: VoidPt
                     let mut agent = Agent::new_attach(vm, "Flare-Profiler");
                     println!("init_agent ..");
                     init_agent(&mut agent);
+
+                    if contention {
+                        agent.capture_monitor_frames(max_depth);
+                        CONTENTION_PROFILING.store(true, Ordering::SeqCst);
+                    }
+
+                    if async_events && !agent.enable_async_events(pipeline::DEFAULT_CAPACITY) {
+                        println!("[{}] Could not start asynchronous event dispatching", nowTime());
+                    }
+
+                    if perf_map {
+                        let path = PerfMap::default_path();
+
+                        match agent.write_perf_map(&path) {
+                            Ok(_) => {
+                                agent.update();
+
+                                match agent.generate_code_events() {
+                                    Ok(_) => println!("[{}] Writing perf map to {}", nowTime(), path.display()),
+                                    Err(err) => println!("[{}] Could not write the code compiled so far to the perf map: {}", nowTime(), translate_error(&err))
+                                }
+                            },
+                            Err(err) => println!("[{}] Could not create perf map {}: {}", nowTime(), path.display(), err)
+                        }
+                    }
+
+                    // Classes loaded before attaching never went through the class file load hook
+                    let (entry_points, active_classes) = match static_context().config.read() {
+                        Ok(cfg) => (cfg.entry_points.clone(), cfg.active_classes.clone()),
+                        Err(_) => (vec![], vec![])
+                    };
+
+                    match agent.instrument(entry_points, active_classes) {
+                        Ok(count) => println!("[{}] Retransformed {} loaded classes", nowTime(), count),
+                        Err(err) => println!("[{}] Could not instrument loaded classes: {}", nowTime(), translate_error(&err))
+                    }
+
+                    let logpoints = match logpoint {
+                        Some((class_name, line_number)) => {
+                            agent.on_breakpoint(Box::new(on_breakpoint));
+                            agent.on_frame_pop(Box::new(on_frame_pop));
+                            agent.capture_breakpoint_frames(max_depth);
+                            agent.update();
+
+                            if !agent.jvm_env.get_capabilities().can_generate_breakpoint_events {
+                                println!("[{}] Could not set logpoints, the JVM only allows breakpoints to agents loaded on startup", nowTime());
+                                vec![]
+                            } else {
+                                match agent.set_line_breakpoints(&class_name, line_number) {
+                                    Ok(breakpoints) => {
+                                        println!("[{}] Set {} logpoints at {}:{}", nowTime(), breakpoints.len(), class_name, line_number);
+                                        breakpoints
+                                    },
+                                    Err(err) => {
+                                        println!("[{}] Could not set logpoints at {}:{}: {}", nowTime(), class_name, line_number, translate_error(&err));
+                                        vec![]
+                                    }
+                                }
+                            }
+                        },
+                        None => vec![]
+                    };
+
                     let jvmti = &agent.jvm_env;
 
+                    // HotSpot only grants some of the monitor capabilities to agents loaded on startup
+                    let capabilities = jvmti.get_capabilities();
+                    let check_deadlocks = deadlocks && capabilities.can_get_current_contended_monitor && capabilities.can_get_owned_monitor_stack_depth_info && capabilities.can_get_monitor_info;
+
+                    if deadlocks && !check_deadlocks {
+                        println!("[{}] Could not look for deadlocks, the JVM only allows inspecting monitors to agents loaded on startup", nowTime());
+                    }
+
                     set_trace_enable(true);
+                    let mut reported_deadlocks = vec![];
                     let mut samples=0;
                     while is_trace_enable() {
                         samples += 1;
 //                        println!("[{}] get sample: {}", nowTime(), samples);
                         let t0 = time::now();
-                        match jvmti.get_all_stacktraces() {
+                        // The thread objects and names fetched for each sample are local references of this thread
+                        let local_frame = jvmti.push_local_frame(LOCAL_FRAME_CAPACITY);
+                        match sample_stack_traces(jvmti, &thread_filter, max_depth) {
                             Ok(stack_traces) => {
                                 let t1 = time::now();
 //                                let output = SAMPLER.lock().unwrap().format_stack_traces(jvmti, &stack_traces);
+                                forget_unloaded_classes();
                                 SAMPLER.lock().unwrap().add_stack_traces(jvmti, &stack_traces);
                                 let t2 = time::now();
 
@@ -361,6 +661,13 @@ pub extern fn Agent_OnAttach(vm: JavaVMPtr, options: MutString, reserved: VoidPt
                                 println!("get all stack traces failed, error: {:?}", e);
                             }
                         }
+                        if local_frame {
+                            jvmti.pop_local_frame();
+                        }
+
+                        if check_deadlocks && samples % DEADLOCK_CHECK_INTERVAL == 0 && MONITOR_CONTENDED.swap(false, Ordering::SeqCst) {
+                            report_deadlocks(jvmti, &mut reported_deadlocks);
+                        }
 
                         if samples % 250 == 0 {
                             let t4 = time::now();
@@ -371,12 +678,46 @@ pub extern fn Agent_OnAttach(vm: JavaVMPtr, options: MutString, reserved: VoidPt
                             SAMPLER.lock().unwrap().write_all_call_trees(&mut file, true);
                             let t5 = time::now();
                             println!("[{}] print all stack traces, cost: {}ms", nowTime(), (t5-t4).num_microseconds().unwrap() as f64 / 1000.0);
+
+                            if contention {
+                                let file_path = Path::new("flare-contention.txt");
+                                // The classes of the methods named are local references of this thread
+                                let local_frame = jvmti.push_local_frame(LOCAL_FRAME_CAPACITY);
+
+                                match std::fs::File::create(file_path).and_then(|mut file| CONTENTION.lock().unwrap().write_call_trees(jvmti, &mut file, true)) {
+                                    Ok(_) => println!("[{}] Wrote monitor contention to {}", nowTime(), file_path.display()),
+                                    Err(err) => println!("[{}] Could not write monitor contention to {}: {}", nowTime(), file_path.display(), err)
+                                }
+
+                                if local_frame {
+                                    jvmti.pop_local_frame();
+                                }
+                            }
                         }
 
                         std::thread::sleep(std::time::Duration::from_millis(20));
                     }
                     set_trace_enable(false);
+                    CONTENTION_PROFILING.store(false, Ordering::SeqCst);
+
+                    if let Err(err) = agent.clear_breakpoints(&logpoints) {
+                        println!("[{}] Could not clear logpoints: {}", nowTime(), translate_error(&err));
+                    }
+
+                    if async_events {
+                        agent.disable_async_events();
+
+                        let stats = pipeline::stats();
+                        println!("[{}] Dispatched {} of {} recorded events, {} dropped", nowTime(), stats.dispatched, stats.recorded, stats.dropped);
+                    }
+
+                    match agent.remove_instrumentation() {
+                        Ok(count) => println!("[{}] Restored {} classes", nowTime(), count),
+                        Err(err) => println!("[{}] Could not remove instrumentation: {}", nowTime(), translate_error(&err))
+                    }
+
                     println!("Trace agent is stopped.");
+                    TRACE_RUNNING.store(false, Ordering::SeqCst);
                 });
             },
             _ => {
@@ -402,20 +743,26 @@ fn init_agent(agent: &mut Agent) {
     agent.capabilities.can_get_source_file_name = true;
     agent.capabilities.can_generate_all_class_hook_events = true;
     agent.capabilities.can_get_bytecodes = true;
-
-    agent.on_garbage_collection_start(Some(on_garbage_collection_start));
-    agent.on_garbage_collection_finish(Some(on_garbage_collection_finish));
-    //agent.on_vm_object_alloc(Some(on_object_alloc));
-    //agent.on_vm_object_free(Some(on_object_free));
-    //agent.on_class_file_load(Some(on_class_file_load));
-//    agent.on_method_entry(Some(on_method_entry));
-//    agent.on_method_exit(Some(on_method_exit));
-    agent.on_thread_start(Some(on_thread_start));
-    agent.on_thread_end(Some(on_thread_end));
-    agent.on_monitor_wait(Some(on_monitor_wait));
-    agent.on_monitor_waited(Some(on_monitor_waited));
-    agent.on_monitor_contended_enter(Some(on_monitor_contended_enter));
-    agent.on_monitor_contended_entered(Some(on_monitor_contended_entered));
+    agent.capabilities.can_get_owned_monitor_info = true;
+    agent.capabilities.can_get_owned_monitor_stack_depth_info = true;
+    agent.capabilities.can_get_current_contended_monitor = true;
+    agent.capabilities.can_get_monitor_info = true;
+
+    agent.on_garbage_collection_start(Box::new(on_garbage_collection_start));
+    agent.on_garbage_collection_finish(Box::new(on_garbage_collection_finish));
+    //agent.on_vm_object_alloc(Box::new(on_object_alloc));
+    //agent.on_vm_object_free(Box::new(on_object_free));
+    agent.on_class_file_load(Box::new(on_class_file_load));
+    agent.on_class_unload(Box::new(on_class_unload));
+    agent.on_vm_death(Box::new(on_vm_death));
+//    agent.on_method_entry(Box::new(on_method_entry));
+//    agent.on_method_exit(Box::new(on_method_exit));
+    agent.on_thread_start(Box::new(on_thread_start));
+    agent.on_thread_end(Box::new(on_thread_end));
+    agent.on_monitor_wait(Box::new(on_monitor_wait));
+    agent.on_monitor_waited(Box::new(on_monitor_waited));
+    agent.on_monitor_contended_enter(Box::new(on_monitor_contended_enter));
+    agent.on_monitor_contended_entered(Box::new(on_monitor_contended_entered));
     agent.update();
 }
 
@@ -425,6 +772,9 @@ fn init_agent(agent: &mut Agent) {
 /// running and the virtual machine is unloading the agent from memory before shutting down.
 /// Note: this method is also called when the JVM crashes due to an internal error.
 ///
+/// JVMTI functions can't be called anymore by then, so instrumented classes are restored on the
+/// VMDeath event instead, see `on_vm_death`. HotSpot never unloads agents while the JVM runs.
+///
 #[no_mangle]
 #[allow(non_snake_case, unused_variables)]
 pub extern fn Agent_OnUnload(vm: JavaVMPtr) {
@@ -2,10 +2,15 @@ extern crate jvmti;
 
 use std::env;
 use std::fs::File;
-//use std::io::{stdout};
+use std::io;
+use std::path::Path;
+use std::process;
 
 use jvmti::bytecode::*;
 use jvmti::bytecode::printer::*;
+use jvmti::bytecode::source::*;
+use jvmti::bytecode::verify::*;
+use jvmti::profile::heap::HeapSnapshot;
 
 fn main2() {
     let class = Classfile::new();
@@ -17,44 +22,137 @@ fn main2() {
 }
 
 // The main program is a simple interface to access the bytecode parsing and generating
-// functionality and as such, it's not intended for actual use.
+// functionality and as such, it's not intended for actual use. Every action accepts a single class
+// file, a directory tree or a jar archive, except for `heap` which analyses a heap snapshot written
+// by the agent.
 fn main() {
-    if let (Some(action), Some(class_name)) = (env::args().nth(1), env::args().nth(2)) {
-        match File::open(class_name.clone()) {
-            Ok(mut file) => {
-                match ClassReader::read_class(&mut file) {
-                    Ok(class) => {
-                        match action.as_str() {
-                            "read" => println!("{}", format!("{:#?}", class)),
-                            "print" => println!("{}", ClassfilePrinter::render_lines(&class).iter().map(|line| format!("{}\n", line)).fold(String::new(), |mut acc, x| { acc.push_str(x.as_str()); acc})),
-                            "counts" => println!("Class: {} Field count: {} Method count: {}", class_name, class.fields.len(), class.methods.len()),
-                            "methods" => show_methods(class, class_name),
-                            "write" => write_class(&class),
-                            _ => println!("Unknown action: {}", action)
+    if let (Some(action), Some(path)) = (env::args().nth(1), env::args().nth(2)) {
+        if action == "heap" {
+            if !analyse_heap(&path, env::args().nth(3)) {
+                process::exit(1);
+            }
+
+            return;
+        }
+
+        match ClassSource::open(&path) {
+            Ok(source) => {
+                let success = match action.as_str() {
+                    "verify" => verify(&source),
+                    "read" | "print" | "counts" | "methods" | "write" => source.classes().fold(true, |success, (entry, result)| {
+                        match result {
+                            Ok(class) => run_action(action.as_str(), entry, &class) && success,
+                            Err(err) => {
+                                println!("Could not parse {}: {}", entry.location, err);
+                                false
+                            }
                         }
-                    },
-                    Err(err) => assert!(false, format!("{:?}", err))
-                }
+                    }),
+                    _ => {
+                        println!("Unknown action: {}", action);
+                        false
+                    }
+                };
 
+                if !success {
+                    process::exit(1);
+                }
             },
-            Err(err) => assert!(false, format!("{:?}", err))
+            Err(err) => {
+                println!("Can't open {}: {}", path, err);
+                process::exit(1);
+            }
         }
     } else {
-        println!("Invalid arguments. Usage: jvmti [read|write] <Class file>")
+        println!("Invalid arguments. Usage: jvmti [read|print|counts|methods|write|verify] <Class file|directory|jar> | jvmti heap <Heap snapshot> [Class name]")
     }
 }
 
-fn write_class(class: &Classfile) {
-    if let Ok(mut outfile) = File::create(format!("{}.out.class", env::args().nth(2).unwrap_or(String::from("tmp.out.class")))) {
-        //let mut out = stdout();
-        let mut writer = ClassWriter::new(&mut outfile);
-        let _ = writer.write_class(class);
-    } else {
-        println!("Can't open output file");
+fn analyse_heap(path: &str, class_name: Option<String>) -> bool {
+    match HeapSnapshot::read(Path::new(path)) {
+        Ok(snapshot) => {
+            let stdout = io::stdout();
+
+            match snapshot.write_report(&mut stdout.lock(), 20, class_name.as_ref().map(|name| name.as_str())) {
+                Ok(_) => true,
+                Err(err) => {
+                    println!("Could not write the report: {}", err);
+                    false
+                }
+            }
+        },
+        Err(err) => {
+            println!("Can't read heap snapshot {}: {}", path, err);
+            false
+        }
+    }
+}
+
+fn run_action(action: &str, entry: &ClassEntry, class: &Classfile) -> bool {
+    match action {
+        "read" => println!("{}", format!("{:#?}", class)),
+        "print" => println!("{}", ClassfilePrinter::render_lines(class).iter().map(|line| format!("{}\n", line)).fold(String::new(), |mut acc, x| { acc.push_str(x.as_str()); acc})),
+        "counts" => println!("Class: {} Field count: {} Method count: {}", entry.location, class.fields.len(), class.methods.len()),
+        "methods" => show_methods(class, &entry.location),
+        "write" => return write_class(entry, class),
+        _ => ()
+    }
+
+    true
+}
+
+fn write_class(entry: &ClassEntry, class: &Classfile) -> bool {
+    let out_name = match entry.file_path() {
+        Some(path) => format!("{}.out.class", path.to_string_lossy()),
+        None => {
+            println!("Can't write {}: only classes read from files can be written", entry.location);
+            return false;
+        }
+    };
+
+    match File::create(&out_name) {
+        Ok(mut outfile) => {
+            //let mut out = stdout();
+            let mut writer = ClassWriter::new(&mut outfile);
+
+            match writer.write_class(class) {
+                Ok(_) => true,
+                Err(err) => {
+                    println!("Failed to write {}: {}", out_name, err);
+                    false
+                }
+            }
+        },
+        Err(err) => {
+            println!("Can't open output file {}: {}", out_name, err);
+            false
+        }
     }
 }
 
-fn show_methods(class: Classfile, class_name: String ) {
+/// Round-trip every class of the source. Returns true if all classes were written back byte for
+/// byte.
+fn verify(source: &ClassSource) -> bool {
+    let failed = source.entries().iter().fold(0, |failed, entry| {
+        match source.read_bytes(entry).map(|bytes| verify_round_trip(&bytes)) {
+            Ok(Ok(_)) => failed,
+            Ok(Err(err)) => {
+                println!("FAIL {}: {}", entry.location, err);
+                failed + 1
+            },
+            Err(err) => {
+                println!("FAIL {}: {}", entry.location, err);
+                failed + 1
+            }
+        }
+    });
+
+    println!("Classes checked: {} Failed: {}", source.entries().len(), failed);
+
+    failed == 0
+}
+
+fn show_methods(class: &Classfile, class_name: &str) {
     class.methods.iter().map(|method| {
         method.attributes.iter().map(|a| {
             match a {
@@ -63,8 +161,9 @@ fn show_methods(class: Classfile, class_name: String ) {
                         match b {
                             &jvmti::bytecode::Attribute::LineNumberTable(ref table) => {
                                 if table.len() > 1 {
-                                    let first = table[0].line_number;
-                                    let last = table[table.len() - 1].line_number;
+                                    // Entries aren't ordered by line, eg. loop conditions come after the body
+                                    let first = table.iter().map(|entry| entry.line_number).min().unwrap_or(0);
+                                    let last = table.iter().map(|entry| entry.line_number).max().unwrap_or(0);
 
                                     let method_name = class.constant_pool.get_utf8_string(method.name_index.idx as u16).unwrap_or(String::from("Unknown"));
 
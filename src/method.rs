@@ -1,4 +1,4 @@
-use super::native::JavaMethod;
+use super::native::{JavaMethod, JavaLong};
 
 #[derive(Hash, Eq, PartialEq, Clone, Copy, Debug)]
 pub struct MethodId {
@@ -11,6 +11,7 @@ pub struct Method {
     pub id: MethodId
 }
 
+#[derive(Clone, Debug)]
 pub struct MethodSignature {
     pub name: String,
     pub signature: String,
@@ -27,3 +28,50 @@ impl MethodSignature {
         MethodSignature { name: "<UNKNOWN METHOD>".to_string(), signature: "<UNKNOWN>".to_string(), generic: "<UNKNOWN>".to_string() }
     }
 }
+
+///
+/// An entry of the line number table of a method, marking the bytecode location where the code of
+/// a source line starts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineNumberEntry {
+    pub start_location: JavaLong,
+    pub line_number: u32
+}
+
+///
+/// An entry of the local variable table of a method, describing a variable by the range of bytecode
+/// locations it's live in and the slot of the frame it's stored in
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalVariableEntry {
+    pub start_location: JavaLong,
+    pub length: u32,
+    pub name: String,
+    pub signature: String,
+    pub generic: Option<String>,
+    pub slot: u32
+}
+
+impl LocalVariableEntry {
+
+    /// Whether the variable is live at the given bytecode location
+    pub fn is_live_at(&self, location: JavaLong) -> bool {
+        location >= self.start_location && location < self.start_location + self.length as JavaLong
+    }
+}
+
+/// Find the source line of the given bytecode location in the line number table of its method
+pub fn line_number_at(table: &Vec<LineNumberEntry>, location: JavaLong) -> Option<u32> {
+    table.iter()
+        .filter(|entry| entry.start_location <= location)
+        .max_by_key(|entry| entry.start_location)
+        .map(|entry| entry.line_number)
+}
+
+/// Find the first bytecode location of a source line in the line number table of a method, where a
+/// breakpoint on the line would be set. A line may have several entries, eg. the condition of a loop.
+pub fn line_start_location(table: &Vec<LineNumberEntry>, line_number: u32) -> Option<JavaLong> {
+    table.iter()
+        .filter(|entry| entry.line_number == line_number)
+        .map(|entry| entry.start_location)
+        .min()
+}
@@ -0,0 +1,210 @@
+use super::environment::Environment;
+use super::environment::jni::JNI;
+use super::environment::jvmti::{JVMTI, JavaStackFrame};
+use super::error::NativeError;
+use super::method::{line_number_at, MethodId};
+use super::native::{JavaObject, JavaThread};
+use super::thread::ThreadState;
+use std::fmt::{Display, Formatter, Error};
+
+/// Deepest frame looked up for where the monitors of a deadlocked thread were locked
+const MAX_LOCK_DEPTH: usize = 64;
+
+///
+/// A monitor owned by a thread and the depth of the frame that locked it, `None` if it was locked
+/// with JNI `MonitorEnter`.
+///
+#[derive(Clone, Copy, Debug)]
+pub struct OwnedMonitor {
+    pub monitor: JavaObject,
+    pub stack_depth: Option<usize>
+}
+
+///
+/// The usage of the monitor of an object: the thread owning it and how many times it entered it,
+/// the threads waiting to enter it and those waiting to be notified in `Object.wait()`.
+///
+#[derive(Clone, Debug)]
+pub struct MonitorUsage {
+    pub owner: Option<JavaThread>,
+    pub entry_count: u32,
+    pub waiters: Vec<JavaThread>,
+    pub notify_waiters: Vec<JavaThread>
+}
+
+///
+/// A monitor, known by the class of its object, along with the frame it was locked or is being
+/// entered at, if known
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct LockedMonitor {
+    pub class_name: String,
+    pub frame: Option<String>
+}
+
+///
+/// A thread of a deadlock, the monitor it's blocked on and the monitors it holds
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeadlockedThread {
+    pub name: String,
+    pub blocked_on: LockedMonitor,
+    pub holds: Vec<LockedMonitor>
+}
+
+///
+/// Threads blocked on each other's monitors: each one is blocked on a monitor held by the next one,
+/// the last one on a monitor held by the first one.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Deadlock {
+    pub threads: Vec<DeadlockedThread>
+}
+
+impl Display for Deadlock {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "Deadlock between {} threads:", self.threads.len())?;
+
+        for (index, thread) in self.threads.iter().enumerate() {
+            let owner = &self.threads[(index + 1) % self.threads.len()];
+
+            write!(f, "\n  \"{}\" is blocked on {} held by \"{}\"", thread.name, thread.blocked_on.class_name, owner.name)?;
+
+            if let Some(ref frame) = thread.blocked_on.frame {
+                write!(f, "\n      at {}", frame)?;
+            }
+
+            for monitor in &thread.holds {
+                match monitor.frame {
+                    Some(ref frame) => write!(f, "\n      locked {} at {}", monitor.class_name, frame)?,
+                    None => write!(f, "\n      locked {}", monitor.class_name)?
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Find the cycles of a wait-for graph where each thread waits for at most one other thread, given
+/// as the index of the thread each thread waits for. Cycles start with their lowest index.
+pub fn wait_for_cycles(waits_for: &[Option<usize>]) -> Vec<Vec<usize>> {
+    // 0 when not visited yet, the number of the walk that visited a thread otherwise
+    let mut visited = vec![0; waits_for.len()];
+    let mut cycles = vec![];
+
+    for start in 0..waits_for.len() {
+        let walk = start + 1;
+        let mut current = Some(start);
+
+        while let Some(thread) = current {
+            if visited[thread] == walk {
+                let mut cycle = vec![thread];
+                let mut next = waits_for[thread].unwrap();
+
+                while next != thread {
+                    cycle.push(next);
+                    next = waits_for[next].unwrap();
+                }
+
+                let lowest = (0..cycle.len()).min_by_key(|index| cycle[*index]).unwrap();
+                cycle.rotate_left(lowest);
+                cycles.push(cycle);
+                break;
+            } else if visited[thread] != 0 {
+                break;
+            }
+
+            visited[thread] = walk;
+            current = waits_for[thread];
+        }
+    }
+
+    cycles
+}
+
+/// Look for threads deadlocked on monitors, building the wait-for graph of all the threads blocked
+/// on entering a monitor. As the threads keep running while the graph is built, the cycles found
+/// are read again and only reported if every thread is still blocked on the same monitor, held by
+/// the same thread. Requires the can_get_current_contended_monitor, can_get_monitor_info and
+/// can_get_owned_monitor_stack_depth_info capabilities. Leaves local references in the current
+/// local frame.
+pub fn find_deadlocks(env: &Environment) -> Result<Vec<Deadlock>, NativeError> {
+    let threads: Vec<JavaThread> = env.get_all_threads()?.iter().map(|thread| thread.native_id).collect();
+    let mut waits_for = vec![None; threads.len()];
+    let mut contended = vec![None; threads.len()];
+
+    for (index, thread) in threads.iter().enumerate() {
+        if let Some((monitor, owner)) = blocked_on(env, thread, &threads) {
+            waits_for[index] = owner;
+            contended[index] = Some(monitor);
+        }
+    }
+
+    let still_blocked = |cycle: &Vec<usize>| cycle.iter().enumerate().all(|(position, index)| {
+        match (blocked_on(env, &threads[*index], &threads), contended[*index]) {
+            (Some((monitor, owner)), Some(ref first_monitor)) => owner == Some(cycle[(position + 1) % cycle.len()]) && env.is_same_object(&monitor, first_monitor),
+            _ => false
+        }
+    });
+
+    Ok(wait_for_cycles(&waits_for).iter().filter(|cycle| still_blocked(cycle)).map(|cycle| {
+        Deadlock { threads: cycle.iter().map(|index| describe_thread(env, &threads[*index], contended[*index])).collect() }
+    }).collect())
+}
+
+/// The monitor a thread is blocked on entering, and the index of the thread owning it among `threads`
+fn blocked_on(env: &Environment, thread: &JavaThread, threads: &[JavaThread]) -> Option<(JavaObject, Option<usize>)> {
+    match env.get_thread_state(thread) {
+        Ok(state) if state.contains(ThreadState::BLOCKED_ON_MONITOR_ENTER) => (),
+        _ => return None
+    }
+
+    match env.get_current_contended_monitor(thread) {
+        Ok(Some(monitor)) => match env.get_object_monitor_usage(&monitor) {
+            Ok(MonitorUsage { owner: Some(owner), .. }) => Some((monitor, threads.iter().position(|other| env.is_same_object(other, &owner)))),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+fn describe_thread(env: &Environment, thread: &JavaThread, contended: Option<JavaObject>) -> DeadlockedThread {
+    let frames = env.get_stack_trace(thread, 0, MAX_LOCK_DEPTH).unwrap_or(vec![]);
+    let frame_at = |depth: Option<usize>| depth.and_then(|depth| frames.get(depth)).map(|frame| frame_name(env, frame));
+
+    DeadlockedThread {
+        name: env.get_thread_info(thread).map(|info| info.name).unwrap_or(String::from("<unknown>")),
+        blocked_on: LockedMonitor { class_name: contended.map(|monitor| class_name(env, &monitor)).unwrap_or(String::from("<unknown>")), frame: frame_at(Some(0)) },
+        holds: env.get_owned_monitor_stack_depth_info(thread).unwrap_or(vec![]).iter()
+            .map(|owned| LockedMonitor { class_name: class_name(env, &owned.monitor), frame: frame_at(owned.stack_depth) })
+            .collect()
+    }
+}
+
+fn class_name(env: &Environment, object: &JavaObject) -> String {
+    let class_id = env.get_object_class(object);
+    let class_name = env.get_class_signature(&class_id).map(|class_sig| class_sig.name).unwrap_or(String::from("<unknown>"));
+
+    env.delete_local_ref(class_id.native_id);
+    class_name
+}
+
+/// Name a frame like `com.example.Foo.bar(Foo.java:123)`, or `com.example.Foo.bar()` without line numbers
+fn frame_name(env: &Environment, frame: &JavaStackFrame) -> String {
+    let method_id = MethodId { native_id: frame.method };
+    let method_name = env.get_method_name(&method_id).map(|method_sig| method_sig.name).unwrap_or(String::from("<unknown>"));
+
+    match env.get_method_declaring_class(&method_id) {
+        Ok(class_id) => {
+            let class_name = env.get_class_signature(&class_id).map(|class_sig| class_sig.name).unwrap_or(String::from("<unknown>"));
+            let line_number = env.get_line_number_table(&method_id).ok().and_then(|table| line_number_at(&table, frame.location));
+
+            match line_number {
+                Some(line_number) => format!("{}.{}({}:{})", class_name, method_name, env.get_source_file_name(&class_id).unwrap_or(String::from("Unknown Source")), line_number),
+                None => format!("{}.{}()", class_name, method_name)
+            }
+        },
+        Err(_) => format!("{}()", method_name)
+    }
+}
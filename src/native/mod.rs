@@ -28,6 +28,7 @@ pub type JavaObject = *mut JavaInstance;
 pub type JavaThread = jvmti_native::jthread;
 pub type JavaClass = jvmti_native::jclass;
 pub type JavaMethod = jvmti_native::jmethodID;
+pub type JavaField = jvmti_native::jfieldID;
 pub type JavaLong = jvmti_native::jlong;
 pub type JavaInt = jvmti_native::jint;
 pub type TagId = jvmti_native::jlong;
@@ -1867,3 +1868,51 @@ pub mod jvmti_native {
         //pub fn Agent_OnUnload(vm: *mut JavaVM) -> ();
     }
 }
+
+///
+/// The compile info records passed to the CompiledMethodLoad event, from jvmticmlr.h
+///
+#[allow(dead_code)]
+#[allow(bad_style)]
+pub mod jvmticmlr {
+
+    use libc::{c_char, c_void};
+    use super::jvmti_native::{jint, jmethodID};
+
+    pub type jvmtiCMLRKind = u32;
+    pub const JVMTI_CMLR_DUMMY: jvmtiCMLRKind = 1;
+    pub const JVMTI_CMLR_INLINE_INFO: jvmtiCMLRKind = 2;
+
+    #[repr(C)]
+    #[derive(Copy, Clone)]
+    pub struct jvmtiCompiledMethodLoadRecordHeader {
+        pub kind: jvmtiCMLRKind,
+        pub majorinfoversion: jint,
+        pub minorinfoversion: jint,
+        pub next: *mut jvmtiCompiledMethodLoadRecordHeader,
+    }
+
+    #[repr(C)]
+    #[derive(Copy, Clone)]
+    pub struct PCStackInfo {
+        pub pc: *mut c_void,
+        pub numstackframes: jint,
+        pub methods: *mut jmethodID,
+        pub bcis: *mut jint,
+    }
+
+    #[repr(C)]
+    #[derive(Copy, Clone)]
+    pub struct jvmtiCompiledMethodLoadInlineRecord {
+        pub header: jvmtiCompiledMethodLoadRecordHeader,
+        pub numpcs: jint,
+        pub pcinfo: *mut PCStackInfo,
+    }
+
+    #[repr(C)]
+    #[derive(Copy, Clone)]
+    pub struct jvmtiCompiledMethodLoadDummyRecord {
+        pub header: jvmtiCompiledMethodLoadRecordHeader,
+        pub message: [c_char; 50],
+    }
+}
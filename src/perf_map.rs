@@ -0,0 +1,48 @@
+use super::class::ClassSignature;
+use super::method::MethodSignature;
+use super::runtime::{CompiledMethodLoadEvent, DynamicCodeGeneratedEvent};
+use std::fs::File;
+use std::io::{self, LineWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+///
+/// Writes the symbols of the code generated by the JVM to a perf map, the file Linux `perf` reads
+/// to name the code of processes generating code at run time. Each line maps an address range to a
+/// symbol, later lines taking precedence over earlier ones covering the same addresses, so code
+/// that's unloaded doesn't need to be removed.
+///
+pub struct PerfMap {
+    writer: LineWriter<File>
+}
+
+impl PerfMap {
+
+    /// Create a new perf map at `path`, replacing the existing one
+    pub fn create(path: &Path) -> io::Result<PerfMap> {
+        Ok(PerfMap { writer: LineWriter::new(File::create(path)?) })
+    }
+
+    /// The path perf looks up the map of the current process at
+    pub fn default_path() -> PathBuf {
+        PathBuf::from(format!("/tmp/perf-{}.map", process::id()))
+    }
+
+    /// Name the `size` bytes of code starting at `address`
+    pub fn write_symbol(&mut self, address: usize, size: usize, name: &str) -> io::Result<()> {
+        writeln!(self.writer, "{:x} {:x} {}", address, size, name)
+    }
+
+    pub fn write_compiled_method(&mut self, event: &CompiledMethodLoadEvent) -> io::Result<()> {
+        self.write_symbol(event.code_address, event.code_size, &method_symbol(&event.class_sig, &event.method_sig))
+    }
+
+    pub fn write_dynamic_code(&mut self, event: &DynamicCodeGeneratedEvent) -> io::Result<()> {
+        self.write_symbol(event.address, event.length, &event.name)
+    }
+}
+
+/// The symbol compiled code of a method is named by, eg. `java.lang.String.hashCode()I`
+pub fn method_symbol(class_sig: &ClassSignature, method_sig: &MethodSignature) -> String {
+    format!("{}.{}{}", class_sig.name, method_sig.name, method_sig.signature)
+}
@@ -0,0 +1,511 @@
+use super::class::{ClassId, ClassSignature};
+use super::environment::Environment;
+use super::environment::jni::JNI;
+use super::environment::jvm::{JVMF, JVMAgent};
+use super::environment::jvmti::JVMTI;
+use super::event_handler::{dispatch, subscribed};
+use super::method::{MethodId, MethodSignature};
+use super::native::{JavaClass, JavaInt, JavaLong, JavaObject, JavaThread, JavaVMPtr};
+use super::runtime::{MethodInvocationEvent, MonitorContendedEnterEvent, MonitorContendedEnteredEvent, MonitorObject, MonitorWaitEvent, MonitorWaitedEvent};
+use super::thread::Thread;
+use std::cell::{Cell, RefCell, UnsafeCell};
+use std::collections::HashMap;
+use std::mem::MaybeUninit;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+/// The number of events buffered per thread unless configured otherwise
+pub const DEFAULT_CAPACITY: usize = 4096;
+
+/// How long the drain thread sleeps when it found every buffer empty
+const IDLE_WAIT: Duration = Duration::from_millis(1);
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+/// Set while the pipeline stops, once events are no longer buffered
+static STOPPING: AtomicBool = AtomicBool::new(false);
+static CAPACITY: AtomicUsize = AtomicUsize::new(DEFAULT_CAPACITY);
+static RECORDED: AtomicUsize = AtomicUsize::new(0);
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+static DISPATCHED: AtomicUsize = AtomicUsize::new(0);
+/// The number of threads in the middle of recording an event, which the drain thread waits for once
+/// the pipeline is stopped
+static RECORDING: AtomicUsize = AtomicUsize::new(0);
+
+lazy_static! {
+    static ref BUFFERS: Mutex<Vec<Arc<ThreadBuffer>>> = Mutex::new(vec![]);
+    static ref DRAIN_THREAD: Mutex<Option<thread::JoinHandle<()>>> = Mutex::new(None);
+}
+
+thread_local! {
+    static THREAD_BUFFER: RefCell<Option<Arc<ThreadBuffer>>> = RefCell::new(None);
+    /// Set on the drain thread, whose own events would otherwise be dispatched by itself, with the
+    /// handlers it calls into Java generating more of them
+    static DRAINING: Cell<bool> = Cell::new(false);
+}
+
+///
+/// A bounded single producer, single consumer queue, without locks. Nothing stops a second thread
+/// from pushing or popping through a shared reference, so `push` and `pop` are unsafe.
+pub struct RingBuffer<T: Copy> {
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    /// The number of values popped so far
+    head: AtomicUsize,
+    /// The number of values pushed so far
+    tail: AtomicUsize
+}
+
+unsafe impl<T: Copy + Send> Send for RingBuffer<T> {}
+unsafe impl<T: Copy + Send> Sync for RingBuffer<T> {}
+
+impl<T: Copy> RingBuffer<T> {
+
+    pub fn new(capacity: usize) -> RingBuffer<T> {
+        RingBuffer {
+            slots: (0..capacity.max(1)).map(|_| UnsafeCell::new(MaybeUninit::uninit())).collect::<Vec<_>>().into_boxed_slice(),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0)
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tail.load(Ordering::Acquire).wrapping_sub(self.head.load(Ordering::Acquire))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Append a value, returning `false` without blocking if the buffer is full
+    ///
+    /// # Safety
+    ///
+    /// Only one thread at a time may push values to a buffer, any number of them racing to fill
+    /// the same slot otherwise.
+    pub unsafe fn push(&self, value: T) -> bool {
+        let tail = self.tail.load(Ordering::Relaxed);
+
+        if tail.wrapping_sub(self.head.load(Ordering::Acquire)) >= self.slots.len() {
+            return false;
+        }
+
+        *self.slots[tail % self.slots.len()].get() = MaybeUninit::new(value);
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        true
+    }
+
+    /// Remove the oldest value, if there's any
+    ///
+    /// # Safety
+    ///
+    /// Only one thread at a time may pop values from a buffer, any number of them taking the same
+    /// value otherwise.
+    pub unsafe fn pop(&self) -> Option<T> {
+        let head = self.head.load(Ordering::Relaxed);
+
+        if head == self.tail.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let value = (*self.slots[head % self.slots.len()].get()).assume_init();
+
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        Some(value)
+    }
+}
+
+///
+/// The compact form events are buffered in. Nothing in a record needs resolving while the
+/// application thread is held up by the callback, names are looked up by the drain thread.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EventRecord {
+    MethodEntry(MethodId),
+    MethodExit(MethodId),
+    ThreadStart,
+    ThreadEnd,
+    MonitorWait(MonitorRecord, JavaLong),
+    MonitorWaited(MonitorRecord, bool),
+    MonitorContendedEnter(MonitorRecord),
+    MonitorContendedEntered(MonitorRecord, Option<JavaLong>),
+    GarbageCollectionStart,
+    GarbageCollectionFinish
+}
+
+impl EventRecord {
+
+    /// The monitor of a monitor event
+    pub fn monitor(&self) -> Option<MonitorRecord> {
+        match *self {
+            EventRecord::MonitorWait(monitor, _) | EventRecord::MonitorWaited(monitor, _) |
+            EventRecord::MonitorContendedEnter(monitor) | EventRecord::MonitorContendedEntered(monitor, _) => Some(monitor),
+            _ => None
+        }
+    }
+}
+
+///
+/// The monitor of a recorded event: a weak global reference to the class of the object, which the
+/// drain thread resolves and deletes, and the identity hash code of the object
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MonitorRecord {
+    class: JavaClass,
+    object_hash: JavaInt
+}
+
+/// Marker trait implementation for `Send`, weak global references are valid on any thread
+unsafe impl Send for MonitorRecord { }
+
+impl MonitorRecord {
+
+    pub fn new(env: &Environment, object: &JavaObject) -> Option<MonitorRecord> {
+        let object_hash = match env.get_object_hash_code(object) {
+            Ok(object_hash) => object_hash,
+            Err(_) => return None
+        };
+        let class_id = env.get_object_class(object);
+        let class = env.new_weak_global_ref(&class_id.native_id);
+
+        env.delete_local_ref(class_id.native_id);
+        Some(MonitorRecord { class: class, object_hash: object_hash })
+    }
+
+    /// Look the class of the monitor up, releasing the reference to it. Returns `None` if the class
+    /// was unloaded in the meantime.
+    pub fn resolve(&self, env: &Environment) -> Option<MonitorObject> {
+        let class_sig = env.get_class_signature(&ClassId { native_id: self.class }).ok();
+
+        self.release(env);
+        class_sig.map(|class_sig| MonitorObject { class_sig: class_sig, object_hash: self.object_hash })
+    }
+
+    /// Release the reference to the class of the monitor without looking it up
+    pub fn release(&self, env: &Environment) {
+        env.delete_weak_global_ref(self.class);
+    }
+}
+
+/// The events recorded by a single thread, along with the thread they were recorded by. The id of
+/// the thread is a global reference, deleted by the drain thread along with the buffer.
+struct ThreadBuffer {
+    thread: Option<Thread>,
+    events: RingBuffer<EventRecord>,
+    dropped: AtomicUsize,
+    finished: AtomicBool,
+    /// Set once the pipeline stopped, for the thread to start a new buffer when it's started again
+    retired: AtomicBool
+}
+
+///
+/// Counters of the events that went through the pipeline since it was first enabled
+#[derive(Debug, Clone, PartialEq)]
+pub struct PipelineStats {
+    pub recorded: usize,
+    pub dropped: usize,
+    pub dispatched: usize,
+    /// Events dropped per thread, for the threads that are still being drained
+    pub dropped_per_thread: Vec<(String, usize)>
+}
+
+/// Whether callbacks record events for the drain thread instead of dispatching them
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+///
+/// Start buffering events in per-thread buffers of `capacity` events, and a thread attached to
+/// `vm` that drains them and dispatches them to the subscribed handlers. Handlers are then called
+/// on the drain thread, in the order each thread recorded its events, but with no ordering between
+/// threads. Events of threads that get ahead of the drain thread by more than `capacity` are
+/// dropped and counted. Returns `false` if the pipeline was already running.
+pub fn start(vm: JavaVMPtr, capacity: usize) -> bool {
+    let mut drain_thread = match DRAIN_THREAD.lock() {
+        Ok(drain_thread) => drain_thread,
+        Err(_) => return false
+    };
+
+    if drain_thread.is_some() {
+        return false;
+    }
+
+    CAPACITY.store(capacity, Ordering::SeqCst);
+    STOPPING.store(false, Ordering::SeqCst);
+    ENABLED.store(true, Ordering::SeqCst);
+
+    let vm_ptr = vm as usize;
+
+    match thread::Builder::new().name(String::from("jvmti-event-drain")).spawn(move || drain(vm_ptr as JavaVMPtr)) {
+        Ok(handle) => {
+            *drain_thread = Some(handle);
+            true
+        },
+        Err(err) => {
+            println!("Couldn't start the event drain thread: {}", err);
+            ENABLED.store(false, Ordering::SeqCst);
+            false
+        }
+    }
+}
+
+/// Go back to dispatching events from the callbacks. Events buffered so far are dispatched before
+/// the drain thread exits, which this waits for.
+pub fn stop() {
+    // Held until the drain thread exited, so it can't be started again in the meantime
+    if let Ok(mut drain_thread) = DRAIN_THREAD.lock() {
+        STOPPING.store(true, Ordering::SeqCst);
+        ENABLED.store(false, Ordering::SeqCst);
+
+        if let Some(handle) = drain_thread.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+pub fn stats() -> PipelineStats {
+    let dropped_per_thread = match BUFFERS.lock() {
+        Ok(buffers) => buffers.iter()
+            .filter(|buffer| buffer.dropped.load(Ordering::Relaxed) > 0)
+            .map(|buffer| (buffer.thread.as_ref().map(|thread| thread.name.clone()).unwrap_or(String::from("<VM>")), buffer.dropped.load(Ordering::Relaxed)))
+            .collect(),
+        Err(_) => vec![]
+    };
+
+    PipelineStats {
+        recorded: RECORDED.load(Ordering::Relaxed),
+        dropped: DROPPED.load(Ordering::Relaxed),
+        dispatched: DISPATCHED.load(Ordering::Relaxed),
+        dropped_per_thread: dropped_per_thread
+    }
+}
+
+/// Look a thread up to buffer its events with. The id of the thread is made a global reference, as
+/// the events are dispatched long after the callback returned.
+pub fn buffered_thread(env: &Environment, thread: &JavaThread) -> Option<Thread> {
+    env.get_thread_info(thread).ok().map(|mut info| {
+        info.id.native_id = env.new_global_ref(thread);
+        info
+    })
+}
+
+///
+/// Record an event of the current thread. The buffer of a thread is created along with its first
+/// event, which is the only time `thread` is called to look the thread up, eg. with
+/// `buffered_thread`. It returns `None` for threads that aren't Java threads. Returns whether the event was buffered. The events of the
+/// drain thread are left out.
+pub fn record<F>(record: EventRecord, thread: F) -> bool where F: FnOnce() -> Option<Thread> {
+    if DRAINING.with(|draining| draining.get()) {
+        return false;
+    }
+
+    // Checked again once counted, as the drain thread only waits for the threads it can see
+    RECORDING.fetch_add(1, Ordering::SeqCst);
+
+    let buffered = !STOPPING.load(Ordering::SeqCst) && THREAD_BUFFER.with(|cell| {
+        let mut current = cell.borrow_mut();
+
+        if current.as_ref().map(|buffer| buffer.retired.load(Ordering::Acquire)).unwrap_or(true) {
+            let buffer = Arc::new(ThreadBuffer {
+                thread: thread(),
+                events: RingBuffer::new(CAPACITY.load(Ordering::Relaxed)),
+                dropped: AtomicUsize::new(0),
+                finished: AtomicBool::new(false),
+                retired: AtomicBool::new(false)
+            });
+
+            if let Ok(mut buffers) = BUFFERS.lock() {
+                buffers.push(buffer.clone());
+            }
+
+            *current = Some(buffer);
+        }
+
+        let buffered = match *current {
+            Some(ref buffer) => {
+                // The buffer of a thread is only ever pushed to by that thread
+                let buffered = unsafe { buffer.events.push(record) };
+
+                if buffered {
+                    RECORDED.fetch_add(1, Ordering::Relaxed);
+                } else {
+                    buffer.dropped.fetch_add(1, Ordering::Relaxed);
+                    DROPPED.fetch_add(1, Ordering::Relaxed);
+                }
+
+                if record == EventRecord::ThreadEnd {
+                    buffer.finished.store(true, Ordering::Release);
+                }
+
+                buffered
+            },
+            None => false
+        };
+
+        if record == EventRecord::ThreadEnd {
+            *current = None;
+        }
+
+        buffered
+    });
+
+    RECORDING.fetch_sub(1, Ordering::SeqCst);
+    buffered
+}
+
+fn drain(vm: JavaVMPtr) {
+    DRAINING.with(|draining| draining.set(true));
+
+    let jvm = JVMAgent::new(vm);
+    let jni = match jvm.attach("Flare-Profiler-Events") {
+        Ok(jni) => jni,
+        Err(_) => {
+            println!("Couldn't attach the event drain thread, dispatching events from the callbacks again");
+            ENABLED.store(false, Ordering::SeqCst);
+            return;
+        }
+    };
+
+    match jvm.get_environment() {
+        Ok(jvmti) => drain_buffers(&Environment::new_from(jvmti, jni)),
+        Err(_) => {
+            println!("Couldn't get the environment of the event drain thread, dispatching events from the callbacks again");
+            ENABLED.store(false, Ordering::SeqCst);
+        }
+    }
+
+    let _ = jvm.detach();
+}
+
+fn drain_buffers(env: &Environment) {
+    let mut methods: HashMap<MethodId, (ClassSignature, MethodSignature)> = HashMap::new();
+
+    loop {
+        // Read before draining, so the events recorded until the pipeline was stopped get drained
+        let running = is_enabled();
+
+        // Nothing gets recorded once the threads still recording an event are done
+        while !running && RECORDING.load(Ordering::SeqCst) > 0 {
+            thread::yield_now();
+        }
+
+        let buffers: Vec<Arc<ThreadBuffer>> = match BUFFERS.lock() {
+            Ok(buffers) => buffers.clone(),
+            Err(_) => return
+        };
+        let mut drained = 0;
+
+        // There's only one drain thread at a time, the only thread popping records
+        for buffer in &buffers {
+            while let Some(record) = unsafe { buffer.events.pop() } {
+                dispatch_record(env, &mut methods, buffer.thread.as_ref(), record);
+                drained += 1;
+            }
+        }
+
+        DISPATCHED.fetch_add(drained, Ordering::Relaxed);
+
+        if let Ok(mut buffers) = BUFFERS.lock() {
+            buffers.retain(|buffer| {
+                let drained = buffer.finished.load(Ordering::Acquire) && buffer.events.is_empty();
+
+                if drained {
+                    release_thread(env, buffer);
+                }
+
+                !drained
+            });
+        }
+
+        if !running {
+            return retire_buffers(env);
+        }
+
+        if drained == 0 {
+            thread::sleep(IDLE_WAIT);
+        }
+    }
+}
+
+/// Release the buffers left once the pipeline stopped, along with any references their events hold
+fn retire_buffers(env: &Environment) {
+    if let Ok(mut buffers) = BUFFERS.lock() {
+        for buffer in buffers.drain(..) {
+            buffer.retired.store(true, Ordering::Release);
+
+            while let Some(record) = unsafe { buffer.events.pop() } {
+                if let Some(monitor) = record.monitor() {
+                    monitor.release(env);
+                }
+            }
+
+            release_thread(env, &buffer);
+        }
+    }
+}
+
+fn release_thread(env: &Environment, buffer: &ThreadBuffer) {
+    if let Some(ref thread) = buffer.thread {
+        env.delete_global_ref(thread.id.native_id);
+    }
+}
+
+fn dispatch_record(env: &Environment, methods: &mut HashMap<MethodId, (ClassSignature, MethodSignature)>, thread: Option<&Thread>, record: EventRecord) {
+    match (record, thread) {
+        (EventRecord::MethodEntry(method_id), Some(thread)) => dispatch_method(subscribed(|callbacks| &callbacks.method_entry), env, methods, thread, method_id),
+        (EventRecord::MethodExit(method_id), Some(thread)) => dispatch_method(subscribed(|callbacks| &callbacks.method_exit), env, methods, thread, method_id),
+        (EventRecord::ThreadStart, Some(thread)) => dispatch(subscribed(|callbacks| &callbacks.thread_start), thread.clone()),
+        (EventRecord::ThreadEnd, Some(thread)) => dispatch(subscribed(|callbacks| &callbacks.thread_end), thread.clone()),
+        (EventRecord::MonitorWait(monitor, timeout), Some(thread)) => if let Some(monitor) = monitor.resolve(env) {
+            dispatch(subscribed(|callbacks| &callbacks.monitor_wait), MonitorWaitEvent { thread: thread.clone(), monitor: monitor, timeout: timeout })
+        },
+        (EventRecord::MonitorWaited(monitor, timed_out), Some(thread)) => if let Some(monitor) = monitor.resolve(env) {
+            dispatch(subscribed(|callbacks| &callbacks.monitor_waited), MonitorWaitedEvent { thread: thread.clone(), monitor: monitor, timed_out: timed_out })
+        },
+        (EventRecord::MonitorContendedEnter(monitor), Some(thread)) => if let Some(monitor) = monitor.resolve(env) {
+            dispatch(subscribed(|callbacks| &callbacks.monitor_contended_enter), MonitorContendedEnterEvent { thread: thread.clone(), monitor: monitor })
+        },
+        (EventRecord::MonitorContendedEntered(monitor, blocked_nanos), Some(thread)) => if let Some(monitor) = monitor.resolve(env) {
+            dispatch(subscribed(|callbacks| &callbacks.monitor_contended_entered), MonitorContendedEnteredEvent { thread: thread.clone(), monitor: monitor, blocked_nanos: blocked_nanos, frames: vec![] })
+        },
+        (EventRecord::GarbageCollectionStart, _) => for handler in subscribed(|callbacks| &callbacks.garbage_collection_start) { handler() },
+        (EventRecord::GarbageCollectionFinish, _) => for handler in subscribed(|callbacks| &callbacks.garbage_collection_finish) { handler() },
+        // The thread couldn't be looked up when its buffer was created
+        (record, None) => if let Some(monitor) = record.monitor() {
+            monitor.release(env);
+        }
+    }
+}
+
+fn dispatch_method<F>(handlers: Vec<Arc<F>>, env: &Environment, methods: &mut HashMap<MethodId, (ClassSignature, MethodSignature)>, thread: &Thread, method_id: MethodId)
+    where F: Fn(MethodInvocationEvent) + Send + Sync + ?Sized {
+
+    if handlers.is_empty() {
+        return;
+    }
+
+    if !methods.contains_key(&method_id) {
+        match resolve_method(env, &method_id) {
+            Some(names) => { methods.insert(method_id, names); },
+            None => return
+        }
+    }
+
+    if let Some(&(ref class_sig, ref method_sig)) = methods.get(&method_id) {
+        for handler in handlers {
+            handler(MethodInvocationEvent { method_id: method_id, method_sig: method_sig.clone(), class_sig: class_sig.clone(), thread: thread.clone() });
+        }
+    }
+}
+
+/// The drain thread never returns to Java, so the local references it creates have to be deleted
+fn resolve_method(env: &Environment, method_id: &MethodId) -> Option<(ClassSignature, MethodSignature)> {
+    let class_id = env.get_method_declaring_class(method_id).ok()?;
+    let class_sig = env.get_class_signature(&class_id).ok();
+
+    env.delete_local_ref(class_id.native_id);
+
+    Some((class_sig?, env.get_method_name(method_id).ok()?))
+}
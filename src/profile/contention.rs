@@ -0,0 +1,147 @@
+use environment::Environment;
+use environment::jvmti::JavaStackFrame;
+use method::MethodId;
+use native::JavaLong;
+use profile::sample::{LineNumberResolver, MethodInfo};
+use profile::tree::CallStackTree;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{self, Write};
+
+///
+/// The number of times threads were blocked entering monitors, for how long in total and at most
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ContentionStats {
+    pub count: u32,
+    pub total_nanos: JavaLong,
+    pub max_nanos: JavaLong
+}
+
+impl ContentionStats {
+
+    pub fn add(&mut self, blocked_nanos: JavaLong) {
+        self.count += 1;
+        self.total_nanos += blocked_nanos;
+        self.max_nanos = self.max_nanos.max(blocked_nanos);
+    }
+}
+
+///
+/// Where monitors were entered: the class of the monitor objects and the methods and bytecode
+/// locations of the stack of the entering thread, innermost first
+///
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct ContentionSite {
+    pub class_name: String,
+    pub frames: Vec<(MethodId, JavaLong)>
+}
+
+///
+/// Aggregates the time threads were blocked entering monitors by monitor class and acquiring stack.
+/// Frames are only named when the call trees are written.
+///
+pub struct ContentionProfiler {
+    sites: HashMap<ContentionSite, ContentionStats>,
+    line_numbers: bool,
+    line_resolver: LineNumberResolver
+}
+
+impl ContentionProfiler {
+
+    pub fn new() -> ContentionProfiler {
+        ContentionProfiler {
+            sites: HashMap::new(),
+            line_numbers: false,
+            line_resolver: LineNumberResolver::new()
+        }
+    }
+
+    /// Tell apart the monitors entered from different lines of a method in the call trees
+    pub fn set_line_numbers(&mut self, line_numbers: bool) {
+        self.line_numbers = line_numbers;
+    }
+
+    /// Add the time a thread was blocked entering a monitor of the given class, with the stack it
+    /// entered the monitor from, innermost frame first
+    pub fn add(&mut self, class_name: &str, frames: &[JavaStackFrame], blocked_nanos: JavaLong) {
+        let site = ContentionSite {
+            class_name: class_name.to_string(),
+            frames: frames.iter().map(|frame| (MethodId { native_id: frame.method }, frame.location)).collect()
+        };
+
+        self.sites.entry(site).or_insert_with(ContentionStats::default).add(blocked_nanos);
+    }
+
+    pub fn sites(&self) -> &HashMap<ContentionSite, ContentionStats> {
+        &self.sites
+    }
+
+    /// The time threads were blocked entering monitors of the given class, from any stack
+    pub fn class_stats(&self, class_name: &str) -> ContentionStats {
+        self.sites.iter().filter(|&(site, _)| site.class_name == class_name).fold(ContentionStats::default(), |mut total, (_, stats)| {
+            total.count += stats.count;
+            total.total_nanos += stats.total_nanos;
+            total.max_nanos = total.max_nanos.max(stats.max_nanos);
+            total
+        })
+    }
+
+    pub fn clear(&mut self) {
+        self.sites.clear();
+    }
+
+    /// Drop the sites entering monitors from the methods of an unloaded class, their identifiers
+    /// may be reused by classes loaded later
+    pub fn on_class_unload(&mut self, methods: &[MethodId]) {
+        let unloaded: HashSet<&MethodId> = methods.iter().collect();
+
+        self.sites.retain(|site, _| !site.frames.iter().any(|&(ref method_id, _)| unloaded.contains(method_id)));
+
+        for method_id in methods {
+            self.line_resolver.forget(method_id);
+        }
+    }
+
+    /// Write a call tree per monitor class, in the format of the sampled call trees: the calls that
+    /// entered the monitors, the times blocked being counted at the frames entering them
+    pub fn write_call_trees(&mut self, jvm_env: &Box<Environment>, writer: &mut Write, compact: bool) -> io::Result<()> {
+        let mut by_class: BTreeMap<&str, Vec<(&ContentionSite, &ContentionStats)>> = BTreeMap::new();
+        let mut method_names: HashMap<MethodId, Option<MethodInfo>> = HashMap::new();
+
+        for (site, stats) in &self.sites {
+            by_class.entry(site.class_name.as_str()).or_insert(vec![]).push((site, stats));
+        }
+
+        for (class_name, sites) in by_class {
+            let mut call_tree = CallStackTree::with_line_numbers(0, class_name, self.line_numbers);
+            let mut total = ContentionStats::default();
+
+            for (site, stats) in sites {
+                call_tree.reset_top_call_stack_node();
+
+                for &(method_id, location) in site.frames.iter().rev() {
+                    let line_number = if self.line_numbers { self.line_resolver.resolve(jvm_env, method_id.native_id, location) } else { None };
+
+                    if !call_tree.begin_call(&method_id.native_id, line_number) {
+                        let name = match *method_names.entry(method_id).or_insert_with(|| MethodInfo::resolve(jvm_env, method_id)) {
+                            Some(ref method_info) => method_info.frame_name(line_number),
+                            None => String::from("<unknown>")
+                        };
+
+                        call_tree.get_mut_top_node().data.name = name;
+                    }
+                }
+
+                call_tree.add_blocked_calls(stats.count, stats.total_nanos, stats.max_nanos);
+                total.count += stats.count;
+                total.total_nanos += stats.total_nanos;
+            }
+
+            writer.write_fmt(format_args!("Monitor: {}, {}, {}\n", class_name, total.count, total.total_nanos as f64/1000_000.0))?;
+            writer.write_all(call_tree.format_call_tree(compact).as_bytes())?;
+            writer.write_all("\n".as_bytes())?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,470 @@
+use class::ClassId;
+use environment::Environment;
+use environment::jni::JNI;
+use environment::jvmti::JVMTI;
+use error::NativeError;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use heap::{HeapFilter, HeapObject, HeapReference, HeapVisit, ReferenceKind};
+use native::JavaLong;
+use serde_json;
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// Objects are tagged with their index in the snapshot plus this while it's being captured
+const NODE_TAG_BASE: JavaLong = 1 << 40;
+const UNDEFINED: usize = usize::max_value();
+
+/// The class index of objects whose class couldn't be told
+pub const UNKNOWN_CLASS: u32 = u32::max_value();
+
+///
+/// The kinds of heap roots
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum RootKind {
+    JniGlobal,
+    SystemClass,
+    Monitor,
+    StackLocal,
+    JniLocal,
+    Thread,
+    Other
+}
+
+///
+/// The kinds of references between objects, with the index of the field, array element or constant
+/// pool entry holding the reference where there's one
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum EdgeKind {
+    Class,
+    Field(i32),
+    ArrayElement(i32),
+    ClassLoader,
+    Signers,
+    ProtectionDomain,
+    Interface,
+    StaticField(i32),
+    ConstantPool(i32),
+    Superclass,
+    Other
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HeapRoot {
+    pub kind: RootKind,
+    pub object: u32
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HeapEdge {
+    pub kind: EdgeKind,
+    pub target: u32
+}
+
+///
+/// An object of a heap snapshot. `class` is the index of the object of its class, which is also
+/// the index of the class name.
+///
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HeapNode {
+    pub class: u32,
+    pub size: u64,
+    pub edges: Vec<HeapEdge>
+}
+
+///
+/// A compact graph of the objects reachable from the heap roots, built with `FollowReferences`.
+/// Objects are known by their index, the loaded classes coming first in the same order as their
+/// names. Snapshots are written gzipped, for the analysis to be done offline.
+///
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HeapSnapshot {
+    pub class_names: Vec<String>,
+    pub objects: Vec<HeapNode>,
+    pub roots: Vec<HeapRoot>
+}
+
+impl HeapSnapshot {
+
+    /// Capture the objects reachable from the heap roots. Objects are tagged while the snapshot is
+    /// taken and given their previous tags back afterwards. Requires the can_tag_objects capability.
+    pub fn capture(env: &Environment) -> Result<HeapSnapshot, NativeError> {
+        let classes = env.get_loaded_classes()?;
+        let mut snapshot = HeapSnapshot { class_names: vec![], objects: vec![], roots: vec![] };
+        let mut previous_tags: HashMap<u32, JavaLong> = HashMap::new();
+
+        let tagged = HeapSnapshot::tag_classes(env, &classes, &mut snapshot, &mut previous_tags);
+
+        // the classes keep their tags, their local references would only show up as roots
+        for class_id in &classes {
+            env.delete_local_ref(class_id.native_id);
+        }
+
+        let class_count = snapshot.class_names.len();
+        let result = tagged.and_then(|_| env.follow_references(HeapFilter::NONE, None, None, &mut |reference: &HeapReference, object: &mut HeapObject| {
+            let class = if object.class_tag >= NODE_TAG_BASE && ((object.class_tag - NODE_TAG_BASE) as usize) < class_count { (object.class_tag - NODE_TAG_BASE) as u32 } else { UNKNOWN_CLASS };
+
+            let index = if object.tag >= NODE_TAG_BASE {
+                (object.tag - NODE_TAG_BASE) as u32
+            } else {
+                let index = snapshot.objects.len() as u32;
+
+                if object.tag != 0 {
+                    previous_tags.insert(index, object.tag);
+                }
+
+                object.tag = NODE_TAG_BASE + index as JavaLong;
+                snapshot.objects.push(HeapNode { class: class, size: object.size as u64, edges: vec![] });
+                index
+            };
+
+            // the classes were added before they were reached
+            if (index as usize) < class_count && snapshot.objects[index as usize].class == UNKNOWN_CLASS {
+                snapshot.objects[index as usize].class = class;
+                snapshot.objects[index as usize].size = object.size as u64;
+            }
+
+            match (root_kind(&reference.kind), reference.referrer_tag) {
+                (Some(kind), _) => snapshot.roots.push(HeapRoot { kind: kind, object: index }),
+                (None, Some(referrer_tag)) if referrer_tag >= NODE_TAG_BASE => {
+                    snapshot.objects[(referrer_tag - NODE_TAG_BASE) as usize].edges.push(HeapEdge { kind: edge_kind(&reference.kind), target: index });
+                },
+                _ => ()
+            }
+
+            HeapVisit::Continue
+        }));
+
+        // give the objects their previous tags back, whether the snapshot was taken or not
+        let cleanup = env.iterate_through_heap(HeapFilter::UNTAGGED, None, &mut |object| {
+            if object.tag >= NODE_TAG_BASE {
+                object.tag = previous_tags.get(&((object.tag - NODE_TAG_BASE) as u32)).cloned().unwrap_or(0);
+            }
+
+            HeapVisit::Continue
+        });
+
+        result.and(cleanup).map(|_| snapshot)
+    }
+
+    /// Tag the classes as the first nodes, remembering the tags they had
+    fn tag_classes(env: &Environment, classes: &Vec<ClassId>, snapshot: &mut HeapSnapshot, previous_tags: &mut HashMap<u32, JavaLong>) -> Result<(), NativeError> {
+        for (index, class_id) in classes.iter().enumerate() {
+            let tag = env.get_tag(&class_id.native_id)?;
+
+            if tag != 0 {
+                previous_tags.insert(index as u32, tag);
+            }
+
+            env.set_tag(&class_id.native_id, NODE_TAG_BASE + index as JavaLong)?;
+            snapshot.class_names.push(env.get_class_signature(class_id).map(|class_sig| class_sig.name).unwrap_or(String::new()));
+            snapshot.objects.push(HeapNode { class: UNKNOWN_CLASS, size: 0, edges: vec![] });
+        }
+
+        Ok(())
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut writer = GzEncoder::new(BufWriter::new(File::create(path)?), Compression::default());
+
+        serde_json::to_writer(&mut writer, self).map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        writer.finish()?.flush()
+    }
+
+    pub fn read(path: &Path) -> io::Result<HeapSnapshot> {
+        let reader = GzDecoder::new(BufReader::new(File::open(path)?));
+
+        serde_json::from_reader(reader).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// The name of the class of an object
+    pub fn class_name(&self, object: u32) -> &str {
+        match self.objects[object as usize].class {
+            UNKNOWN_CLASS => "<unknown>",
+            class => &self.class_names[class as usize]
+        }
+    }
+
+    /// The class of an object followed by its index, or the class it stands for if it's a class
+    fn describe(&self, object: u32) -> String {
+        match self.class_names.get(object as usize) {
+            Some(class_name) => format!("class {}", class_name),
+            None => format!("{}#{}", self.class_name(object), object)
+        }
+    }
+
+    /// The objects that are instances of the given class
+    pub fn instances_of(&self, class_name: &str) -> Vec<u32> {
+        (0..self.objects.len() as u32).filter(|object| self.class_name(*object) == class_name).collect()
+    }
+
+    /// Compute the dominator tree of the objects, where an object dominates the objects that are
+    /// only reachable from the roots through it, along with the retained sizes of the objects
+    pub fn dominators(&self) -> Dominators {
+        let roots = self.objects.len();
+        let successors = |node: usize| -> Vec<usize> {
+            if node == roots {
+                self.roots.iter().map(|root| root.object as usize).collect()
+            } else {
+                self.objects[node].edges.iter().map(|edge| edge.target as usize).collect()
+            }
+        };
+
+        // post order of a depth first search from a virtual node standing for all the roots
+        let mut post_order = vec![];
+        let mut visited = vec![false; roots + 1];
+        let mut stack = vec![(roots, successors(roots), 0)];
+        visited[roots] = true;
+
+        while let Some((node, children, next)) = stack.pop() {
+            if next < children.len() {
+                let child = children[next];
+                stack.push((node, children, next + 1));
+
+                if !visited[child] {
+                    visited[child] = true;
+                    stack.push((child, successors(child), 0));
+                }
+            } else {
+                post_order.push(node);
+            }
+        }
+
+        let mut post_index = vec![UNDEFINED; roots + 1];
+        let mut predecessors = vec![vec![]; roots + 1];
+
+        for (index, node) in post_order.iter().enumerate() {
+            post_index[*node] = index;
+
+            for successor in successors(*node) {
+                predecessors[successor].push(*node);
+            }
+        }
+
+        // Cooper, Harvey and Kennedy's iterative algorithm, in reverse post order
+        let mut idom = vec![UNDEFINED; roots + 1];
+        idom[roots] = roots;
+        let mut changed = true;
+
+        while changed {
+            changed = false;
+
+            for node in post_order.iter().rev().skip(1) {
+                let mut new_idom = UNDEFINED;
+
+                for predecessor in &predecessors[*node] {
+                    if idom[*predecessor] != UNDEFINED {
+                        new_idom = if new_idom == UNDEFINED { *predecessor } else { intersect(&idom, &post_index, *predecessor, new_idom) };
+                    }
+                }
+
+                if idom[*node] != new_idom {
+                    idom[*node] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+
+        // dominated objects come before their dominators in post order
+        let mut retained: Vec<u64> = self.objects.iter().map(|object| object.size).collect();
+
+        for node in &post_order {
+            if *node != roots && idom[*node] != roots {
+                retained[idom[*node]] += retained[*node];
+            }
+        }
+
+        Dominators { idom: idom, retained: retained, roots: roots }
+    }
+
+    /// Find the shortest paths from the roots to every object
+    pub fn root_paths(&self) -> RootPaths {
+        let mut parents = vec![None; self.objects.len()];
+        let mut queue = VecDeque::new();
+
+        for root in &self.roots {
+            if parents[root.object as usize].is_none() {
+                parents[root.object as usize] = Some(PathStep::Root(root.kind, root.object));
+                queue.push_back(root.object);
+            }
+        }
+
+        while let Some(object) = queue.pop_front() {
+            for edge in &self.objects[object as usize].edges {
+                if parents[edge.target as usize].is_none() {
+                    parents[edge.target as usize] = Some(PathStep::Edge(object, edge.kind, edge.target));
+                    queue.push_back(edge.target);
+                }
+            }
+        }
+
+        RootPaths { parents: parents }
+    }
+
+    /// Write the objects retaining the most memory, and the shortest paths from the roots to the
+    /// instances of `class_name` if given
+    pub fn write_report(&self, writer: &mut Write, top: usize, class_name: Option<&str>) -> io::Result<()> {
+        let dominators = self.dominators();
+        let total: u64 = self.objects.iter().map(|object| object.size).sum();
+
+        writeln!(writer, "{} objects, {} bytes, {} roots", self.objects.len(), total, self.roots.len())?;
+        writeln!(writer, "")?;
+        writeln!(writer, "Largest retained sizes:")?;
+
+        for object in dominators.largest(top) {
+            writeln!(writer, "{:14} {:14}  {}", dominators.retained_size(object), self.objects[object as usize].size, self.describe(object))?;
+        }
+
+        if let Some(class_name) = class_name {
+            let paths = self.root_paths();
+            let instances = self.instances_of(class_name);
+
+            writeln!(writer, "")?;
+            writeln!(writer, "{} instances of {}", instances.len(), class_name)?;
+
+            for object in instances.iter().take(top) {
+                writeln!(writer, "")?;
+
+                match paths.path_to(*object) {
+                    Some(path) => {
+                        for step in path {
+                            match step {
+                                PathStep::Root(kind, object) => writeln!(writer, "{:?} root {}", kind, self.describe(object))?,
+                                PathStep::Edge(_, kind, object) => writeln!(writer, "  --{:?}--> {}", kind, self.describe(object))?
+                            }
+                        }
+                    },
+                    None => writeln!(writer, "{}#{} isn't reachable", class_name, object)?
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn intersect(idom: &Vec<usize>, post_index: &Vec<usize>, a: usize, b: usize) -> usize {
+    let (mut a, mut b) = (a, b);
+
+    while a != b {
+        while post_index[a] < post_index[b] {
+            a = idom[a];
+        }
+
+        while post_index[b] < post_index[a] {
+            b = idom[b];
+        }
+    }
+
+    a
+}
+
+fn root_kind(kind: &ReferenceKind) -> Option<RootKind> {
+    match kind {
+        &ReferenceKind::JniGlobal => Some(RootKind::JniGlobal),
+        &ReferenceKind::SystemClass => Some(RootKind::SystemClass),
+        &ReferenceKind::Monitor => Some(RootKind::Monitor),
+        &ReferenceKind::StackLocal { .. } => Some(RootKind::StackLocal),
+        &ReferenceKind::JniLocal { .. } => Some(RootKind::JniLocal),
+        &ReferenceKind::Thread => Some(RootKind::Thread),
+        &ReferenceKind::Other => Some(RootKind::Other),
+        _ => None
+    }
+}
+
+fn edge_kind(kind: &ReferenceKind) -> EdgeKind {
+    match kind {
+        &ReferenceKind::Class => EdgeKind::Class,
+        &ReferenceKind::Field(index) => EdgeKind::Field(index),
+        &ReferenceKind::ArrayElement(index) => EdgeKind::ArrayElement(index),
+        &ReferenceKind::ClassLoader => EdgeKind::ClassLoader,
+        &ReferenceKind::Signers => EdgeKind::Signers,
+        &ReferenceKind::ProtectionDomain => EdgeKind::ProtectionDomain,
+        &ReferenceKind::Interface => EdgeKind::Interface,
+        &ReferenceKind::StaticField(index) => EdgeKind::StaticField(index),
+        &ReferenceKind::ConstantPool(index) => EdgeKind::ConstantPool(index),
+        &ReferenceKind::Superclass => EdgeKind::Superclass,
+        _ => EdgeKind::Other
+    }
+}
+
+///
+/// The dominator tree of a heap snapshot and the retained sizes of its objects, the memory that
+/// would be freed along with them
+///
+pub struct Dominators {
+    idom: Vec<usize>,
+    retained: Vec<u64>,
+    roots: usize
+}
+
+impl Dominators {
+
+    /// The object an object is dominated by, `None` if it's only dominated by the roots or isn't
+    /// reachable at all
+    pub fn immediate_dominator(&self, object: u32) -> Option<u32> {
+        match self.idom[object as usize] {
+            UNDEFINED => None,
+            idom if idom == self.roots => None,
+            idom => Some(idom as u32)
+        }
+    }
+
+    pub fn retained_size(&self, object: u32) -> u64 {
+        self.retained[object as usize]
+    }
+
+    /// The `count` objects with the largest retained sizes, largest first
+    pub fn largest(&self, count: usize) -> Vec<u32> {
+        let mut objects: Vec<u32> = (0..self.retained.len() as u32).filter(|object| self.idom[*object as usize] != UNDEFINED).collect();
+
+        objects.sort_by(|a, b| self.retained[*b as usize].cmp(&self.retained[*a as usize]).then(a.cmp(b)));
+        objects.truncate(count);
+        objects
+    }
+}
+
+///
+/// A step of a path from a root: the root itself, or a reference from an object to the next one
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PathStep {
+    Root(RootKind, u32),
+    Edge(u32, EdgeKind, u32)
+}
+
+///
+/// The shortest paths from the roots to the objects of a heap snapshot
+///
+pub struct RootPaths {
+    parents: Vec<Option<PathStep>>
+}
+
+impl RootPaths {
+
+    /// The steps from a root to the given object, `None` if it isn't reachable
+    pub fn path_to(&self, object: u32) -> Option<Vec<PathStep>> {
+        let mut path = vec![];
+        let mut current = object;
+
+        loop {
+            match self.parents[current as usize] {
+                Some(PathStep::Root(kind, root)) => {
+                    path.push(PathStep::Root(kind, root));
+                    path.reverse();
+                    return Some(path);
+                },
+                Some(PathStep::Edge(referrer, kind, target)) => {
+                    path.push(PathStep::Edge(referrer, kind, target));
+                    current = referrer;
+                },
+                None => return None
+            }
+        }
+    }
+}
@@ -1,3 +1,4 @@
-
+pub mod contention;
+pub mod heap;
 pub mod sample;
-mod tree;
\ No newline at end of file
+mod tree;
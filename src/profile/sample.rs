@@ -1,10 +1,10 @@
 
 use super::super::environment::jvmti::*;
-use method::{MethodId, MethodSignature};
+use method::{line_number_at, LineNumberEntry, MethodId, MethodSignature};
 use std::collections::*;
-use native::JavaMethod;
+use native::{JavaLong, JavaMethod};
 use class::ClassSignature;
-use thread::{ThreadId, Thread};
+use thread::{JavaThreadState, ThreadId, Thread};
 use environment::Environment;
 use serde::{Deserialize, Serialize};
 use serde_json::Result;
@@ -34,13 +34,85 @@ pub struct Sampler {
     method_cache: HashMap<MethodId, MethodInfo>,
     threads : Vec<ThreadId>,
     enabled: bool,
-    tree_arena: TreeArena
+    tree_arena: TreeArena,
+    line_resolver: LineNumberResolver,
+    unknown_method: MethodInfo
 }
 
 pub struct MethodInfo {
     method_id: MethodId,
     method: MethodSignature,
-    class: ClassSignature
+    class: ClassSignature,
+    source_file: Option<String>
+}
+
+impl MethodInfo {
+
+    /// Look up the names of a method and its class, `None` if the method is no longer valid
+    pub fn resolve(jvm_env: &Box<Environment>, method_id: MethodId) -> Option<MethodInfo> {
+        let method = jvm_env.get_method_name(&method_id).ok()?;
+        let class_id = jvm_env.get_method_declaring_class(&method_id).ok()?;
+        let class = jvm_env.get_class_signature(&class_id).ok()?;
+        let source_file = jvm_env.get_source_file_name(&class_id).ok();
+
+        Some(MethodInfo {
+            method_id: method_id,
+            method,
+            class,
+            source_file
+        })
+    }
+
+    /// Stands for a method whose names can't be looked up, eg. because its class has been unloaded
+    pub fn unknown(method_id: MethodId) -> MethodInfo {
+        MethodInfo {
+            method_id: method_id,
+            method: MethodSignature { name: "<unknown>".to_string(), signature: "".to_string(), generic: "".to_string() },
+            class: ClassSignature { package: "".to_string(), name: "<unknown>".to_string(), generic: "".to_string() },
+            source_file: None
+        }
+    }
+
+    /// The name of a frame of this method, eg. `com.example.Foo.bar(Foo.java:123)` if the line is known
+    pub fn frame_name(&self, line_number: Option<u32>) -> String {
+        match line_number {
+            Some(line_number) => format!("{}.{}({}:{})", &self.class.name, &self.method.name, self.source_file.as_ref().map(|file| file.as_str()).unwrap_or("Unknown Source"), line_number),
+            None => format!("{}.{}()", &self.class.name, &self.method.name)
+        }
+    }
+}
+
+///
+/// Resolves the bytecode locations of stack frames to source lines, caching the line number tables
+/// of the methods seen. Methods without line numbers, such as native ones, are cached as well.
+///
+pub struct LineNumberResolver {
+    line_tables: HashMap<MethodId, Vec<LineNumberEntry>>
+}
+
+impl LineNumberResolver {
+
+    pub fn new() -> LineNumberResolver {
+        LineNumberResolver { line_tables: HashMap::new() }
+    }
+
+    /// Find the source line of a bytecode location of the given method
+    pub fn resolve(&mut self, jvm_env: &Box<Environment>, method: JavaMethod, location: JavaLong) -> Option<u32> {
+        // native frames have no location
+        if location < 0 {
+            return None;
+        }
+
+        let method_id = MethodId { native_id: method };
+        let line_table = self.line_tables.entry(method_id).or_insert_with(|| jvm_env.get_line_number_table(&method_id).unwrap_or(vec![]));
+
+        line_number_at(line_table, location)
+    }
+
+    /// Forget the line number table of a method, its identifier may be reused once its class is unloaded
+    pub fn forget(&mut self, method_id: &MethodId) {
+        self.line_tables.remove(method_id);
+    }
 }
 
 impl Sampler {
@@ -49,10 +121,18 @@ impl Sampler {
             method_cache: HashMap::new(),
             threads: vec![],
             enabled: false,
-            tree_arena: TreeArena::new()
+            tree_arena: TreeArena::new(),
+            line_resolver: LineNumberResolver::new(),
+            unknown_method: MethodInfo::unknown(MethodId { native_id: std::ptr::null_mut() })
         }
     }
 
+    /// Tell apart the calls made from different source lines of a method in the call trees created
+    /// from now on
+    pub fn set_line_numbers(&mut self, line_numbers: bool) {
+        self.tree_arena.set_line_numbers(line_numbers);
+    }
+
     pub fn set_enable(&mut self, val: bool) {
         self.enabled = val;
     }
@@ -72,6 +152,21 @@ impl Sampler {
 //        }
     }
 
+    /// Forget the methods of an unloaded class, their identifiers may be reused by classes loaded later
+    pub fn on_class_unload(&mut self, class: &ClassSignature) {
+        let line_resolver = &mut self.line_resolver;
+
+        self.method_cache.retain(|method_id, method_info| {
+            let retained = method_info.class.name != class.name;
+
+            if !retained {
+                line_resolver.forget(method_id);
+            }
+
+            retained
+        });
+    }
+
     pub fn write_all_call_trees(&self, writer: &mut std::io::Write, compact: bool) {
         for (thread_id, call_tree) in self.tree_arena.get_all_call_trees() {
             let tree_name = &call_tree.get_root_node().data.name;
@@ -98,34 +193,38 @@ impl Sampler {
                     println!("get_thread_cpu_time error");
                 }
 
+                // blocked threads don't use the cpu, their samples are kept to tell where they block
+                let state = stack_info.thread_state().java_state();
                 let call_tree = self.tree_arena.get_call_tree(&thread_info);
                 call_tree.reset_top_call_stack_node();
-                if call_tree.total_duration == cpu_time {
+                if call_tree.total_duration == cpu_time && state != JavaThreadState::Blocked {
                     continue;
                 }
 
-                let mut call_methods :Vec<JavaMethod> = vec![];
+                let line_numbers = call_tree.has_line_numbers();
+                let mut call_methods :Vec<(JavaMethod, Option<u32>)> = vec![];
                 for stack_frame in &stack_info.frame_buffer {
-                    call_methods.push(stack_frame.method);
+                    let line_number = if line_numbers { self.line_resolver.resolve(jvm_env, stack_frame.method, stack_frame.location) } else { None };
+                    call_methods.push((stack_frame.method, line_number));
                 }
                 //save nodes in temp vec, process it after build call tree, avoid second borrow muttable *self
-                let mut naming_nodes: Vec<(NodeId, JavaMethod)> = vec![];
+                let mut naming_nodes: Vec<(NodeId, JavaMethod, Option<u32>)> = vec![];
 
                 //reverse call
-                for method_id in call_methods.iter().rev() {
-                    if !call_tree.begin_call(method_id) {
-                        naming_nodes.push((call_tree.get_top_node().data.node_id, method_id.clone()));
+                let call_tree = self.tree_arena.get_call_tree(&thread_info);
+                for &(method_id, line_number) in call_methods.iter().rev() {
+                    if !call_tree.begin_call(&method_id, line_number) {
+                        naming_nodes.push((call_tree.get_top_node().data.node_id, method_id, line_number));
                     }
                 }
 
-                call_tree.end_last_call(cpu_time);
+                call_tree.end_last_call(cpu_time, state);
                 //println!("add call stack: {} cpu_time:{}", thread_info.name, cpu_time);
 
                 //get method call_name of node
                 let mut node_methods: Vec<(NodeId, String)> = vec![];
-                for (node_id, method_id) in naming_nodes {
-                    let method_info = self.get_method_info(jvm_env, method_id);
-                    let call_name = format!("{}.{}()", &method_info.class.name, &method_info.method.name);
+                for (node_id, method_id, line_number) in naming_nodes {
+                    let call_name = self.get_method_info(jvm_env, method_id).frame_name(line_number);
                     node_methods.push((node_id, call_name));
                 }
 
@@ -143,7 +242,7 @@ impl Sampler {
     pub fn format_stack_traces(&mut self, jvm_env: &Box<Environment>, stack_traces: &Vec<JavaStackTrace>) -> String {
         let mut result  = String::new();
         for (i, stack_info) in stack_traces.iter().enumerate() {
-            result.push_str(&format!("\nstack_info: {}, thread: {:?}, state: {:?}\n", (i+1), stack_info.thread, stack_info.state));
+            result.push_str(&format!("\nstack_info: {}, thread: {:?}, state: {}\n", (i+1), stack_info.thread, stack_info.thread_state().java_state()));
 
             let mut cpu_time = -1f64;
             match jvm_env.get_thread_cpu_time(&stack_info.thread) {
@@ -154,15 +253,17 @@ impl Sampler {
             }
 
             if let Ok(thread_info) = jvm_env.get_thread_info(&stack_info.thread) {
-                result.push_str(&format!("Thread {}: (id = {}, priority = {}, daemon = {}, state = {:?}, cpu_time = {}) \n",
-                                         thread_info.name, thread_info.thread_id,  thread_info.priority, thread_info.is_daemon, stack_info.state, cpu_time ));
+                result.push_str(&format!("Thread {}: (id = {}, priority = {}, daemon = {}, state = {}, cpu_time = {}) \n",
+                                         thread_info.name, thread_info.thread_id,  thread_info.priority, thread_info.is_daemon, stack_info.thread_state().java_state(), cpu_time ));
             } else {
                 result.push_str(&format!("Thread UNKNOWN [{:?}]: (cpu_time = {}) \n", stack_info.thread, cpu_time));
             }
 
             for stack_frame in &stack_info.frame_buffer {
+                let line_number = self.line_resolver.resolve(jvm_env, stack_frame.method, stack_frame.location);
                 let method_info = self.get_method_info(jvm_env, stack_frame.method);
-                result.push_str(&format!("{}.{}()\n", &method_info.class.name, &method_info.method.name));
+                result.push_str(&method_info.frame_name(line_number));
+                result.push_str("\n");
             }
         }
         result
@@ -170,16 +271,15 @@ impl Sampler {
 
     fn get_method_info(&mut self, jvm_env: &Box<Environment>, method: JavaMethod) -> &MethodInfo {
         let method_id = MethodId { native_id: method };
-        self.method_cache.entry(method_id).or_insert_with(|| {
-            let method = jvm_env.get_method_name(&method_id).unwrap();
-            let class_id = jvm_env.get_method_declaring_class(&method_id).unwrap();
-            let class = jvm_env.get_class_signature(&class_id).unwrap();
-            MethodInfo {
-                method_id: method_id,
-                method,
-                class
+
+        if !self.method_cache.contains_key(&method_id) {
+            match MethodInfo::resolve(jvm_env, method_id) {
+                Some(method_info) => { self.method_cache.insert(method_id, method_info); },
+                // not cached, as the method won't be forgotten when its class is unloaded
+                None => return &self.unknown_method
             }
-        })
-        //self.method_cache.get(&method_id).unwrap()
+        }
+
+        &self.method_cache[&method_id]
     }
 }
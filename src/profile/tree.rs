@@ -1,5 +1,5 @@
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::rc::*;
 use std::borrow::Cow;
 
@@ -26,6 +26,7 @@ fn get_next_nodeid() {
 // assume thread safe, get lock outside
 pub struct TreeArena {
     thread_trees: HashMap<JavaLong, CallStackTree>,
+    line_numbers: bool,
 //    lock: RwLock<u32>
 }
 
@@ -33,16 +34,28 @@ impl TreeArena {
     pub fn new() -> TreeArena {
         TreeArena {
             thread_trees: HashMap::new(),
+            line_numbers: false,
             //lock: RwLock::new(0)
         }
     }
 
+    /// Key the nodes of the call trees created from now on by method and source line, telling apart
+    /// the calls made from different lines of the same method
+    pub fn set_line_numbers(&mut self, line_numbers: bool) {
+        self.line_numbers = line_numbers;
+    }
+
+    pub fn has_line_numbers(&self) -> bool {
+        self.line_numbers
+    }
+
     pub fn get_all_call_trees(&self) -> &HashMap<JavaLong, CallStackTree>{
         &self.thread_trees
     }
 
     pub fn get_call_tree(&mut self, thread: &Thread) -> &mut CallStackTree {
-        self.thread_trees.entry(thread.thread_id).or_insert_with(||{ CallStackTree::new(thread.thread_id, &thread.name) });
+        let line_numbers = self.line_numbers;
+        self.thread_trees.entry(thread.thread_id).or_insert_with(||{ CallStackTree::with_line_numbers(thread.thread_id, &thread.name, line_numbers) });
         self.thread_trees.get_mut(&thread.thread_id).unwrap()
     }
 
@@ -105,29 +118,43 @@ pub struct CallStackTree {
     root_node: NodeId,
     top_call_stack_node: NodeId,
     pub total_duration: i64,
-    pub thread_id: JavaLong
+    pub thread_id: JavaLong,
+    line_numbers: bool
 }
 
 impl CallStackTree {
 
     pub fn new(thread_id: JavaLong, thread_name: &str) -> CallStackTree {
+        CallStackTree::with_line_numbers(thread_id, thread_name, false)
+    }
+
+    /// Create a call tree keying its nodes by method and source line if `line_numbers` is set, or
+    /// by method only otherwise
+    pub fn with_line_numbers(thread_id: JavaLong, thread_name: &str, line_numbers: bool) -> CallStackTree {
         CallStackTree {
             nodes: vec![TreeNode::newRootNode(thread_name)],
             root_node: NodeId { index: 0 },
             top_call_stack_node: NodeId { index: 0 },
             total_duration: 0,
-            thread_id: thread_id
+            thread_id: thread_id,
+            line_numbers: line_numbers
         }
     }
 
+    pub fn has_line_numbers(&self) -> bool {
+        self.line_numbers
+    }
+
     pub fn reset_top_call_stack_node(&mut self) {
         self.top_call_stack_node = self.root_node;
     }
 
-    pub fn begin_call(&mut self, method_id: &JavaMethod) -> bool {
+    pub fn begin_call(&mut self, method_id: &JavaMethod, line_number: Option<u32>) -> bool {
+        // the line is ignored unless the nodes are keyed by line
+        let call_key = CallKey { method: *method_id as u64, line_number: if self.line_numbers { line_number } else { None } };
         //find exist call node
         let topNode = self.get_top_node();
-        match topNode.find_child(method_id) {
+        match topNode.find_child(&call_key) {
             Some(child_id) => {
                 let node = self.get_node(child_id);
                 self.top_call_stack_node = node.data.node_id.clone();
@@ -139,7 +166,7 @@ impl CallStackTree {
                 let next_index = self.nodes.len();
 
                 let topNode = self.get_mut_top_node();
-                let node_data = TreeNode::newCallNode(topNode, next_index, method_id);
+                let node_data = TreeNode::newCallNode(topNode, next_index, call_key);
                 self.top_call_stack_node = node_data.data.node_id.clone();
 
                 // Push the node into the arena
@@ -176,7 +203,7 @@ impl CallStackTree {
         }
     }
 
-    pub fn end_last_call(&mut self, total_duration: i64) {
+    pub fn end_last_call(&mut self, total_duration: i64, state: JavaThreadState) {
         let last_duration = self.total_duration;
         let top_node = self.get_mut_top_node();
         //ignore first call duration
@@ -184,9 +211,20 @@ impl CallStackTree {
             top_node.data.call_duration += (total_duration - last_duration);
         }
         top_node.data.call_count += 1;
+        *top_node.data.state_samples.entry(state).or_insert(0) += 1;
         self.total_duration = total_duration;
     }
 
+    /// Add `count` calls of the top node that were blocked for `duration` in total, at most
+    /// `max_duration` each, eg. on entering a monitor
+    pub fn add_blocked_calls(&mut self, count: u32, duration: i64, max_duration: i64) {
+        let top_node = self.get_mut_top_node();
+        top_node.data.call_count += count;
+        top_node.data.call_duration += duration;
+        top_node.data.max_duration = top_node.data.max_duration.max(max_duration);
+        *top_node.data.state_samples.entry(JavaThreadState::Blocked).or_insert(0) += count;
+    }
+
     //
     // compact: bool 是否为紧凑模式，即树结点深度使用数字表示。如果为false，则树深度使用多个' '表示
     //
@@ -216,7 +254,7 @@ impl CallStackTree {
 
         }
 
-        //"depth, call_name, calls, duration\n"
+        //"depth, call_name, calls, duration, samples by thread state and longest blocked call\n"
         //let duration = call_duration as f64/1000_000.0;
         let duration = call_duration/1000_000;
         result.push_str(&node.data.name);
@@ -224,6 +262,12 @@ impl CallStackTree {
         result.push_str(&node.data.call_count.to_string());
         result.push_str(",");
         result.push_str(&duration.to_string());
+        result.push_str(",");
+        let mut states: Vec<String> = node.data.state_samples.iter().map(|(state, samples)| format!("{}={}", state, samples)).collect();
+        if node.data.max_duration > 0 {
+            states.push(format!("max={}", node.data.max_duration/1000_000));
+        }
+        result.push_str(&states.join(" "));
         result.push_str("\n");
 
         for child in node.children.values() {
@@ -260,9 +304,18 @@ pub struct NodeData {
 //    path: String,
     pub call_count: u32, // call count
     pub call_duration: i64, // call duration
+    pub state_samples: BTreeMap<JavaThreadState, u32>, // samples ending at this node, by thread state
+    pub max_duration: i64, // longest blocked call
     pub children_size: u32 //children size
 }
 
+/// The key children nodes are told apart by: the method called and the line it was called from
+#[derive(Clone, Copy, Hash, Eq, PartialEq)]
+pub struct CallKey {
+    method: u64,
+    line_number: Option<u32>
+}
+
 #[derive(Clone, Copy)]
 pub struct NodeId {
     index: usize,
@@ -273,7 +326,7 @@ pub struct TreeNode {
     id: u64,
     pub data: NodeData,
     parent: Option<NodeId>,
-    children: HashMap<u64, NodeId>
+    children: HashMap<CallKey, NodeId>
 }
 
 impl TreeNode {
@@ -288,6 +341,8 @@ impl TreeNode {
 //                path: name.to_string(),
                 call_count: 0,
                 call_duration: 0,
+                state_samples: BTreeMap::new(),
+                max_duration: 0,
                 children_size: 0,
             },
             parent: None,
@@ -295,7 +350,7 @@ impl TreeNode {
         }
     }
 
-    pub fn newCallNode(parentNode: &mut TreeNode, next_index: usize, method_id: &JavaMethod) -> TreeNode {
+    pub fn newCallNode(parentNode: &mut TreeNode, next_index: usize, call_key: CallKey) -> TreeNode {
 
         //call path
 //        let mut path = parentNode.data.path.to_string();
@@ -304,11 +359,11 @@ impl TreeNode {
 
         let node_id = NodeId{index:next_index};
 
-        parentNode.children.insert(*method_id as u64, node_id.clone());
+        parentNode.children.insert(call_key, node_id.clone());
         parentNode.data.children_size += 1;
 
         TreeNode{
-            id: *method_id as u64,
+            id: call_key.method,
             data : NodeData {
                 node_id: node_id,
                 name: String::new(),
@@ -316,6 +371,8 @@ impl TreeNode {
                 depth: parentNode.data.depth + 1,
                 call_count: 0,
                 call_duration: 0,
+                state_samples: BTreeMap::new(),
+                max_duration: 0,
                 children_size: 0,
             },
             parent: Some(parentNode.data.node_id.clone()),
@@ -324,9 +381,40 @@ impl TreeNode {
 
     }
 
-    fn find_child(&self,  method_id: &JavaMethod) -> Option<&NodeId> {
-        let key = *method_id as u64;
-        self.children.get(&key)
+    fn find_child(&self, call_key: &CallKey) -> Option<&NodeId> {
+        self.children.get(call_key)
     }
 
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+
+    use super::CallStackTree;
+    use native::JavaMethod;
+
+    #[test]
+    fn calls_from_different_lines_are_told_apart_with_line_numbers() {
+        let method = 1usize as JavaMethod;
+        let mut tree = CallStackTree::with_line_numbers(1, "main", true);
+
+        assert!(!tree.begin_call(&method, Some(10)));
+        tree.reset_top_call_stack_node();
+        assert!(tree.begin_call(&method, Some(10)));
+        tree.reset_top_call_stack_node();
+        assert!(!tree.begin_call(&method, Some(12)));
+
+        assert_eq!(2, tree.get_root_node().data.children_size);
+    }
+
+    #[test]
+    fn calls_from_different_lines_are_merged_without_line_numbers() {
+        let method = 1usize as JavaMethod;
+        let mut tree = CallStackTree::new(1, "main");
+
+        assert!(!tree.begin_call(&method, Some(10)));
+        tree.reset_top_call_stack_node();
+        assert!(tree.begin_call(&method, Some(12)));
+
+        assert_eq!(1, tree.get_root_node().data.children_size);
+    }
+}
@@ -1,11 +1,19 @@
-use super::bytecode::Classfile;
+use super::bytecode::{Classfile, ClassReader, ClassReadError};
 use super::class::{ClassId, ClassSignature};
+use super::environment::Environment;
+use super::environment::jvmti::JavaStackFrame;
+use super::field::{FieldId, FieldSignature};
 use super::method::{MethodId, MethodSignature};
+use super::native::{JavaInt, JavaLong, JavaObject};
 use super::thread::Thread;
+use super::snapshot::FrameSnapshot;
+use super::value::JavaValue;
+use std::io::Cursor;
 
 pub trait RuntimeEvent {
 }
 
+#[derive(Clone)]
 pub struct ObjectAllocationEvent {
     pub class_id: ClassId,
     pub thread: Thread,
@@ -16,6 +24,7 @@ pub struct ObjectFreeEvent {
 
 }
 
+#[derive(Clone)]
 pub struct MethodInvocationEvent {
     pub method_id: MethodId,
     pub method_sig: MethodSignature,
@@ -23,12 +32,275 @@ pub struct MethodInvocationEvent {
     pub thread: Thread
 }
 
+///
+/// A bytecode location in a method, along with the source line it belongs to if the class has
+/// line number information
+#[derive(Clone)]
+pub struct CodeLocation {
+    pub method_id: MethodId,
+    pub method_sig: MethodSignature,
+    pub class_sig: ClassSignature,
+    pub location: JavaLong,
+    pub line_number: Option<u32>
+}
+
+///
+/// An exception was thrown at `location`. `catch_location` is the handler the exception will be
+/// caught by, or `None` if no Java code catches it. `frames` are the top frames of the throwing
+/// thread, only captured if enabled with `Agent::capture_exception_frames`
+#[derive(Clone)]
+pub struct ExceptionEvent {
+    pub thread: Thread,
+    pub location: CodeLocation,
+    pub exception_class: ClassSignature,
+    pub catch_location: Option<CodeLocation>,
+    pub frames: Vec<FrameSnapshot>
+}
+
+///
+/// A thrown exception was caught by the handler at `location`
+#[derive(Clone)]
+pub struct ExceptionCatchEvent {
+    pub thread: Thread,
+    pub location: CodeLocation,
+    pub exception_class: ClassSignature
+}
+
+///
+/// A watched field was read at `location`. `object` is the object holding the field, null for
+/// static fields, and is only valid until the handler returns
+#[derive(Clone)]
+pub struct FieldAccessEvent {
+    pub thread: Thread,
+    pub location: CodeLocation,
+    pub field_id: FieldId,
+    pub field_class: ClassSignature,
+    pub field: FieldSignature,
+    pub object: JavaObject
+}
+
+///
+/// A watched field is about to be set to `new_value` at `location`. `object` is the object holding
+/// the field, null for static fields, and is only valid until the handler returns
+#[derive(Clone)]
+pub struct FieldModificationEvent {
+    pub thread: Thread,
+    pub location: CodeLocation,
+    pub field_id: FieldId,
+    pub field_class: ClassSignature,
+    pub field: FieldSignature,
+    pub object: JavaObject,
+    pub new_value: JavaValue
+}
+
+///
+/// The monitor of an object, known by the class of the object and its identity hash code, which
+/// tells apart the monitors of objects of the same class
+#[derive(Clone, Debug)]
+pub struct MonitorObject {
+    pub class_sig: ClassSignature,
+    pub object_hash: JavaInt
+}
+
+///
+/// A thread is about to wait on a monitor in `Object.wait()`, for up to `timeout` milliseconds,
+/// zero waiting until notified
+#[derive(Clone)]
+pub struct MonitorWaitEvent {
+    pub thread: Thread,
+    pub monitor: MonitorObject,
+    pub timeout: JavaLong
+}
+
+///
+/// A thread is done waiting on a monitor in `Object.wait()`, because it was notified or `timed_out`
+#[derive(Clone)]
+pub struct MonitorWaitedEvent {
+    pub thread: Thread,
+    pub monitor: MonitorObject,
+    pub timed_out: bool
+}
+
+///
+/// A thread is about to block on entering a monitor owned by another thread
+#[derive(Clone)]
+pub struct MonitorContendedEnterEvent {
+    pub thread: Thread,
+    pub monitor: MonitorObject
+}
+
+///
+/// A thread entered a monitor after being blocked on it for `blocked_nanos`, unknown if it was
+/// already blocked when the events were enabled. `frames` are the frames of the thread entering the
+/// monitor, innermost first, only captured if enabled with `Agent::capture_monitor_frames` and
+/// never for events dispatched asynchronously
+#[derive(Clone)]
+pub struct MonitorContendedEnteredEvent {
+    pub thread: Thread,
+    pub monitor: MonitorObject,
+    pub blocked_nanos: Option<JavaLong>,
+    pub frames: Vec<JavaStackFrame>
+}
+
+///
+/// A class was loaded. Its fields and methods aren't available until it's prepared. `class_id` is
+/// a local reference, only valid until the handler returns
+#[derive(Clone)]
+pub struct ClassLoadEvent {
+    pub thread: Thread,
+    pub class_id: ClassId,
+    pub class_sig: ClassSignature,
+    /// The identity hash code of the defining class loader, `None` for the bootstrap class loader
+    pub class_loader: Option<JavaInt>,
+    pub source_file: Option<String>
+}
+
+///
+/// A class was prepared: its fields, methods and implemented interfaces are available, but none of
+/// its code has run yet. `class_id` is a local reference, only valid until the handler returns
+#[derive(Clone)]
+pub struct ClassPrepareEvent {
+    pub thread: Thread,
+    pub class_id: ClassId,
+    pub class_sig: ClassSignature,
+    /// The identity hash code of the defining class loader, `None` for the bootstrap class loader
+    pub class_loader: Option<JavaInt>,
+    pub source_file: Option<String>
+}
+
+///
+/// A class was unloaded, along with its methods and fields, whose identifiers are no longer valid
+#[derive(Clone)]
+pub struct ClassUnloadEvent {
+    pub class_sig: ClassSignature,
+    /// The identity hash code of the class loader that defined the class
+    pub class_loader: Option<JavaInt>,
+    /// The methods of the class, as they were when it was tracked for unloading
+    pub methods: Vec<MethodId>
+}
+
+///
+/// The native code starting at `address` was compiled from the bytecode at `location`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AddressLocation {
+    pub address: usize,
+    pub location: JavaLong
+}
+
+///
+/// The frames of the methods inlined into the native code at `address`, starting with the
+/// innermost one, each along with the bytecode index it's executing
+#[derive(Clone, Debug)]
+pub struct InlinedFrames {
+    pub address: usize,
+    pub frames: Vec<(MethodId, JavaInt)>
+}
+
+///
+/// A method was compiled to native code by the JIT compiler, and the code loaded at `code_address`
+#[derive(Clone)]
+pub struct CompiledMethodLoadEvent {
+    pub method_id: MethodId,
+    pub method_sig: MethodSignature,
+    pub class_sig: ClassSignature,
+    pub code_address: usize,
+    pub code_size: usize,
+    pub location_map: Vec<AddressLocation>,
+    pub inlining: Vec<InlinedFrames>
+}
+
+///
+/// The native code of a compiled method was unloaded. The method itself may be unloaded as well,
+/// so `method_id` is only good for looking up what was recorded when the code was loaded
+#[derive(Clone)]
+pub struct CompiledMethodUnloadEvent {
+    pub method_id: MethodId,
+    pub code_address: usize
+}
+
+///
+/// The JVM generated native code of its own, such as the interpreter or a stub
+#[derive(Clone)]
+pub struct DynamicCodeGeneratedEvent {
+    pub name: String,
+    pub address: usize,
+    pub length: usize
+}
+
 impl RuntimeEvent for ObjectAllocationEvent {}
 impl RuntimeEvent for MethodInvocationEvent {}
+impl RuntimeEvent for ExceptionEvent {}
+impl RuntimeEvent for ExceptionCatchEvent {}
+impl RuntimeEvent for FieldAccessEvent {}
+impl RuntimeEvent for FieldModificationEvent {}
+impl RuntimeEvent for MonitorWaitEvent {}
+impl RuntimeEvent for MonitorWaitedEvent {}
+impl RuntimeEvent for MonitorContendedEnterEvent {}
+impl RuntimeEvent for MonitorContendedEnteredEvent {}
+impl RuntimeEvent for ClassLoadEvent {}
+impl RuntimeEvent for ClassPrepareEvent {}
+impl RuntimeEvent for ClassUnloadEvent {}
+impl RuntimeEvent for CompiledMethodLoadEvent {}
+impl RuntimeEvent for CompiledMethodUnloadEvent {}
+impl RuntimeEvent for DynamicCodeGeneratedEvent {}
 
-pub struct ClassFileLoadEvent {
+///
+/// A class file is about to be loaded, or redefined or retransformed. `class_data` is only parsed
+/// by handlers that ask for it with `parse_class`, which matters as every class goes through here
+pub struct ClassFileLoadEvent<'a> {
     pub class_name: String,
-    pub class: Classfile
+    /// The class file bytes, as transformed by the handlers subscribed before this one
+    pub class_data: &'a [u8],
+    /// The defining class loader, `None` for the bootstrap class loader. Valid until the handler
+    /// returns
+    pub class_loader: Option<JavaObject>,
+    /// The environment of the thread loading the class, valid until the handler returns
+    pub environment: Environment
+}
+
+impl<'a> ClassFileLoadEvent<'a> {
+
+    pub fn parse_class(&self) -> Result<Classfile, ClassReadError> {
+        ClassReader::read_class(&mut Cursor::new(self.class_data))
+    }
+}
+
+impl<'a> RuntimeEvent for ClassFileLoadEvent<'a> {}
+
+///
+/// A thread hit a breakpoint set at `location`, which is about to be executed. `frames` are the
+/// top frames of the thread, only captured if enabled with `Agent::capture_breakpoint_frames`
+pub struct BreakpointEvent {
+    pub thread: Thread,
+    pub location: CodeLocation,
+    pub frames: Vec<FrameSnapshot>,
+    /// The environment of the thread, valid until the handler returns, eg. to ask for a FramePop
+    /// event of the current frame or to single step the thread
+    pub environment: Environment
+}
+
+///
+/// A thread single stepping is about to execute the bytecode at `location`
+pub struct SingleStepEvent {
+    pub thread: Thread,
+    pub location: CodeLocation,
+    /// The environment of the thread, valid until the handler returns
+    pub environment: Environment
+}
+
+///
+/// A frame a FramePop event was asked for with `notify_frame_pop` returned, or was popped by an
+/// exception thrown out of it
+pub struct FramePopEvent {
+    pub thread: Thread,
+    pub method_id: MethodId,
+    pub method_sig: MethodSignature,
+    pub class_sig: ClassSignature,
+    pub popped_by_exception: bool,
+    /// The environment of the thread, valid until the handler returns
+    pub environment: Environment
 }
 
-impl RuntimeEvent for ClassFileLoadEvent {}
+impl RuntimeEvent for BreakpointEvent {}
+impl RuntimeEvent for SingleStepEvent {}
+impl RuntimeEvent for FramePopEvent {}
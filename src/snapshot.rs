@@ -0,0 +1,144 @@
+use super::class::ClassSignature;
+use super::environment::Environment;
+use super::environment::jvmti::JVMTI;
+use super::error::NativeError;
+use super::method::{line_number_at, LocalVariableEntry, MethodId, MethodSignature};
+use super::native::{JavaLong, JavaObject, JavaThread};
+use super::value::JavaValue;
+use std::cmp;
+use std::fmt::{Display, Formatter, Error};
+
+///
+/// A local variable of a captured frame along with the value it held. The value is missing if it
+/// couldn't be read.
+///
+#[derive(Debug, Clone)]
+pub struct LocalVariable {
+    pub name: String,
+    pub signature: String,
+    pub slot: u32,
+    pub value: Option<JavaValue>
+}
+
+///
+/// A frame of a thread captured by `take_snapshot`: the method it executes, the location it's at and
+/// the values of its live local variables, arguments included. Object values and `this` are local
+/// references, only valid until the local frame they were read in is popped.
+///
+#[derive(Debug, Clone)]
+pub struct FrameSnapshot {
+    pub depth: usize,
+    pub method_id: MethodId,
+    pub class_sig: ClassSignature,
+    pub method_sig: MethodSignature,
+    pub location: JavaLong,
+    pub line_number: Option<u32>,
+    pub this: Option<JavaObject>,
+    pub locals: Vec<LocalVariable>
+}
+
+impl Display for LocalVariable {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match self.value {
+            Some(JavaValue::Boolean(v)) => write!(f, "{} = {}", self.name, v),
+            Some(JavaValue::Byte(v)) => write!(f, "{} = {}", self.name, v),
+            Some(JavaValue::Char(v)) => write!(f, "{} = {:?}", self.name, ::std::char::from_u32(v as u32).unwrap_or('\u{fffd}')),
+            Some(JavaValue::Short(v)) => write!(f, "{} = {}", self.name, v),
+            Some(JavaValue::Int(v)) => write!(f, "{} = {}", self.name, v),
+            Some(JavaValue::Long(v)) => write!(f, "{} = {}", self.name, v),
+            Some(JavaValue::Float(v)) => write!(f, "{} = {}", self.name, v),
+            Some(JavaValue::Double(v)) => write!(f, "{} = {}", self.name, v),
+            Some(JavaValue::Object(v)) if v.is_null() => write!(f, "{} = null", self.name),
+            Some(JavaValue::Object(_)) => write!(f, "{} = <{}>", self.name, self.signature),
+            None => write!(f, "{} = <unavailable>", self.name)
+        }
+    }
+}
+
+impl Display for FrameSnapshot {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match self.line_number {
+            Some(line_number) => write!(f, "{}.{}:{}", self.class_sig.name, self.method_sig.name, line_number)?,
+            None => write!(f, "{}.{}", self.class_sig.name, self.method_sig.name)?
+        }
+
+        for variable in &self.locals {
+            write!(f, "\n    {}", variable)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Read a local variable of the frame at the given depth of the stack of a thread, according to its
+/// type signature. The thread must be suspended or be the current thread.
+pub fn get_local_value(env: &Environment, thread_id: &JavaThread, depth: usize, variable: &LocalVariableEntry) -> Result<JavaValue, NativeError> {
+    match variable.signature.chars().next() {
+        Some('Z') => env.get_local_int(thread_id, depth, variable.slot).map(|v| JavaValue::Boolean(v != 0)),
+        Some('B') => env.get_local_int(thread_id, depth, variable.slot).map(|v| JavaValue::Byte(v as i8)),
+        Some('C') => env.get_local_int(thread_id, depth, variable.slot).map(|v| JavaValue::Char(v as u16)),
+        Some('S') => env.get_local_int(thread_id, depth, variable.slot).map(|v| JavaValue::Short(v as i16)),
+        Some('I') => env.get_local_int(thread_id, depth, variable.slot).map(JavaValue::Int),
+        Some('J') => env.get_local_long(thread_id, depth, variable.slot).map(JavaValue::Long),
+        Some('F') => env.get_local_float(thread_id, depth, variable.slot).map(JavaValue::Float),
+        Some('D') => env.get_local_double(thread_id, depth, variable.slot).map(JavaValue::Double),
+        Some('L') | Some('[') => env.get_local_object(thread_id, depth, variable.slot).map(JavaValue::Object),
+        _ => Err(NativeError::TypeMismatch)
+    }
+}
+
+/// Change a local variable of the frame at the given depth of the stack of a thread. The value has
+/// to match the type of the variable.
+pub fn set_local_value(env: &Environment, thread_id: &JavaThread, depth: usize, variable: &LocalVariableEntry, value: JavaValue) -> Result<(), NativeError> {
+    match value {
+        JavaValue::Boolean(v) => env.set_local_int(thread_id, depth, variable.slot, v as i32),
+        JavaValue::Byte(v) => env.set_local_int(thread_id, depth, variable.slot, v as i32),
+        JavaValue::Char(v) => env.set_local_int(thread_id, depth, variable.slot, v as i32),
+        JavaValue::Short(v) => env.set_local_int(thread_id, depth, variable.slot, v as i32),
+        JavaValue::Int(v) => env.set_local_int(thread_id, depth, variable.slot, v),
+        JavaValue::Long(v) => env.set_local_long(thread_id, depth, variable.slot, v),
+        JavaValue::Float(v) => env.set_local_float(thread_id, depth, variable.slot, v),
+        JavaValue::Double(v) => env.set_local_double(thread_id, depth, variable.slot, v),
+        JavaValue::Object(v) => env.set_local_object(thread_id, depth, variable.slot, v)
+    }
+}
+
+/// Capture the top `max_frames` frames of a thread, which must be suspended or be the current
+/// thread. Reading local variables requires the can_access_local_variables capability, without it
+/// the frames are captured without their variables.
+pub fn take_snapshot(env: &Environment, thread_id: &JavaThread, max_frames: usize) -> Result<Vec<FrameSnapshot>, NativeError> {
+    let frame_count = env.get_frame_count(thread_id)?;
+
+    (0..cmp::min(frame_count, max_frames))
+        .map(|depth| snapshot_frame(env, thread_id, depth))
+        .collect()
+}
+
+fn snapshot_frame(env: &Environment, thread_id: &JavaThread, depth: usize) -> Result<FrameSnapshot, NativeError> {
+    let frame = env.get_frame_location(thread_id, depth)?;
+    let method_id = MethodId { native_id: frame.method };
+    let method_sig = env.get_method_name(&method_id)?;
+    let class_sig = env.get_class_signature(&env.get_method_declaring_class(&method_id)?)?;
+    let line_number = env.get_line_number_table(&method_id).ok().and_then(|table| line_number_at(&table, frame.location));
+
+    let locals = env.get_local_variable_table(&method_id).unwrap_or(vec![]).iter()
+        .filter(|variable| variable.is_live_at(frame.location))
+        .map(|variable| LocalVariable {
+            name: variable.name.clone(),
+            signature: variable.signature.clone(),
+            slot: variable.slot,
+            value: get_local_value(env, thread_id, depth, variable).ok()
+        })
+        .collect();
+
+    Ok(FrameSnapshot {
+        depth: depth,
+        method_id: method_id,
+        class_sig: class_sig,
+        method_sig: method_sig,
+        location: frame.location,
+        line_number: line_number,
+        this: env.get_local_instance(thread_id, depth).ok(),
+        locals: locals
+    })
+}
@@ -1,5 +1,6 @@
 use super::native::JavaThread;
 use std::fmt::{Display, Formatter, Error};
+use std::ops::BitOr;
 use native::JavaLong;
 
 //use jni::sys::*;
@@ -33,3 +34,117 @@ pub struct Thread {
     pub priority: u32,
     pub is_daemon: bool
 }
+
+///
+/// Selects threads by name or by thread group. Names may end with a `*` to match any suffix, eg.
+/// `http-nio-*`. A filter without any names or groups selects every thread.
+///
+#[derive(Clone, Debug, Default)]
+pub struct ThreadFilter {
+    name_patterns: Vec<String>,
+    thread_groups: Vec<String>
+}
+
+impl ThreadFilter {
+
+    pub fn new(name_patterns: Vec<String>, thread_groups: Vec<String>) -> ThreadFilter {
+        ThreadFilter { name_patterns: name_patterns, thread_groups: thread_groups }
+    }
+
+    pub fn selects_all(&self) -> bool {
+        self.name_patterns.is_empty() && self.thread_groups.is_empty()
+    }
+
+    /// Whether the thread with the given name, belonging to the given thread group, is selected
+    pub fn matches(&self, thread_name: &str, thread_group: &str) -> bool {
+        self.selects_all() ||
+            self.name_patterns.iter().any(|pattern| if pattern.ends_with('*') { thread_name.starts_with(&pattern[..pattern.len() - 1]) } else { pattern == thread_name }) ||
+            self.thread_groups.iter().any(|group| group == thread_group)
+    }
+}
+
+///
+/// The state of a thread as returned by `GetThreadState`, a set of flags. Threads that are alive
+/// are either runnable, blocked on entering a monitor or waiting, the other flags adding detail.
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct ThreadState {
+    bits: u32
+}
+
+impl ThreadState {
+
+    pub const ALIVE: ThreadState = ThreadState { bits: 0x0001 };
+    pub const TERMINATED: ThreadState = ThreadState { bits: 0x0002 };
+    pub const RUNNABLE: ThreadState = ThreadState { bits: 0x0004 };
+    pub const WAITING_INDEFINITELY: ThreadState = ThreadState { bits: 0x0010 };
+    pub const WAITING_WITH_TIMEOUT: ThreadState = ThreadState { bits: 0x0020 };
+    pub const SLEEPING: ThreadState = ThreadState { bits: 0x0040 };
+    pub const WAITING: ThreadState = ThreadState { bits: 0x0080 };
+    pub const IN_OBJECT_WAIT: ThreadState = ThreadState { bits: 0x0100 };
+    pub const PARKED: ThreadState = ThreadState { bits: 0x0200 };
+    pub const BLOCKED_ON_MONITOR_ENTER: ThreadState = ThreadState { bits: 0x0400 };
+    pub const SUSPENDED: ThreadState = ThreadState { bits: 0x100000 };
+    pub const INTERRUPTED: ThreadState = ThreadState { bits: 0x200000 };
+    pub const IN_NATIVE: ThreadState = ThreadState { bits: 0x400000 };
+
+    pub fn from_bits(bits: u32) -> ThreadState {
+        ThreadState { bits: bits }
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    /// Whether all the flags of `other` are set
+    pub fn contains(&self, other: ThreadState) -> bool {
+        self.bits & other.bits == other.bits
+    }
+
+    /// The `java.lang.Thread.State` this state maps to
+    pub fn java_state(&self) -> JavaThreadState {
+        if !self.contains(ThreadState::ALIVE) {
+            if self.contains(ThreadState::TERMINATED) { JavaThreadState::Terminated } else { JavaThreadState::New }
+        } else if self.contains(ThreadState::BLOCKED_ON_MONITOR_ENTER) {
+            JavaThreadState::Blocked
+        } else if self.contains(ThreadState::WAITING) {
+            if self.contains(ThreadState::WAITING_WITH_TIMEOUT) { JavaThreadState::TimedWaiting } else { JavaThreadState::Waiting }
+        } else {
+            JavaThreadState::Runnable
+        }
+    }
+}
+
+impl BitOr for ThreadState {
+    type Output = ThreadState;
+
+    fn bitor(self, other: ThreadState) -> ThreadState {
+        ThreadState { bits: self.bits | other.bits }
+    }
+}
+
+///
+/// The states of `java.lang.Thread.State`
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub enum JavaThreadState {
+    New,
+    Runnable,
+    Blocked,
+    Waiting,
+    TimedWaiting,
+    Terminated
+}
+
+impl Display for JavaThreadState {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match self {
+            &JavaThreadState::New => write!(f, "NEW"),
+            &JavaThreadState::Runnable => write!(f, "RUNNABLE"),
+            &JavaThreadState::Blocked => write!(f, "BLOCKED"),
+            &JavaThreadState::Waiting => write!(f, "WAITING"),
+            &JavaThreadState::TimedWaiting => write!(f, "TIMED_WAITING"),
+            &JavaThreadState::Terminated => write!(f, "TERMINATED")
+        }
+    }
+}
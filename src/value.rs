@@ -0,0 +1,63 @@
+use super::native::JavaObject;
+use super::native::jvmti_native::{jbyte, jvalue};
+
+///
+/// A Java value along with its type. Object values are references that are only valid for as
+/// long as the reference they were read from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JavaValue {
+    Boolean(bool),
+    Byte(i8),
+    Char(u16),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    Object(JavaObject)
+}
+
+impl JavaValue {
+
+    /// Read a `jvalue` according to the first character of the type signature of the value, `Z`
+    /// for boolean, `L` or `[` for references and so on. `None` is returned for unknown types.
+    pub fn from_jvalue(signature_type: char, value: jvalue) -> Option<JavaValue> {
+        let mut value = value;
+
+        unsafe {
+            match signature_type {
+                'Z' => Some(JavaValue::Boolean(*value.z() != 0)),
+                'B' => Some(JavaValue::Byte(*value.b() as i8)),
+                'C' => Some(JavaValue::Char(*value.c())),
+                'S' => Some(JavaValue::Short(*value.s())),
+                'I' => Some(JavaValue::Int(*value.i())),
+                'J' => Some(JavaValue::Long(*value.j())),
+                'F' => Some(JavaValue::Float(*value.f())),
+                'D' => Some(JavaValue::Double(*value.d())),
+                'L' | '[' => Some(JavaValue::Object(*value.l())),
+                _ => None
+            }
+        }
+    }
+
+    /// Convert this value into a `jvalue`, to be passed back to the JVM
+    pub fn to_jvalue(&self) -> jvalue {
+        let mut value = jvalue::default();
+
+        unsafe {
+            match self {
+                &JavaValue::Boolean(v) => *value.z() = v as u8,
+                &JavaValue::Byte(v) => *value.b() = v as jbyte,
+                &JavaValue::Char(v) => *value.c() = v,
+                &JavaValue::Short(v) => *value.s() = v,
+                &JavaValue::Int(v) => *value.i() = v,
+                &JavaValue::Long(v) => *value.j() = v,
+                &JavaValue::Float(v) => *value.f() = v,
+                &JavaValue::Double(v) => *value.d() = v,
+                &JavaValue::Object(v) => *value.l() = v
+            }
+        }
+
+        value
+    }
+}
@@ -5,8 +5,7 @@ mod tests {
 
     use jvmti::agent::Agent;
     use jvmti::emulator::JVMEmulator;
-    use jvmti::runtime::MethodInvocationEvent;
-    use jvmti::thread::Thread;
+    use jvmti::runtime::{MethodInvocationEvent, MonitorContendedEnterEvent, MonitorWaitEvent, MonitorWaitedEvent};
     use jvmti::version::VersionNumber;
 
     #[test]
@@ -56,23 +55,18 @@ mod tests {
         let emulator = JVMEmulator::new();
         let mut agent = Agent::new_from(Box::new(emulator));
 
-        agent.on_method_entry(Some(test_on_method_entry));
+        let subscription = agent.on_method_entry(Box::new(test_on_method_entry));
         assert_eq!(true, agent.capabilities.can_generate_method_entry_events);
-        agent.on_method_entry(None);
-        assert_eq!(false, agent.capabilities.can_generate_method_entry_events);
+        assert_eq!(true, subscription.unsubscribe());
+        assert_eq!(false, subscription.unsubscribe());
 
         assert_eq!(false, agent.capabilities.can_generate_monitor_events);
-        agent.on_monitor_wait(Some(test_on_monitor_events));
-        assert_eq!(true, agent.capabilities.can_generate_monitor_events);
-        agent.on_monitor_waited(Some(test_on_monitor_events));
-        assert_eq!(true, agent.capabilities.can_generate_monitor_events);
-        agent.on_monitor_contended_enter(Some(test_on_monitor_events));
+        agent.on_monitor_wait(Box::new(test_on_monitor_wait));
         assert_eq!(true, agent.capabilities.can_generate_monitor_events);
-        agent.on_monitor_wait(None);
+        agent.on_monitor_waited(Box::new(test_on_monitor_waited));
         assert_eq!(true, agent.capabilities.can_generate_monitor_events);
-        agent.on_monitor_waited(None);
+        agent.on_monitor_contended_enter(Box::new(test_on_monitor_contended_enter));
         assert_eq!(true, agent.capabilities.can_generate_monitor_events);
-        agent.on_monitor_contended_enter(None);
     }
 
     #[allow(unused_variables)]
@@ -81,7 +75,17 @@ mod tests {
     }
 
     #[allow(unused_variables)]
-    fn test_on_monitor_events(thread: Thread) {
+    fn test_on_monitor_wait(event: MonitorWaitEvent) {
+        // this is a callback method for testing purposes
+    }
+
+    #[allow(unused_variables)]
+    fn test_on_monitor_waited(event: MonitorWaitedEvent) {
+        // this is a callback method for testing purposes
+    }
+
+    #[allow(unused_variables)]
+    fn test_on_monitor_contended_enter(event: MonitorContendedEnterEvent) {
         // this is a callback method for testing purposes
     }
 }
@@ -3,9 +3,18 @@ extern crate jvmti;
 #[cfg(test)]
 mod tests {
 
+    use flate2::Compression;
+    use flate2::write::DeflateEncoder;
     use jvmti::bytecode::*;
+    use jvmti::bytecode::code::*;
+    use jvmti::bytecode::frames::*;
+    use jvmti::bytecode::jar::*;
+    use jvmti::bytecode::source::*;
+    use jvmti::bytecode::verify::*;
     use std::fs::File;
-    use std::io::{ Cursor, Read, Write, Error };
+    use std::io::{ Cursor, Read, Write };
+    use jvmti::instrumentation::asm::transformer::Transformer;
+    use jvmti::instrumentation::probe::*;
 
     #[test]
     fn test_read_simple() {
@@ -107,7 +116,7 @@ mod tests {
         }
 //        assert!(false, format!("{:?}", target));
         {
-            let read_result: Result<Classfile, Error> = ClassReader::read_class(&mut Cursor::new(&mut target));
+            let read_result: Result<Classfile, ClassReadError> = ClassReader::read_class(&mut Cursor::new(&mut target));
 
             assert!(read_result.is_ok(), format!("{:?}", read_result.err()));
 
@@ -129,6 +138,782 @@ mod tests {
         assert!(true, format!("{:?}", target));
     }
 
+    #[test]
+    fn test_read_write_modern_constants() {
+        let class: Classfile = Classfile {
+            constant_pool: ConstantPool::new(vec![
+                Constant::Placeholder,
+                Constant::Utf8("java.base".to_string().into_bytes()),
+                Constant::Module(ConstantPoolIndex::new(1)),
+                Constant::Utf8("java/lang".to_string().into_bytes()),
+                Constant::Package(ConstantPoolIndex::new(3)),
+                Constant::Dynamic { bootstrap_method_attr_index: ConstantPoolIndex::new(0), name_and_type_index: ConstantPoolIndex::new(6) },
+                Constant::NameAndType { name_index: ConstantPoolIndex::new(1), descriptor_index: ConstantPoolIndex::new(3) },
+                Constant::Integer(42)
+            ]),
+            ..Default::default()
+        };
+
+        let mut target: Vec<u8> = vec![];
+        {
+            let mut writer: ClassWriter = ClassWriter::new(&mut target);
+            assert!(writer.write_class(&class).is_ok());
+        }
+
+        let read_result: Result<Classfile, ClassReadError> = ClassReader::read_class(&mut Cursor::new(&mut target));
+        assert!(read_result.is_ok(), format!("{:?}", read_result.err()));
+
+        let read_class = read_result.ok().unwrap();
+        assert_eq!(class.constant_pool.constants, read_class.constant_pool.constants);
+    }
+
+    #[test]
+    fn test_read_write_modern_attributes() {
+        let utf8 = |s: &str| Constant::Utf8(s.to_string().into_bytes());
+
+        let class: Classfile = Classfile {
+            constant_pool: ConstantPool::new(vec![
+                Constant::Placeholder,
+                utf8("Module"),
+                utf8("ModulePackages"),
+                utf8("ModuleMainClass"),
+                utf8("NestHost"),
+                utf8("NestMembers"),
+                utf8("Record"),
+                utf8("PermittedSubclasses"),
+                utf8("SourceDebugExtension"),
+                utf8("Signature"),
+                utf8("demo"),
+                Constant::Module(ConstantPoolIndex::new(10)),
+                Constant::Package(ConstantPoolIndex::new(10)),
+                Constant::Class(ConstantPoolIndex::new(10))
+            ]),
+            attributes: vec![
+                Attribute::Module {
+                    name_index: ConstantPoolIndex::new(11),
+                    flags: AccessFlags::of(0x0020),
+                    version_index: ConstantPoolIndex::new(0),
+                    requires: vec![ ModuleRequires { requires_index: ConstantPoolIndex::new(11), requires_flags: AccessFlags::of(0x8000), requires_version_index: ConstantPoolIndex::new(0) } ],
+                    exports: vec![ ModuleExports { exports_index: ConstantPoolIndex::new(12), exports_flags: AccessFlags::of(0), exports_to_index: vec![ ConstantPoolIndex::new(11) ] } ],
+                    opens: vec![ ModuleOpens { opens_index: ConstantPoolIndex::new(12), opens_flags: AccessFlags::of(0), opens_to_index: vec![] } ],
+                    uses: vec![ ConstantPoolIndex::new(13) ],
+                    provides: vec![ ModuleProvides { provides_index: ConstantPoolIndex::new(13), provides_with_index: vec![ ConstantPoolIndex::new(13), ConstantPoolIndex::new(13) ] } ]
+                },
+                Attribute::ModulePackages(vec![ ConstantPoolIndex::new(12) ]),
+                Attribute::ModuleMainClass(ConstantPoolIndex::new(13)),
+                Attribute::NestHost(ConstantPoolIndex::new(13)),
+                Attribute::NestMembers(vec![ ConstantPoolIndex::new(13), ConstantPoolIndex::new(13) ]),
+                Attribute::Record(vec![
+                    RecordComponent { name_index: ConstantPoolIndex::new(10), descriptor_index: ConstantPoolIndex::new(10), attributes: vec![ Attribute::Signature(ConstantPoolIndex::new(10)) ] }
+                ]),
+                Attribute::PermittedSubclasses(vec![ ConstantPoolIndex::new(13) ]),
+                Attribute::SourceDebugExtension(vec![ 1, 2, 3 ])
+            ],
+            ..Default::default()
+        };
+
+        let mut target: Vec<u8> = vec![];
+        {
+            let mut writer: ClassWriter = ClassWriter::new(&mut target);
+            assert!(writer.write_class(&class).is_ok());
+        }
+
+        let read_result: Result<Classfile, ClassReadError> = ClassReader::read_class(&mut Cursor::new(&mut target.clone()));
+        assert!(read_result.is_ok(), format!("{:?}", read_result.err()));
+
+        let read_class = read_result.ok().unwrap();
+
+        match read_class.attributes.as_slice() {
+            &[ Attribute::Module { .. }, Attribute::ModulePackages(_), Attribute::ModuleMainClass(_), Attribute::NestHost(_), Attribute::NestMembers(_),
+               Attribute::Record(ref components), Attribute::PermittedSubclasses(_), Attribute::SourceDebugExtension(_) ] => {
+                assert_eq!(1, components.len());
+                assert_eq!(1, components[0].attributes.len());
+            },
+            other => assert!(false, format!("{:?}", other))
+        }
+
+        let mut rewritten: Vec<u8> = vec![];
+        {
+            let mut writer: ClassWriter = ClassWriter::new(&mut rewritten);
+            assert!(writer.write_class(&read_class).is_ok());
+        }
+
+        assert_eq!(target, rewritten);
+    }
+
+    #[test]
+    fn test_read_unknown_constant_fails() {
+        let mut target: Vec<u8> = vec![ 0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 52, 0, 3, 99, 0, 1, 3, 0, 0, 0, 1 ];
+
+        match ClassReader::read_class(&mut Cursor::new(&mut target)) {
+            Err(err) => {
+                assert_eq!(ReadErrorKind::InvalidConstant, err.kind);
+                assert_eq!(10, err.offset);
+                assert_eq!("constant_pool[1]", err.section);
+                assert_eq!(Some(1), err.constant_pool_index);
+            },
+            Ok(_) => assert!(false)
+        }
+    }
+
+    #[test]
+    fn test_read_truncated_class_fails() {
+        let mut target: Vec<u8> = vec![ 0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 52, 0, 2, 1, 0, 10, 65, 65 ];
+
+        match ClassReader::read_class(&mut Cursor::new(&mut target)) {
+            Err(err) => {
+                assert_eq!(ReadErrorKind::UnexpectedEof, err.kind);
+                assert_eq!(13, err.offset);
+                assert_eq!("constant_pool[1]", err.section);
+                assert_eq!("10 bytes", err.expected);
+                assert_eq!("2 bytes", err.found);
+            },
+            Ok(_) => assert!(false)
+        }
+    }
+
+    #[test]
+    fn test_read_invalid_attribute_name_fails() {
+        let class: Classfile = Classfile {
+            constant_pool: ConstantPool::new(vec![ Constant::Placeholder, Constant::Integer(1) ]),
+            attributes: vec![ Attribute::RawAttribute { name_index: ConstantPoolIndex::new(1), info: vec![] } ],
+            ..Default::default()
+        };
+
+        let mut target: Vec<u8> = vec![];
+        {
+            let mut writer: ClassWriter = ClassWriter::new(&mut target);
+            assert!(writer.write_class(&class).is_ok());
+        }
+
+        match ClassReader::read_class(&mut Cursor::new(&mut target)) {
+            Err(err) => {
+                assert_eq!(ReadErrorKind::InvalidIndex, err.kind);
+                assert_eq!("attributes", err.section);
+                assert_eq!(Some(1), err.constant_pool_index);
+            },
+            Ok(_) => assert!(false)
+        }
+    }
+
+    #[test]
+    fn test_read_invalid_opcode_fails() {
+        let class: Classfile = Classfile {
+            constant_pool: ConstantPool::new(vec![ Constant::Placeholder, Constant::Utf8("Code".to_string().into_bytes()) ]),
+            methods: vec![
+                Method { access_flags: AccessFlags::of(0), name_index: ConstantPoolIndex::new(1), descriptor_index: ConstantPoolIndex::new(1), attributes: vec![
+                    Attribute::Code { max_stack: 0, max_locals: 0, code: vec![ Instruction::NOP, Instruction::WTF(0xff) ], exception_table: vec![], attributes: vec![] }
+                ]}
+            ],
+            ..Default::default()
+        };
+
+        let mut target: Vec<u8> = vec![];
+        {
+            let mut writer: ClassWriter = ClassWriter::new(&mut target);
+            assert!(writer.write_class(&class).is_ok());
+        }
+
+        match ClassReader::read_class(&mut Cursor::new(&mut target)) {
+            Err(err) => {
+                assert_eq!(ReadErrorKind::InvalidOpcode, err.kind);
+                assert_eq!("methods[0].Code.code[1]", err.section);
+                assert_eq!("0xff", err.found);
+            },
+            Ok(_) => assert!(false)
+        }
+    }
+
+    #[test]
+    fn test_verify_round_trip() {
+        let class: Classfile = Classfile {
+            constant_pool: ConstantPool::new(vec![ Constant::Placeholder, Constant::Utf8("Code".to_string().into_bytes()), Constant::Utf8("Code".to_string().into_bytes()) ]),
+            methods: vec![
+                Method { access_flags: AccessFlags::of(0), name_index: ConstantPoolIndex::new(1), descriptor_index: ConstantPoolIndex::new(1), attributes: vec![
+                    Attribute::Code { max_stack: 1, max_locals: 1, code: vec![ Instruction::ICONST_0, Instruction::IRETURN ], exception_table: vec![], attributes: vec![] }
+                ]}
+            ],
+            ..Default::default()
+        };
+
+        let mut target: Vec<u8> = vec![];
+        {
+            let mut writer: ClassWriter = ClassWriter::new(&mut target);
+            assert!(writer.write_class(&class).is_ok());
+        }
+
+        assert!(verify_round_trip(&target).is_ok());
+
+        // Point the Code attribute at the second, duplicate "Code" constant. The writer always
+        // picks the first one, so the rewritten class differs in the attribute name index
+        assert_eq!(1, target[45]);
+        target[45] = 2;
+
+        match verify_round_trip(&target) {
+            Err(RoundTripError::Mismatch(mismatch)) => {
+                assert_eq!(45, mismatch.offset);
+                assert_eq!(Some(2), mismatch.original);
+                assert_eq!(Some(1), mismatch.rewritten);
+                assert_eq!("methods[0] (Code) Code header", mismatch.location);
+            },
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn test_verify_locates_instruction() {
+        let class: Classfile = Classfile {
+            constant_pool: ConstantPool::new(vec![ Constant::Placeholder, Constant::Utf8("Code".to_string().into_bytes()) ]),
+            methods: vec![
+                Method { access_flags: AccessFlags::of(0), name_index: ConstantPoolIndex::new(1), descriptor_index: ConstantPoolIndex::new(1), attributes: vec![
+                    Attribute::Code { max_stack: 1, max_locals: 1, code: vec![ Instruction::NOP, Instruction::BIPUSH(7), Instruction::IRETURN ], exception_table: vec![], attributes: vec![] }
+                ]}
+            ],
+            ..Default::default()
+        };
+
+        // header 10 + constant 7 + class header 8 + counts 4 + method header 6 + attribute count 2 + Code header 14
+        assert_eq!("methods[0] (Code) Code instruction #1 (pc 1) BIPUSH(7)", locate(&class, 53));
+        assert_eq!("constant #1 Utf8([67, 111, 100, 101])", locate(&class, 12));
+        assert_eq!("end of class", locate(&class, 1000));
+    }
+
+    /// A method with a tableswitch, a backward goto, an exception handler, line numbers, a local
+    /// variable and stack map frames
+    fn switch_code() -> Attribute {
+        Attribute::Code {
+            max_stack: 1,
+            max_locals: 1,
+            code: vec![
+                /* 0 */ Instruction::ILOAD_0,
+                /* 1 */ Instruction::TABLESWITCH(27, 0, 1, vec![ 23, 25 ]),
+                /* 24 */ Instruction::ICONST_1,
+                /* 25 */ Instruction::IRETURN,
+                /* 26 */ Instruction::ICONST_2,
+                /* 27 */ Instruction::IRETURN,
+                /* 28 */ Instruction::ICONST_0,
+                /* 29 */ Instruction::GOTO(-4)
+            ],
+            exception_table: vec![ ExceptionHandler { start_pc: 24, end_pc: 28, handler_pc: 28, catch_type: ConstantPoolIndex::new(0) } ],
+            attributes: vec![
+                Attribute::LineNumberTable(vec![ LineNumberTable { start_pc: 0, line_number: 10 }, LineNumberTable { start_pc: 24, line_number: 11 }, LineNumberTable { start_pc: 28, line_number: 12 } ]),
+                Attribute::LocalVariableTable(vec![ LocalVariableTable { start_pc: 0, length: 32, name_index: ConstantPoolIndex::new(1), descriptor_index: ConstantPoolIndex::new(1), index: 0 } ]),
+                Attribute::StackMapTable(vec![ StackMapFrame::SameFrame { tag: 24 }, StackMapFrame::SameFrame { tag: 1 }, StackMapFrame::SameFrame { tag: 1 } ])
+            ]
+        }
+    }
+
+    fn write_attribute(attribute: &Attribute) -> Vec<u8> {
+        let cp = ConstantPool::new(vec![ Constant::Placeholder ]);
+        let mut target: Vec<u8> = vec![];
+        {
+            let mut writer: ClassWriter = ClassWriter::new(&mut target);
+            assert!(writer.write_attribute(attribute, &cp).is_ok());
+        }
+        target
+    }
+
+    #[test]
+    fn test_code_body_round_trip() {
+        let code = switch_code();
+        let lowered = CodeBody::from_code(&code).and_then(|body| body.lower()).unwrap();
+
+        assert_eq!(write_attribute(&code), write_attribute(&lowered));
+    }
+
+    #[test]
+    fn test_code_body_insert_fixes_offsets() {
+        let mut body = CodeBody::from_code(&switch_code()).unwrap();
+        body.insert(0, vec![ CodeItem::Instruction(Instruction::NOP), CodeItem::Instruction(Instruction::NOP), CodeItem::Instruction(Instruction::NOP) ]);
+
+        match body.lower().unwrap() {
+            Attribute::Code { code, exception_table, attributes, .. } => {
+                // The switch moves to offset 4 and needs 3 bytes of padding instead of 2
+                assert_eq!("TABLESWITCH(28, 0, 1, [24, 26])", format!("{:?}", code[4]));
+                assert_eq!("GOTO(-4)", format!("{:?}", code[10]));
+                assert_eq!((28, 32, 32), (exception_table[0].start_pc, exception_table[0].end_pc, exception_table[0].handler_pc));
+
+                match (&attributes[0], &attributes[1], &attributes[2]) {
+                    (&Attribute::LineNumberTable(ref lines), &Attribute::LocalVariableTable(ref variables), &Attribute::StackMapTable(ref frames)) => {
+                        assert_eq!(vec![ (3, 10), (28, 11), (32, 12) ], lines.iter().map(|line| (line.start_pc, line.line_number)).collect::<Vec<(u16, u16)>>());
+                        assert_eq!((3, 33), (variables[0].start_pc, variables[0].length));
+                        assert_eq!("[SameFrame { tag: 28 }, SameFrame { tag: 1 }, SameFrame { tag: 1 }]", format!("{:?}", frames));
+                    },
+                    _ => assert!(false)
+                }
+            },
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn test_code_body_promotes_wide_jumps() {
+        let mut body = CodeBody::new(1, 0);
+        let (loop_start, exit) = (body.new_label(), body.new_label());
+
+        body.push(CodeItem::Label(loop_start));
+        body.push(CodeItem::Instruction(Instruction::ICONST_0));
+        body.push(CodeItem::Jump(Jump::IFEQ, exit));
+        (0..40000).for_each(|_| body.push(CodeItem::Instruction(Instruction::NOP)));
+        body.push(CodeItem::Jump(Jump::GOTO, loop_start));
+        body.push(CodeItem::Label(exit));
+        body.push(CodeItem::Instruction(Instruction::RETURN));
+
+        match body.lower().unwrap() {
+            Attribute::Code { code, .. } => {
+                // The conditional is inverted to skip over a goto_w to the original target
+                assert_eq!("IFNE(8)", format!("{:?}", code[1]));
+                assert_eq!("GOTO_W(40010)", format!("{:?}", code[2]));
+                assert_eq!("GOTO_W(-40009)", format!("{:?}", code[40003]));
+                assert_eq!("RETURN", format!("{:?}", code[40004]));
+            },
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn test_code_body_rejects_invalid_targets() {
+        let code = Attribute::Code { max_stack: 0, max_locals: 0, code: vec![ Instruction::GOTO(1), Instruction::NOP ], exception_table: vec![], attributes: vec![] };
+        assert_eq!(CodeError::InvalidTarget { pc: 0, target: 1 }, CodeBody::from_code(&code).unwrap_err());
+
+        let mut body = CodeBody::new(0, 0);
+        let nowhere = body.new_label();
+        body.push(CodeItem::Jump(Jump::GOTO, nowhere));
+        assert_eq!(CodeError::UnplacedLabel(nowhere), body.lower().unwrap_err());
+    }
+
+    #[test]
+    fn test_transformer_edit_code() {
+        let mut class: Classfile = Classfile {
+            constant_pool: ConstantPool::new(vec![
+                Constant::Placeholder,
+                Constant::Utf8("Code".to_string().into_bytes()),
+                Constant::Utf8("(I)I".to_string().into_bytes()),
+                Constant::Utf8("Test".to_string().into_bytes()),
+                Constant::Class(ConstantPoolIndex::new(3))
+            ]),
+            this_class: ConstantPoolIndex::new(4),
+            methods: vec![
+                Method { access_flags: AccessFlags::of(MethodAccessFlags::Static as u16), name_index: ConstantPoolIndex::new(1), descriptor_index: ConstantPoolIndex::new(2), attributes: vec![ switch_code() ] }
+            ],
+            ..Default::default()
+        };
+
+        {
+            let mut transformer = Transformer::new(&mut class, &DefaultHierarchy);
+            let result = transformer.edit_code(0, |body, cp| {
+                let idx = cp.add_constant(Constant::Integer(42));
+                body.insert(0, vec![ CodeItem::Instruction(Instruction::LDC(idx.idx as u8)), CodeItem::Instruction(Instruction::POP) ]);
+                // The handler of the fixture leaves a value on the stack the code it jumps to doesn't expect
+                body.try_catch_blocks.clear();
+            });
+
+            assert!(result.is_ok());
+        }
+
+        // The tables carried over by the code need their names in the constant pool
+        assert!(class.constant_pool.find_ut8_index("LineNumberTable").is_some());
+        assert!(class.constant_pool.find_ut8_index("StackMapTable").is_some());
+
+        match class.methods[0].attributes[0] {
+            Attribute::Code { max_stack, max_locals, ref code, ref attributes, .. } => {
+                assert_eq!((1, 1), (max_stack, max_locals));
+                assert_eq!("LDC(5)", format!("{:?}", code[0]));
+                assert_eq!("TABLESWITCH(28, 0, 1, [24, 26])", format!("{:?}", code[3]));
+
+                // The frames are recomputed, adding the one the fixture misses at the target of its goto
+                assert_eq!("StackMapTable([SameFrame { tag: 28 }, SameLocals1StackItemFrame { tag: 64, stack: Integer }, SameFrame { tag: 0 }, SameFrame { tag: 1 }])", format!("{:?}", attributes[2]));
+            },
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn test_inject_probes() {
+        let code = Attribute::Code {
+            max_stack: 1,
+            max_locals: 1,
+            code: vec![
+                /* 0 */ Instruction::ILOAD_0,
+                /* 1 */ Instruction::IFEQ(5),
+                /* 4 */ Instruction::ICONST_1,
+                /* 5 */ Instruction::IRETURN,
+                /* 6 */ Instruction::ICONST_0,
+                /* 7 */ Instruction::IRETURN
+            ],
+            exception_table: vec![],
+            attributes: vec![]
+        };
+
+        let mut class: Classfile = Classfile {
+            constant_pool: ConstantPool::new(vec![
+                Constant::Placeholder,
+                Constant::Utf8("Code".to_string().into_bytes()),
+                Constant::Utf8("(I)I".to_string().into_bytes()),
+                Constant::Utf8("com/example/Test".to_string().into_bytes()),
+                Constant::Class(ConstantPoolIndex::new(3)),
+                Constant::Utf8("test".to_string().into_bytes()),
+                Constant::Utf8("<init>".to_string().into_bytes())
+            ]),
+            this_class: ConstantPoolIndex::new(4),
+            methods: vec![
+                Method { access_flags: AccessFlags::of(MethodAccessFlags::Static as u16), name_index: ConstantPoolIndex::new(5), descriptor_index: ConstantPoolIndex::new(2), attributes: vec![ code.clone() ] },
+                Method { access_flags: AccessFlags::of(MethodAccessFlags::Static as u16), name_index: ConstantPoolIndex::new(6), descriptor_index: ConstantPoolIndex::new(2), attributes: vec![ code.clone() ] }
+            ],
+            ..Default::default()
+        };
+
+        let filter = ProbeFilter::new(&vec![], &vec![ String::from("com.example.*") ]);
+        assert_eq!(1, inject_probes(&mut class, &filter, &DefaultHierarchy));
+
+        let probe_name = |class: &Classfile, idx: u16| match class.constant_pool.constants[idx as usize] {
+            Constant::MethodRef { ref name_and_type_index, .. } => match class.constant_pool.constants[name_and_type_index.idx] {
+                Constant::NameAndType { ref name_index, .. } => class.constant_pool.get_utf8_string(name_index.idx as u16),
+                _ => None
+            },
+            _ => None
+        };
+
+        match class.methods[0].attributes[0] {
+            Attribute::Code { max_stack, ref code, ref exception_table, .. } => {
+                assert_eq!(2, max_stack);
+                assert_eq!(15, code.len());
+
+                // Entry probe, an exit probe before both returns and one in the catch-all handler
+                let probes: Vec<(usize, Option<String>)> = code.iter().enumerate().filter_map(|(idx, instruction)| match instruction {
+                    &Instruction::INVOKESTATIC(method_idx) => Some((idx, probe_name(&class, method_idx))),
+                    _ => None
+                }).collect();
+
+                assert_eq!(vec![ (1, Some(String::from("enter"))), (6, Some(String::from("exit"))), (10, Some(String::from("exit"))), (13, Some(String::from("exit"))) ], probes);
+                assert_eq!("ATHROW", format!("{:?}", code[14]));
+
+                match code[0] {
+                    Instruction::LDC_W(idx) => match class.constant_pool.constants[idx as usize] {
+                        Constant::Integer(id) => assert_eq!(Some(String::from("com.example.Test.test(I)I")), probed_method(id)),
+                        _ => assert!(false)
+                    },
+                    _ => assert!(false)
+                }
+
+                // The catch-all handler covers the original code up to each return, leaving out the exit probes
+                let ranges: Vec<(u16, u16, u16, usize)> = exception_table.iter().map(|handler| (handler.start_pc, handler.end_pc, handler.handler_pc, handler.catch_type.idx)).collect();
+                assert_eq!(vec![ (6, 11, 26, 0), (18, 19, 26, 0) ], ranges);
+            },
+            _ => assert!(false)
+        }
+
+        // Constructors are left alone
+        match class.methods[1].attributes[0] {
+            Attribute::Code { ref code, .. } => assert_eq!(6, code.len()),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn test_register_method_keeps_identifiers() {
+        let id = register_method(String::from("com.example.Registered.run()V"));
+        let other = register_method(String::from("com.example.Registered.stop()V"));
+
+        assert!(id != other);
+        assert_eq!(id, register_method(String::from("com.example.Registered.run()V")));
+        assert_eq!(Some(String::from("com.example.Registered.stop()V")), probed_method(other));
+    }
+
+    #[test]
+    fn test_probe_filter() {
+        let filter = ProbeFilter::new(&vec![ String::from(".Hello.main"), String::from("java.io.OutputStream.flush"), String::from("com/example/Foo.get*") ],
+                                      &vec![ String::from("java/util/Queue") ]);
+
+        assert!(filter.matches_method("Hello", "main"));
+        assert!(!filter.matches_method("Hello", "toString"));
+        assert!(filter.matches_method("java/io/OutputStream", "flush"));
+        assert!(filter.matches_method("com/example/Foo", "getName"));
+        assert!(!filter.matches_method("com/example/Foo", "setName"));
+        assert!(filter.matches_method("java.util.Queue", "offer"));
+        assert!(filter.matches_class("com/example/Foo"));
+        assert!(!filter.matches_class("com/example/Bar"));
+        assert!(!ProbeFilter::new(&vec![], &vec![ String::from("*") ]).matches_class(PROBE_CLASS));
+    }
+
+    /// Frame computation context of a static method of the `Test` class
+    fn static_context(descriptor: &str) -> MethodContext {
+        MethodContext { class_name: String::from("Test"), method_name: String::from("test"), descriptor: descriptor.to_string(), is_static: true }
+    }
+
+    #[test]
+    fn test_compute_frames_in_loop() {
+        let mut body = CodeBody::new(0, 0);
+        let (head, exit) = (body.new_label(), body.new_label());
+
+        body.items = vec![
+            CodeItem::Instruction(Instruction::ICONST_0),
+            CodeItem::Instruction(Instruction::ISTORE_1),
+            CodeItem::Label(head),
+            CodeItem::Instruction(Instruction::ILOAD_1),
+            CodeItem::Instruction(Instruction::ILOAD_0),
+            CodeItem::Jump(Jump::IF_ICMPGE, exit),
+            CodeItem::Instruction(Instruction::IINC(1, 1)),
+            CodeItem::Jump(Jump::GOTO, head),
+            CodeItem::Label(exit),
+            CodeItem::Instruction(Instruction::ILOAD_1),
+            CodeItem::Instruction(Instruction::IRETURN)
+        ];
+
+        let mut cp = ConstantPool::new(vec![ Constant::Placeholder ]);
+        assert!(compute_frames(&mut body, &static_context("(I)I"), &mut cp, &DefaultHierarchy).is_ok());
+
+        assert_eq!((2, 2), (body.max_stack, body.max_locals));
+        assert_eq!(2, body.frames.len());
+        assert_eq!((head, "Append([Integer])"), (body.frames[0].label, format!("{:?}", body.frames[0].kind).as_str()));
+        assert_eq!((exit, "Same"), (body.frames[1].label, format!("{:?}", body.frames[1].kind).as_str()));
+    }
+
+    #[test]
+    fn test_compute_frames_merges_references() {
+        struct Hierarchy;
+        struct Unresolved;
+
+        impl ClassHierarchy for Hierarchy {
+            fn common_superclass(&self, a: &str, b: &str) -> Option<String> {
+                common_superclass_by(a, b, |name| match name {
+                    "A" | "D" => Some(ClassInfo { super_name: Some(String::from("B")), is_interface: false }),
+                    "B" => Some(ClassInfo { super_name: Some(String::from("java/lang/Object")), is_interface: false }),
+                    "I" => Some(ClassInfo { super_name: Some(String::from("java/lang/Object")), is_interface: true }),
+                    _ => None
+                })
+            }
+        }
+
+        impl ClassHierarchy for Unresolved {
+            fn common_superclass(&self, a: &str, b: &str) -> Option<String> {
+                common_superclass_by(a, b, |_| None)
+            }
+        }
+
+        let utf8 = |value: &str| Constant::Utf8(value.to_string().into_bytes());
+        let mut cp = ConstantPool::new(vec![
+            Constant::Placeholder,
+            utf8("Test"), Constant::Class(ConstantPoolIndex::new(1)),
+            utf8("a"), utf8("LA;"), Constant::NameAndType { name_index: ConstantPoolIndex::new(3), descriptor_index: ConstantPoolIndex::new(4) },
+            Constant::FieldRef { class_index: ConstantPoolIndex::new(2), name_and_type_index: ConstantPoolIndex::new(5) },
+            utf8("d"), utf8("LD;"), Constant::NameAndType { name_index: ConstantPoolIndex::new(7), descriptor_index: ConstantPoolIndex::new(8) },
+            Constant::FieldRef { class_index: ConstantPoolIndex::new(2), name_and_type_index: ConstantPoolIndex::new(9) }
+        ]);
+
+        let mut body = CodeBody::new(0, 0);
+        let (other, join) = (body.new_label(), body.new_label());
+
+        body.items = vec![
+            CodeItem::Instruction(Instruction::ILOAD_0),
+            CodeItem::Jump(Jump::IFEQ, other),
+            CodeItem::Instruction(Instruction::GETSTATIC(6)),
+            CodeItem::Jump(Jump::GOTO, join),
+            CodeItem::Label(other),
+            CodeItem::Instruction(Instruction::GETSTATIC(10)),
+            CodeItem::Label(join),
+            CodeItem::Instruction(Instruction::ARETURN)
+        ];
+
+        // Classes that can't be resolved are not assumed to merge to java/lang/Object
+        assert!(match compute_frames(&mut body.clone(), &static_context("(I)LB;"), &mut cp.clone(), &Unresolved) {
+            Err(FrameError::UnresolvedMerge(_, _)) => true,
+            _ => false
+        });
+
+        assert!(compute_frames(&mut body, &static_context("(I)LB;"), &mut cp, &Hierarchy).is_ok());
+
+        // The merged type is added to the constant pool
+        assert_eq!(Some(&Constant::Class(ConstantPoolIndex::new(11))), cp.constants.get(12));
+        assert_eq!("SameLocals1StackItem(Object(ConstantPoolIndex { idx: 12 }))", format!("{:?}", body.frames[1].kind));
+
+        assert_eq!(Some(String::from("B")), Hierarchy.common_superclass("A", "D"));
+        assert_eq!(Some(String::from("java/lang/Object")), Hierarchy.common_superclass("A", "I"));
+        assert_eq!(None, Hierarchy.common_superclass("A", "Unknown"));
+    }
+
+    #[test]
+    fn test_compute_frames_removes_dead_code() {
+        let mut body = CodeBody::new(0, 0);
+        let (start, end, handler) = (body.new_label(), body.new_label(), body.new_label());
+
+        body.items = vec![
+            CodeItem::Instruction(Instruction::ICONST_1),
+            CodeItem::Instruction(Instruction::IRETURN),
+            CodeItem::Label(start),
+            CodeItem::Instruction(Instruction::ICONST_2),
+            CodeItem::Instruction(Instruction::IRETURN),
+            CodeItem::Label(end),
+            CodeItem::Label(handler),
+            CodeItem::Instruction(Instruction::ATHROW)
+        ];
+        body.try_catch_blocks.push(TryCatchBlock { start: start, end: end, handler: handler, catch_type: ConstantPoolIndex::new(0) });
+
+        let mut cp = ConstantPool::new(vec![ Constant::Placeholder ]);
+        assert!(compute_frames(&mut body, &static_context("()I"), &mut cp, &DefaultHierarchy).is_ok());
+
+        assert_eq!(5, body.items.len());
+        assert!(body.try_catch_blocks.is_empty());
+        assert!(body.frames.is_empty());
+    }
+
+    #[test]
+    fn test_compute_maxs_with_subroutines() {
+        let mut body = CodeBody::new(0, 0);
+        let subroutine = body.new_label();
+
+        body.items = vec![
+            CodeItem::Jump(Jump::JSR, subroutine),
+            CodeItem::Instruction(Instruction::LCONST_1),
+            CodeItem::Instruction(Instruction::LRETURN),
+            CodeItem::Label(subroutine),
+            CodeItem::Instruction(Instruction::ASTORE_2),
+            CodeItem::Instruction(Instruction::RET(2))
+        ];
+
+        let mut cp = ConstantPool::new(vec![ Constant::Placeholder ]);
+        assert_eq!(Err(FrameError::Subroutine(0)), compute_frames(&mut body.clone(), &static_context("(J)J"), &mut cp, &DefaultHierarchy));
+
+        assert!(compute_maxs(&mut body, &static_context("(J)J"), &cp).is_ok());
+        assert_eq!((2, 3), (body.max_stack, body.max_locals));
+    }
+
+    /// Build a minimal zip archive, deflating the entries flagged so
+    fn build_jar(entries: Vec<(&str, Vec<u8>, bool)>) -> Vec<u8> {
+        fn u16le(out: &mut Vec<u8>, value: usize) { out.extend_from_slice(&[ value as u8, (value >> 8) as u8 ]); }
+        fn u32le(out: &mut Vec<u8>, value: usize) { u16le(out, value & 0xFFFF); u16le(out, value >> 16); }
+
+        let mut out: Vec<u8> = vec![];
+        let mut central: Vec<u8> = vec![];
+
+        for &(name, ref content, deflate) in entries.iter() {
+            let data = if deflate {
+                let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+                encoder.write_all(content).unwrap();
+                encoder.finish().unwrap()
+            } else {
+                content.clone()
+            };
+
+            let offset = out.len();
+            let method = if deflate { 8 } else { 0 };
+
+            u32le(&mut out, 0x04034b50); u16le(&mut out, 20); u16le(&mut out, 0); u16le(&mut out, method);
+            u32le(&mut out, 0); u32le(&mut out, 0);
+            u32le(&mut out, data.len()); u32le(&mut out, content.len());
+            u16le(&mut out, name.len()); u16le(&mut out, 0);
+            out.extend_from_slice(name.as_bytes());
+            out.extend_from_slice(&data);
+
+            u32le(&mut central, 0x02014b50); u16le(&mut central, 20); u16le(&mut central, 20); u16le(&mut central, 0); u16le(&mut central, method);
+            u32le(&mut central, 0); u32le(&mut central, 0);
+            u32le(&mut central, data.len()); u32le(&mut central, content.len());
+            u16le(&mut central, name.len()); u16le(&mut central, 0); u16le(&mut central, 0);
+            u16le(&mut central, 0); u16le(&mut central, 0); u32le(&mut central, 0);
+            u32le(&mut central, offset);
+            central.extend_from_slice(name.as_bytes());
+        }
+
+        let central_offset = out.len();
+        out.extend_from_slice(&central);
+
+        u32le(&mut out, 0x06054b50); u16le(&mut out, 0); u16le(&mut out, 0);
+        u16le(&mut out, entries.len()); u16le(&mut out, entries.len());
+        u32le(&mut out, central.len()); u32le(&mut out, central_offset);
+        u16le(&mut out, 0);
+
+        out
+    }
+
+    fn class_bytes(method_count: usize) -> Vec<u8> {
+        let class: Classfile = Classfile {
+            constant_pool: ConstantPool::new(vec![ Constant::Placeholder, Constant::Utf8("m".to_string().into_bytes()) ]),
+            methods: (0..method_count).map(|_| Method { access_flags: AccessFlags::of(0), name_index: ConstantPoolIndex::new(1), descriptor_index: ConstantPoolIndex::new(1), attributes: vec![] }).collect(),
+            ..Default::default()
+        };
+
+        let mut target: Vec<u8> = vec![];
+        {
+            let mut writer: ClassWriter = ClassWriter::new(&mut target);
+            assert!(writer.write_class(&class).is_ok());
+        }
+        target
+    }
+
+    #[test]
+    fn test_jar_entry_with_wrong_size_fails() {
+        let mut jar = build_jar(vec![ ("com/example/Deflated.class", class_bytes(2), true) ]);
+
+        // Claim an uncompressed size of almost 4GB in the central directory
+        let central = (0..jar.len() - 4).find(|&idx| jar[idx..idx + 4] == [ 0x50, 0x4b, 0x01, 0x02 ]).unwrap();
+        jar[central + 24..central + 28].copy_from_slice(&[ 0xfe, 0xff, 0xff, 0xff ]);
+
+        let archive = JarArchive::from_bytes(jar).unwrap();
+        let entry = archive.find_entry("com/example/Deflated.class").unwrap().clone();
+
+        assert!(archive.read_entry(&entry).is_err());
+    }
+
+    #[test]
+    fn test_class_source_reads_jar_entries() {
+        let jar = build_jar(vec![
+            ("META-INF/", vec![], false),
+            ("com/example/Stored.class", class_bytes(1), false),
+            ("com/example/Deflated.class", class_bytes(2), true),
+            ("com/example/readme.txt", b"not a class".to_vec(), true)
+        ]);
+
+        let source = ClassSource::from_archive(JarArchive::from_bytes(jar).unwrap(), "test.jar", None).unwrap();
+
+        assert_eq!(2, source.entries().len());
+        assert_eq!("test.jar!/com/example/Deflated.class", source.find("com/example/Deflated").unwrap().location);
+
+        let methods: Vec<usize> = source.classes().map(|(_, class)| class.unwrap().methods.len()).collect();
+        assert_eq!(vec![ 1, 2 ], methods);
+    }
+
+    #[test]
+    fn test_class_source_resolves_multi_release_overlays() {
+        let build = |multi_release: bool| build_jar(vec![
+            ("META-INF/MANIFEST.MF", format!("Manifest-Version: 1.0\r\nMulti-Release: {}\r\n", multi_release).into_bytes(), true),
+            ("com/example/A.class", class_bytes(1), true),
+            ("META-INF/versions/9/com/example/A.class", class_bytes(9), true),
+            ("META-INF/versions/11/com/example/A.class", class_bytes(11), true),
+            ("META-INF/versions/11/com/example/B.class", class_bytes(0), true)
+        ]);
+
+        let resolve = |release: Option<u32>| {
+            let source = ClassSource::from_archive(JarArchive::from_bytes(build(true)).unwrap(), "mr.jar", release).unwrap();
+            let entry = source.find("com/example/A").unwrap();
+
+            (entry.release, source.read_class(entry).unwrap().methods.len(), source.find("com/example/B").is_some())
+        };
+
+        assert_eq!((Some(11), 11, true), resolve(None));
+        assert_eq!((Some(9), 9, false), resolve(Some(10)));
+        assert_eq!((None, 1, false), resolve(Some(8)));
+
+        // Without the manifest attribute versioned entries are ordinary entries
+        let plain = ClassSource::from_archive(JarArchive::from_bytes(build(false)).unwrap(), "plain.jar", None).unwrap();
+        assert_eq!(4, plain.entries().len());
+        assert_eq!(None, plain.find("com/example/A").unwrap().release);
+    }
+
+    #[test]
+    fn test_class_source_reads_nested_archives() {
+        let inner = build_jar(vec![ ("com/example/Inner.class", class_bytes(1), true) ]);
+        let outer = build_jar(vec![
+            ("BOOT-INF/classes/com/example/Outer.class", class_bytes(1), true),
+            ("BOOT-INF/lib/inner.jar", inner, false)
+        ]);
+
+        let source = ClassSource::from_archive(JarArchive::from_bytes(outer).unwrap(), "app.jar", None).unwrap();
+
+        assert_eq!("BOOT-INF/classes/com/example/Outer.class", source.find("com/example/Outer").unwrap().name);
+        assert_eq!("app.jar!/BOOT-INF/lib/inner.jar!/com/example/Inner.class", source.find("com/example/Inner").unwrap().location);
+        assert!(source.classes().all(|(_, class)| class.is_ok()));
+    }
+
     #[test]
     fn test_cursor_read_usage() {
         let mut cursor = Cursor::new(vec![ 1, 2, 3, 4 as u8 ]);
@@ -40,4 +40,24 @@ mod tests {
         assert_eq!(true, caps_result.can_pop_frame);
         assert_eq!(true, caps_result.can_generate_monitor_events);
     }
+
+    #[test]
+    fn intersect_keeps_flags_enabled_in_both_capabilities() {
+        let mut caps1 = Capabilities::new();
+        let mut caps2 = Capabilities::new();
+
+        caps1.can_tag_objects = true;
+        caps1.can_retransform_classes = true;
+        caps1.can_generate_method_entry_events = true;
+        caps2.can_retransform_classes = true;
+        caps2.can_generate_method_entry_events = true;
+        caps2.can_pop_frame = true;
+
+        let caps_result = caps1.intersect(&caps2);
+
+        assert_eq!(false, caps_result.can_tag_objects);
+        assert_eq!(false, caps_result.can_pop_frame);
+        assert_eq!(true, caps_result.can_retransform_classes);
+        assert_eq!(true, caps_result.can_generate_method_entry_events);
+    }
 }
@@ -0,0 +1,59 @@
+extern crate jvmti;
+
+#[cfg(test)]
+mod tests {
+
+    use jvmti::environment::jvmti::JavaStackFrame;
+    use jvmti::method::MethodId;
+    use jvmti::native::JavaMethod;
+    use jvmti::profile::contention::{ContentionProfiler, ContentionStats};
+
+    fn frame(method: usize, location: i64) -> JavaStackFrame {
+        JavaStackFrame { method: method as JavaMethod, location: location }
+    }
+
+    #[test]
+    fn blocked_time_is_aggregated_by_monitor_class_and_stack() {
+        let mut profiler = ContentionProfiler::new();
+        let stack = vec![ frame(1, 10), frame(2, 20) ];
+
+        profiler.add("java.lang.Object", &stack, 5_000_000);
+        profiler.add("java.lang.Object", &stack, 20_000_000);
+        profiler.add("java.lang.Object", &[ frame(1, 12), frame(2, 20) ], 1_000_000);
+        profiler.add("java.util.HashMap", &stack, 3_000_000);
+
+        assert_eq!(3, profiler.sites().len());
+
+        let stats: Vec<ContentionStats> = profiler.sites().iter().filter(|&(site, _)| site.class_name == "java.lang.Object" && site.frames[0].1 == 10).map(|(_, stats)| *stats).collect();
+        assert_eq!(vec![ ContentionStats { count: 2, total_nanos: 25_000_000, max_nanos: 20_000_000 } ], stats);
+    }
+
+    #[test]
+    fn class_stats_add_up_all_the_stacks() {
+        let mut profiler = ContentionProfiler::new();
+
+        profiler.add("java.lang.Object", &[ frame(1, 10) ], 5_000_000);
+        profiler.add("java.lang.Object", &[ frame(3, 0) ], 8_000_000);
+        profiler.add("java.util.HashMap", &[ frame(1, 10) ], 3_000_000);
+
+        assert_eq!(ContentionStats { count: 2, total_nanos: 13_000_000, max_nanos: 8_000_000 }, profiler.class_stats("java.lang.Object"));
+        assert_eq!(ContentionStats::default(), profiler.class_stats("java.lang.String"));
+
+        profiler.clear();
+        assert!(profiler.sites().is_empty());
+    }
+
+    #[test]
+    fn sites_entered_from_unloaded_methods_are_dropped() {
+        let mut profiler = ContentionProfiler::new();
+
+        profiler.add("java.lang.Object", &[ frame(1, 10), frame(2, 20) ], 5_000_000);
+        profiler.add("java.lang.Object", &[ frame(3, 0), frame(2, 20) ], 8_000_000);
+        profiler.add("java.util.HashMap", &[ frame(4, 10) ], 3_000_000);
+
+        profiler.on_class_unload(&[ MethodId { native_id: 1 as JavaMethod }, MethodId { native_id: 4 as JavaMethod } ]);
+
+        assert_eq!(1, profiler.sites().len());
+        assert_eq!(ContentionStats { count: 1, total_nanos: 8_000_000, max_nanos: 8_000_000 }, profiler.class_stats("java.lang.Object"));
+    }
+}
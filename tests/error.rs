@@ -0,0 +1,43 @@
+extern crate jvmti;
+
+#[cfg(test)]
+mod tests {
+
+    use jvmti::error::{wrap_error, wrap_jni_error, JNIError, NativeError};
+
+    #[test]
+    fn error_codes_survive_a_round_trip() {
+        for code in 0..1000 {
+            assert_eq!(code as i64, wrap_error(code).code());
+        }
+
+        for code in -10..10 {
+            assert_eq!(code, wrap_jni_error(code).code());
+        }
+    }
+
+    #[test]
+    fn redefinition_errors_are_recognized() {
+        assert_eq!(NativeError::InvalidClassFormat, wrap_error(60));
+        assert_eq!(NativeError::FailsVerification, wrap_error(62));
+        assert_eq!(NativeError::UnsupportedRedefinitionClassAttributeChanged, wrap_error(72));
+        assert_eq!(NativeError::UnmodifiableClass, wrap_error(79));
+    }
+
+    #[test]
+    fn unknown_codes_are_kept() {
+        assert_eq!(NativeError::UnknownError(33), wrap_error(33));
+        assert_eq!(JNIError::UnknownError(-42), wrap_jni_error(-42));
+        assert_eq!("Unknown error code: 33. (error 33)", format!("{}", wrap_error(33)));
+    }
+
+    #[test]
+    fn jni_errors_are_native_errors() {
+        let error = NativeError::from(wrap_jni_error(-3));
+
+        assert_eq!(NativeError::JNIError(JNIError::UnsupportedVersion), error);
+        assert_eq!("The requested JNI or JVM TI version is not supported. (JNI error -3)", format!("{}", wrap_jni_error(-3)));
+        assert_eq!(-3, error.code());
+        assert_eq!("The requested JNI or JVM TI version is not supported. (error -3)", format!("{}", error));
+    }
+}
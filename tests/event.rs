@@ -3,11 +3,103 @@ extern crate jvmti;
 #[cfg(test)]
 mod tests {
 
-    use jvmti::event::EventCallbacks;
+    use jvmti::event::{EventCallbacks, FnClassUnload, FnMethodEntry, FnThreadStart, FnVMObjectFree, Handlers, VMEvent};
+    use jvmti::event_handler::subscribe;
+    use std::sync::{Arc, Mutex};
 
     #[test]
     fn empty_event_callbacks_are_instantiatable_using_new() {
         let ec = EventCallbacks::new();
-        assert_eq!(None, ec.method_entry);
+        assert!(ec.method_entry.is_empty());
+    }
+
+    #[test]
+    fn handlers_are_called_in_subscription_order() {
+        let calls = Arc::new(Mutex::new(vec![]));
+        let mut handlers: Handlers<Fn(u32) + Send + Sync> = Handlers::new();
+
+        for id in 0..3 {
+            let calls = calls.clone();
+            handlers.add(id, Box::new(move |value| calls.lock().unwrap().push((id, value))));
+        }
+
+        for handler in handlers.snapshot() {
+            handler(7);
+        }
+
+        assert_eq!(vec![(0, 7), (1, 7), (2, 7)], *calls.lock().unwrap());
+    }
+
+    #[test]
+    fn handlers_are_removed_by_id() {
+        let mut handlers: Handlers<Fn() + Send + Sync> = Handlers::new();
+
+        handlers.add(4, Box::new(|| ()));
+        handlers.add(9, Box::new(|| ()));
+
+        assert_eq!(true, handlers.remove(4));
+        assert_eq!(false, handlers.remove(4));
+        assert_eq!(1, handlers.len());
+    }
+
+    #[test]
+    fn subscriptions_can_be_unsubscribed() {
+        let first = subscribe(|callbacks| &mut callbacks.thread_start, Box::new(|_| ()) as Box<FnThreadStart>);
+        let second = subscribe(|callbacks| &mut callbacks.thread_start, Box::new(|_| ()) as Box<FnThreadStart>);
+
+        assert!(first != second);
+        assert_eq!(true, first.unsubscribe());
+        assert_eq!(false, first.unsubscribe());
+        assert_eq!(true, second.unsubscribe());
+    }
+
+    #[test]
+    fn class_unload_handlers_are_removed_from_callbacks() {
+        let mut callbacks = EventCallbacks::new();
+
+        callbacks.class_unload.add(3, Box::new(|_| ()) as Box<FnClassUnload>);
+
+        assert_eq!(true, callbacks.remove(3));
+        assert!(callbacks.class_unload.is_empty());
+    }
+
+    #[test]
+    fn events_are_only_notified_while_they_have_handlers() {
+        let mut callbacks = EventCallbacks::new();
+        let notified = |callbacks: &EventCallbacks, event: VMEvent| callbacks.notifications().into_iter().any(|(notified, enabled)| notified == event && enabled);
+
+        assert_eq!(false, notified(&callbacks, VMEvent::MethodEntry));
+
+        callbacks.method_entry.add(1, Box::new(|_| ()) as Box<FnMethodEntry>);
+        callbacks.method_entry.add(2, Box::new(|_| ()) as Box<FnMethodEntry>);
+
+        assert_eq!(true, callbacks.remove(1));
+        assert_eq!(true, notified(&callbacks, VMEvent::MethodEntry));
+        assert_eq!(true, callbacks.remove(2));
+        assert_eq!(false, notified(&callbacks, VMEvent::MethodEntry));
+    }
+
+    #[test]
+    fn object_free_events_are_notified_while_classes_are_tracked_for_unloading() {
+        let mut callbacks = EventCallbacks::new();
+        let notified = |callbacks: &EventCallbacks, event: VMEvent| callbacks.notifications().into_iter().any(|(notified, enabled)| notified == event && enabled);
+
+        callbacks.vm_object_free.add(5, Box::new(|| ()) as Box<FnVMObjectFree>);
+        callbacks.class_unload.add(6, Box::new(|_| ()) as Box<FnClassUnload>);
+
+        assert_eq!(true, callbacks.remove(5));
+        assert_eq!(true, notified(&callbacks, VMEvent::VMObjectFree));
+        assert_eq!(true, notified(&callbacks, VMEvent::ClassPrepare));
+        assert_eq!(true, callbacks.remove(6));
+        assert_eq!(false, notified(&callbacks, VMEvent::VMObjectFree));
+        assert_eq!(false, notified(&callbacks, VMEvent::ClassPrepare));
+    }
+
+    #[test]
+    fn handlers_can_be_unsubscribed_before_the_agent_is_updated() {
+        let subscription = subscribe(|callbacks| &mut callbacks.method_entry, Box::new(|_| ()) as Box<FnMethodEntry>);
+
+        assert_eq!(true, subscription.unsubscribe());
+        assert_eq!(false, subscription.unsubscribe());
     }
 }
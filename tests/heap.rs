@@ -0,0 +1,60 @@
+extern crate jvmti;
+
+#[cfg(test)]
+mod tests {
+
+    use jvmti::heap::{ClassHistogram, HeapFilter, HeapVisit, ReferenceKind};
+
+    #[test]
+    fn histograms_list_the_largest_classes_first() {
+        let mut histogram = ClassHistogram::new();
+
+        histogram.add("java.lang.String", 24);
+        histogram.add("byte[]", 100);
+        histogram.add("java.lang.String", 24);
+        histogram.add("java.lang.Object", 16);
+
+        let entries: Vec<(&str, u64, u64)> = histogram.entries().iter().map(|entry| (entry.class_name.as_str(), entry.instances, entry.bytes)).collect();
+
+        assert_eq!(vec![("byte[]", 1, 100), ("java.lang.String", 2, 48), ("java.lang.Object", 1, 16)], entries);
+        assert_eq!(4, histogram.total_instances());
+        assert_eq!(164, histogram.total_bytes());
+    }
+
+    #[test]
+    fn histograms_are_written_like_jmap_prints_them() {
+        let mut histogram = ClassHistogram::new();
+        let mut output = vec![];
+
+        histogram.add("java.lang.String", 24);
+        histogram.write(&mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert_eq!(" num     #instances         #bytes  class name", lines[0]);
+        assert_eq!("   1:              1             24  java.lang.String", lines[2]);
+        assert_eq!("Total              1             24", lines[3]);
+    }
+
+    #[test]
+    fn heap_filters_combine_their_flags() {
+        assert_eq!(0, HeapFilter::NONE.bits());
+        assert_eq!(4 | 32, (HeapFilter::TAGGED | HeapFilter::CLASS_UNTAGGED).bits());
+    }
+
+    #[test]
+    fn only_continuing_visits_follow_references() {
+        assert_eq!(0x100, HeapVisit::Continue.to_native());
+        assert_eq!(0, HeapVisit::Skip.to_native());
+        assert_eq!(0x8000, HeapVisit::Abort.to_native());
+    }
+
+    #[test]
+    fn references_from_roots_are_told_apart() {
+        assert!(ReferenceKind::JniGlobal.is_root());
+        assert!(ReferenceKind::Thread.is_root());
+        assert!(!ReferenceKind::Field(2).is_root());
+        assert!(!ReferenceKind::Class.is_root());
+    }
+}
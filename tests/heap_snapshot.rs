@@ -0,0 +1,80 @@
+extern crate jvmti;
+
+#[cfg(test)]
+mod tests {
+
+    use jvmti::profile::heap::{EdgeKind, HeapEdge, HeapNode, HeapRoot, HeapSnapshot, PathStep, RootKind};
+    use std::env;
+
+    fn node(size: u64, targets: &[u32]) -> HeapNode {
+        HeapNode { class: 0, size: size, edges: targets.iter().enumerate().map(|(index, target)| HeapEdge { kind: EdgeKind::Field(index as i32), target: *target }).collect() }
+    }
+
+    // 0 -> 1 -> 3, 0 -> 2 -> 3, 3 -> 4, 2 -> 5; 6 is unreachable
+    fn diamond() -> HeapSnapshot {
+        HeapSnapshot {
+            class_names: vec![ "Node".to_string() ],
+            objects: vec![ node(1, &[1, 2]), node(2, &[3]), node(4, &[3, 5]), node(8, &[4]), node(16, &[]), node(32, &[]), node(64, &[]) ],
+            roots: vec![ HeapRoot { kind: RootKind::Thread, object: 0 } ]
+        }
+    }
+
+    #[test]
+    fn objects_are_dominated_by_the_objects_all_their_paths_go_through() {
+        let dominators = diamond().dominators();
+
+        assert_eq!(None, dominators.immediate_dominator(0));
+        assert_eq!(Some(0), dominators.immediate_dominator(1));
+        assert_eq!(Some(0), dominators.immediate_dominator(3));
+        assert_eq!(Some(3), dominators.immediate_dominator(4));
+        assert_eq!(Some(2), dominators.immediate_dominator(5));
+        assert_eq!(None, dominators.immediate_dominator(6));
+    }
+
+    #[test]
+    fn retained_sizes_include_the_dominated_objects() {
+        let dominators = diamond().dominators();
+
+        assert_eq!(63, dominators.retained_size(0));
+        assert_eq!(2, dominators.retained_size(1));
+        assert_eq!(36, dominators.retained_size(2));
+        assert_eq!(24, dominators.retained_size(3));
+        assert_eq!(vec![0, 2, 5], dominators.largest(3));
+    }
+
+    #[test]
+    fn objects_reachable_from_several_roots_are_only_dominated_by_the_roots() {
+        let mut snapshot = diamond();
+        snapshot.roots.push(HeapRoot { kind: RootKind::JniGlobal, object: 3 });
+
+        let dominators = snapshot.dominators();
+
+        assert_eq!(None, dominators.immediate_dominator(3));
+        assert_eq!(Some(3), dominators.immediate_dominator(4));
+        assert_eq!(39, dominators.retained_size(0));
+    }
+
+    #[test]
+    fn paths_from_the_roots_are_the_shortest_ones() {
+        let mut snapshot = diamond();
+        snapshot.roots.push(HeapRoot { kind: RootKind::StackLocal, object: 2 });
+
+        let paths = snapshot.root_paths();
+
+        assert_eq!(Some(vec![ PathStep::Root(RootKind::StackLocal, 2), PathStep::Edge(2, EdgeKind::Field(0), 3), PathStep::Edge(3, EdgeKind::Field(0), 4) ]), paths.path_to(4));
+        assert_eq!(Some(vec![ PathStep::Root(RootKind::Thread, 0) ]), paths.path_to(0));
+        assert_eq!(None, paths.path_to(6));
+    }
+
+    #[test]
+    fn snapshots_are_read_back_as_written() {
+        let snapshot = diamond();
+        let path = env::temp_dir().join(format!("heap-snapshot-{}.json.gz", ::std::process::id()));
+
+        snapshot.write(&path).unwrap();
+        let read = HeapSnapshot::read(&path);
+        let _ = ::std::fs::remove_file(&path);
+
+        assert_eq!(Some(snapshot), read.ok());
+    }
+}
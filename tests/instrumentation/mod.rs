@@ -3,14 +3,19 @@ extern crate jvmti;
 
 #[cfg(test)]
 mod tests {
+    use jvmti::bytecode::ClassWriter;
     use jvmti::instrumentation::JavaClass;
 
     #[test]
     fn can_create_empty_class() {
         let new_class = JavaClass::new();
 
-        let classfile = new_class.to_classfile();
+        let mut first: Vec<u8> = vec![];
+        let mut second: Vec<u8> = vec![];
 
-        assert_eq!(classfile, classfile);
+        ClassWriter::new(&mut first).write_class(&new_class.to_classfile()).unwrap();
+        ClassWriter::new(&mut second).write_class(&new_class.to_classfile()).unwrap();
+
+        assert_eq!(first, second);
     }
 }
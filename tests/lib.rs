@@ -1,5 +1,6 @@
 extern crate jvmti;
 extern crate libc;
+extern crate flate2;
 
 mod bytecode;
 mod environment;
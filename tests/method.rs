@@ -0,0 +1,50 @@
+extern crate jvmti;
+
+#[cfg(test)]
+mod tests {
+
+    use jvmti::method::{line_number_at, line_start_location, LineNumberEntry, LocalVariableEntry};
+
+    #[test]
+    fn line_numbers_are_resolved_from_the_closest_preceding_entry() {
+        let table = vec![
+            LineNumberEntry { start_location: 0, line_number: 10 },
+            LineNumberEntry { start_location: 12, line_number: 14 },
+            LineNumberEntry { start_location: 5, line_number: 11 }
+        ];
+
+        assert_eq!(Some(10), line_number_at(&table, 0));
+        assert_eq!(Some(11), line_number_at(&table, 7));
+        assert_eq!(Some(14), line_number_at(&table, 12));
+        assert_eq!(Some(14), line_number_at(&table, 40));
+    }
+
+    #[test]
+    fn locations_without_entries_have_no_line_number() {
+        assert_eq!(None, line_number_at(&vec![], 3));
+        assert_eq!(None, line_number_at(&vec![ LineNumberEntry { start_location: 4, line_number: 1 } ], 3));
+    }
+
+    #[test]
+    fn lines_start_at_their_first_entry() {
+        let table = vec![
+            LineNumberEntry { start_location: 0, line_number: 10 },
+            LineNumberEntry { start_location: 20, line_number: 12 },
+            LineNumberEntry { start_location: 5, line_number: 12 }
+        ];
+
+        assert_eq!(Some(0), line_start_location(&table, 10));
+        assert_eq!(Some(5), line_start_location(&table, 12));
+        assert_eq!(None, line_start_location(&table, 11));
+    }
+
+    #[test]
+    fn local_variables_are_live_within_their_range() {
+        let variable = LocalVariableEntry { start_location: 4, length: 10, name: "count".to_string(), signature: "I".to_string(), generic: None, slot: 1 };
+
+        assert!(!variable.is_live_at(3));
+        assert!(variable.is_live_at(4));
+        assert!(variable.is_live_at(13));
+        assert!(!variable.is_live_at(14));
+    }
+}
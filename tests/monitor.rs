@@ -0,0 +1,50 @@
+extern crate jvmti;
+
+#[cfg(test)]
+mod tests {
+
+    use jvmti::monitor::{wait_for_cycles, Deadlock, DeadlockedThread, LockedMonitor};
+
+    #[test]
+    fn threads_waiting_for_each_other_form_cycles() {
+        // 0 -> 1 -> 2 -> 0, 3 -> 0, 4 -> 5 -> 4, 6 isn't waiting
+        let waits_for = vec![ Some(1), Some(2), Some(0), Some(0), Some(5), Some(4), None ];
+
+        assert_eq!(vec![ vec![0, 1, 2], vec![4, 5] ], wait_for_cycles(&waits_for));
+    }
+
+    #[test]
+    fn cycles_start_with_their_lowest_thread() {
+        let waits_for = vec![ Some(3), Some(2), Some(3), Some(1) ];
+
+        assert_eq!(vec![ vec![1, 2, 3] ], wait_for_cycles(&waits_for));
+    }
+
+    #[test]
+    fn threads_waiting_in_a_chain_are_not_deadlocked() {
+        let waits_for = vec![ Some(1), Some(2), None ];
+
+        assert!(wait_for_cycles(&waits_for).is_empty());
+    }
+
+    #[test]
+    fn deadlocks_name_the_owner_of_each_monitor() {
+        let deadlock = Deadlock {
+            threads: vec![
+                DeadlockedThread {
+                    name: "worker-1".to_string(),
+                    blocked_on: LockedMonitor { class_name: "java.lang.String".to_string(), frame: Some("Worker.run(Worker.java:12)".to_string()) },
+                    holds: vec![ LockedMonitor { class_name: "java.lang.Object".to_string(), frame: Some("Worker.run(Worker.java:11)".to_string()) } ]
+                },
+                DeadlockedThread {
+                    name: "worker-2".to_string(),
+                    blocked_on: LockedMonitor { class_name: "java.lang.Object".to_string(), frame: None },
+                    holds: vec![ LockedMonitor { class_name: "java.lang.String".to_string(), frame: None } ]
+                }
+            ]
+        };
+
+        assert_eq!("Deadlock between 2 threads:\n  \"worker-1\" is blocked on java.lang.String held by \"worker-2\"\n      at Worker.run(Worker.java:12)\n      locked java.lang.Object at Worker.run(Worker.java:11)\n  \"worker-2\" is blocked on java.lang.Object held by \"worker-1\"\n      locked java.lang.String",
+                   format!("{}", deadlock));
+    }
+}
@@ -0,0 +1,38 @@
+extern crate jvmti;
+
+#[cfg(test)]
+mod tests {
+
+    use jvmti::class::{ClassSignature, JavaType};
+    use jvmti::method::MethodSignature;
+    use jvmti::perf_map::{method_symbol, PerfMap};
+    use jvmti::runtime::DynamicCodeGeneratedEvent;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    #[test]
+    fn method_symbols_include_class_and_descriptor() {
+        let class_sig = ClassSignature::new(&JavaType::parse("Ljava/lang/String;").unwrap(), "".to_string());
+        let method_sig = MethodSignature::new("hashCode".to_string(), "()I".to_string(), "".to_string());
+
+        assert_eq!("java.lang.String.hashCode()I", method_symbol(&class_sig, &method_sig));
+    }
+
+    #[test]
+    fn symbols_are_written_as_hexadecimal_ranges() {
+        let path = env::temp_dir().join(format!("perf-map-test-{}.map", process::id()));
+
+        {
+            let mut perf_map = PerfMap::create(&path).unwrap();
+
+            perf_map.write_symbol(0x7f00_1000, 0x1a0, "Example.run()V").unwrap();
+            perf_map.write_dynamic_code(&DynamicCodeGeneratedEvent { name: "Interpreter".to_string(), address: 0x7f00_2000, length: 64 }).unwrap();
+        }
+
+        let content = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!("7f001000 1a0 Example.run()V\n7f002000 40 Interpreter\n", content);
+    }
+}
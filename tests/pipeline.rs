@@ -0,0 +1,86 @@
+extern crate jvmti;
+
+#[cfg(test)]
+mod tests {
+
+    use jvmti::pipeline::{record, stats, EventRecord, RingBuffer};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn ring_buffers_pop_values_in_push_order() {
+        let buffer: RingBuffer<u32> = RingBuffer::new(4);
+
+        unsafe {
+            assert!(buffer.push(1));
+            assert!(buffer.push(2));
+            assert_eq!(Some(1), buffer.pop());
+            assert!(buffer.push(3));
+            assert_eq!(Some(2), buffer.pop());
+            assert_eq!(Some(3), buffer.pop());
+            assert_eq!(None, buffer.pop());
+        }
+    }
+
+    #[test]
+    fn full_ring_buffers_reject_values() {
+        let buffer: RingBuffer<u32> = RingBuffer::new(2);
+
+        unsafe {
+            assert!(buffer.push(1));
+            assert!(buffer.push(2));
+            assert!(!buffer.push(3));
+            assert_eq!(2, buffer.len());
+            assert_eq!(Some(1), buffer.pop());
+            assert!(buffer.push(4));
+            assert_eq!(Some(2), buffer.pop());
+            assert_eq!(Some(4), buffer.pop());
+        }
+    }
+
+    #[test]
+    fn ring_buffers_hand_values_over_between_threads() {
+        let buffer: Arc<RingBuffer<usize>> = Arc::new(RingBuffer::new(16));
+        let producer_buffer = buffer.clone();
+        let count = 100000;
+
+        // The spawned thread is the only one pushing, this one the only one popping
+        let producer = thread::spawn(move || {
+            for value in 0..count {
+                while !unsafe { producer_buffer.push(value) } {
+                    thread::yield_now();
+                }
+            }
+        });
+
+        let mut expected = 0;
+
+        while expected < count {
+            match unsafe { buffer.pop() } {
+                Some(value) => {
+                    assert_eq!(expected, value);
+                    expected += 1;
+                },
+                None => thread::yield_now()
+            }
+        }
+
+        producer.join().unwrap();
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn events_beyond_the_capacity_of_a_thread_are_dropped() {
+        thread::spawn(|| {
+            for _ in 0..5000 {
+                record(EventRecord::GarbageCollectionStart, || None);
+            }
+        }).join().unwrap();
+
+        let stats = stats();
+
+        assert!(stats.recorded >= 4096);
+        assert!(stats.dropped >= 5000 - 4096);
+        assert!(stats.dropped_per_thread.iter().any(|&(ref name, dropped)| name == "<VM>" && dropped == 5000 - 4096));
+    }
+}
@@ -0,0 +1,40 @@
+extern crate jvmti;
+
+#[cfg(test)]
+mod tests {
+
+    use jvmti::class::ClassSignature;
+    use jvmti::method::{MethodId, MethodSignature};
+    use jvmti::snapshot::{FrameSnapshot, LocalVariable};
+    use jvmti::value::JavaValue;
+    use std::ptr;
+
+    fn local(name: &str, signature: &str, value: Option<JavaValue>) -> LocalVariable {
+        LocalVariable { name: name.to_string(), signature: signature.to_string(), slot: 0, value: value }
+    }
+
+    #[test]
+    fn local_variables_are_displayed_with_their_values() {
+        assert_eq!("count = 3", local("count", "I", Some(JavaValue::Int(3))).to_string());
+        assert_eq!("flag = true", local("flag", "Z", Some(JavaValue::Boolean(true))).to_string());
+        assert_eq!("c = 'x'", local("c", "C", Some(JavaValue::Char('x' as u16))).to_string());
+        assert_eq!("name = null", local("name", "Ljava/lang/String;", Some(JavaValue::Object(ptr::null_mut()))).to_string());
+        assert_eq!("ratio = <unavailable>", local("ratio", "D", None).to_string());
+    }
+
+    #[test]
+    fn frames_are_displayed_with_their_line_and_locals() {
+        let frame = FrameSnapshot {
+            depth: 0,
+            method_id: MethodId { native_id: ptr::null_mut() },
+            class_sig: ClassSignature { package: "com.example".to_string(), name: "com.example.Orders".to_string(), generic: String::new() },
+            method_sig: MethodSignature::new("place".to_string(), "(IJ)V".to_string(), String::new()),
+            location: 12,
+            line_number: Some(42),
+            this: None,
+            locals: vec![ local("quantity", "I", Some(JavaValue::Int(2))), local("customer", "J", Some(JavaValue::Long(7))) ]
+        };
+
+        assert_eq!("com.example.Orders.place:42\n    quantity = 2\n    customer = 7", frame.to_string());
+    }
+}
@@ -0,0 +1,53 @@
+extern crate jvmti;
+
+#[cfg(test)]
+mod tests {
+
+    use jvmti::thread::{JavaThreadState, ThreadFilter, ThreadState};
+
+    #[test]
+    fn empty_filters_select_every_thread() {
+        let filter = ThreadFilter::new(vec![], vec![]);
+
+        assert!(filter.selects_all());
+        assert!(filter.matches("main", "main"));
+    }
+
+    #[test]
+    fn filters_match_thread_names_and_prefixes() {
+        let filter = ThreadFilter::new(vec![ "main".to_string(), "http-nio-*".to_string() ], vec![]);
+
+        assert!(filter.matches("main", "main"));
+        assert!(filter.matches("http-nio-8080-exec-1", "main"));
+        assert!(!filter.matches("mainly", "main"));
+        assert!(!filter.matches("Signal Dispatcher", "system"));
+    }
+
+    #[test]
+    fn filters_match_thread_groups() {
+        let filter = ThreadFilter::new(vec![], vec![ "system".to_string() ]);
+
+        assert!(filter.matches("Signal Dispatcher", "system"));
+        assert!(!filter.matches("main", "main"));
+    }
+
+    #[test]
+    fn thread_states_map_to_java_thread_states() {
+        assert_eq!(JavaThreadState::New, ThreadState::from_bits(0).java_state());
+        assert_eq!(JavaThreadState::Terminated, ThreadState::TERMINATED.java_state());
+        assert_eq!(JavaThreadState::Runnable, (ThreadState::ALIVE | ThreadState::RUNNABLE | ThreadState::IN_NATIVE).java_state());
+        assert_eq!(JavaThreadState::Blocked, (ThreadState::ALIVE | ThreadState::BLOCKED_ON_MONITOR_ENTER).java_state());
+        assert_eq!(JavaThreadState::Waiting, (ThreadState::ALIVE | ThreadState::WAITING | ThreadState::WAITING_INDEFINITELY | ThreadState::PARKED).java_state());
+        assert_eq!(JavaThreadState::TimedWaiting, (ThreadState::ALIVE | ThreadState::WAITING | ThreadState::WAITING_WITH_TIMEOUT | ThreadState::SLEEPING).java_state());
+    }
+
+    #[test]
+    fn thread_states_contain_their_flags() {
+        let state = ThreadState::from_bits(0x0001 | 0x0004 | 0x200000);
+
+        assert!(state.contains(ThreadState::ALIVE | ThreadState::RUNNABLE));
+        assert!(state.contains(ThreadState::INTERRUPTED));
+        assert!(!state.contains(ThreadState::SUSPENDED));
+        assert_eq!("TIMED_WAITING", JavaThreadState::TimedWaiting.to_string());
+    }
+}
@@ -0,0 +1,37 @@
+extern crate jvmti;
+
+#[cfg(test)]
+mod tests {
+
+    use jvmti::value::JavaValue;
+    use std::ptr;
+
+    #[test]
+    fn values_survive_a_jvalue_round_trip() {
+        let values = vec![
+            ('Z', JavaValue::Boolean(true)),
+            ('B', JavaValue::Byte(-3)),
+            ('C', JavaValue::Char(0x263a)),
+            ('S', JavaValue::Short(-1200)),
+            ('I', JavaValue::Int(1 << 30)),
+            ('J', JavaValue::Long(-1 << 40)),
+            ('F', JavaValue::Float(2.5)),
+            ('D', JavaValue::Double(-0.125)),
+            ('L', JavaValue::Object(ptr::null_mut()))
+        ];
+
+        for (signature_type, value) in values {
+            assert_eq!(Some(value), JavaValue::from_jvalue(signature_type, value.to_jvalue()));
+        }
+    }
+
+    #[test]
+    fn arrays_are_read_as_objects() {
+        assert_eq!(Some(JavaValue::Object(ptr::null_mut())), JavaValue::from_jvalue('[', JavaValue::Object(ptr::null_mut()).to_jvalue()));
+    }
+
+    #[test]
+    fn unknown_types_are_not_read() {
+        assert_eq!(None, JavaValue::from_jvalue('V', JavaValue::Int(1).to_jvalue()));
+    }
+}